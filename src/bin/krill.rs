@@ -1,15 +1,91 @@
 extern crate krill;
 
+use std::fs::File;
+use std::io::BufReader;
 use std::process;
 
+use krill::commons::error::Error;
 use krill::commons::util::file;
+use krill::commons::util::softsigner::OpenSslSigner;
+use krill::daemon::backup::Restore;
 use krill::daemon::config::Config;
 use krill::daemon::http::server;
+use krill::upgrades;
 
 #[tokio::main]
 async fn main() {
     match Config::create() {
         Ok(config) => {
+            if let Some(rekey) = Config::get_rekey_args() {
+                let new = config.key_encryption.as_ref();
+                match OpenSslSigner::rekey(&config.data_dir, rekey.old.as_ref(), new) {
+                    Ok(count) => println!("Re-encrypted {} keys", count),
+                    Err(e) => {
+                        eprintln!("Could not re-encrypt the key store: {}", e);
+                        ::std::process::exit(1);
+                    }
+                }
+                return;
+            }
+
+            if Config::get_migrate_storage() {
+                match upgrades::migrate_storage(&config.data_dir, config.storage_backend) {
+                    Ok(migrated) => {
+                        for (name_space, count) in migrated {
+                            println!(
+                                "Converted {} aggregates in '{}' to {} storage",
+                                count, name_space, config.storage_backend
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Could not convert the storage: {}", e);
+                        ::std::process::exit(1);
+                    }
+                }
+                return;
+            }
+
+            if Config::get_verify_snapshots() {
+                match upgrades::verify_snapshots(&config.data_dir) {
+                    Ok(checks) => {
+                        for check in &checks {
+                            println!("{}", check);
+                        }
+                        if !checks.iter().all(|check| check.is_valid()) {
+                            ::std::process::exit(1);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Could not verify snapshots: {}", e);
+                        ::std::process::exit(1);
+                    }
+                }
+                return;
+            }
+
+            match Config::get_restore_args() {
+                Ok(Some(args)) => {
+                    let res = File::open(&args.archive)
+                        .map_err(Error::IoError)
+                        .and_then(|archive| Restore::read(BufReader::new(archive)))
+                        .and_then(|restore| restore.restore(&config.data_dir, &args.point));
+                    match res {
+                        Ok(report) => print!("{}", report),
+                        Err(e) => {
+                            eprintln!("Could not restore backup: {}", e);
+                            ::std::process::exit(1);
+                        }
+                    }
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ::std::process::exit(1);
+                }
+            }
+
             let pid_file = config.pid_file();
             if let Err(e) = file::save(process::id().to_string().as_bytes(), &pid_file) {
                 eprintln!("Could not write PID file: {}", e);
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::{env, fmt, io};
 
 use serde::de::DeserializeOwned;
@@ -7,15 +8,16 @@ use rpki::uri;
 
 use crate::cli::options::{
     BulkCaCommand, CaCommand, Command, KrillInitDetails, Options, PublishersCommand,
+    SchedulerCommand, TokensCommand,
 };
 use crate::cli::report::{ApiResponse, ReportError};
 use crate::commons::api::{
-    AllCertAuthIssues, CaRepoDetails, CertAuthIssues, ChildCaInfo, CurrentRepoState,
-    ParentCaContact, PublisherDetails, PublisherList, Token,
+    AllCertAuthIssues, CaRepoDetails, CaToken, CaTokenList, CertAuthIssues, ChildCaInfo,
+    CurrentRepoState, ParentCaContact, PublisherDetails, PublisherList, Token,
 };
 use crate::commons::bgp::BgpAnalysisReport;
 use crate::commons::remote::rfc8183;
-use crate::commons::util::httpclient;
+use crate::commons::util::{file, httpclient};
 use crate::constants::KRILL_CLI_API_ENV;
 use crate::daemon::config::Config;
 
@@ -59,8 +61,11 @@ impl KrillClient {
             Command::Health => client.health().await,
             Command::Info => client.info().await,
             Command::Bulk(cmd) => client.bulk(cmd).await,
+            Command::Scheduler(cmd) => client.scheduler(cmd).await,
             Command::CertAuth(cmd) => client.certauth(cmd).await,
             Command::Publishers(cmd) => client.publishers(cmd).await,
+            Command::Tokens(cmd) => client.tokens(cmd).await,
+            Command::Backup(output) => client.backup(output).await,
             Command::Init(details) => client.init(details),
             Command::NotSet => Err(Error::MissingCommand),
         }
@@ -90,6 +95,26 @@ impl KrillClient {
                 self.post_empty("api/v1/bulk/cas/sync/repo").await?;
                 Ok(ApiResponse::Empty)
             }
+            BulkCaCommand::Archive => {
+                let report = self
+                    .post_empty_with_response("api/v1/bulk/cas/archive")
+                    .await?;
+                Ok(ApiResponse::HistoryArchive(report))
+            }
+        }
+    }
+
+    async fn scheduler(&self, command: SchedulerCommand) -> Result<ApiResponse, Error> {
+        match command {
+            SchedulerCommand::Status => {
+                let status = self.get_json("api/v1/scheduler").await?;
+                Ok(ApiResponse::SchedulerStatus(status))
+            }
+            SchedulerCommand::Run(job) => {
+                let uri = format!("api/v1/scheduler/{}/run", job);
+                self.post_empty(&uri).await?;
+                Ok(ApiResponse::Empty)
+            }
         }
     }
 
@@ -212,6 +237,54 @@ impl KrillClient {
                 Ok(ApiResponse::Empty)
             }
 
+            CaCommand::AspasList(handle) => {
+                let uri = format!("api/v1/cas/{}/aspas", handle);
+                let aspas = self.get_json(&uri).await?;
+                Ok(ApiResponse::AspaDefinitions(aspas))
+            }
+
+            CaCommand::AspasUpdate(handle, updates) => {
+                let uri = format!("api/v1/cas/{}/aspas", handle);
+                self.post_json(&uri, updates).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::RouterCertsList(handle) => {
+                let uri = format!("api/v1/cas/{}/bgpsec", handle);
+                let certs = self.get_json(&uri).await?;
+                Ok(ApiResponse::RouterCerts(certs))
+            }
+
+            CaCommand::RouterCertify(handle, request) => {
+                let uri = format!("api/v1/cas/{}/bgpsec", handle);
+                self.post_json(&uri, request).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::RouterRevoke(handle, key) => {
+                let uri = format!("api/v1/cas/{}/bgpsec/{}", handle, key);
+                self.delete(&uri).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::GhostbusterShow(handle) => {
+                let uri = format!("api/v1/cas/{}/ghostbuster", handle);
+                let record = self.get_json(&uri).await?;
+                Ok(ApiResponse::Ghostbuster(record))
+            }
+
+            CaCommand::GhostbusterUpdate(handle, record) => {
+                let uri = format!("api/v1/cas/{}/ghostbuster", handle);
+                self.post_json(&uri, record).await?;
+                Ok(ApiResponse::Empty)
+            }
+
+            CaCommand::GhostbusterRemove(handle) => {
+                let uri = format!("api/v1/cas/{}/ghostbuster", handle);
+                self.delete(&uri).await?;
+                Ok(ApiResponse::Empty)
+            }
+
             CaCommand::BgpAnalysisFull(handle) => {
                 let uri = format!("api/v1/cas/{}/routes/analysis/full", handle);
                 let report = self.get_json(&uri).await?;
@@ -285,6 +358,12 @@ impl KrillClient {
                 let stats = self.get_json("stats/repo").await?;
                 Ok(ApiResponse::RepoStats(stats))
             }
+            PublishersCommand::SessionReset => {
+                let reset = self
+                    .post_empty_with_response("api/v1/publishers/session_reset")
+                    .await?;
+                Ok(ApiResponse::RepoSessionReset(reset))
+            }
             PublishersCommand::AddPublisher(req) => {
                 let res = self
                     .post_json_with_response("api/v1/publishers", req)
@@ -309,6 +388,35 @@ impl KrillClient {
         }
     }
 
+    async fn tokens(&self, command: TokensCommand) -> Result<ApiResponse, Error> {
+        match command {
+            TokensCommand::List => {
+                let list: CaTokenList = self.get_json("api/v1/tokens").await?;
+                Ok(ApiResponse::CaTokenList(list))
+            }
+            TokensCommand::Add(req) => {
+                let token: CaToken = self.post_json_with_response("api/v1/tokens", req).await?;
+                Ok(ApiResponse::CaToken(token))
+            }
+            TokensCommand::Remove(name) => {
+                let uri = format!("api/v1/tokens/{}", name);
+                self.delete(&uri).await?;
+                Ok(ApiResponse::Empty)
+            }
+        }
+    }
+
+    async fn backup(&self, output: PathBuf) -> Result<ApiResponse, Error> {
+        let uri = self.resolve_uri("api/v1/backup");
+        let archive = httpclient::get_binary(&uri, Some(&self.token)).await?;
+        file::save(&archive, &output)?;
+        Ok(ApiResponse::GenericBody(format!(
+            "Saved backup ({} bytes) to {}",
+            archive.len(),
+            output.to_string_lossy()
+        )))
+    }
+
     fn resolve_uri(&self, path: &str) -> String {
         format!("{}{}", &self.server, path)
     }
@@ -377,6 +485,13 @@ impl KrillClient {
             .map_err(Error::HttpClientError)
     }
 
+    async fn post_empty_with_response<T: DeserializeOwned>(&self, uri: &str) -> Result<T, Error> {
+        let uri = self.resolve_uri(uri);
+        httpclient::post_empty_with_response(&uri, Some(&self.token))
+            .await
+            .map_err(Error::HttpClientError)
+    }
+
     async fn post_json(&self, uri: &str, data: impl Serialize) -> Result<(), Error> {
         let uri = self.resolve_uri(uri);
         httpclient::post_json(&uri, data, Some(&self.token))
@@ -7,15 +7,17 @@ use std::{env, fmt};
 use bytes::Bytes;
 use clap::{App, Arg, ArgMatches, SubCommand};
 
+use rpki::crypto::KeyIdentifier;
 use rpki::uri;
 use rpki::x509::Time;
 
 use crate::cli::report::{ReportError, ReportFormat};
 use crate::commons::api::RepositoryUpdate;
 use crate::commons::api::{
-    AddChildRequest, AuthorizationFmtError, CertAuthInit, ChildAuthRequest, ChildHandle, Handle,
+    AddChildRequest, AsNumber, AspaDefinition, AspaDefinitionUpdates, AuthorizationFmtError,
+    Base64, CaTokenRequest, CertAuthInit, ChildAuthRequest, ChildHandle, GhostbusterRecord, Handle,
     ParentCaContact, ParentCaReq, ParentHandle, PublisherHandle, ResourceSet, ResourceSetError,
-    RoaDefinitionUpdates, Token, UpdateChildRequest,
+    RoaDefinitionUpdates, RouterCertRequest, SchedulerJob, Token, UpdateChildRequest,
 };
 use crate::commons::remote::id::IdCert;
 use crate::commons::remote::rfc8183;
@@ -644,6 +646,213 @@ impl Options {
         app.subcommand(sub)
     }
 
+    fn make_cas_aspas_list_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("list").about("Show current ASPA definitions.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_aspas_add_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("add")
+            .about("Add an ASPA definition, or replace the definition for the customer AS.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub.arg(
+            Arg::with_name("aspa")
+                .long("aspa")
+                .help("The customer AS and its providers, e.g.: \"64496 => 64497, 64498\"")
+                .value_name("definition")
+                .required(true),
+        );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_aspas_remove_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub =
+            SubCommand::with_name("remove").about("Remove the ASPA definition for a customer AS.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub.arg(
+            Arg::with_name("customer")
+                .long("customer")
+                .help("The customer AS number, e.g.: 64496")
+                .value_name("ASN")
+                .required(true),
+        );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_aspas_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("aspas").about("Manage ASPA objects for your CA.");
+
+        sub = Self::make_cas_aspas_list_sc(sub);
+        sub = Self::make_cas_aspas_add_sc(sub);
+        sub = Self::make_cas_aspas_remove_sc(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_bgpsec_list_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub =
+            SubCommand::with_name("list").about("Show current BGPsec router certificates.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_bgpsec_add_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("add")
+            .about("Certify a router key, or replace the ASNs for a certified router key.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub
+            .arg(
+                Arg::with_name("csr")
+                    .long("csr")
+                    .help("The PKCS#10 request made by the router (DER or PEM)")
+                    .value_name("file")
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("asn")
+                    .long("asn")
+                    .help("An AS number the router key may sign for. Can be repeated.")
+                    .value_name("ASN")
+                    .multiple(true)
+                    .number_of_values(1)
+                    .required(true),
+            );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_bgpsec_remove_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("remove")
+            .about("Revoke the router certificate for a router key.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub.arg(
+            Arg::with_name("key")
+                .long("key")
+                .help("The key identifier of the router key (hex)")
+                .value_name("key id")
+                .required(true),
+        );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_bgpsec_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub =
+            SubCommand::with_name("bgpsec").about("Manage BGPsec router certificates for your CA.");
+
+        sub = Self::make_cas_bgpsec_list_sc(sub);
+        sub = Self::make_cas_bgpsec_add_sc(sub);
+        sub = Self::make_cas_bgpsec_remove_sc(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_ghostbuster_show_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("show").about("Show the current Ghostbusters vCard.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_ghostbuster_set_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("set")
+            .about("Set the Ghostbusters vCard, from contact details or a vCard file.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        sub = sub
+            .arg(
+                Arg::with_name("vcard")
+                    .long("vcard")
+                    .help("A file containing a version 4.0 vCard (RFC 6493 profile)")
+                    .value_name("file")
+                    .conflicts_with_all(&["fn", "org", "email", "tel", "adr"])
+                    .required_unless("fn"),
+            )
+            .arg(
+                Arg::with_name("fn")
+                    .long("fn")
+                    .help("The formatted name of the contact (FN)")
+                    .value_name("name")
+                    .required_unless("vcard"),
+            )
+            .arg(
+                Arg::with_name("org")
+                    .long("org")
+                    .help("The organization name (ORG)")
+                    .value_name("org")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("email")
+                    .long("email")
+                    .help("The email address (EMAIL)")
+                    .value_name("email")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("tel")
+                    .long("tel")
+                    .help("The telephone number (TEL)")
+                    .value_name("tel")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("adr")
+                    .long("adr")
+                    .help("The structured postal address (ADR), e.g. ';;Street 1;City;;1234 AB;NL'")
+                    .value_name("adr")
+                    .required(false),
+            );
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_ghostbuster_remove_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("remove")
+            .about("Remove the Ghostbusters vCard and withdraw its records.");
+
+        sub = Self::add_general_args(sub);
+        sub = Self::add_my_ca_arg(sub);
+
+        app.subcommand(sub)
+    }
+
+    fn make_cas_ghostbuster_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("ghostbuster")
+            .about("Manage the Ghostbusters contact information for your CA.");
+
+        sub = Self::make_cas_ghostbuster_show_sc(sub);
+        sub = Self::make_cas_ghostbuster_set_sc(sub);
+        sub = Self::make_cas_ghostbuster_remove_sc(sub);
+
+        app.subcommand(sub)
+    }
+
     fn make_cas_repo_request_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
         let mut sub = SubCommand::with_name("request").about("Show RFC8183 Publisher Request XML.");
 
@@ -735,6 +944,13 @@ impl Options {
         app.subcommand(sub)
     }
 
+    fn make_publishers_session_reset_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("session-reset")
+            .about("Start a new RRDP session, e.g. when RRDP files were lost or corrupted.");
+        sub = Self::add_general_args(sub);
+        app.subcommand(sub)
+    }
+
     fn add_publisher_arg<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
         app.arg(
             Arg::with_name("publisher")
@@ -799,6 +1015,7 @@ impl Options {
         sub = Self::make_publishers_list_sc(sub);
         sub = Self::make_publishers_stale_sc(sub);
         sub = Self::make_publishers_stats_sc(sub);
+        sub = Self::make_publishers_session_reset_sc(sub);
         sub = Self::make_publishers_add_sc(sub);
         sub = Self::make_publishers_remove_sc(sub);
         sub = Self::make_publishers_show_sc(sub);
@@ -807,6 +1024,58 @@ impl Options {
         app.subcommand(sub)
     }
 
+    fn add_token_name_arg<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        app.arg(
+            Arg::with_name("name")
+                .value_name("name")
+                .short("n")
+                .long("name")
+                .help("The name of the API token.")
+                .required(true),
+        )
+    }
+
+    fn make_tokens_list_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("list").about("List all CA API tokens.");
+        sub = Self::add_general_args(sub);
+        app.subcommand(sub)
+    }
+
+    fn make_tokens_add_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("add")
+            .about("Add an API token which can only manage the given CA(s).");
+        sub = Self::add_general_args(sub);
+        sub = Self::add_token_name_arg(sub);
+        sub = sub.arg(
+            Arg::with_name("ca")
+                .value_name("name")
+                .long("ca")
+                .help("The name of a CA this token can manage. Can be repeated.")
+                .multiple(true)
+                .number_of_values(1)
+                .required(true),
+        );
+        app.subcommand(sub)
+    }
+
+    fn make_tokens_remove_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("remove").about("Revoke a CA API token.");
+        sub = Self::add_general_args(sub);
+        sub = Self::add_token_name_arg(sub);
+        app.subcommand(sub)
+    }
+
+    fn make_tokens_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub =
+            SubCommand::with_name("tokens").about("Manage API tokens bound to specific CAs.");
+
+        sub = Self::make_tokens_list_sc(sub);
+        sub = Self::make_tokens_add_sc(sub);
+        sub = Self::make_tokens_remove_sc(sub);
+
+        app.subcommand(sub)
+    }
+
     fn make_bulk_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
         let mut sub = SubCommand::with_name("bulk")
             .about("Manually trigger refresh/republish/resync for all CAs.");
@@ -824,10 +1093,47 @@ impl Options {
             SubCommand::with_name("sync").about("Force that all CAs sync with their repo server");
         resync = Self::add_general_args(resync);
 
+        let mut archive = SubCommand::with_name("archive")
+            .about("Move old republish commands out of the history of all CAs into the archive");
+        archive = Self::add_general_args(archive);
+
         sub = sub
             .subcommand(refresh)
             .subcommand(republish)
-            .subcommand(resync);
+            .subcommand(resync)
+            .subcommand(archive);
+
+        app.subcommand(sub)
+    }
+
+    fn make_scheduler_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut sub = SubCommand::with_name("scheduler")
+            .about("Show the status of background jobs, or run one now.");
+
+        let mut status = SubCommand::with_name("status")
+            .about("Show the last run, result and next run of all background jobs");
+        status = Self::add_general_args(status);
+
+        let mut run = SubCommand::with_name("run").about("Run a background job now");
+        run = Self::add_general_args(run);
+        run = run.arg(
+            Arg::with_name("job")
+                .value_name("job")
+                .long("job")
+                .help("The name of the job.")
+                .possible_values(&[
+                    "events",
+                    "republish",
+                    "ca_refresh",
+                    "archive",
+                    "webhooks",
+                    "announcements",
+                    "mirrors",
+                ])
+                .required(true),
+        );
+
+        sub = sub.subcommand(status).subcommand(run);
 
         app.subcommand(sub)
     }
@@ -839,6 +1145,21 @@ impl Options {
         app.subcommand(health)
     }
 
+    fn make_backup_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+        let mut backup = SubCommand::with_name("backup")
+            .about("Download a backup of the server state, while it keeps running.");
+        backup = Self::add_general_args(backup);
+        backup = backup.arg(
+            Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .value_name("FILE")
+                .help("The file to save the backup (.tar) to.")
+                .required(true),
+        );
+        app.subcommand(backup)
+    }
+
     fn make_info_sc<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
         let info = SubCommand::with_name("info").about("Show server info");
         let info = Self::add_general_args(info);
@@ -858,17 +1179,26 @@ impl Options {
         app = Self::make_cas_parents_sc(app);
         app = Self::make_cas_keyroll_sc(app);
         app = Self::make_cas_routes_sc(app);
+        app = Self::make_cas_aspas_sc(app);
+        app = Self::make_cas_bgpsec_sc(app);
+        app = Self::make_cas_ghostbuster_sc(app);
         app = Self::make_cas_repo_sc(app);
         app = Self::make_cas_issues_sc(app);
 
         app = Self::make_publishers_sc(app);
 
+        app = Self::make_tokens_sc(app);
+
         app = Self::make_health_sc(app);
 
         app = Self::make_info_sc(app);
 
         app = Self::make_bulk_sc(app);
 
+        app = Self::make_scheduler_sc(app);
+
+        app = Self::make_backup_sc(app);
+
         app.get_matches()
     }
 
@@ -1331,6 +1661,174 @@ impl Options {
         }
     }
 
+    fn parse_matches_cas_aspas_list(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::AspasList(my_ca));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_aspas_add(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let definition = AspaDefinition::from_str(matches.value_of("aspa").unwrap())?;
+        let updates = AspaDefinitionUpdates::new(vec![definition], vec![]);
+
+        let command = Command::CertAuth(CaCommand::AspasUpdate(my_ca, updates));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_aspas_remove(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let customer = AsNumber::from_str(matches.value_of("customer").unwrap())?;
+        let updates = AspaDefinitionUpdates::new(vec![], vec![customer]);
+
+        let command = Command::CertAuth(CaCommand::AspasUpdate(my_ca, updates));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_aspas(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("list") {
+            Self::parse_matches_cas_aspas_list(m)
+        } else if let Some(m) = matches.subcommand_matches("add") {
+            Self::parse_matches_cas_aspas_add(m)
+        } else if let Some(m) = matches.subcommand_matches("remove") {
+            Self::parse_matches_cas_aspas_remove(m)
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
+    fn parse_matches_cas_bgpsec_list(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::RouterCertsList(my_ca));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_bgpsec_add(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let csr = Self::read_file_arg(matches.value_of("csr").unwrap())?;
+        let csr = Self::csr_der(csr)?;
+
+        let mut asns = vec![];
+        for asn in matches.values_of("asn").unwrap() {
+            asns.push(AsNumber::from_str(asn)?);
+        }
+
+        let request = RouterCertRequest::new(asns, Base64::from_content(&csr));
+        let command = Command::CertAuth(CaCommand::RouterCertify(my_ca, request));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    /// Returns the DER encoded router CSR, decoding it first if PEM was used.
+    fn csr_der(csr: Bytes) -> Result<Bytes, Error> {
+        let pem = match std::str::from_utf8(csr.as_ref()) {
+            Ok(pem) if pem.trim_start().starts_with("-----BEGIN") => pem,
+            _ => return Ok(csr),
+        };
+
+        let base64: String = pem
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("-----"))
+            .collect();
+
+        base64::decode(&base64)
+            .map(Bytes::from)
+            .map_err(|_| Error::general("Cannot parse PEM encoded CSR"))
+    }
+
+    fn parse_matches_cas_bgpsec_remove(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let key = KeyIdentifier::from_str(matches.value_of("key").unwrap())
+            .map_err(|_| Error::general("Invalid router key identifier"))?;
+
+        let command = Command::CertAuth(CaCommand::RouterRevoke(my_ca, key));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_bgpsec(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("list") {
+            Self::parse_matches_cas_bgpsec_list(m)
+        } else if let Some(m) = matches.subcommand_matches("add") {
+            Self::parse_matches_cas_bgpsec_add(m)
+        } else if let Some(m) = matches.subcommand_matches("remove") {
+            Self::parse_matches_cas_bgpsec_remove(m)
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
+    fn parse_matches_cas_ghostbuster_show(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::GhostbusterShow(my_ca));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_ghostbuster_set(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let record = if let Some(path) = matches.value_of("vcard") {
+            let bytes = Self::read_file_arg(path)?;
+            let vcard = String::from_utf8(bytes.to_vec())
+                .map_err(|_| Error::general("vCard file must be UTF-8 text"))?;
+            GhostbusterRecord::new(vcard)
+        } else {
+            GhostbusterRecord::from_fields(
+                matches.value_of("fn").unwrap(),
+                matches.value_of("org"),
+                matches.value_of("email"),
+                matches.value_of("tel"),
+                matches.value_of("adr"),
+            )
+        };
+
+        let command = Command::CertAuth(CaCommand::GhostbusterUpdate(my_ca, record));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_ghostbuster_remove(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let my_ca = Self::parse_my_ca(matches)?;
+
+        let command = Command::CertAuth(CaCommand::GhostbusterRemove(my_ca));
+
+        Ok(Options::make(general_args, command))
+    }
+
+    fn parse_matches_cas_ghostbuster(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("show") {
+            Self::parse_matches_cas_ghostbuster_show(m)
+        } else if let Some(m) = matches.subcommand_matches("set") {
+            Self::parse_matches_cas_ghostbuster_set(m)
+        } else if let Some(m) = matches.subcommand_matches("remove") {
+            Self::parse_matches_cas_ghostbuster_remove(m)
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
     fn parse_matches_cas_repo_request(matches: &ArgMatches) -> Result<Options, Error> {
         let general_args = GeneralArgs::from_matches(matches)?;
         let my_ca = Self::parse_my_ca(matches)?;
@@ -1420,6 +1918,12 @@ impl Options {
         Ok(Options::make(general_args, command))
     }
 
+    fn parse_matches_publishers_session_reset(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let command = Command::Publishers(PublishersCommand::SessionReset);
+        Ok(Options::make(general_args, command))
+    }
+
     fn parse_matches_publishers_add(matches: &ArgMatches) -> Result<Options, Error> {
         let general_args = GeneralArgs::from_matches(matches)?;
 
@@ -1467,6 +1971,8 @@ impl Options {
             Self::parse_matches_publishers_stale(m)
         } else if let Some(m) = matches.subcommand_matches("stats") {
             Self::parse_matches_publishers_stats(m)
+        } else if let Some(m) = matches.subcommand_matches("session-reset") {
+            Self::parse_matches_publishers_session_reset(m)
         } else if let Some(m) = matches.subcommand_matches("add") {
             Self::parse_matches_publishers_add(m)
         } else if let Some(m) = matches.subcommand_matches("remove") {
@@ -1480,6 +1986,47 @@ impl Options {
         }
     }
 
+    fn parse_matches_tokens(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("list") {
+            let general_args = GeneralArgs::from_matches(m)?;
+            let command = Command::Tokens(TokensCommand::List);
+            Ok(Options::make(general_args, command))
+        } else if let Some(m) = matches.subcommand_matches("add") {
+            let general_args = GeneralArgs::from_matches(m)?;
+            let name = m.value_of("name").unwrap();
+            let mut cas = vec![];
+            for ca in m.values_of("ca").unwrap() {
+                cas.push(Handle::from_str(ca).map_err(|_| Error::InvalidHandle)?);
+            }
+            let req = CaTokenRequest::new(name, cas);
+            let command = Command::Tokens(TokensCommand::Add(req));
+            Ok(Options::make(general_args, command))
+        } else if let Some(m) = matches.subcommand_matches("remove") {
+            let general_args = GeneralArgs::from_matches(m)?;
+            let name = m.value_of("name").unwrap().to_string();
+            let command = Command::Tokens(TokensCommand::Remove(name));
+            Ok(Options::make(general_args, command))
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
+    fn parse_matches_scheduler(matches: &ArgMatches) -> Result<Options, Error> {
+        if let Some(m) = matches.subcommand_matches("status") {
+            let general_args = GeneralArgs::from_matches(m)?;
+            let command = Command::Scheduler(SchedulerCommand::Status);
+            Ok(Options::make(general_args, command))
+        } else if let Some(m) = matches.subcommand_matches("run") {
+            let general_args = GeneralArgs::from_matches(m)?;
+            let job = SchedulerJob::from_str(m.value_of("job").unwrap())
+                .map_err(Error::GeneralArgumentError)?;
+            let command = Command::Scheduler(SchedulerCommand::Run(job));
+            Ok(Options::make(general_args, command))
+        } else {
+            Err(Error::UnrecognisedSubCommand)
+        }
+    }
+
     fn parse_matches_bulk(matches: &ArgMatches) -> Result<Options, Error> {
         if let Some(m) = matches.subcommand_matches("publish") {
             let general_args = GeneralArgs::from_matches(m)?;
@@ -1493,6 +2040,10 @@ impl Options {
             let general_args = GeneralArgs::from_matches(m)?;
             let command = Command::Bulk(BulkCaCommand::Sync);
             Ok(Options::make(general_args, command))
+        } else if let Some(m) = matches.subcommand_matches("archive") {
+            let general_args = GeneralArgs::from_matches(m)?;
+            let command = Command::Bulk(BulkCaCommand::Archive);
+            Ok(Options::make(general_args, command))
         } else {
             Err(Error::UnrecognisedSubCommand)
         }
@@ -1510,6 +2061,13 @@ impl Options {
         Ok(Options::make(general_args, command))
     }
 
+    fn parse_matches_backup(matches: &ArgMatches) -> Result<Options, Error> {
+        let general_args = GeneralArgs::from_matches(matches)?;
+        let output = PathBuf::from(matches.value_of("output").unwrap());
+        let command = Command::Backup(output);
+        Ok(Options::make(general_args, command))
+    }
+
     fn parse_matches(matches: ArgMatches) -> Result<Options, Error> {
         if let Some(m) = matches.subcommand_matches("config") {
             Self::parse_matches_config(m)
@@ -1531,18 +2089,30 @@ impl Options {
             Self::parse_matches_cas_keyroll(m)
         } else if let Some(m) = matches.subcommand_matches("roas") {
             Self::parse_matches_cas_routes(m)
+        } else if let Some(m) = matches.subcommand_matches("aspas") {
+            Self::parse_matches_cas_aspas(m)
+        } else if let Some(m) = matches.subcommand_matches("bgpsec") {
+            Self::parse_matches_cas_bgpsec(m)
+        } else if let Some(m) = matches.subcommand_matches("ghostbuster") {
+            Self::parse_matches_cas_ghostbuster(m)
         } else if let Some(m) = matches.subcommand_matches("repo") {
             Self::parse_matches_cas_repo(m)
         } else if let Some(m) = matches.subcommand_matches("issues") {
             Self::parse_matches_cas_issues(m)
         } else if let Some(m) = matches.subcommand_matches("publishers") {
             Self::parse_matches_publishers(m)
+        } else if let Some(m) = matches.subcommand_matches("tokens") {
+            Self::parse_matches_tokens(m)
         } else if let Some(m) = matches.subcommand_matches("bulk") {
             Self::parse_matches_bulk(m)
+        } else if let Some(m) = matches.subcommand_matches("scheduler") {
+            Self::parse_matches_scheduler(m)
         } else if let Some(m) = matches.subcommand_matches("health") {
             Self::parse_matches_health(m)
         } else if let Some(m) = matches.subcommand_matches("info") {
             Self::parse_matches_info(m)
+        } else if let Some(m) = matches.subcommand_matches("backup") {
+            Self::parse_matches_backup(m)
         } else {
             Err(Error::UnrecognisedSubCommand)
         }
@@ -1569,12 +2139,21 @@ pub enum Command {
     #[display(fmt = "bulk: {}", _0)]
     Bulk(BulkCaCommand),
 
+    #[display(fmt = "scheduler: {}", _0)]
+    Scheduler(SchedulerCommand),
+
     #[display(fmt = "ca: {}", _0)]
     CertAuth(CaCommand),
 
     #[display(fmt = "publishers: {}", _0)]
     Publishers(PublishersCommand),
 
+    #[display(fmt = "tokens: {}", _0)]
+    Tokens(TokensCommand),
+
+    #[display(fmt = "backup")]
+    Backup(PathBuf),
+
     #[display(fmt = "init")]
     Init(KrillInitDetails),
 }
@@ -1649,6 +2228,33 @@ pub enum CaCommand {
     #[display(fmt = "Update ROAS for ca: '{}' -> {}", _0, _1)]
     RouteAuthorizationsUpdate(Handle, RoaDefinitionUpdates),
 
+    // ASPA
+    #[display(fmt = "list ASPA definitions for ca: '{}'", _0)]
+    AspasList(Handle),
+
+    #[display(fmt = "Update ASPA definitions for ca: '{}' -> {}", _0, _1)]
+    AspasUpdate(Handle, AspaDefinitionUpdates),
+
+    // BGPsec
+    #[display(fmt = "list BGPsec router certificates for ca: '{}'", _0)]
+    RouterCertsList(Handle),
+
+    #[display(fmt = "Certify BGPsec router key for ca: '{}' -> {}", _0, _1)]
+    RouterCertify(Handle, RouterCertRequest),
+
+    #[display(fmt = "Revoke BGPsec router key for ca: '{}' -> {}", _0, _1)]
+    RouterRevoke(Handle, KeyIdentifier),
+
+    // Ghostbusters
+    #[display(fmt = "show Ghostbusters vCard for ca: '{}'", _0)]
+    GhostbusterShow(Handle),
+
+    #[display(fmt = "Set Ghostbusters vCard for ca: '{}'", _0)]
+    GhostbusterUpdate(Handle, GhostbusterRecord),
+
+    #[display(fmt = "Remove Ghostbusters vCard for ca: '{}'", _0)]
+    GhostbusterRemove(Handle),
+
     #[display(fmt = "Show detailed ROA vs BGP analysis for ca: '{}'", _0)]
     BgpAnalysisFull(Handle),
 
@@ -1743,6 +2349,18 @@ pub enum BulkCaCommand {
 
     #[display(fmt = "sync")]
     Sync,
+
+    #[display(fmt = "archive")]
+    Archive,
+}
+
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum SchedulerCommand {
+    #[display(fmt = "status")]
+    Status,
+
+    #[display(fmt = "run {}", _0)]
+    Run(SchedulerJob),
 }
 
 #[derive(Clone, Debug, Display, Eq, PartialEq)]
@@ -1769,10 +2387,25 @@ pub enum PublishersCommand {
     #[display(fmt = "Show server stats")]
     Stats,
 
+    #[display(fmt = "Reset RRDP session")]
+    SessionReset,
+
     #[display(fmt = "Show publisher list")]
     PublisherList,
 }
 
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum TokensCommand {
+    #[display(fmt = "List CA tokens")]
+    List,
+
+    #[display(fmt = "Add {}", _0)]
+    Add(CaTokenRequest),
+
+    #[display(fmt = "Remove token '{}'", _0)]
+    Remove(String),
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct KrillInitDetails {
     rsync_base: Option<uri::Rsync>,
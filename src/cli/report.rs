@@ -6,16 +6,17 @@ use serde::Serialize;
 use rpki::x509::Time;
 
 use crate::commons::api::{
-    AllCertAuthIssues, CaCommandDetails, CaCommandResult, CaRepoDetails, CertAuthInfo,
-    CertAuthIssues, CertAuthList, ChildCaInfo, CommandHistory, CurrentObjects, CurrentRepoState,
-    ParentCaContact, PublisherDetails, PublisherList, RepositoryContact, RoaDefinition, ServerInfo,
-    StoredEffect,
+    AllCertAuthIssues, AspaDefinitionList, CaCommandDetails, CaCommandResult, CaRepoDetails,
+    CaToken, CaTokenList, CertAuthInfo, CertAuthIssues, CertAuthList, ChildCaInfo, CommandHistory,
+    CurrentObjects, CurrentRepoState, GhostbusterRecord, HistoryArchiveReport, ParentCaContact,
+    PublisherDetails, PublisherList, RepositoryContact, RoaDefinition, RouterCertList,
+    SchedulerStatus, ServerInfo, StoredEffect,
 };
 use crate::commons::bgp::{AnnouncementReport, BgpAnalysisReport, RoaReport};
 use crate::commons::eventsourcing::WithStorableDetails;
 use crate::commons::remote::api::ClientInfo;
 use crate::commons::remote::rfc8183;
-use crate::pubd::RepoStats;
+use crate::pubd::{RepoStats, SessionReset};
 
 //------------ ApiResponse ---------------------------------------------------
 
@@ -28,12 +29,17 @@ pub enum ApiResponse {
 
     CertAuthInfo(CertAuthInfo),
     CertAuthHistory(CommandHistory),
+    HistoryArchive(HistoryArchiveReport),
+    SchedulerStatus(SchedulerStatus),
     CertAuthAction(CaCommandDetails),
     CertAuths(CertAuthList),
     RouteAuthorizations(Vec<RoaDefinition>),
     BgpAnalysisFull(BgpAnalysisReport),
     BgpAnalysisAnnouncements(AnnouncementReport),
     BgpAnalysisRoas(RoaReport),
+    AspaDefinitions(AspaDefinitionList),
+    RouterCerts(RouterCertList),
+    Ghostbuster(Option<GhostbusterRecord>),
 
     ParentCaContact(ParentCaContact),
 
@@ -42,6 +48,10 @@ pub enum ApiResponse {
     PublisherDetails(PublisherDetails),
     PublisherList(PublisherList),
     RepoStats(RepoStats),
+    RepoSessionReset(SessionReset),
+
+    CaToken(CaToken),
+    CaTokenList(CaTokenList),
 
     Rfc8181ClientList(Vec<ClientInfo>),
     Rfc8183RepositoryResponse(rfc8183::RepositoryResponse),
@@ -69,6 +79,8 @@ impl ApiResponse {
                 ApiResponse::CertAuths(list) => Ok(Some(list.report(fmt)?)),
                 ApiResponse::CertAuthInfo(info) => Ok(Some(info.report(fmt)?)),
                 ApiResponse::CertAuthHistory(history) => Ok(Some(history.report(fmt)?)),
+                ApiResponse::HistoryArchive(report) => Ok(Some(report.report(fmt)?)),
+                ApiResponse::SchedulerStatus(status) => Ok(Some(status.report(fmt)?)),
                 ApiResponse::CertAuthAction(details) => Ok(Some(details.report(fmt)?)),
                 ApiResponse::CertAuthIssues(issues) => Ok(Some(issues.report(fmt)?)),
                 ApiResponse::AllCertAuthIssues(issues) => Ok(Some(issues.report(fmt)?)),
@@ -76,11 +88,17 @@ impl ApiResponse {
                 ApiResponse::BgpAnalysisFull(table) => Ok(Some(table.report(fmt)?)),
                 ApiResponse::BgpAnalysisAnnouncements(summary) => Ok(Some(summary.report(fmt)?)),
                 ApiResponse::BgpAnalysisRoas(summary) => Ok(Some(summary.report(fmt)?)),
+                ApiResponse::AspaDefinitions(list) => Ok(Some(list.report(fmt)?)),
+                ApiResponse::RouterCerts(list) => Ok(Some(list.report(fmt)?)),
+                ApiResponse::Ghostbuster(record) => Ok(Some(record.report(fmt)?)),
                 ApiResponse::ParentCaContact(contact) => Ok(Some(contact.report(fmt)?)),
                 ApiResponse::ChildInfo(info) => Ok(Some(info.report(fmt)?)),
                 ApiResponse::PublisherList(list) => Ok(Some(list.report(fmt)?)),
                 ApiResponse::PublisherDetails(details) => Ok(Some(details.report(fmt)?)),
                 ApiResponse::RepoStats(stats) => Ok(Some(stats.report(fmt)?)),
+                ApiResponse::RepoSessionReset(reset) => Ok(Some(reset.report(fmt)?)),
+                ApiResponse::CaToken(token) => Ok(Some(token.report(fmt)?)),
+                ApiResponse::CaTokenList(list) => Ok(Some(list.report(fmt)?)),
                 ApiResponse::Rfc8181ClientList(list) => Ok(Some(list.report(fmt)?)),
                 ApiResponse::Rfc8183ChildRequest(req) => Ok(Some(req.report(fmt)?)),
                 ApiResponse::Rfc8183PublisherRequest(req) => Ok(Some(req.report(fmt)?)),
@@ -235,7 +253,7 @@ impl Report for CommandHistory {
     fn text(&self) -> Result<String, ReportError> {
         let mut res = String::new();
 
-        res.push_str("time::command::key::success\n");
+        res.push_str("time::actor::command::key::success\n");
 
         for command in self.commands() {
             let success_string = match &command.effect {
@@ -243,8 +261,9 @@ impl Report for CommandHistory {
                 StoredEffect::Events(_) => "OK".to_string(),
             };
             res.push_str(&format!(
-                "{}::{} ::{}::{}\n",
+                "{}::{}::{} ::{}::{}\n",
                 command.time().to_rfc3339_opts(SecondsFormat::Secs, true),
+                command.actor,
                 command.summary.msg,
                 command.key,
                 success_string
@@ -255,6 +274,27 @@ impl Report for CommandHistory {
     }
 }
 
+impl Report for HistoryArchiveReport {
+    fn text(&self) -> Result<String, ReportError> {
+        let mut res = String::new();
+
+        res.push_str("ca::commands::segment::reclaimed bytes\n");
+
+        for archived in self.archived() {
+            res.push_str(&format!(
+                "{}::{}::{}::{}\n",
+                archived.handle(),
+                archived.commands(),
+                archived.segment(),
+                archived.reclaimed_bytes()
+            ))
+        }
+        res.push_str(&format!("{}\n", self));
+
+        Ok(res)
+    }
+}
+
 impl Report for CaCommandDetails {
     fn text(&self) -> Result<String, ReportError> {
         let mut res = String::new();
@@ -264,6 +304,7 @@ impl Report for CaCommandDetails {
             "Time:   {}\n",
             command.time().to_rfc3339_opts(SecondsFormat::Secs, true)
         ));
+        res.push_str(&format!("Actor:  {}\n", command.actor()));
         res.push_str(&format!("Action: {}\n", command.details().summary().msg));
 
         match self.effect() {
@@ -324,6 +365,39 @@ impl Report for PublisherList {
     }
 }
 
+impl Report for CaToken {
+    fn text(&self) -> Result<String, ReportError> {
+        let mut res = String::new();
+
+        res.push_str(&format!("Name:  {}\n", self.name()));
+        res.push_str(&format!("Token: {}\n", self.token()));
+        res.push_str("CAs:  ");
+        for ca in self.cas() {
+            res.push_str(&format!(" {}", ca));
+        }
+        res.push_str("\n");
+
+        Ok(res)
+    }
+}
+
+impl Report for CaTokenList {
+    fn text(&self) -> Result<String, ReportError> {
+        let mut res = String::new();
+
+        for token in self.tokens() {
+            res.push_str(token.name());
+            res.push(':');
+            for ca in token.cas() {
+                res.push_str(&format!(" {}", ca));
+            }
+            res.push_str("\n");
+        }
+
+        Ok(res)
+    }
+}
+
 impl Report for RepoStats {
     fn text(&self) -> Result<String, ReportError> {
         let mut res = String::new();
@@ -348,10 +422,40 @@ impl Report for RepoStats {
             }
         }
 
+        if !self.mirrors().is_empty() {
+            res.push_str("\n");
+            res.push_str("Mirror, Last Success, Failures, Next Attempt, Last Error\n");
+            for (target, status) in self.mirrors() {
+                let last_success = match status.last_success() {
+                    Some(time) => time.to_string(),
+                    None => "never".to_string(),
+                };
+                let next_attempt = match status.next_attempt() {
+                    Some(time) => time.to_string(),
+                    None => "".to_string(),
+                };
+                let last_error = status.last_error().map(|e| e.msg()).unwrap_or("");
+                res.push_str(&format!(
+                    "{}, {}, {}, {}, {}\n",
+                    target,
+                    last_success,
+                    status.failures(),
+                    next_attempt,
+                    last_error
+                ));
+            }
+        }
+
         Ok(res)
     }
 }
 
+impl Report for SessionReset {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
 impl Report for PublisherDetails {
     fn text(&self) -> Result<String, ReportError> {
         let mut res = String::new();
@@ -414,6 +518,27 @@ impl Report for Vec<RoaDefinition> {
     }
 }
 
+impl Report for AspaDefinitionList {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for RouterCertList {
+    fn text(&self) -> Result<String, ReportError> {
+        Ok(self.to_string())
+    }
+}
+
+impl Report for Option<GhostbusterRecord> {
+    fn text(&self) -> Result<String, ReportError> {
+        match self {
+            Some(record) => Ok(record.to_string()),
+            None => Ok("No Ghostbusters vCard configured\n".to_string()),
+        }
+    }
+}
+
 impl Report for BgpAnalysisReport {
     fn text(&self) -> Result<String, ReportError> {
         Ok(self.to_string())
@@ -452,6 +577,23 @@ impl Report for CaRepoDetails {
             }
         }
 
+        if let Some(status) = self.status() {
+            res.push_str("\n");
+            res.push_str("Publication Status:\n");
+            match status.last_success() {
+                Some(time) => res.push_str(&format!("  last success: {}\n", time)),
+                None => res.push_str("  last success: never\n"),
+            }
+            if let Some(next_update) = status.next_update() {
+                res.push_str(&format!("  next update:  {}\n", next_update));
+            }
+            if let Some(error) = status.last_error() {
+                res.push_str(&format!("  failures:     {}\n", status.failures()));
+                res.push_str(&format!("  last error:   {}\n", error.msg()));
+                res.push_str(&format!("  pending:      {}\n", status.pending()));
+            }
+        }
+
         res.push_str("\n");
 
         Ok(res)
@@ -488,12 +630,18 @@ impl Report for CertAuthIssues {
             if let Some(repo_issue) = self.repo_issue() {
                 res.push_str(&format!("Repository Issue: {}\n", repo_issue));
             }
+            if let Some(publication_issue) = self.publication_issue() {
+                res.push_str(&format!("Publication Issue: {}\n", publication_issue));
+            }
             let parent_issues = self.parent_issues();
             if !parent_issues.is_empty() {
                 for (parent, issue) in parent_issues.iter() {
                     res.push_str(&format!("Parent '{}' has issue: {}\n", parent, issue));
                 }
             }
+            for (child, issue) in self.child_issues().iter() {
+                res.push_str(&format!("Child '{}' has issue: {}\n", child, issue));
+            }
         }
         Ok(res)
     }
@@ -512,18 +660,62 @@ impl Report for AllCertAuthIssues {
                 if let Some(repo_issue) = issues.repo_issue() {
                     res.push_str(&format!("   Repository Issue: {}\n", repo_issue));
                 }
+                if let Some(publication_issue) = issues.publication_issue() {
+                    res.push_str(&format!("   Publication Issue: {}\n", publication_issue));
+                }
                 let parent_issues = issues.parent_issues();
                 if !parent_issues.is_empty() {
                     for (parent, issue) in parent_issues.iter() {
                         res.push_str(&format!("   Parent '{}' has issue: {}\n", parent, issue));
                     }
                 }
+                for (child, issue) in issues.child_issues().iter() {
+                    res.push_str(&format!("   Child '{}' has issue: {}\n", child, issue));
+                }
             }
         }
         Ok(res)
     }
 }
 
+impl Report for SchedulerStatus {
+    fn text(&self) -> Result<String, ReportError> {
+        fn time(timestamp: i64) -> String {
+            let dt = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(timestamp, 0), Utc);
+            Time::new(dt).to_rfc3339()
+        }
+
+        let mut res = String::new();
+
+        res.push_str("job::interval::last run::duration (ms)::result::next run\n");
+
+        for job in self.jobs() {
+            let (last_run, duration) = match (job.last_run(), job.last_duration_ms()) {
+                (Some(last_run), Some(duration)) => (time(last_run), duration.to_string()),
+                _ => ("never".to_string(), "-".to_string()),
+            };
+            let result = if job.running() {
+                "running".to_string()
+            } else {
+                job.last_result()
+                    .map(|r| r.to_string())
+                    .unwrap_or_else(|| "-".to_string())
+            };
+            res.push_str(&format!(
+                "{}::{}::{}::{}::{}::{}\n",
+                job.job(),
+                job.interval(),
+                last_run,
+                duration,
+                result,
+                time(job.next_run())
+            ));
+        }
+
+        Ok(res)
+    }
+}
+
 impl Report for ServerInfo {
     fn text(&self) -> Result<String, ReportError> {
         let dt = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(self.started(), 0), Utc);
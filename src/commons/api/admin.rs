@@ -160,6 +160,107 @@ impl fmt::Display for Token {
     }
 }
 
+//------------ CaTokenRequest ------------------------------------------------
+
+/// Request to create a new API token which can only manage the given CAs.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CaTokenRequest {
+    name: String,
+    cas: Vec<Handle>,
+}
+
+impl CaTokenRequest {
+    pub fn new(name: &str, cas: Vec<Handle>) -> Self {
+        CaTokenRequest {
+            name: name.to_string(),
+            cas,
+        }
+    }
+
+    pub fn unpack(self) -> (String, Vec<Handle>) {
+        (self.name, self.cas)
+    }
+}
+
+impl fmt::Display for CaTokenRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "token '{}' for CAs:", self.name)?;
+        for ca in &self.cas {
+            write!(f, " {}", ca)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ CaToken -------------------------------------------------------
+
+/// An API token bound to one or more CAs. The secret token is only
+/// returned to the API user once: when it is created.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CaToken {
+    name: String,
+    token: Token,
+    cas: Vec<Handle>,
+}
+
+impl CaToken {
+    pub fn new(name: String, token: Token, cas: Vec<Handle>) -> Self {
+        CaToken { name, token, cas }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    pub fn cas(&self) -> &[Handle] {
+        &self.cas
+    }
+}
+
+//------------ CaTokenInfo ---------------------------------------------------
+
+/// The name and CAs of a CA token, without the secret.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CaTokenInfo {
+    name: String,
+    cas: Vec<Handle>,
+}
+
+impl CaTokenInfo {
+    pub fn new(name: String, cas: Vec<Handle>) -> Self {
+        CaTokenInfo { name, cas }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn cas(&self) -> &[Handle] {
+        &self.cas
+    }
+}
+
+//------------ CaTokenList ---------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct CaTokenList {
+    tokens: Vec<CaTokenInfo>,
+}
+
+impl CaTokenList {
+    pub fn new(tokens: Vec<CaTokenInfo>) -> Self {
+        CaTokenList { tokens }
+    }
+
+    pub fn tokens(&self) -> &Vec<CaTokenInfo> {
+        &self.tokens
+    }
+}
+
 //------------ PublisherSummaryInfo ------------------------------------------
 
 /// Defines a summary of publisher information to be used in the publisher
@@ -0,0 +1,194 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::commons::api::{AsNumber, AuthorizationFmtError};
+
+//------------ AspaDefinition ----------------------------------------------
+
+/// This type defines an Autonomous System Provider Authorization (ASPA),
+/// i.e. the customer AS and the set of ASes which are authorized to act
+/// as its upstream providers.
+///
+/// The providers are kept sorted and without duplicates, as they need to
+/// be in the ASPA object.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct AspaDefinition {
+    customer: AsNumber,
+    providers: Vec<AsNumber>,
+}
+
+impl AspaDefinition {
+    pub fn new(customer: AsNumber, mut providers: Vec<AsNumber>) -> Self {
+        providers.sort();
+        providers.dedup();
+        AspaDefinition {
+            customer,
+            providers,
+        }
+    }
+
+    pub fn customer(&self) -> AsNumber {
+        self.customer
+    }
+
+    pub fn providers(&self) -> &Vec<AsNumber> {
+        &self.providers
+    }
+
+    /// Returns `true` if the customer AS is also listed as a provider,
+    /// which is not allowed.
+    pub fn customer_used_as_provider(&self) -> bool {
+        self.providers.contains(&self.customer)
+    }
+}
+
+impl FromStr for AspaDefinition {
+    type Err = AuthorizationFmtError;
+
+    // "64496 => 64497, 64498"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split("=>");
+
+        let customer_str = parts.next().ok_or_else(|| AuthorizationFmtError::aspa(s))?;
+        let customer = AsNumber::from_str(customer_str)?;
+
+        let providers_str = parts.next().ok_or_else(|| AuthorizationFmtError::aspa(s))?;
+        if parts.next().is_some() {
+            return Err(AuthorizationFmtError::aspa(s));
+        }
+
+        let mut providers = vec![];
+        for provider_str in providers_str.split(',') {
+            if !provider_str.trim().is_empty() {
+                providers.push(AsNumber::from_str(provider_str)?);
+            }
+        }
+
+        Ok(AspaDefinition::new(customer, providers))
+    }
+}
+
+impl fmt::Display for AspaDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} =>", self.customer)?;
+        for (i, provider) in self.providers.iter().enumerate() {
+            if i == 0 {
+                write!(f, " {}", provider)?;
+            } else {
+                write!(f, ", {}", provider)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//------------ AspaDefinitionList ------------------------------------------
+
+/// The ASPA definitions configured for a CA, ordered by customer AS.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaDefinitionList(Vec<AspaDefinition>);
+
+impl AspaDefinitionList {
+    pub fn new(mut definitions: Vec<AspaDefinition>) -> Self {
+        definitions.sort_by_key(|def| def.customer());
+        AspaDefinitionList(definitions)
+    }
+
+    pub fn definitions(&self) -> &Vec<AspaDefinition> {
+        &self.0
+    }
+}
+
+impl fmt::Display for AspaDefinitionList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for def in self.0.iter() {
+            writeln!(f, "{}", def)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ AspaDefinitionUpdates --------------------------------------
+
+/// This type defines a delta of ASPA definitions. Definitions which are
+/// added replace any existing definition for the same customer AS, while
+/// definitions are removed by their customer AS.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaDefinitionUpdates {
+    add_or_replace: Vec<AspaDefinition>,
+    remove: Vec<AsNumber>,
+}
+
+impl AspaDefinitionUpdates {
+    pub fn new(add_or_replace: Vec<AspaDefinition>, remove: Vec<AsNumber>) -> Self {
+        AspaDefinitionUpdates {
+            add_or_replace,
+            remove,
+        }
+    }
+
+    pub fn add_or_replace(&self) -> &Vec<AspaDefinition> {
+        &self.add_or_replace
+    }
+
+    pub fn remove(&self) -> &Vec<AsNumber> {
+        &self.remove
+    }
+
+    pub fn unpack(self) -> (Vec<AspaDefinition>, Vec<AsNumber>) {
+        (self.add_or_replace, self.remove)
+    }
+}
+
+impl fmt::Display for AspaDefinitionUpdates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "add:")?;
+        for def in &self.add_or_replace {
+            write!(f, " '{}'", def)?;
+        }
+        write!(f, " remove:")?;
+        for customer in &self.remove {
+            write!(f, " {}", customer)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ Tests -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_aspa_definition() {
+        let def = AspaDefinition::from_str("64496 => 64499, 64497,64497").unwrap();
+        assert_eq!(AsNumber::new(64496), def.customer());
+        assert_eq!(
+            &vec![AsNumber::new(64497), AsNumber::new(64499)],
+            def.providers()
+        );
+        assert_eq!("64496 => 64497, 64499", def.to_string());
+        assert!(!def.customer_used_as_provider());
+
+        let reparsed = AspaDefinition::from_str(&def.to_string()).unwrap();
+        assert_eq!(def, reparsed);
+
+        let def = AspaDefinition::from_str("64496 => 64496, 64497").unwrap();
+        assert!(def.customer_used_as_provider());
+
+        assert!(AspaDefinition::from_str("64496").is_err());
+        assert!(AspaDefinition::from_str("64496 => AS64497").is_err());
+        assert!(AspaDefinition::from_str("64496 => 64497 => 64498").is_err());
+    }
+
+    #[test]
+    fn serde_aspa_definition() {
+        let def = AspaDefinition::from_str("64496 => 64497, 64498").unwrap();
+        let json = serde_json::to_string(&def).unwrap();
+        assert_eq!("{\"customer\":64496,\"providers\":[64497,64498]}", json);
+
+        let des: AspaDefinition = serde_json::from_str(&json).unwrap();
+        assert_eq!(def, des);
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+
+use chrono::SecondsFormat;
+
+use rpki::crypto::KeyIdentifier;
+use rpki::x509::Time;
+
+use crate::commons::api::{AsNumber, Base64, ObjectName};
+
+//------------ RouterCertRequest -------------------------------------------
+
+/// This type defines a request to certify a BGPsec router key. It contains
+/// the PKCS#10 request generated by the router (DER encoded, in base64) and
+/// the ASNs which the router key may sign for.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RouterCertRequest {
+    asns: Vec<AsNumber>,
+    csr: Base64,
+}
+
+impl RouterCertRequest {
+    pub fn new(mut asns: Vec<AsNumber>, csr: Base64) -> Self {
+        asns.sort();
+        asns.dedup();
+        RouterCertRequest { asns, csr }
+    }
+
+    pub fn asns(&self) -> &Vec<AsNumber> {
+        &self.asns
+    }
+
+    pub fn csr(&self) -> &Base64 {
+        &self.csr
+    }
+
+    pub fn unpack(self) -> (Vec<AsNumber>, Base64) {
+        (self.asns, self.csr)
+    }
+}
+
+impl fmt::Display for RouterCertRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "certify router key for ASNs:")?;
+        for asn in &self.asns {
+            write!(f, " {}", asn)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ RouterCertInfo ----------------------------------------------
+
+/// This type shows a BGPsec router certificate issued by a CA.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RouterCertInfo {
+    key: KeyIdentifier,
+    asns: Vec<AsNumber>,
+    name: ObjectName,
+    not_after: Time,
+}
+
+impl RouterCertInfo {
+    pub fn new(key: KeyIdentifier, asns: Vec<AsNumber>, name: ObjectName, not_after: Time) -> Self {
+        RouterCertInfo {
+            key,
+            asns,
+            name,
+            not_after,
+        }
+    }
+
+    pub fn key(&self) -> &KeyIdentifier {
+        &self.key
+    }
+
+    pub fn asns(&self) -> &Vec<AsNumber> {
+        &self.asns
+    }
+
+    pub fn name(&self) -> &ObjectName {
+        &self.name
+    }
+
+    pub fn not_after(&self) -> Time {
+        self.not_after
+    }
+}
+
+impl fmt::Display for RouterCertInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ASNs:", self.key)?;
+        for asn in &self.asns {
+            write!(f, " {}", asn)?;
+        }
+        write!(
+            f,
+            " file: {} expires: {}",
+            self.name,
+            self.not_after.to_rfc3339_opts(SecondsFormat::Secs, true)
+        )
+    }
+}
+
+//------------ RouterCertList ----------------------------------------------
+
+/// The BGPsec router certificates issued by a CA.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RouterCertList(Vec<RouterCertInfo>);
+
+impl RouterCertList {
+    pub fn new(mut certs: Vec<RouterCertInfo>) -> Self {
+        certs.sort_by(|a, b| a.name.as_str().cmp(b.name.as_str()));
+        RouterCertList(certs)
+    }
+
+    pub fn certs(&self) -> &Vec<RouterCertInfo> {
+        &self.0
+    }
+}
+
+impl fmt::Display for RouterCertList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for cert in self.0.iter() {
+            writeln!(f, "{}", cert)?;
+        }
+        Ok(())
+    }
+}
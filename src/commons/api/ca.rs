@@ -16,14 +16,16 @@ use rpki::crypto::KeyIdentifier;
 use rpki::manifest::{FileAndHash, Manifest};
 use rpki::resources::{AsBlocks, AsResources, IpBlocks, IpBlocksForFamily, IpResources};
 use rpki::roa::Roa;
+use rpki::sigobj::SignedObject;
 use rpki::uri;
 use rpki::x509::{Serial, Time};
 
 use crate::commons::api::publication;
 use crate::commons::api::publication::Publish;
 use crate::commons::api::{
-    Base64, ChildHandle, ErrorResponse, Handle, HexEncodedHash, IssuanceRequest, ListReply,
-    ParentCaContact, ParentHandle, RepositoryContact, RequestResourceLimit, RoaDefinition,
+    AsNumber, Base64, ChildHandle, ErrorResponse, Handle, HexEncodedHash, IssuanceRequest,
+    ListReply, ParentCaContact, ParentHandle, RepositoryContact, RequestResourceLimit,
+    RoaDefinition,
 };
 use crate::commons::remote::id::IdCert;
 use crate::commons::util::ext_serde;
@@ -568,6 +570,14 @@ pub struct CurrentObject {
 }
 
 impl CurrentObject {
+    pub fn new(content: Base64, serial: Serial, expires: Time) -> Self {
+        CurrentObject {
+            content,
+            serial,
+            expires,
+        }
+    }
+
     pub fn content(&self) -> &Base64 {
         &self.content
     }
@@ -639,6 +649,20 @@ impl From<&Roa> for CurrentObject {
     }
 }
 
+impl From<&SignedObject> for CurrentObject {
+    fn from(object: &SignedObject) -> Self {
+        let content = Base64::from(object);
+        let serial = object.cert().serial_number();
+        let expires = object.cert().validity().not_after();
+
+        CurrentObject {
+            content,
+            serial,
+            expires,
+        }
+    }
+}
+
 //------------ ObjectName ----------------------------------------------------
 
 /// This type is used to represent the (deterministic) file names for
@@ -650,6 +674,29 @@ impl ObjectName {
     pub fn new(ki: &KeyIdentifier, extension: &str) -> Self {
         ObjectName(format!("{}.{}", ki, extension))
     }
+
+    /// ASPA objects are named after their customer AS.
+    pub fn aspa(customer: AsNumber) -> Self {
+        ObjectName(format!("AS{}.asa", customer))
+    }
+
+    /// Aggregated ROAs are named after their origin AS. These names cannot
+    /// clash with the hex encoded names used for ROAs for one authorization.
+    pub fn aggregate_roa(asn: AsNumber) -> Self {
+        ObjectName(format!("AS{}.roa", asn))
+    }
+
+    /// BGPsec router certificates are named after the router key, so that
+    /// the name stays the same when the ASNs for a key are changed.
+    pub fn router_cert(key: &KeyIdentifier) -> Self {
+        ObjectName(format!("ROUTER-{}.cer", key))
+    }
+
+    /// A resource class publishes at most one Ghostbusters Record, which
+    /// keeps its name when it is re-issued under a new key.
+    pub fn ghostbuster() -> Self {
+        ObjectName("contact.gbr".to_string())
+    }
 }
 
 impl From<&Cert> for ObjectName {
@@ -1513,11 +1560,96 @@ pub enum ParentKindInfo {
     Rfc6492,
 }
 
+//------------ ParentStatus --------------------------------------------------
+
+/// The status of synchronisation with a parent. Times are in seconds since
+/// the epoch. While a parent keeps failing, the next attempt is backed off.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ParentStatus {
+    last_attempt: Option<i64>,
+    last_success: Option<i64>,
+    last_error: Option<ErrorResponse>,
+    failures: u32,
+    next_attempt: Option<i64>,
+}
+
+impl ParentStatus {
+    pub fn last_attempt(&self) -> Option<i64> {
+        self.last_attempt
+    }
+
+    pub fn last_success(&self) -> Option<i64> {
+        self.last_success
+    }
+
+    pub fn last_error(&self) -> Option<&ErrorResponse> {
+        self.last_error.as_ref()
+    }
+
+    /// The number of consecutive failures.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// The earliest time at which the parent should be contacted again,
+    /// if it is backed off.
+    pub fn next_attempt(&self) -> Option<i64> {
+        self.next_attempt
+    }
+
+    pub fn is_failing(&self) -> bool {
+        self.failures > 0
+    }
+
+    /// Returns whether the parent may be contacted at the given time.
+    pub fn is_due(&self, now: i64) -> bool {
+        self.next_attempt.map(|next| next <= now).unwrap_or(true)
+    }
+
+    pub fn set_success(&mut self, now: i64) {
+        self.last_attempt = Some(now);
+        self.last_success = Some(now);
+        self.last_error = None;
+        self.failures = 0;
+        self.next_attempt = None;
+    }
+
+    pub fn set_failure(&mut self, now: i64, error: ErrorResponse, next_attempt: i64) {
+        self.last_attempt = Some(now);
+        self.last_error = Some(error);
+        self.failures += 1;
+        self.next_attempt = Some(next_attempt);
+    }
+}
+
+impl fmt::Display for ParentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.last_success {
+            Some(success) => write!(f, "last success: {}", success)?,
+            None => write!(f, "never synced")?,
+        }
+        if let Some(error) = &self.last_error {
+            write!(
+                f,
+                ", failures: {}, last error: {}",
+                self.failures,
+                error.msg()
+            )?;
+        }
+        if let Some(next) = self.next_attempt {
+            write!(f, ", next attempt: {}", next)?;
+        }
+        Ok(())
+    }
+}
+
 //------------ ParentInfo ----------------------------------------------------
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct ParentInfo {
     handle: ParentHandle,
     kind: ParentKindInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<ParentStatus>,
 }
 
 impl ParentInfo {
@@ -1527,13 +1659,29 @@ impl ParentInfo {
             ParentCaContact::Embedded => ParentKindInfo::Embedded,
             ParentCaContact::Rfc6492(_) => ParentKindInfo::Rfc6492,
         };
-        ParentInfo { handle, kind }
+        ParentInfo {
+            handle,
+            kind,
+            status: None,
+        }
+    }
+
+    pub fn handle(&self) -> &ParentHandle {
+        &self.handle
+    }
+
+    pub fn status(&self) -> Option<&ParentStatus> {
+        self.status.as_ref()
     }
 }
 
 impl fmt::Display for ParentInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Handle: {} Kind: {}", self.handle, self.kind)
+        write!(f, "Handle: {} Kind: {}", self.handle, self.kind)?;
+        if let Some(status) = &self.status {
+            write!(f, " Status: {}", status)?;
+        }
+        Ok(())
     }
 }
 
@@ -1601,6 +1749,13 @@ impl CertAuthInfo {
         &self.parents
     }
 
+    /// Adds the known synchronisation status for each parent.
+    pub fn set_parent_statuses(&mut self, statuses: &HashMap<ParentHandle, ParentStatus>) {
+        for parent in self.parents.iter_mut() {
+            parent.status = statuses.get(&parent.handle).cloned();
+        }
+    }
+
     pub fn resources(&self) -> &ResourceSet {
         &self.resources
     }
@@ -1809,16 +1964,92 @@ impl CurrentRepoState {
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct CaRepoDetails {
     contact: RepositoryContact,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<RepoStatus>,
 }
 
 impl CaRepoDetails {
-    pub fn new(contact: RepositoryContact) -> Self {
-        CaRepoDetails { contact }
+    pub fn new(contact: RepositoryContact, status: Option<RepoStatus>) -> Self {
+        CaRepoDetails { contact, status }
     }
 
     pub fn contact(&self) -> &RepositoryContact {
         &self.contact
     }
+
+    pub fn status(&self) -> Option<&RepoStatus> {
+        self.status.as_ref()
+    }
+}
+
+//------------ RepoStatus ----------------------------------------------------
+
+/// The status of publication by a CA at its repository. Times are in
+/// seconds since the epoch.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RepoStatus {
+    last_attempt: Option<i64>,
+    last_success: Option<i64>,
+    last_error: Option<ErrorResponse>,
+    failures: u32,
+    pending: usize,
+    next_update: Option<i64>,
+}
+
+impl RepoStatus {
+    pub fn last_attempt(&self) -> Option<i64> {
+        self.last_attempt
+    }
+
+    pub fn last_success(&self) -> Option<i64> {
+        self.last_success
+    }
+
+    pub fn last_error(&self) -> Option<&ErrorResponse> {
+        self.last_error.as_ref()
+    }
+
+    /// The number of consecutive failures.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// The number of objects to publish, update or withdraw, which could not
+    /// be sent to the repository in the last attempt.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// The earliest manifest next update time of the objects which were
+    /// last published successfully.
+    pub fn next_update(&self) -> Option<i64> {
+        self.next_update
+    }
+
+    pub fn is_failing(&self) -> bool {
+        self.failures > 0
+    }
+
+    pub fn set_success(&mut self, now: i64, next_update: Option<i64>) {
+        self.last_attempt = Some(now);
+        self.last_success = Some(now);
+        self.last_error = None;
+        self.failures = 0;
+        self.pending = 0;
+        self.next_update = next_update;
+    }
+
+    /// Records a failure. The number of pending objects is only updated if
+    /// it is known, i.e. if the failure did not occur while listing the
+    /// objects in the repository.
+    pub fn set_failure(&mut self, now: i64, error: ErrorResponse, pending: Option<usize>) {
+        self.last_attempt = Some(now);
+        self.last_error = Some(error);
+        self.failures += 1;
+        if let Some(pending) = pending {
+            self.pending = pending;
+        }
+    }
 }
 
 //------------ AllCertAuthIssues ---------------------------------------------
@@ -1851,14 +2082,20 @@ impl AllCertAuthIssues {
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct CertAuthIssues {
     repo: Option<ErrorResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    publication: Option<ErrorResponse>,
     parents: HashMap<ParentHandle, ErrorResponse>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    children: HashMap<ChildHandle, ErrorResponse>,
 }
 
 impl Default for CertAuthIssues {
     fn default() -> Self {
         CertAuthIssues {
             repo: None,
+            publication: None,
             parents: HashMap::new(),
+            children: HashMap::new(),
         }
     }
 }
@@ -1872,6 +2109,16 @@ impl CertAuthIssues {
         self.repo.as_ref()
     }
 
+    /// Adds a warning that the objects published by the CA are about to go
+    /// stale, or have gone stale, because publication did not succeed.
+    pub fn add_publication_issue(&mut self, issue: ErrorResponse) {
+        self.publication = Some(issue);
+    }
+
+    pub fn publication_issue(&self) -> Option<&ErrorResponse> {
+        self.publication.as_ref()
+    }
+
     pub fn add_parent_issue(&mut self, parent: ParentHandle, issue: ErrorResponse) {
         self.parents.insert(parent, issue);
     }
@@ -1880,8 +2127,21 @@ impl CertAuthIssues {
         &self.parents
     }
 
+    /// Adds a warning that the child still holds certificates for resources
+    /// which were taken away from it.
+    pub fn add_child_issue(&mut self, child: ChildHandle, issue: ErrorResponse) {
+        self.children.insert(child, issue);
+    }
+
+    pub fn child_issues(&self) -> &HashMap<ChildHandle, ErrorResponse> {
+        &self.children
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.repo.is_none() && self.parents.is_empty()
+        self.repo.is_none()
+            && self.publication.is_none()
+            && self.parents.is_empty()
+            && self.children.is_empty()
     }
 }
 
@@ -0,0 +1,196 @@
+use std::fmt;
+
+//------------ GhostbusterRecord -------------------------------------------
+
+/// This type defines the contact information for a CA, as a vCard which
+/// is published in a Ghostbusters Record (RFC 6493).
+///
+/// The vCard is kept as text, as it is signed into the object as is. Use
+/// [`validate`] to check that it follows the RFC 6493 profile.
+///
+/// [`validate`]: #method.validate
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GhostbusterRecord {
+    vcard: String,
+}
+
+impl GhostbusterRecord {
+    pub fn new(vcard: String) -> Self {
+        GhostbusterRecord { vcard }
+    }
+
+    /// Makes a record with a vCard for the given contact details. Values
+    /// for ADR should use the structured vCard format, e.g.:
+    /// ";;Street 1;City;;1234 AB;Netherlands"
+    pub fn from_fields(
+        full_name: &str,
+        org: Option<&str>,
+        email: Option<&str>,
+        tel: Option<&str>,
+        adr: Option<&str>,
+    ) -> Self {
+        let mut vcard = String::new();
+        vcard.push_str("BEGIN:VCARD\r\n");
+        vcard.push_str("VERSION:4.0\r\n");
+        vcard.push_str(&format!("FN:{}\r\n", Self::escape(full_name)));
+        if let Some(org) = org {
+            vcard.push_str(&format!("ORG:{}\r\n", Self::escape(org)));
+        }
+        if let Some(adr) = adr {
+            vcard.push_str(&format!("ADR:{}\r\n", adr));
+        }
+        if let Some(tel) = tel {
+            vcard.push_str(&format!("TEL:{}\r\n", tel));
+        }
+        if let Some(email) = email {
+            vcard.push_str(&format!("EMAIL:{}\r\n", email));
+        }
+        vcard.push_str("END:VCARD\r\n");
+
+        GhostbusterRecord { vcard }
+    }
+
+    fn escape(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace(',', "\\,")
+            .replace(';', "\\;")
+    }
+
+    pub fn vcard(&self) -> &str {
+        &self.vcard
+    }
+
+    /// Checks the vCard against the profile in section 5 of RFC 6493: it must
+    /// be a version 4.0 vCard, which only uses the properties BEGIN, VERSION,
+    /// FN, N, ORG, ADR, TEL, EMAIL and END. It must contain an FN property and
+    /// at least one of ADR, TEL or EMAIL. Returns a description of the problem
+    /// if the vCard does not follow the profile.
+    pub fn validate(&self) -> Result<(), String> {
+        let lines = Self::unfold(&self.vcard);
+        let line_is = |i: usize, expected: &str| {
+            lines
+                .get(i)
+                .map(|line| line.eq_ignore_ascii_case(expected))
+                .unwrap_or(false)
+        };
+
+        if !line_is(0, "BEGIN:VCARD") {
+            return Err("vCard must start with BEGIN:VCARD".to_string());
+        }
+        if !line_is(1, "VERSION:4.0") {
+            return Err("vCard must have VERSION:4.0 following BEGIN".to_string());
+        }
+        if !line_is(lines.len() - 1, "END:VCARD") {
+            return Err("vCard must end with END:VCARD".to_string());
+        }
+
+        let mut full_name = 0;
+        let mut contact = false;
+        for (i, line) in lines.iter().enumerate() {
+            if !line.contains(':') {
+                return Err(format!("invalid vCard line: '{}'", line));
+            }
+            match Self::property_name(line).as_str() {
+                "BEGIN" | "END" if i == 0 || i == lines.len() - 1 => {}
+                "VERSION" if i == 1 => {}
+                "FN" => full_name += 1,
+                "N" | "ORG" => {}
+                "ADR" | "TEL" | "EMAIL" => contact = true,
+                other => return Err(format!("property '{}' is not allowed", other)),
+            }
+        }
+
+        if full_name != 1 {
+            Err("vCard must contain exactly one FN property".to_string())
+        } else if !contact {
+            Err("vCard must contain at least one ADR, TEL or EMAIL property".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Unfolds the content lines of the vCard (RFC 6350 section 3.2), and
+    /// drops empty lines.
+    fn unfold(vcard: &str) -> Vec<String> {
+        let mut lines: Vec<String> = vec![];
+        for line in vcard.lines() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with(' ') || line.starts_with('\t') {
+                if let Some(last) = lines.last_mut() {
+                    last.push_str(&line[1..]);
+                    continue;
+                }
+            }
+            if !line.is_empty() {
+                lines.push(line.to_string());
+            }
+        }
+        lines
+    }
+
+    /// Returns the upper case name of the property on a content line,
+    /// without any group or parameters.
+    fn property_name(line: &str) -> String {
+        let name = line.split(&[':', ';'][..]).next().unwrap_or("");
+        let name = name.rsplit('.').next().unwrap_or(name);
+        name.to_ascii_uppercase()
+    }
+}
+
+impl fmt::Display for GhostbusterRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in Self::unfold(&self.vcard) {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ Tests -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_vcard() {
+        let record = GhostbusterRecord::from_fields(
+            "Operations, Example",
+            Some("Example Org"),
+            Some("noc@example.com"),
+            Some("+31 20 123 4567"),
+            None,
+        );
+        assert!(record.vcard().contains("FN:Operations\\, Example\r\n"));
+        record.validate().unwrap();
+
+        let folded = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Ops\r\nitem1.EMAIL;TYPE=work:n\r\n oc@example.com\r\nEND:VCARD\r\n";
+        GhostbusterRecord::new(folded.to_string())
+            .validate()
+            .unwrap();
+
+        let no_contact = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Ops\r\nORG:Example\r\nEND:VCARD\r\n";
+        assert!(GhostbusterRecord::new(no_contact.to_string())
+            .validate()
+            .is_err());
+
+        let no_fn = "BEGIN:VCARD\r\nVERSION:4.0\r\nEMAIL:noc@example.com\r\nEND:VCARD\r\n";
+        assert!(GhostbusterRecord::new(no_fn.to_string())
+            .validate()
+            .is_err());
+
+        let v3 = "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:Ops\r\nEMAIL:noc@example.com\r\nEND:VCARD\r\n";
+        assert!(GhostbusterRecord::new(v3.to_string()).validate().is_err());
+
+        let photo = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Ops\r\nEMAIL:noc@example.com\r\nPHOTO:http://example.com/me.jpg\r\nEND:VCARD\r\n";
+        assert!(GhostbusterRecord::new(photo.to_string())
+            .validate()
+            .is_err());
+
+        let unterminated = "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Ops\r\nEMAIL:noc@example.com\r\n";
+        assert!(GhostbusterRecord::new(unterminated.to_string())
+            .validate()
+            .is_err());
+    }
+}
@@ -9,9 +9,9 @@ use chrono::Utc;
 use chrono::{DateTime, NaiveDateTime};
 
 use crate::commons::api::{
-    ArgKey, ArgVal, ChildHandle, Handle, Label, Message, ParentHandle, PublisherHandle,
-    RequestResourceLimit, ResourceClassName, ResourceSet, RevocationRequest, RoaDefinitionUpdates,
-    StorableParentContact,
+    ArgKey, ArgVal, AsNumber, AspaDefinitionUpdates, ChildHandle, Handle, Label, Message,
+    ParentHandle, PublisherHandle, RequestResourceLimit, ResourceClassName, ResourceSet,
+    RevocationRequest, RoaDefinitionUpdates, StorableParentContact,
 };
 use crate::commons::eventsourcing::{
     CommandKey, CommandKeyError, StoredCommand, WithStorableDetails,
@@ -235,6 +235,10 @@ pub struct CommandHistoryCriteria {
 }
 
 impl CommandHistoryCriteria {
+    pub fn set_include(&mut self, labels: &[&str]) {
+        self.label_includes = Some(labels.iter().map(|s| (*s).to_string()).collect());
+    }
+
     pub fn set_exclude(&mut self, labels: &[&str]) {
         self.label_excludes = Some(labels.iter().map(|s| (*s).to_string()).collect());
     }
@@ -307,6 +311,102 @@ impl Default for CommandHistoryCriteria {
     }
 }
 
+//------------ ArchivedHistory -----------------------------------------------
+
+/// Describes the commands of an aggregate which were moved from its history
+/// into a compressed archive segment.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ArchivedHistory {
+    handle: Handle,
+    segment: String,
+    commands: usize,
+    stored_bytes: u64,
+    archived_bytes: u64,
+}
+
+impl ArchivedHistory {
+    pub fn new(
+        handle: Handle,
+        segment: String,
+        commands: usize,
+        stored_bytes: u64,
+        archived_bytes: u64,
+    ) -> Self {
+        ArchivedHistory {
+            handle,
+            segment,
+            commands,
+            stored_bytes,
+            archived_bytes,
+        }
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// The file name of the archive segment.
+    pub fn segment(&self) -> &str {
+        &self.segment
+    }
+
+    pub fn commands(&self) -> usize {
+        self.commands
+    }
+
+    /// The size of the archived commands, as they were stored.
+    pub fn stored_bytes(&self) -> u64 {
+        self.stored_bytes
+    }
+
+    /// The size of the compressed archive segment.
+    pub fn archived_bytes(&self) -> u64 {
+        self.archived_bytes
+    }
+
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.stored_bytes.saturating_sub(self.archived_bytes)
+    }
+}
+
+//------------ HistoryArchiveReport ------------------------------------------
+
+/// The result of archiving old history for all aggregates in a store.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HistoryArchiveReport {
+    archived: Vec<ArchivedHistory>,
+}
+
+impl HistoryArchiveReport {
+    pub fn add(&mut self, archived: ArchivedHistory) {
+        self.archived.push(archived)
+    }
+
+    pub fn archived(&self) -> &Vec<ArchivedHistory> {
+        &self.archived
+    }
+
+    pub fn commands(&self) -> usize {
+        self.archived.iter().map(|a| a.commands).sum()
+    }
+
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.archived.iter().map(|a| a.reclaimed_bytes()).sum()
+    }
+}
+
+impl fmt::Display for HistoryArchiveReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Archived {} commands from {} histories, reclaimed {} bytes",
+            self.commands(),
+            self.archived.len(),
+            self.reclaimed_bytes()
+        )
+    }
+}
+
 //------------ StorableCaCommand -------------------------------------------
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -325,6 +425,7 @@ pub enum StorableCaCommand {
     ),
     ChildRevokeKey(ChildHandle, RevocationRequest),
     ChildRemove(ChildHandle),
+    ChildShrinkCertificates(i64),
     GenerateNewIdKey,
     AddParent(ParentHandle, StorableParentContact),
     UpdateParentContact(ParentHandle, StorableParentContact),
@@ -335,7 +436,13 @@ pub enum StorableCaCommand {
     KeyRollActivate(i64),
     KeyRollFinish(ResourceClassName),
     RoaDefinitionUpdates(RoaDefinitionUpdates),
+    AspasUpdate(AspaDefinitionUpdates),
+    RouterCertify(KeyIdentifier, Vec<AsNumber>),
+    RouterRevoke(KeyIdentifier),
+    GhostbusterUpdate,
+    GhostbusterRemove,
     Republish,
+    RaisePublicationNumbers(u64),
     RepoUpdate(Option<ServiceUri>),
     RepoRemoveOld,
 }
@@ -369,6 +476,9 @@ impl WithStorableDetails for StorableCaCommand {
             StorableCaCommand::ChildRemove(child) => {
                 CommandSummary::new("cmd-ca-child-remove", &self).with_child(child)
             }
+            StorableCaCommand::ChildShrinkCertificates(seconds) => {
+                CommandSummary::new("cmd-ca-child-shrink", &self).with_seconds(*seconds)
+            }
             StorableCaCommand::ChildRevokeKey(child, revoke_request) => {
                 CommandSummary::new("cmd-ca-child-revoke", &self)
                     .with_child(child)
@@ -413,7 +523,27 @@ impl WithStorableDetails for StorableCaCommand {
                     .with_added(updates.added().len())
                     .with_removed(updates.removed().len())
             }
+            StorableCaCommand::AspasUpdate(updates) => {
+                CommandSummary::new("cmd-ca-aspas-update", &self)
+                    .with_added(updates.add_or_replace().len())
+                    .with_removed(updates.remove().len())
+            }
+            StorableCaCommand::RouterCertify(key, _) => {
+                CommandSummary::new("cmd-ca-bgpsec-certify", &self).with_key(key)
+            }
+            StorableCaCommand::RouterRevoke(key) => {
+                CommandSummary::new("cmd-ca-bgpsec-revoke", &self).with_key(key)
+            }
+            StorableCaCommand::GhostbusterUpdate => {
+                CommandSummary::new("cmd-ca-ghostbuster-update", &self)
+            }
+            StorableCaCommand::GhostbusterRemove => {
+                CommandSummary::new("cmd-ca-ghostbuster-remove", &self)
+            }
             StorableCaCommand::Republish => CommandSummary::new("cmd-ca-publish", &self),
+            StorableCaCommand::RaisePublicationNumbers(_) => {
+                CommandSummary::new("cmd-ca-publish-raise", &self)
+            }
             StorableCaCommand::RepoUpdate(service_uri_opt) => {
                 CommandSummary::new("cmd-ca-repo-update", &self)
                     .with_service_uri_opt(service_uri_opt.as_ref())
@@ -466,6 +596,11 @@ impl fmt::Display for StorableCaCommand {
             StorableCaCommand::ChildRemove(child) => {
                 write!(f, "Remove child '{}' and revoke&remove its certs", child)
             }
+            StorableCaCommand::ChildShrinkCertificates(grace) => write!(
+                f,
+                "Shrink child certs exceeding resources for longer than '{}' seconds",
+                grace
+            ),
 
             // ------------------------------------------------------------
             // Being a child (only allowed if this CA is not self-signed)
@@ -524,10 +659,38 @@ impl fmt::Display for StorableCaCommand {
                 updates.removed().len()
             ),
 
+            // ------------------------------------------------------------
+            // ASPA Support
+            // ------------------------------------------------------------
+            StorableCaCommand::AspasUpdate(updates) => write!(f, "Update ASPAs {}", updates),
+
+            // ------------------------------------------------------------
+            // BGPsec Support
+            // ------------------------------------------------------------
+            StorableCaCommand::RouterCertify(key, asns) => {
+                write!(f, "Certify router key '{}' for ASNs:", key)?;
+                for asn in asns {
+                    write!(f, " {}", asn)?;
+                }
+                Ok(())
+            }
+            StorableCaCommand::RouterRevoke(key) => write!(f, "Revoke router key '{}'", key),
+
+            // ------------------------------------------------------------
+            // Ghostbusters Support
+            // ------------------------------------------------------------
+            StorableCaCommand::GhostbusterUpdate => write!(f, "Update Ghostbusters vCard"),
+            StorableCaCommand::GhostbusterRemove => write!(f, "Remove Ghostbusters vCard"),
+
             // ------------------------------------------------------------
             // Publishing
             // ------------------------------------------------------------
             StorableCaCommand::Republish => write!(f, "Republish"),
+            StorableCaCommand::RaisePublicationNumbers(number) => write!(
+                f,
+                "Republish with manifest and CRL numbers above {}",
+                number
+            ),
             StorableCaCommand::RepoUpdate(service_uri_opt) => match service_uri_opt {
                 None => write!(f, "Update repo to embedded server"),
                 Some(uri) => write!(f, "Update repo to server at: {}", uri),
@@ -546,6 +709,7 @@ pub enum StorableRepositoryCommand {
     AddPublisher(PublisherHandle, String),
     RemovePublisher(PublisherHandle),
     Publish(PublisherHandle, usize, usize, usize),
+    ResetSession,
 }
 
 impl WithStorableDetails for StorableRepositoryCommand {
@@ -566,6 +730,9 @@ impl WithStorableDetails for StorableRepositoryCommand {
                     .with_arg("updated", updated)
                     .with_arg("withdrawn", withdrawn)
             }
+            StorableRepositoryCommand::ResetSession => {
+                CommandSummary::new("pubd-session-reset", &self)
+            }
         }
     }
 }
@@ -584,6 +751,7 @@ impl fmt::Display for StorableRepositoryCommand {
                 "Published for '{}': {} published, {} updated, {} withdrawn",
                 pbl, published, updated, withdrawn
             ),
+            StorableRepositoryCommand::ResetSession => write!(f, "Reset RRDP session"),
         }
     }
 }
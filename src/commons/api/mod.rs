@@ -3,9 +3,18 @@
 mod admin;
 pub use self::admin::*;
 
+mod aspa;
+pub use self::aspa::*;
+
+mod bgpsec;
+pub use self::bgpsec::*;
+
 mod ca;
 pub use self::ca::*;
 
+mod ghostbuster;
+pub use self::ghostbuster::*;
+
 mod history;
 pub use self::history::*;
 
@@ -18,11 +27,16 @@ pub use self::publication::*;
 mod roas;
 pub use self::roas::*;
 
+mod scheduler;
+pub use self::scheduler::*;
+
 pub mod rrdp;
 
 use std::collections::HashMap;
 use std::fmt;
 
+use bcder::encode::Values;
+use bcder::Mode;
 use bytes::Bytes;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
@@ -31,6 +45,7 @@ use rpki::crl::Crl;
 use rpki::crypto::KeyIdentifier;
 use rpki::manifest::Manifest;
 use rpki::roa::Roa;
+use rpki::sigobj::SignedObject;
 
 use crate::commons::util::sha256;
 use crate::daemon::ca::RouteAuthorization;
@@ -98,6 +113,12 @@ impl From<&Roa> for Base64 {
     }
 }
 
+impl From<&SignedObject> for Base64 {
+    fn from(object: &SignedObject) -> Self {
+        Base64::from_content(&object.encode_ref().to_captured(Mode::Der).into_bytes())
+    }
+}
+
 impl From<&Manifest> for Base64 {
     fn from(mft: &Manifest) -> Self {
         Base64::from_content(&mft.to_captured().into_bytes())
@@ -297,10 +318,22 @@ impl ErrorResponse {
         res
     }
 
+    pub fn with_customer(self, customer: AsNumber) -> Self {
+        self.with_arg("customer", customer)
+    }
+
+    pub fn with_asn(self, asn: AsNumber) -> Self {
+        self.with_arg("asn", asn)
+    }
+
     pub fn with_key_identifier(self, ki: &KeyIdentifier) -> Self {
         self.with_arg("key_id", ki)
     }
 
+    pub fn with_token(self, name: &str) -> Self {
+        self.with_arg("token", name)
+    }
+
     pub fn with_resource_class(self, class_name: &ResourceClassName) -> Self {
         self.with_arg("class_name", class_name)
     }
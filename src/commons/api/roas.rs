@@ -7,7 +7,7 @@ use std::str::FromStr;
 
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-use rpki::resources::{AsBlocks, AsId, IpBlocks, IpBlocksBuilder, Prefix};
+use rpki::resources::{AsBlocks, AsBlocksBuilder, AsId, IpBlocks, IpBlocksBuilder, Prefix};
 
 use crate::commons::api::ResourceSet;
 use crate::daemon::ca::RouteAuthorizationUpdates;
@@ -471,6 +471,10 @@ impl AsNumber {
     pub fn new(number: u32) -> Self {
         AsNumber(number)
     }
+
+    pub fn into_u32(self) -> u32 {
+        self.0
+    }
 }
 
 impl From<AsNumber> for AsId {
@@ -479,6 +483,16 @@ impl From<AsNumber> for AsId {
     }
 }
 
+impl From<AsNumber> for ResourceSet {
+    fn from(asn: AsNumber) -> ResourceSet {
+        let mut builder = AsBlocksBuilder::new();
+        builder.push(AsId::from(asn));
+        let blocks = builder.finalize();
+
+        ResourceSet::new(blocks, IpBlocks::empty(), IpBlocks::empty())
+    }
+}
+
 impl FromStr for AsNumber {
     type Err = AuthorizationFmtError;
 
@@ -528,6 +542,9 @@ pub enum AuthorizationFmtError {
 
     #[display(fmt = "Invalid authorization delta string: {}", _0)]
     Delta(String),
+
+    #[display(fmt = "Invalid ASPA definition string: {}", _0)]
+    Aspa(String),
 }
 
 impl AuthorizationFmtError {
@@ -546,6 +563,10 @@ impl AuthorizationFmtError {
     pub fn delta(s: &str) -> Self {
         AuthorizationFmtError::Delta(s.to_string())
     }
+
+    pub fn aspa(s: &str) -> Self {
+        AuthorizationFmtError::Aspa(s.to_string())
+    }
 }
 
 //------------ Tests -------------------------------------------------------
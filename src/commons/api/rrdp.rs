@@ -588,6 +588,15 @@ impl Snapshot {
         self.serial
     }
 
+    /// Returns a snapshot with the same objects, for a new session and serial.
+    pub fn with_session(&self, session: RrdpSession, serial: u64) -> Self {
+        Snapshot {
+            session,
+            serial,
+            current_objects: self.current_objects.clone(),
+        }
+    }
+
     pub fn apply_delta(&mut self, delta: Delta) {
         let (session, serial, elements) = delta.unwrap();
         self.session = session;
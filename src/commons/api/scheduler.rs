@@ -0,0 +1,197 @@
+//! The status of the background jobs run by the scheduler.
+use std::fmt;
+use std::str::FromStr;
+
+//------------ SchedulerJob --------------------------------------------------
+
+/// The background jobs run by the scheduler.
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulerJob {
+    /// Processes queued CA events, e.g. to publish.
+    #[display(fmt = "events")]
+    Events,
+
+    /// Republishes manifests and CRLs which are about to go stale.
+    #[display(fmt = "republish")]
+    Republish,
+
+    /// Asks parents for updated entitlements.
+    #[display(fmt = "ca_refresh")]
+    CaRefresh,
+
+    /// Moves old republish commands to the history archive.
+    #[display(fmt = "archive")]
+    Archive,
+
+    /// Sends (and retries) webhook deliveries.
+    #[display(fmt = "webhooks")]
+    Webhooks,
+
+    /// Refreshes BGP announcement information.
+    #[display(fmt = "announcements")]
+    Announcements,
+
+    /// Retries mirroring the RRDP files to failing targets.
+    #[display(fmt = "mirrors")]
+    Mirrors,
+}
+
+impl SchedulerJob {
+    pub fn all() -> &'static [SchedulerJob] {
+        &[
+            SchedulerJob::Events,
+            SchedulerJob::Republish,
+            SchedulerJob::CaRefresh,
+            SchedulerJob::Archive,
+            SchedulerJob::Webhooks,
+            SchedulerJob::Announcements,
+            SchedulerJob::Mirrors,
+        ]
+    }
+}
+
+impl FromStr for SchedulerJob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SchedulerJob::all()
+            .iter()
+            .find(|job| job.to_string() == s)
+            .cloned()
+            .ok_or_else(|| format!("Unknown scheduler job: {}", s))
+    }
+}
+
+//------------ JobResult -----------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobResult {
+    Success,
+    Failure { error: String },
+}
+
+impl fmt::Display for JobResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobResult::Success => write!(f, "success"),
+            JobResult::Failure { error } => write!(f, "failure: {}", error),
+        }
+    }
+}
+
+//------------ JobStatus -----------------------------------------------------
+
+/// The status of a scheduler job. Times are in seconds since the epoch.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct JobStatus {
+    job: SchedulerJob,
+    interval: u32,
+    running: bool,
+    last_run: Option<i64>,
+    last_duration_ms: Option<u64>,
+    last_result: Option<JobResult>,
+    next_run: i64,
+}
+
+impl JobStatus {
+    pub fn new(
+        job: SchedulerJob,
+        interval: u32,
+        running: bool,
+        last_run: Option<i64>,
+        last_duration_ms: Option<u64>,
+        last_result: Option<JobResult>,
+        next_run: i64,
+    ) -> Self {
+        JobStatus {
+            job,
+            interval,
+            running,
+            last_run,
+            last_duration_ms,
+            last_result,
+            next_run,
+        }
+    }
+
+    pub fn job(&self) -> SchedulerJob {
+        self.job
+    }
+
+    /// The interval between runs, in seconds.
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn last_run(&self) -> Option<i64> {
+        self.last_run
+    }
+
+    pub fn last_duration_ms(&self) -> Option<u64> {
+        self.last_duration_ms
+    }
+
+    pub fn last_result(&self) -> Option<&JobResult> {
+        self.last_result.as_ref()
+    }
+
+    pub fn next_run(&self) -> i64 {
+        self.next_run
+    }
+}
+
+//------------ SchedulerStatus -----------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SchedulerStatus {
+    jobs: Vec<JobStatus>,
+}
+
+impl SchedulerStatus {
+    pub fn new(jobs: Vec<JobStatus>) -> Self {
+        SchedulerStatus { jobs }
+    }
+
+    pub fn jobs(&self) -> &Vec<JobStatus> {
+        &self.jobs
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn job_names_round_trip() {
+        for job in SchedulerJob::all() {
+            assert_eq!(*job, SchedulerJob::from_str(&job.to_string()).unwrap());
+            let json = serde_json::to_string(job).unwrap();
+            assert_eq!(format!("\"{}\"", job), json);
+        }
+        assert!(SchedulerJob::from_str("reboot").is_err());
+    }
+
+    #[test]
+    fn job_result_json() {
+        let failure = JobResult::Failure {
+            error: "parent unreachable".to_string(),
+        };
+        assert_eq!(
+            r#"{"status":"failure","error":"parent unreachable"}"#,
+            serde_json::to_string(&failure).unwrap()
+        );
+        assert_eq!(
+            r#"{"status":"success"}"#,
+            serde_json::to_string(&JobResult::Success).unwrap()
+        );
+    }
+}
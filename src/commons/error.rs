@@ -12,7 +12,7 @@ use rpki::x509::ValidationError;
 
 use crate::commons::api::rrdp::PublicationDeltaError;
 use crate::commons::api::{
-    ChildHandle, ErrorResponse, Handle, ParentHandle, PublisherHandle, ResourceClassName,
+    AsNumber, ChildHandle, ErrorResponse, Handle, ParentHandle, PublisherHandle, ResourceClassName,
     ResourceSetError,
 };
 use crate::commons::eventsourcing::AggregateStoreError;
@@ -38,12 +38,21 @@ pub enum Error {
     #[display(fmt = "Signing issue: {}", _0)]
     SignerError(String),
 
+    #[display(fmt = "Configured signer does not have the keys of CA(s): {}", _0)]
+    SignerKeysMissing(String),
+
     #[display(fmt = "Cannot set up HTTPS: {}", _0)]
     HttpsSetup(String),
 
     #[display(fmt = "HTTP client error: {}", _0)]
     HttpClientError(httpclient::Error),
 
+    #[display(fmt = "Invalid backup: {}", _0)]
+    BackupInvalid(String),
+
+    #[display(fmt = "Cannot restore backup: {}", _0)]
+    RestoreNotPossible(String),
+
     //-----------------------------------------------------------------
     // General API Client Issues
     //-----------------------------------------------------------------
@@ -62,6 +71,9 @@ pub enum Error {
     #[display(fmt = "Invalid path argument for seconds")]
     ApiInvalidSeconds,
 
+    #[display(fmt = "Invalid query parameter: {}", _0)]
+    ApiInvalidQuery(String),
+
     #[display(fmt = "POST body exceeds configured limit")]
     PostTooBig,
 
@@ -129,6 +141,12 @@ pub enum Error {
     CaRepoResponseInvalidXml(Handle, String),
     #[display(fmt = "CA '{}' got parent instead of repository response", _0)]
     CaRepoResponseWrongXml(Handle),
+    #[display(
+        fmt = "CA '{}' did not publish recently, its published manifests go stale at {}",
+        _0,
+        _1
+    )]
+    CaRepoPublicationStale(Handle, String),
 
     // CA Parent Issues
     #[display(fmt = "CA '{}' already has a parent named '{}'", _0, _1)]
@@ -188,6 +206,14 @@ pub enum Error {
     )]
     CaChildUpdateOneThing(Handle, ChildHandle),
 
+    #[display(
+        fmt = "Child '{}' of CA '{}' holds certificates for resources it is no longer entitled to, they will be shrunk at {}",
+        _1,
+        _0,
+        _2
+    )]
+    CaChildOverclaiming(Handle, ChildHandle, String),
+
     // RouteAuthorizations - ROAs
     #[display(fmt = "Cannot remove unknown ROA '{}'", _1)]
     CaAuthorizationUnknown(Handle, RouteAuthorization),
@@ -210,6 +236,39 @@ pub enum Error {
     #[display(fmt = "Prefix in ROA '{}' not held by you", _1)]
     CaAuthorizationNotEntitled(Handle, RouteAuthorization),
 
+    // ASPA definitions
+    #[display(fmt = "Cannot remove unknown ASPA for customer AS '{}'", _1)]
+    CaAspaUnknown(Handle, AsNumber),
+
+    #[display(fmt = "Customer AS '{}' in ASPA not held by you", _1)]
+    CaAspaNotEntitled(Handle, AsNumber),
+
+    #[display(fmt = "ASPA for customer AS '{}' has no providers", _1)]
+    CaAspaNoProviders(Handle, AsNumber),
+
+    #[display(fmt = "ASPA for customer AS '{}' lists the customer as provider", _1)]
+    CaAspaCustomerAsProvider(Handle, AsNumber),
+
+    // BGPsec router certificates
+    #[display(fmt = "Invalid router CSR: {}", _1)]
+    CaBgpSecInvalidCsr(Handle, String),
+
+    #[display(fmt = "Router certificate request contains no ASNs")]
+    CaBgpSecNoAsns(Handle),
+
+    #[display(fmt = "ASN '{}' in router certificate request not held by you", _1)]
+    CaBgpSecNotEntitled(Handle, AsNumber),
+
+    #[display(fmt = "Cannot revoke unknown router key '{}'", _1)]
+    CaBgpSecUnknown(Handle, KeyIdentifier),
+
+    // Ghostbusters
+    #[display(fmt = "Invalid Ghostbusters vCard: {}", _1)]
+    CaGhostbusterInvalid(Handle, String),
+
+    #[display(fmt = "No Ghostbusters vCard configured")]
+    CaGhostbusterUnknown(Handle),
+
     //-----------------------------------------------------------------
     // Key Usage Issues
     //-----------------------------------------------------------------
@@ -255,6 +314,27 @@ pub enum Error {
     #[display(fmt = "TrustAnchor was already initialised")]
     TaAlreadyInitialised,
 
+    //-----------------------------------------------------------------
+    // API Token Issues
+    //-----------------------------------------------------------------
+    #[display(fmt = "API token name '{}' is already in use", _0)]
+    ApiTokenDuplicate(String),
+
+    #[display(fmt = "Unknown API token '{}'", _0)]
+    ApiTokenUnknown(String),
+
+    #[display(fmt = "API token '{}' must be bound to at least one CA", _0)]
+    ApiTokenNoCas(String),
+
+    //-----------------------------------------------------------------
+    // Login Issues
+    //-----------------------------------------------------------------
+    #[display(fmt = "Login is not configured")]
+    LoginNotConfigured,
+
+    #[display(fmt = "Login failed: {}", _0)]
+    LoginFailed(String),
+
     //-----------------------------------------------------------------
     // If we really don't know any more..
     //-----------------------------------------------------------------
@@ -317,6 +397,10 @@ impl Error {
         Error::PublisherUriOutsideBase(uri.to_string(), jail.to_string())
     }
 
+    pub fn login_failed(msg: impl fmt::Display) -> Self {
+        Error::LoginFailed(msg.to_string())
+    }
+
     pub fn custom(msg: impl fmt::Display) -> Self {
         Error::Custom(msg.to_string())
     }
@@ -329,15 +413,20 @@ impl Error {
     pub fn status(&self) -> StatusCode {
         match self {
             // Most is bad requests by users, so just mapping the things that are not
-            Error::IoError(_) | Error::SignerError(_) | Error::AggregateStoreError(_) => {
-                StatusCode::INTERNAL_SERVER_ERROR
-            }
+            Error::IoError(_)
+            | Error::SignerError(_)
+            | Error::SignerKeysMissing(_)
+            | Error::AggregateStoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::PublisherUnknown(_)
             | Error::CaUnknown(_)
             | Error::CaChildUnknown(_, _)
             | Error::CaParentUnknown(_, _)
+            | Error::ApiTokenUnknown(_)
+            | Error::LoginNotConfigured
             | Error::ApiUnknownResource => StatusCode::NOT_FOUND,
 
+            Error::LoginFailed(_) => StatusCode::FORBIDDEN,
+
             _ => StatusCode::BAD_REQUEST,
         }
     }
@@ -357,12 +446,23 @@ impl Error {
             // internal server error
             Error::SignerError(e) => ErrorResponse::new("sys-signer", &self).with_cause(e),
 
+            // internal server error
+            Error::SignerKeysMissing(cas) => {
+                ErrorResponse::new("sys-signer-keys", &self).with_cause(cas)
+            }
+
             // internal server error
             Error::HttpsSetup(e) => ErrorResponse::new("sys-https", &self).with_cause(e),
 
             // internal server error
             Error::HttpClientError(e) => ErrorResponse::new("sys-http-client", &self).with_cause(e),
 
+            Error::BackupInvalid(e) => {
+                ErrorResponse::new("sys-backup-invalid", &self).with_cause(e)
+            }
+
+            Error::RestoreNotPossible(e) => ErrorResponse::new("sys-restore", &self).with_cause(e),
+
             //-----------------------------------------------------------------
             // General API Client Issues (label: api-*)
             //-----------------------------------------------------------------
@@ -377,6 +477,10 @@ impl Error {
 
             Error::ApiInvalidSeconds => ErrorResponse::new("api-invalid-path-seconds", &self),
 
+            Error::ApiInvalidQuery(e) => {
+                ErrorResponse::new("api-invalid-query", &self).with_cause(e)
+            }
+
             Error::PostTooBig => ErrorResponse::new("api-post-body-exceeds-limit", &self),
 
             Error::PostCannotRead => ErrorResponse::new("api-post-body-cannot-read", &self),
@@ -446,6 +550,10 @@ impl Error {
                 ErrorResponse::new("ca-repo-response-wrong-xml", &self).with_ca(ca)
             }
 
+            Error::CaRepoPublicationStale(ca, _) => {
+                ErrorResponse::new("ca-repo-publication-stale", &self).with_ca(ca)
+            }
+
             Error::CaParentDuplicate(ca, parent) => {
                 ErrorResponse::new("ca-parent-duplicate", &self)
                     .with_ca(ca)
@@ -505,6 +613,11 @@ impl Error {
                     .with_ca(ca)
                     .with_child(child)
             }
+            Error::CaChildOverclaiming(ca, child, _) => {
+                ErrorResponse::new("ca-child-overclaiming", &self)
+                    .with_ca(ca)
+                    .with_child(child)
+            }
             Error::CaChildUnauthorized(ca, child) => {
                 ErrorResponse::new("ca-child-unauthorized", &self)
                     .with_ca(ca)
@@ -552,6 +665,59 @@ impl Error {
                     .with_auth(auth)
             }
 
+            // ASPA definitions
+            Error::CaAspaUnknown(ca, customer) => ErrorResponse::new("ca-aspa-unknown", &self)
+                .with_ca(ca)
+                .with_customer(*customer),
+
+            Error::CaAspaNotEntitled(ca, customer) => {
+                ErrorResponse::new("ca-aspa-not-entitled", &self)
+                    .with_ca(ca)
+                    .with_customer(*customer)
+            }
+
+            Error::CaAspaNoProviders(ca, customer) => {
+                ErrorResponse::new("ca-aspa-no-providers", &self)
+                    .with_ca(ca)
+                    .with_customer(*customer)
+            }
+
+            Error::CaAspaCustomerAsProvider(ca, customer) => {
+                ErrorResponse::new("ca-aspa-customer-as-provider", &self)
+                    .with_ca(ca)
+                    .with_customer(*customer)
+            }
+
+            // BGPsec router certificates
+            Error::CaBgpSecInvalidCsr(ca, cause) => {
+                ErrorResponse::new("ca-bgpsec-invalid-csr", &self)
+                    .with_ca(ca)
+                    .with_cause(cause)
+            }
+
+            Error::CaBgpSecNoAsns(ca) => ErrorResponse::new("ca-bgpsec-no-asns", &self).with_ca(ca),
+
+            Error::CaBgpSecNotEntitled(ca, asn) => {
+                ErrorResponse::new("ca-bgpsec-not-entitled", &self)
+                    .with_ca(ca)
+                    .with_asn(*asn)
+            }
+
+            Error::CaBgpSecUnknown(ca, key) => ErrorResponse::new("ca-bgpsec-unknown", &self)
+                .with_ca(ca)
+                .with_key_identifier(key),
+
+            // Ghostbusters
+            Error::CaGhostbusterInvalid(ca, cause) => {
+                ErrorResponse::new("ca-ghostbuster-invalid", &self)
+                    .with_ca(ca)
+                    .with_cause(cause)
+            }
+
+            Error::CaGhostbusterUnknown(ca) => {
+                ErrorResponse::new("ca-ghostbuster-unknown", &self).with_ca(ca)
+            }
+
             //-----------------------------------------------------------------
             // Key Usage Issues (key-*)
             //-----------------------------------------------------------------
@@ -580,6 +746,25 @@ impl Error {
             Error::TaNameReserved => ErrorResponse::new("ta-name-reserved", &self),
             Error::TaAlreadyInitialised => ErrorResponse::new("ta-initialised", &self),
 
+            //-----------------------------------------------------------------
+            // API Token Issues (label: token-*)
+            //-----------------------------------------------------------------
+            Error::ApiTokenDuplicate(name) => {
+                ErrorResponse::new("token-duplicate", &self).with_token(name)
+            }
+            Error::ApiTokenUnknown(name) => {
+                ErrorResponse::new("token-unknown", &self).with_token(name)
+            }
+            Error::ApiTokenNoCas(name) => {
+                ErrorResponse::new("token-no-cas", &self).with_token(name)
+            }
+
+            //-----------------------------------------------------------------
+            // Login Issues (label: login-*)
+            //-----------------------------------------------------------------
+            Error::LoginNotConfigured => ErrorResponse::new("login-not-configured", &self),
+            Error::LoginFailed(e) => ErrorResponse::new("login-failed", &self).with_cause(e),
+
             //-----------------------------------------------------------------
             // If we really don't know any more..
             //-----------------------------------------------------------------
@@ -658,6 +843,10 @@ mod tests {
             include_str!("../../test-resources/api/regressions/errors/sys-signer.json"),
             Error::SignerError("signer issue".to_string()),
         );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/sys-signer-keys.json"),
+            Error::SignerKeysMissing("ca1, ca2".to_string()),
+        );
         verify(
             include_str!("../../test-resources/api/regressions/errors/sys-https.json"),
             Error::HttpsSetup("can't find pem file".to_string()),
@@ -666,6 +855,14 @@ mod tests {
             include_str!("../../test-resources/api/regressions/errors/sys-http-client.json"),
             Error::HttpClientError(httpclient::Error::Forbidden),
         );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/sys-backup-invalid.json"),
+            Error::BackupInvalid("hash mismatch for file: cas/ta/info.json".to_string()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/sys-restore.json"),
+            Error::RestoreNotPossible("data directory is not empty".to_string()),
+        );
 
         //-----------------------------------------------------------------
         // General API Client Issues
@@ -686,6 +883,10 @@ mod tests {
             include_str!("../../test-resources/api/regressions/errors/api-unknown-resource.json"),
             Error::ApiUnknownResource,
         );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/api-invalid-query.json"),
+            Error::ApiInvalidQuery("'from' requires 'ca'".to_string()),
+        );
 
         //-----------------------------------------------------------------
         // Repository Issues
@@ -778,6 +979,12 @@ mod tests {
             ),
             Error::CaRepoResponseWrongXml(ca.clone()),
         );
+        verify(
+            include_str!(
+                "../../test-resources/api/regressions/errors/ca-repo-publication-stale.json"
+            ),
+            Error::CaRepoPublicationStale(ca.clone(), "2020-10-14T08:00:00+00:00".to_string()),
+        );
 
         verify(
             include_str!("../../test-resources/api/regressions/errors/ca-parent-duplicate.json"),
@@ -839,6 +1046,14 @@ mod tests {
             ),
             Error::CaChildExtraResources(ca.clone(), child.clone()),
         );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-child-overclaiming.json"),
+            Error::CaChildOverclaiming(
+                ca.clone(),
+                child.clone(),
+                "2020-10-14T08:00:00+00:00".to_string(),
+            ),
+        );
         verify(
             include_str!("../../test-resources/api/regressions/errors/ca-child-unauthorized.json"),
             Error::CaChildUnauthorized(ca.clone(), child),
@@ -869,7 +1084,56 @@ mod tests {
         );
         verify(
             include_str!("../../test-resources/api/regressions/errors/ca-roa-not-entitled.json"),
-            Error::CaAuthorizationNotEntitled(ca, auth),
+            Error::CaAuthorizationNotEntitled(ca.clone(), auth),
+        );
+
+        let customer = AsNumber::new(64496);
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-aspa-unknown.json"),
+            Error::CaAspaUnknown(ca.clone(), customer),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-aspa-not-entitled.json"),
+            Error::CaAspaNotEntitled(ca.clone(), customer),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-aspa-no-providers.json"),
+            Error::CaAspaNoProviders(ca.clone(), customer),
+        );
+        verify(
+            include_str!(
+                "../../test-resources/api/regressions/errors/ca-aspa-customer-as-provider.json"
+            ),
+            Error::CaAspaCustomerAsProvider(ca.clone(), customer),
+        );
+
+        let router_key = test_id_certificate()
+            .subject_public_key_info()
+            .key_identifier();
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-bgpsec-invalid-csr.json"),
+            Error::CaBgpSecInvalidCsr(ca.clone(), "invalid signature".to_string()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-bgpsec-no-asns.json"),
+            Error::CaBgpSecNoAsns(ca.clone()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-bgpsec-not-entitled.json"),
+            Error::CaBgpSecNotEntitled(ca.clone(), customer),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-bgpsec-unknown.json"),
+            Error::CaBgpSecUnknown(ca.clone(), router_key),
+        );
+
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-ghostbuster-invalid.json"),
+            Error::CaGhostbusterInvalid(ca.clone(), "vCard must end with END:VCARD".to_string()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/ca-ghostbuster-unknown.json"),
+            Error::CaGhostbusterUnknown(ca),
         );
 
         verify(
@@ -926,6 +1190,28 @@ mod tests {
             Error::TaAlreadyInitialised,
         );
 
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/token-duplicate.json"),
+            Error::ApiTokenDuplicate("unit-a".to_string()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/token-unknown.json"),
+            Error::ApiTokenUnknown("unit-a".to_string()),
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/token-no-cas.json"),
+            Error::ApiTokenNoCas("unit-a".to_string()),
+        );
+
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/login-not-configured.json"),
+            Error::LoginNotConfigured,
+        );
+        verify(
+            include_str!("../../test-resources/api/regressions/errors/login-failed.json"),
+            Error::login_failed("ID token has expired"),
+        );
+
         verify(
             include_str!("../../test-resources/api/regressions/errors/general-error.json"),
             Error::custom("some unlikely corner case"),
@@ -1,22 +1,26 @@
 use std::collections::HashMap;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::{RwLock, RwLockReadGuard};
 
 use rpki::x509::Time;
 
-use crate::commons::api::{CommandHistory, CommandHistoryCriteria, Handle};
+use crate::commons::api::{CommandHistory, CommandHistoryCriteria, Handle, HistoryArchiveReport};
 use crate::commons::eventsourcing::cmd::{Command, StoredCommandBuilder};
 use crate::commons::eventsourcing::{
-    Aggregate, CommandKey, DiskKeyStore, Event, EventListener, KeyStore, KeyStoreError,
-    KeyStoreVersion, StoredCommand,
+    Aggregate, AnyKeyStore, CommandKey, Event, EventListener, KeyStore, KeyStoreError,
+    KeyStoreVersion, StorageBackend, StoredCommand,
 };
 
 const SNAPSHOT_FREQ: u64 = 5;
 
 pub type StoreResult<T> = Result<T, AggregateStoreError>;
 
+/// The updated aggregate and the events applied to it, or the error for a
+/// command which could not be applied.
+type CommandOutcome<A> = Result<(Arc<A>, Vec<<A as Aggregate>::Event>), <A as Aggregate>::Error>;
+
 pub trait AggregateStore<A: Aggregate>: Send + Sync
 where
     A::Error: From<AggregateStoreError>,
@@ -101,8 +105,10 @@ impl From<KeyStoreError> for AggregateStoreError {
     }
 }
 
+/// An AggregateStore which keeps aggregates in a KeyStore, using any of the
+/// supported storage backends, and caches the latest versions in memory.
 pub struct DiskAggregateStore<A: Aggregate> {
-    store: DiskKeyStore,
+    store: AnyKeyStore,
     cache: RwLock<HashMap<Handle, Arc<A>>>,
     use_cache: bool,
     listeners: Vec<Arc<dyn EventListener<A>>>,
@@ -110,13 +116,25 @@ pub struct DiskAggregateStore<A: Aggregate> {
 }
 
 impl<A: Aggregate> DiskAggregateStore<A> {
+    /// Creates a store for the name_space under the work_dir, using the
+    /// storage backend which holds its data, or the disk for a new store.
     pub fn new(work_dir: &PathBuf, name_space: &str) -> StoreResult<Self> {
-        let store = DiskKeyStore::under_work_dir(work_dir, name_space)
-            .map_err(AggregateStoreError::IoError)?;
+        let backend = StorageBackend::detect(work_dir, name_space).unwrap_or_default();
+        Self::with_backend(work_dir, name_space, backend)
+    }
 
+    /// Creates a store for the name_space under the work_dir, using the
+    /// given storage backend. Returns an error if the name space already
+    /// holds data in another backend.
+    pub fn with_backend(
+        work_dir: &PathBuf,
+        name_space: &str,
+        backend: StorageBackend,
+    ) -> StoreResult<Self> {
+        let store = AnyKeyStore::open(work_dir, name_space, backend)?;
         if store.aggregates().is_empty() {
             store
-                .set_version(&KeyStoreVersion::V0_7)
+                .set_version(&KeyStoreVersion::V0_8)
                 .map_err(AggregateStoreError::KeyStoreError)?;
         }
 
@@ -135,6 +153,37 @@ impl<A: Aggregate> DiskAggregateStore<A> {
 }
 
 impl<A: Aggregate> DiskAggregateStore<A> {
+    /// Blocks all changes to this store until the returned guard is dropped,
+    /// so that its files on disk are consistent, e.g. while they are backed
+    /// up. Aggregates can still be read.
+    pub fn lock_for_backup(&self) -> RwLockReadGuard<'_, ()> {
+        self.outer_lock.read().unwrap()
+    }
+
+    /// Moves the commands matching the criteria out of the history of all
+    /// aggregates, into compressed segments under the archive_dir. See
+    /// KeyStore::archive_commands.
+    pub fn archive_history(
+        &self,
+        crit: &CommandHistoryCriteria,
+        archive_dir: &Path,
+    ) -> StoreResult<HistoryArchiveReport> {
+        let mut report = HistoryArchiveReport::default();
+        for id in self.store.aggregates() {
+            let _lock = self.outer_lock.write().unwrap();
+            if let Some(archived) = self.store.archive_commands(&id, crit, archive_dir)? {
+                report.add(archived);
+            }
+        }
+
+        if !report.archived().is_empty() {
+            let _lock = self.outer_lock.write().unwrap();
+            self.store.compact()?;
+        }
+
+        Ok(report)
+    }
+
     fn has_updates(&self, id: &Handle, aggregate: &A) -> StoreResult<bool> {
         Ok(self
             .store
@@ -156,6 +205,12 @@ impl<A: Aggregate> DiskAggregateStore<A> {
         }
     }
 
+    fn cache_remove(&self, id: &Handle) {
+        if self.use_cache {
+            self.cache.write().unwrap().remove(id);
+        }
+    }
+
     fn get_latest_no_lock(&self, handle: &Handle) -> StoreResult<Arc<A>> {
         trace!("Trying to load aggregate id: {}", handle);
         match self.cache_get(handle) {
@@ -181,43 +236,15 @@ impl<A: Aggregate> DiskAggregateStore<A> {
             }
         }
     }
-}
-
-impl<A: Aggregate> AggregateStore<A> for DiskAggregateStore<A>
-where
-    A::Error: From<AggregateStoreError>,
-{
-    fn get_latest(&self, handle: &Handle) -> StoreResult<Arc<A>> {
-        let _lock = self.outer_lock.read().unwrap();
-        self.get_latest_no_lock(handle)
-    }
-
-    fn add(&self, init: A::InitEvent) -> StoreResult<Arc<A>> {
-        let _lock = self.outer_lock.write().unwrap();
-
-        self.store.store_event(&init)?;
-
-        let handle = init.handle().clone();
-
-        let aggregate = A::init(init).map_err(|_| AggregateStoreError::InitError)?;
-        self.store.store_snapshot(&handle, &aggregate)?;
-
-        let arc = Arc::new(aggregate);
-        self.cache_update(&handle, arc.clone());
-
-        Ok(arc)
-    }
-
-    fn command(&self, cmd: A::Command) -> Result<Arc<A>, A::Error> {
-        let _lock = self.outer_lock.write().unwrap();
 
+    /// Processes a command and saves the command and its events. Returns an
+    /// error if anything could not be stored, or else the outcome of the
+    /// command: the updated aggregate and the events applied to it.
+    fn process_command(&self, cmd: A::Command) -> StoreResult<CommandOutcome<A>> {
         // Get the latest arc.
         let handle = cmd.handle().clone();
 
-        let mut info = self
-            .store
-            .get_info(&handle)
-            .map_err(AggregateStoreError::KeyStoreError)?;
+        let mut info = self.store.get_info(&handle)?;
         info.last_update = Time::now();
         info.last_command += 1;
 
@@ -232,9 +259,7 @@ where
                     latest.version()
                 );
 
-                return Err(A::Error::from(AggregateStoreError::ConcurrentModification(
-                    handle,
-                )));
+                return Err(AggregateStoreError::ConcurrentModification(handle));
             }
         }
 
@@ -244,14 +269,12 @@ where
         let res = match latest.process_command(cmd) {
             Err(e) => {
                 let stored_command = stored_command_builder.finish_with_error(&e);
-                self.store
-                    .store_command(stored_command)
-                    .map_err(AggregateStoreError::KeyStoreError)?;
+                self.store.store_command(stored_command)?;
                 Err(e)
             }
             Ok(events) => {
                 if events.is_empty() {
-                    return Ok(latest); // otherwise the version info will be updated
+                    return Ok(Ok((latest, events))); // otherwise the version info will be updated
                 } else {
                     let agg = Arc::make_mut(&mut latest);
 
@@ -281,53 +304,90 @@ where
                     for i in 0..nr_events {
                         let event = &events[i as usize];
                         if event.version() != version_before + i || event.handle() != &handle {
-                            return Err(A::Error::from(
-                                AggregateStoreError::WrongEventForAggregate,
-                            ));
+                            return Err(AggregateStoreError::WrongEventForAggregate);
                         }
                     }
 
                     // Time to start saving things.
                     let stored_command =
                         stored_command_builder.finish_with_events(events.as_slice());
-                    self.store
-                        .store_command(stored_command)
-                        .map_err(AggregateStoreError::KeyStoreError)?;
+                    self.store.store_command(stored_command)?;
 
                     for event in &events {
-                        self.store
-                            .store_event(event)
-                            .map_err(AggregateStoreError::KeyStoreError)?;
+                        self.store.store_event(event)?;
 
                         agg.apply(event.clone());
                         if agg.version() % SNAPSHOT_FREQ == 0 {
                             info.snapshot_version = agg.version();
 
-                            self.store
-                                .store_snapshot(&handle, agg)
-                                .map_err(AggregateStoreError::KeyStoreError)?;
+                            self.store.store_snapshot(&handle, agg)?;
                         }
                     }
 
                     cache.insert(handle.clone(), Arc::new(agg.clone()));
 
-                    // Only send this to listeners after everything has been saved.
-                    for event in events {
-                        for listener in &self.listeners {
-                            listener.as_ref().listen(agg, &event);
-                        }
-                    }
-
-                    Ok(latest)
+                    Ok((latest, events))
                 }
             }
         };
 
-        self.store
-            .save_info(&handle, &info)
-            .map_err(AggregateStoreError::KeyStoreError)?;
+        self.store.save_info(&handle, &info)?;
 
-        res
+        Ok(res)
+    }
+}
+
+impl<A: Aggregate> AggregateStore<A> for DiskAggregateStore<A>
+where
+    A::Error: From<AggregateStoreError>,
+{
+    fn get_latest(&self, handle: &Handle) -> StoreResult<Arc<A>> {
+        let _lock = self.outer_lock.read().unwrap();
+        self.get_latest_no_lock(handle)
+    }
+
+    fn add(&self, init: A::InitEvent) -> StoreResult<Arc<A>> {
+        let _lock = self.outer_lock.write().unwrap();
+
+        let handle = init.handle().clone();
+
+        let aggregate = self.store.transaction(|| -> StoreResult<A> {
+            self.store.store_event(&init)?;
+
+            let aggregate = A::init(init).map_err(|_| AggregateStoreError::InitError)?;
+            self.store.store_snapshot(&handle, &aggregate)?;
+            Ok(aggregate)
+        })?;
+
+        let arc = Arc::new(aggregate);
+        self.cache_update(&handle, arc.clone());
+
+        Ok(arc)
+    }
+
+    fn command(&self, cmd: A::Command) -> Result<Arc<A>, A::Error> {
+        let _lock = self.outer_lock.write().unwrap();
+
+        let handle = cmd.handle().clone();
+
+        match self.store.transaction(|| self.process_command(cmd)) {
+            Ok(Ok((latest, events))) => {
+                // Only send this to listeners after everything has been saved.
+                for event in events {
+                    for listener in &self.listeners {
+                        listener.as_ref().listen(&latest, &event);
+                    }
+                }
+                Ok(latest)
+            }
+            Ok(Err(e)) => Err(e),
+            Err(e) => {
+                // The cached aggregate may include events which were not
+                // kept, so make sure that it is read from the store again.
+                self.cache_remove(&handle);
+                Err(A::Error::from(e))
+            }
+        }
     }
 
     fn has(&self, id: &Handle) -> bool {
@@ -350,6 +410,7 @@ where
         id: &Handle,
         crit: CommandHistoryCriteria,
     ) -> StoreResult<CommandHistory> {
+        let _lock = self.outer_lock.read().unwrap();
         self.store
             .command_history::<A>(id, crit)
             .map_err(AggregateStoreError::KeyStoreError)
@@ -360,6 +421,7 @@ where
         id: &Handle,
         key: &CommandKey,
     ) -> StoreResult<Option<StoredCommand<<A as Aggregate>::StorableCommandDetails>>> {
+        let _lock = self.outer_lock.read().unwrap();
         self.store
             .get(id, &key.into())
             .map_err(AggregateStoreError::KeyStoreError)
@@ -370,7 +432,8 @@ where
         id: &Handle,
         version: u64,
     ) -> StoreResult<Option<<A as Aggregate>::Event>> {
-        let key = DiskKeyStore::key_for_event(version);
+        let _lock = self.outer_lock.read().unwrap();
+        let key = AnyKeyStore::key_for_event(version);
         self.store
             .get(id, &key)
             .map_err(AggregateStoreError::KeyStoreError)
@@ -0,0 +1,383 @@
+use std::any::Any;
+use std::path::PathBuf;
+use std::{fmt, fs};
+
+use serde::Serialize;
+use serde_json;
+
+use crate::commons::api::{CommandHistoryCriteria, Handle};
+use crate::commons::eventsourcing::{
+    CommandKey, DiskKeyStore, Event, KeyStore, KeyStoreError, KeyStoreVersion, SqliteKeyStore,
+    Storable, StoredCommand, WithStorableDetails, SQLITE_DB,
+};
+
+//------------ StorageBackend ------------------------------------------------
+
+/// The storage used for new aggregate stores, as configured with
+/// 'storage_backend' in krill.conf.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// One json file for every event, command and snapshot.
+    Disk,
+
+    /// An embedded SQLite database for every name space.
+    Sqlite,
+}
+
+impl StorageBackend {
+    /// Returns the backend which holds the existing data for a name space,
+    /// if there is any.
+    pub fn detect(work_dir: &PathBuf, name_space: &str) -> Option<Self> {
+        let mut db = work_dir.clone();
+        db.push(name_space);
+        db.push(SQLITE_DB);
+
+        if db.is_file() {
+            Some(StorageBackend::Sqlite)
+        } else if !DiskKeyStore::new(work_dir, name_space)
+            .aggregates()
+            .is_empty()
+        {
+            Some(StorageBackend::Disk)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for StorageBackend {
+    fn default() -> Self {
+        StorageBackend::Disk
+    }
+}
+
+impl fmt::Display for StorageBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageBackend::Disk => write!(f, "disk"),
+            StorageBackend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+//------------ AnyKeyStore ---------------------------------------------------
+
+/// A KeyStore using any of the supported storage backends.
+pub enum AnyKeyStore {
+    Disk(DiskKeyStore),
+    Sqlite(SqliteKeyStore),
+}
+
+impl AnyKeyStore {
+    /// Opens the store for the name_space under the work_dir, using the
+    /// given backend. Returns an error if the name space already holds data
+    /// in another backend.
+    pub fn open(
+        work_dir: &PathBuf,
+        name_space: &str,
+        backend: StorageBackend,
+    ) -> Result<Self, KeyStoreError> {
+        match StorageBackend::detect(work_dir, name_space) {
+            Some(found) if found != backend => Err(KeyStoreError::StorageMismatch(
+                found,
+                name_space.to_string(),
+                backend,
+            )),
+            _ => Self::create(work_dir, name_space, backend),
+        }
+    }
+
+    /// Opens the store for the name_space under the work_dir, using the
+    /// backend which holds its data, or the disk if there is no data. Unlike
+    /// 'open' this does not create a directory for the name space.
+    pub fn existing(work_dir: &PathBuf, name_space: &str) -> Result<Self, KeyStoreError> {
+        match StorageBackend::detect(work_dir, name_space) {
+            Some(StorageBackend::Sqlite) => {
+                Self::create(work_dir, name_space, StorageBackend::Sqlite)
+            }
+            _ => Ok(AnyKeyStore::Disk(DiskKeyStore::new(work_dir, name_space))),
+        }
+    }
+
+    fn create(
+        work_dir: &PathBuf,
+        name_space: &str,
+        backend: StorageBackend,
+    ) -> Result<Self, KeyStoreError> {
+        match backend {
+            StorageBackend::Disk => Ok(AnyKeyStore::Disk(DiskKeyStore::under_work_dir(
+                work_dir, name_space,
+            )?)),
+            StorageBackend::Sqlite => Ok(AnyKeyStore::Sqlite(SqliteKeyStore::under_work_dir(
+                work_dir, name_space,
+            )?)),
+        }
+    }
+
+    pub fn backend(&self) -> StorageBackend {
+        match self {
+            AnyKeyStore::Disk(_) => StorageBackend::Disk,
+            AnyKeyStore::Sqlite(_) => StorageBackend::Sqlite,
+        }
+    }
+
+    /// Moves all data for the name_space under the work_dir to the target
+    /// backend. Returns the number of aggregates moved, which is zero if
+    /// the data already uses the target backend.
+    ///
+    /// The data is removed from the old backend only after it was fully
+    /// written to the new one, and the new backend is picked up only once
+    /// it is complete. So, if the migration is interrupted it can simply be
+    /// done again.
+    pub fn migrate(
+        work_dir: &PathBuf,
+        name_space: &str,
+        target: StorageBackend,
+    ) -> Result<usize, KeyStoreError> {
+        let source = match StorageBackend::detect(work_dir, name_space) {
+            Some(found) if found != target => Self::create(work_dir, name_space, found)?,
+            _ => return Ok(0),
+        };
+        let version = source.get_version()?;
+        let aggregates = source.aggregates();
+
+        match source {
+            AnyKeyStore::Disk(disk) => {
+                let mut tmp = work_dir.clone();
+                tmp.push(name_space);
+                tmp.push(format!("{}.tmp", SQLITE_DB));
+                if tmp.exists() {
+                    fs::remove_file(&tmp)?;
+                }
+
+                let db = SqliteKeyStore::open(tmp.clone())?;
+                db.transaction(|| {
+                    for id in &aggregates {
+                        copy_aggregate(&disk, &db, id)?;
+                    }
+                    db.set_version(&version)
+                })?;
+                std::mem::drop(db);
+
+                let mut path = tmp.clone();
+                path.set_file_name(SQLITE_DB);
+                fs::rename(&tmp, &path)?;
+
+                for id in &aggregates {
+                    disk.remove_aggregate(id)?;
+                }
+            }
+            AnyKeyStore::Sqlite(db) => {
+                let disk = DiskKeyStore::under_work_dir(work_dir, name_space)?;
+                for id in &aggregates {
+                    copy_aggregate(&db, &disk, id)?;
+                }
+                disk.set_version(&version)?;
+
+                let path = db.path().to_path_buf();
+                std::mem::drop(db);
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(aggregates.len())
+    }
+}
+
+/// Copies all values for an aggregate as they are.
+fn copy_aggregate<F, T>(from: &F, to: &T, id: &Handle) -> Result<(), KeyStoreError>
+where
+    F: KeyStore<Key = PathBuf>,
+    T: KeyStore<Key = PathBuf>,
+{
+    for key in from.keys(id, "") {
+        let value: serde_json::Value = from
+            .get(id, &key)?
+            .ok_or_else(|| KeyStoreError::KeyUnknown(key.to_string_lossy().to_string()))?;
+        to.store(id, &key, &value)?;
+    }
+    Ok(())
+}
+
+impl KeyStore for AnyKeyStore {
+    type Key = PathBuf;
+
+    fn get_version(&self) -> Result<KeyStoreVersion, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.get_version(),
+            AnyKeyStore::Sqlite(store) => store.get_version(),
+        }
+    }
+
+    fn set_version(&self, version: &KeyStoreVersion) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.set_version(version),
+            AnyKeyStore::Sqlite(store) => store.set_version(version),
+        }
+    }
+
+    fn key_for_info() -> Self::Key {
+        DiskKeyStore::key_for_info()
+    }
+
+    fn key_for_snapshot() -> Self::Key {
+        DiskKeyStore::key_for_snapshot()
+    }
+
+    fn key_for_previous_snapshot() -> Self::Key {
+        DiskKeyStore::key_for_previous_snapshot()
+    }
+
+    fn key_for_event(version: u64) -> Self::Key {
+        DiskKeyStore::key_for_event(version)
+    }
+
+    fn key_for_command<S: WithStorableDetails>(command: &StoredCommand<S>) -> CommandKey {
+        DiskKeyStore::key_for_command(command)
+    }
+
+    fn keys(&self, id: &Handle, matching: &str) -> Vec<Self::Key> {
+        match self {
+            AnyKeyStore::Disk(store) => store.keys(id, matching),
+            AnyKeyStore::Sqlite(store) => store.keys(id, matching),
+        }
+    }
+
+    fn keys_ascending(&self, id: &Handle, matching: &str) -> Vec<Self::Key> {
+        match self {
+            AnyKeyStore::Disk(store) => store.keys_ascending(id, matching),
+            AnyKeyStore::Sqlite(store) => store.keys_ascending(id, matching),
+        }
+    }
+
+    fn command_keys_ascending(
+        &self,
+        id: &Handle,
+        crit: &CommandHistoryCriteria,
+    ) -> Vec<CommandKey> {
+        match self {
+            AnyKeyStore::Disk(store) => store.command_keys_ascending(id, crit),
+            AnyKeyStore::Sqlite(store) => store.command_keys_ascending(id, crit),
+        }
+    }
+
+    fn has_key(&self, id: &Handle, key: &Self::Key) -> bool {
+        match self {
+            AnyKeyStore::Disk(store) => store.has_key(id, key),
+            AnyKeyStore::Sqlite(store) => store.has_key(id, key),
+        }
+    }
+
+    fn has_aggregate(&self, id: &Handle) -> bool {
+        match self {
+            AnyKeyStore::Disk(store) => store.has_aggregate(id),
+            AnyKeyStore::Sqlite(store) => store.has_aggregate(id),
+        }
+    }
+
+    fn aggregates(&self) -> Vec<Handle> {
+        match self {
+            AnyKeyStore::Disk(store) => store.aggregates(),
+            AnyKeyStore::Sqlite(store) => store.aggregates(),
+        }
+    }
+
+    fn store<V: Any + Serialize>(
+        &self,
+        id: &Handle,
+        key: &Self::Key,
+        value: &V,
+    ) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store(id, key, value),
+            AnyKeyStore::Sqlite(store) => store.store(id, key, value),
+        }
+    }
+
+    fn get<V: Any + Storable>(
+        &self,
+        id: &Handle,
+        key: &Self::Key,
+    ) -> Result<Option<V>, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.get(id, key),
+            AnyKeyStore::Sqlite(store) => store.get(id, key),
+        }
+    }
+
+    fn drop(&self, id: &Handle, key: &Self::Key) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.drop(id, key),
+            AnyKeyStore::Sqlite(store) => store.drop(id, key),
+        }
+    }
+
+    fn value_size(&self, id: &Handle, key: &Self::Key) -> Result<u64, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.value_size(id, key),
+            AnyKeyStore::Sqlite(store) => store.value_size(id, key),
+        }
+    }
+
+    fn move_value(
+        &self,
+        id: &Handle,
+        from: &Self::Key,
+        to: &Self::Key,
+    ) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.move_value(id, from, to),
+            AnyKeyStore::Sqlite(store) => store.move_value(id, from, to),
+        }
+    }
+
+    fn get_event<V: Event>(&self, id: &Handle, version: u64) -> Result<Option<V>, KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.get_event(id, version),
+            AnyKeyStore::Sqlite(store) => store.get_event(id, version),
+        }
+    }
+
+    fn store_event<V: Event>(&self, event: &V) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store_event(event),
+            AnyKeyStore::Sqlite(store) => store.store_event(event),
+        }
+    }
+
+    fn store_command<S: WithStorableDetails>(
+        &self,
+        command: StoredCommand<S>,
+    ) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.store_command(command),
+            AnyKeyStore::Sqlite(store) => store.store_command(command),
+        }
+    }
+
+    fn remove_aggregate(&self, id: &Handle) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.remove_aggregate(id),
+            AnyKeyStore::Sqlite(store) => store.remove_aggregate(id),
+        }
+    }
+
+    fn compact(&self) -> Result<(), KeyStoreError> {
+        match self {
+            AnyKeyStore::Disk(store) => store.compact(),
+            AnyKeyStore::Sqlite(store) => store.compact(),
+        }
+    }
+
+    fn transaction<T, E, F>(&self, op: F) -> Result<T, E>
+    where
+        E: From<KeyStoreError>,
+        F: FnOnce() -> Result<T, E>,
+    {
+        match self {
+            AnyKeyStore::Disk(store) => store.transaction(op),
+            AnyKeyStore::Sqlite(store) => store.transaction(op),
+        }
+    }
+}
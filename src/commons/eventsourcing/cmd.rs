@@ -74,6 +74,7 @@ pub trait Command: fmt::Display {
 pub struct SentCommand<C: CommandDetails> {
     handle: Handle,
     version: Option<u64>,
+    actor: String,
     details: C,
 }
 
@@ -89,6 +90,10 @@ impl<C: CommandDetails> Command for SentCommand<C> {
         self.version
     }
 
+    fn actor(&self) -> &str {
+        &self.actor
+    }
+
     fn store(&self) -> Self::StorableDetails {
         self.details.store()
     }
@@ -99,10 +104,18 @@ impl<C: CommandDetails> SentCommand<C> {
         SentCommand {
             handle: id.clone(),
             version,
+            actor: "krill".to_string(),
             details,
         }
     }
 
+    /// Sets the actor who sent this command, so that it is recorded in the
+    /// command history. Defaults to "krill".
+    pub fn with_actor(mut self, actor: &str) -> Self {
+        self.actor = actor.to_string();
+        self
+    }
+
     pub fn into_details(self) -> C {
         self.details
     }
@@ -172,6 +185,10 @@ impl<S: WithStorableDetails> StoredCommand<S> {
         }
     }
 
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
     pub fn time(&self) -> Time {
         self.time
     }
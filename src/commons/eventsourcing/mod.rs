@@ -11,10 +11,16 @@ pub use self::cmd::{Command, CommandDetails, SentCommand, StoredCommand, WithSto
 
 mod store;
 pub use self::store::{
-    CommandKey, CommandKeyError, DiskKeyStore, KeyStore, KeyStoreError, KeyStoreVersion, Storable,
-    StoredValueInfo,
+    ArchivedCommand, CommandKey, CommandKeyError, DiskKeyStore, KeyStore, KeyStoreError,
+    KeyStoreVersion, SnapshotCheck, SnapshotKind, SnapshotStatus, Storable, StoredValueInfo,
 };
 
+mod sqlite;
+pub use self::sqlite::{SqliteKeyStore, SQLITE_DB};
+
+mod backend;
+pub use self::backend::{AnyKeyStore, StorageBackend};
+
 mod agg_store;
 pub use self::agg_store::{AggregateStore, AggregateStoreError, DiskAggregateStore};
 
@@ -36,7 +42,10 @@ mod tests {
 
     use serde::Serialize;
 
+    use rpki::x509::Time;
+
     use crate::commons::api::{CommandHistoryCriteria, CommandSummary, Handle};
+    use crate::commons::util::file;
     use crate::test;
 
     use super::*;
@@ -278,11 +287,11 @@ mod tests {
         }
     }
 
-    #[test]
-    fn event_sourcing_framework() {
+    fn event_sourcing_framework(backend: StorageBackend) {
         test::test_under_tmp(|d| {
             let counter = Arc::new(EventCounter::default());
-            let mut manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+            let mut manager =
+                DiskAggregateStore::<Person>::with_backend(&d, "person", backend).unwrap();
             manager.add_listener(counter.clone());
 
             let id_alice = unsafe { Handle::from_str_unsafe("alice") };
@@ -340,4 +349,321 @@ mod tests {
             assert_eq!(history.total(), 1);
         })
     }
+
+    #[test]
+    fn event_sourcing_framework_disk() {
+        event_sourcing_framework(StorageBackend::Disk)
+    }
+
+    #[test]
+    fn event_sourcing_framework_sqlite() {
+        event_sourcing_framework(StorageBackend::Sqlite)
+    }
+
+    fn truncate_history(backend: StorageBackend) {
+        test::test_under_tmp(|d| {
+            let manager =
+                DiskAggregateStore::<Person>::with_backend(&d, "person", backend).unwrap();
+
+            let id_alice = unsafe { Handle::from_str_unsafe("alice") };
+            manager
+                .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                .unwrap();
+
+            for _ in 0..12 {
+                let get_older = PersonCommand::go_around_sun(&id_alice, None);
+                manager.command(get_older).unwrap();
+            }
+
+            let store = AnyKeyStore::existing(&d, "person").unwrap();
+            assert_eq!(backend, store.backend());
+            assert_eq!(8, store.truncate_history(&id_alice, 4).unwrap());
+            assert_eq!(0, store.truncate_history(&id_alice, 4).unwrap());
+
+            // Should rebuild state from the remaining history
+            let manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+            let alice = manager.get_latest(&id_alice).unwrap();
+            assert_eq!(4, alice.age());
+            assert_eq!(5, alice.version());
+
+            let get_older = PersonCommand::go_around_sun(&id_alice, None);
+            let alice = manager.command(get_older).unwrap();
+            assert_eq!(5, alice.age());
+
+            let history = manager
+                .command_history(&id_alice, CommandHistoryCriteria::default())
+                .unwrap();
+            assert_eq!(5, history.total());
+            assert_eq!(5, history.commands().last().unwrap().sequence);
+        })
+    }
+
+    #[test]
+    fn truncate_history_disk() {
+        truncate_history(StorageBackend::Disk)
+    }
+
+    #[test]
+    fn truncate_history_sqlite() {
+        truncate_history(StorageBackend::Sqlite)
+    }
+
+    fn archive_history(backend: StorageBackend) {
+        test::test_under_tmp(|d| {
+            let manager =
+                DiskAggregateStore::<Person>::with_backend(&d, "person", backend).unwrap();
+
+            let id_alice = unsafe { Handle::from_str_unsafe("alice") };
+            manager
+                .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                .unwrap();
+
+            for _ in 0..12 {
+                let get_older = PersonCommand::go_around_sun(&id_alice, None);
+                manager.command(get_older).unwrap();
+            }
+            let change_name = PersonCommand::change_name(&id_alice, None, "alice smith-doe");
+            manager.command(change_name).unwrap();
+
+            let archive_dir = d.join("archive");
+            let mut crit = CommandHistoryCriteria::default();
+            crit.set_include(&["person-around-sun"]);
+            crit.set_before(Time::now().timestamp());
+
+            let report = manager.archive_history(&crit, &archive_dir).unwrap();
+            assert_eq!(12, report.commands());
+            assert!(report.reclaimed_bytes() > 0);
+
+            let archived = report.archived().first().unwrap();
+            assert_eq!("commands-1-12.json.gz", archived.segment());
+            let segment = archive_dir.join("alice").join(archived.segment());
+            assert_eq!(
+                archived.archived_bytes(),
+                std::fs::metadata(segment).unwrap().len()
+            );
+
+            let report = manager.archive_history(&crit, &archive_dir).unwrap();
+            assert!(report.archived().is_empty());
+
+            // The archived commands are no longer in the history, but their
+            // events are kept, so the state can still be rebuilt.
+            let history = manager
+                .command_history(&id_alice, CommandHistoryCriteria::default())
+                .unwrap();
+            assert_eq!(1, history.total());
+            assert_eq!(13, history.commands().first().unwrap().sequence);
+
+            let manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+            let alice = manager.get_latest(&id_alice).unwrap();
+            assert_eq!("alice smith-doe", alice.name());
+            assert_eq!(12, alice.age());
+
+            let get_older = PersonCommand::go_around_sun(&id_alice, None);
+            manager.command(get_older).unwrap();
+            let history = manager
+                .command_history(&id_alice, CommandHistoryCriteria::default())
+                .unwrap();
+            assert_eq!(14, history.commands().last().unwrap().sequence);
+        })
+    }
+
+    #[test]
+    fn archive_history_disk() {
+        archive_history(StorageBackend::Disk)
+    }
+
+    #[test]
+    fn archive_history_sqlite() {
+        archive_history(StorageBackend::Sqlite)
+    }
+
+    fn truncate_archived_history(backend: StorageBackend) {
+        test::test_under_tmp(|d| {
+            let manager =
+                DiskAggregateStore::<Person>::with_backend(&d, "person", backend).unwrap();
+
+            let id_alice = unsafe { Handle::from_str_unsafe("alice") };
+            manager
+                .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                .unwrap();
+
+            for _ in 0..12 {
+                let get_older = PersonCommand::go_around_sun(&id_alice, None);
+                manager.command(get_older).unwrap();
+            }
+
+            let archive_dir = d.join("archive");
+            let mut crit = CommandHistoryCriteria::default();
+            crit.set_before(Time::now().timestamp() + 1);
+            manager.archive_history(&crit, &archive_dir).unwrap();
+
+            let store = AnyKeyStore::existing(&d, "person").unwrap();
+            let now = Time::now().timestamp();
+            assert_eq!(12, store.command_sequence_at(&id_alice, now).unwrap());
+            assert!(store.command_time(&id_alice, 4).unwrap().is_some());
+
+            // Events of archived commands after the sequence are removed
+            assert_eq!(8, store.truncate_history(&id_alice, 4).unwrap());
+            assert_eq!(0, store.truncate_history(&id_alice, 4).unwrap());
+
+            let manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+            let alice = manager.get_latest(&id_alice).unwrap();
+            assert_eq!(4, alice.age());
+
+            // Sequences of archived commands are not reused
+            let get_older = PersonCommand::go_around_sun(&id_alice, None);
+            manager.command(get_older).unwrap();
+            let history = manager
+                .command_history(&id_alice, CommandHistoryCriteria::default())
+                .unwrap();
+            assert_eq!(13, history.commands().last().unwrap().sequence);
+
+            // An existing segment is not replaced
+            let segment = archive_dir.join("alice").join("commands-13-13.json.gz");
+            file::save(b"existing", &segment).unwrap();
+            assert!(manager.archive_history(&crit, &archive_dir).is_err());
+            assert_eq!(b"existing".to_vec(), std::fs::read(&segment).unwrap());
+            let history = manager
+                .command_history(&id_alice, CommandHistoryCriteria::default())
+                .unwrap();
+            assert_eq!(1, history.total());
+        })
+    }
+
+    #[test]
+    fn truncate_archived_history_disk() {
+        truncate_archived_history(StorageBackend::Disk)
+    }
+
+    #[test]
+    fn truncate_archived_history_sqlite() {
+        truncate_archived_history(StorageBackend::Sqlite)
+    }
+
+    fn snapshots(backend: StorageBackend) {
+        test::test_under_tmp(|d| {
+            let manager =
+                DiskAggregateStore::<Person>::with_backend(&d, "person", backend).unwrap();
+
+            let id_alice = unsafe { Handle::from_str_unsafe("alice") };
+            manager
+                .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                .unwrap();
+            for _ in 0..12 {
+                let get_older = PersonCommand::go_around_sun(&id_alice, None);
+                manager.command(get_older).unwrap();
+            }
+
+            let store = AnyKeyStore::existing(&d, "person").unwrap();
+            let statuses = |store: &AnyKeyStore| -> Vec<SnapshotStatus> {
+                store
+                    .verify_snapshots::<Person>(&id_alice)
+                    .unwrap()
+                    .into_iter()
+                    .map(|check| check.status().clone())
+                    .collect()
+            };
+            assert_eq!(
+                vec![SnapshotStatus::Valid(10), SnapshotStatus::Valid(5)],
+                statuses(&store)
+            );
+
+            // A snapshot which does not match its events
+            let mut wrong: Person = store
+                .get(&id_alice, &AnyKeyStore::key_for_snapshot())
+                .unwrap()
+                .unwrap();
+            wrong.name = "bob".to_string();
+            store
+                .store(&id_alice, &AnyKeyStore::key_for_snapshot(), &wrong)
+                .unwrap();
+            assert_eq!(
+                vec![SnapshotStatus::Mismatch(10), SnapshotStatus::Valid(5)],
+                statuses(&store)
+            );
+
+            // A snapshot which is ahead of the events is not used
+            wrong.version = 20;
+            store
+                .store(&id_alice, &AnyKeyStore::key_for_snapshot(), &wrong)
+                .unwrap();
+            assert_eq!(
+                vec![SnapshotStatus::AheadOfEvents(20), SnapshotStatus::Valid(5)],
+                statuses(&store)
+            );
+            let manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+            let alice = manager.get_latest(&id_alice).unwrap();
+            assert_eq!("alice smith", alice.name());
+            assert_eq!(12, alice.age());
+
+            // A corrupt snapshot is not used
+            store
+                .store(&id_alice, &AnyKeyStore::key_for_snapshot(), &"corrupt")
+                .unwrap();
+            assert_eq!(
+                vec![SnapshotStatus::Corrupt, SnapshotStatus::Valid(5)],
+                statuses(&store)
+            );
+            let manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+            assert_eq!(12, manager.get_latest(&id_alice).unwrap().age());
+        })
+    }
+
+    #[test]
+    fn snapshots_disk() {
+        snapshots(StorageBackend::Disk)
+    }
+
+    #[test]
+    fn snapshots_sqlite() {
+        snapshots(StorageBackend::Sqlite)
+    }
+
+    #[test]
+    fn migrate_storage() {
+        test::test_under_tmp(|d| {
+            let manager = DiskAggregateStore::<Person>::new(&d, "person").unwrap();
+
+            let id_alice = unsafe { Handle::from_str_unsafe("alice") };
+            let id_bob = unsafe { Handle::from_str_unsafe("bob") };
+            manager
+                .add(InitPersonEvent::init(&id_alice, "alice smith"))
+                .unwrap();
+            manager
+                .add(InitPersonEvent::init(&id_bob, "bob jones"))
+                .unwrap();
+            for _ in 0..7 {
+                let get_older = PersonCommand::go_around_sun(&id_alice, None);
+                manager.command(get_older).unwrap();
+            }
+
+            let sqlite = StorageBackend::Sqlite;
+            assert!(DiskAggregateStore::<Person>::with_backend(&d, "person", sqlite).is_err());
+
+            assert_eq!(2, AnyKeyStore::migrate(&d, "person", sqlite).unwrap());
+            assert_eq!(0, AnyKeyStore::migrate(&d, "person", sqlite).unwrap());
+            assert_eq!(Some(sqlite), StorageBackend::detect(&d, "person"));
+
+            let manager = DiskAggregateStore::<Person>::with_backend(&d, "person", sqlite).unwrap();
+            let alice = manager.get_latest(&id_alice).unwrap();
+            assert_eq!(7, alice.age());
+            assert_eq!(2, manager.list().len());
+
+            let get_older = PersonCommand::go_around_sun(&id_alice, None);
+            manager.command(get_older).unwrap();
+            std::mem::drop(manager);
+
+            let disk = StorageBackend::Disk;
+            assert_eq!(2, AnyKeyStore::migrate(&d, "person", disk).unwrap());
+            assert_eq!(Some(disk), StorageBackend::detect(&d, "person"));
+
+            let manager = DiskAggregateStore::<Person>::with_backend(&d, "person", disk).unwrap();
+            let alice = manager.get_latest(&id_alice).unwrap();
+            assert_eq!(8, alice.age());
+            let history = manager
+                .command_history(&id_alice, CommandHistoryCriteria::default())
+                .unwrap();
+            assert_eq!(8, history.total());
+        })
+    }
 }
@@ -0,0 +1,395 @@
+use std::any::Any;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json;
+
+use rpki::x509::Time;
+
+use crate::commons::api::{CommandHistoryCriteria, Handle};
+use crate::commons::eventsourcing::{
+    CommandKey, DiskKeyStore, Event, KeyStore, KeyStoreError, KeyStoreVersion, Storable,
+    StoredCommand, WithStorableDetails,
+};
+
+/// The name of the database file in the directory of a name space.
+pub const SQLITE_DB: &str = "krill.db";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS entries (
+        aggregate TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        updated INTEGER NOT NULL,
+        PRIMARY KEY (aggregate, key)
+    );
+    CREATE TABLE IF NOT EXISTS meta (
+        name TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+//------------ SqliteKeyStore ------------------------------------------------
+
+/// This type stores and retrieves values in an embedded SQLite database,
+/// using json serialization. It uses the same keys as the DiskKeyStore,
+/// but keeps all aggregates of a name space in a single file, and can
+/// store all changes for a command in one transaction.
+pub struct SqliteKeyStore {
+    path: PathBuf,
+    conn: Mutex<Connection>,
+}
+
+impl SqliteKeyStore {
+    /// Opens, or creates, the database for the name_space under the work_dir.
+    pub fn under_work_dir(work_dir: &Path, name_space: &str) -> Result<Self, KeyStoreError> {
+        let dir = work_dir.join(name_space);
+        if !dir.is_dir() {
+            fs::create_dir_all(&dir)?;
+        }
+        Self::open(dir.join(SQLITE_DB))
+    }
+
+    /// Opens, or creates, the database in the given file.
+    pub fn open(path: PathBuf) -> Result<Self, KeyStoreError> {
+        let conn = Connection::open(&path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteKeyStore {
+            path,
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the time when the init event of the aggregate was stored, in
+    /// seconds since the epoch.
+    pub fn created(&self, id: &Handle) -> Result<Option<i64>, KeyStoreError> {
+        let key = Self::key_for_event(0);
+        let conn = self.conn.lock().unwrap();
+        let updated = conn
+            .query_row(
+                "SELECT updated FROM entries WHERE aggregate = ?1 AND key = ?2",
+                params![id.as_str(), key.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(updated)
+    }
+
+    fn execute(&self, sql: &str) -> Result<(), KeyStoreError> {
+        self.conn.lock().unwrap().execute_batch(sql)?;
+        Ok(())
+    }
+
+    fn get_json(&self, id: &Handle, key: &Path) -> Result<Option<String>, KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let json = conn
+            .query_row(
+                "SELECT value FROM entries WHERE aggregate = ?1 AND key = ?2",
+                params![id.as_str(), key.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(json)
+    }
+}
+
+impl KeyStore for SqliteKeyStore {
+    type Key = PathBuf;
+
+    fn get_version(&self) -> Result<KeyStoreVersion, KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let json: Option<String> = conn
+            .query_row(
+                "SELECT value FROM meta WHERE name = 'version'",
+                params![],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match json {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Ok(KeyStoreVersion::Pre0_6),
+        }
+    }
+
+    fn set_version(&self, version: &KeyStoreVersion) -> Result<(), KeyStoreError> {
+        let json = serde_json::to_string(version)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO meta (name, value) VALUES ('version', ?1)",
+            params![json],
+        )?;
+        Ok(())
+    }
+
+    fn key_for_info() -> Self::Key {
+        DiskKeyStore::key_for_info()
+    }
+
+    fn key_for_snapshot() -> Self::Key {
+        DiskKeyStore::key_for_snapshot()
+    }
+
+    fn key_for_previous_snapshot() -> Self::Key {
+        DiskKeyStore::key_for_previous_snapshot()
+    }
+
+    fn key_for_event(version: u64) -> Self::Key {
+        DiskKeyStore::key_for_event(version)
+    }
+
+    fn key_for_command<S: WithStorableDetails>(command: &StoredCommand<S>) -> CommandKey {
+        DiskKeyStore::key_for_command(command)
+    }
+
+    fn keys(&self, id: &Handle, matching: &str) -> Vec<Self::Key> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = match conn
+            .prepare("SELECT key FROM entries WHERE aggregate = ?1 AND instr(key, ?2) > 0")
+        {
+            Ok(statement) => statement,
+            Err(e) => {
+                error!("Could not query keys for '{}': {}", id, e);
+                return vec![];
+            }
+        };
+        let keys = statement.query_map(params![id.as_str(), matching], |row| {
+            row.get::<_, String>(0)
+        });
+        match keys {
+            Ok(keys) => keys.filter_map(Result::ok).map(PathBuf::from).collect(),
+            Err(e) => {
+                error!("Could not query keys for '{}': {}", id, e);
+                vec![]
+            }
+        }
+    }
+
+    fn keys_ascending(&self, id: &Handle, matching: &str) -> Vec<Self::Key> {
+        let mut res = self.keys(id, matching);
+        res.sort_by(|a, b| a.to_string_lossy().cmp(&b.to_string_lossy()));
+        res
+    }
+
+    fn command_keys_ascending(
+        &self,
+        id: &Handle,
+        crit: &CommandHistoryCriteria,
+    ) -> Vec<CommandKey> {
+        let mut command_keys: Vec<CommandKey> = self
+            .keys(id, "command--")
+            .into_iter()
+            .filter_map(|key| CommandKey::try_from(key).ok())
+            .filter(|key| key.matches_crit(crit))
+            .collect();
+
+        command_keys.sort_by_key(|key| key.sequence());
+        command_keys
+    }
+
+    fn has_key(&self, id: &Handle, key: &Self::Key) -> bool {
+        matches!(self.get_json(id, key), Ok(Some(_)))
+    }
+
+    fn has_aggregate(&self, id: &Handle) -> bool {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT 1 FROM entries WHERE aggregate = ?1 LIMIT 1",
+            params![id.as_str()],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|found| found.is_some())
+        .unwrap_or(false)
+    }
+
+    fn aggregates(&self) -> Vec<Handle> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = match conn.prepare("SELECT DISTINCT aggregate FROM entries") {
+            Ok(statement) => statement,
+            Err(e) => {
+                error!("Could not query aggregates: {}", e);
+                return vec![];
+            }
+        };
+        let ids = statement.query_map(params![], |row| row.get::<_, String>(0));
+        match ids {
+            Ok(ids) => ids
+                .filter_map(Result::ok)
+                .filter_map(|id| Handle::from_str(&id).ok())
+                .collect(),
+            Err(e) => {
+                error!("Could not query aggregates: {}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn store<V: Any + Serialize>(
+        &self,
+        id: &Handle,
+        key: &Self::Key,
+        value: &V,
+    ) -> Result<(), KeyStoreError> {
+        let json = serde_json::to_string(value)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO entries (aggregate, key, value, updated) VALUES (?1, ?2, ?3, ?4)",
+            params![id.as_str(), key.to_string_lossy(), json, Time::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    fn get<V: Any + Storable>(
+        &self,
+        id: &Handle,
+        key: &Self::Key,
+    ) -> Result<Option<V>, KeyStoreError> {
+        match self.get_json(id, key)? {
+            Some(json) => match serde_json::from_str(&json) {
+                Err(e) => {
+                    warn!(
+                        "Could not deserialize json for '{}' in '{}', got error: '{}'. Will fall back to events.",
+                        key.to_string_lossy(), id, e
+                    );
+                    Ok(None)
+                }
+                Ok(v) => Ok(Some(v)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn drop(&self, id: &Handle, key: &Self::Key) -> Result<(), KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute(
+            "DELETE FROM entries WHERE aggregate = ?1 AND key = ?2",
+            params![id.as_str(), key.to_string_lossy()],
+        )?;
+        if removed == 0 {
+            Err(KeyStoreError::KeyUnknown(key.to_string_lossy().to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn value_size(&self, id: &Handle, key: &Self::Key) -> Result<u64, KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let size: Option<i64> = conn
+            .query_row(
+                "SELECT length(CAST(value AS BLOB)) FROM entries WHERE aggregate = ?1 AND key = ?2",
+                params![id.as_str(), key.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(size.unwrap_or(0) as u64)
+    }
+
+    fn move_value(
+        &self,
+        id: &Handle,
+        from: &Self::Key,
+        to: &Self::Key,
+    ) -> Result<(), KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        let moved = conn.execute(
+            "REPLACE INTO entries (aggregate, key, value, updated)
+             SELECT aggregate, ?3, value, updated FROM entries WHERE aggregate = ?1 AND key = ?2",
+            params![id.as_str(), from.to_string_lossy(), to.to_string_lossy()],
+        )?;
+        if moved == 0 {
+            return Err(KeyStoreError::KeyUnknown(
+                from.to_string_lossy().to_string(),
+            ));
+        }
+        conn.execute(
+            "DELETE FROM entries WHERE aggregate = ?1 AND key = ?2",
+            params![id.as_str(), from.to_string_lossy()],
+        )?;
+        Ok(())
+    }
+
+    fn get_event<V: Event>(&self, id: &Handle, version: u64) -> Result<Option<V>, KeyStoreError> {
+        let key = Self::key_for_event(version);
+        match self.get_json(id, &key)? {
+            Some(json) => match serde_json::from_str(&json) {
+                Err(e) => {
+                    error!(
+                        "Could not deserialize event {} for '{}': {}",
+                        version, id, e
+                    );
+                    Err(KeyStoreError::JsonError(e))
+                }
+                Ok(v) => Ok(Some(v)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn store_event<V: Event>(&self, event: &V) -> Result<(), KeyStoreError> {
+        trace!("Storing event: {}", event);
+
+        let id = event.handle();
+        let key = Self::key_for_event(event.version());
+        if self.has_key(id, &key) {
+            Err(KeyStoreError::KeyExists(key.to_string_lossy().to_string()))
+        } else {
+            self.store(id, &key, event)
+        }
+    }
+
+    fn store_command<S: WithStorableDetails>(
+        &self,
+        command: StoredCommand<S>,
+    ) -> Result<(), KeyStoreError> {
+        let id = command.handle();
+        let key = Self::key_for_command(&command).into();
+
+        if self.has_key(id, &key) {
+            Err(KeyStoreError::KeyExists(key.to_string_lossy().to_string()))
+        } else {
+            self.store(id, &key, &command)
+        }
+    }
+
+    fn remove_aggregate(&self, id: &Handle) -> Result<(), KeyStoreError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM entries WHERE aggregate = ?1",
+            params![id.as_str()],
+        )?;
+        Ok(())
+    }
+
+    fn compact(&self) -> Result<(), KeyStoreError> {
+        self.execute("VACUUM")
+    }
+
+    fn transaction<T, E, F>(&self, op: F) -> Result<T, E>
+    where
+        E: From<KeyStoreError>,
+        F: FnOnce() -> Result<T, E>,
+    {
+        self.execute("BEGIN IMMEDIATE")?;
+        match op() {
+            Ok(res) => {
+                self.execute("COMMIT")?;
+                Ok(res)
+            }
+            Err(e) => {
+                if let Err(rollback) = self.execute("ROLLBACK") {
+                    error!("Could not roll back transaction: {}", rollback);
+                }
+                Err(e)
+            }
+        }
+    }
+}
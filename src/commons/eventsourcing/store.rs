@@ -3,10 +3,11 @@ use std::convert::{TryFrom, TryInto};
 use std::fs::File;
 use std::io;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::{fmt, fs};
 
+use libflate::gzip::Encoder;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
@@ -14,9 +15,11 @@ use serde_json;
 use rpki::x509::Time;
 
 use crate::commons::api::{
-    CommandHistory, CommandHistoryCriteria, CommandHistoryRecord, Handle, Label,
+    ArchivedHistory, CommandHistory, CommandHistoryCriteria, CommandHistoryRecord, Handle, Label,
+};
+use crate::commons::eventsourcing::{
+    Aggregate, Event, StorageBackend, StoredCommand, WithStorableDetails,
 };
-use crate::commons::eventsourcing::{Aggregate, Event, StoredCommand, WithStorableDetails};
 use crate::commons::util::file;
 
 //------------ Storable ------------------------------------------------------
@@ -30,6 +33,17 @@ pub struct StoredValueInfo {
     pub last_event: u64,
     pub last_command: u64,
     pub last_update: Time,
+
+    /// The commands which were moved to an archive segment. They are no
+    /// longer in the store, but their events are.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archived_commands: Vec<ArchivedCommand>,
+
+    /// The highest sequence of any command that was ever archived. This
+    /// sequence is not reused, even if the history is truncated to an
+    /// earlier command, so that archive segments are never replaced.
+    #[serde(default)]
+    pub last_archived_command: u64,
 }
 
 impl Default for StoredValueInfo {
@@ -39,15 +53,26 @@ impl Default for StoredValueInfo {
             last_event: 0,
             last_command: 0,
             last_update: Time::now(),
+            archived_commands: vec![],
+            last_archived_command: 0,
         }
     }
 }
 
+/// The sequence, time and aggregate version of an archived command.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ArchivedCommand {
+    pub sequence: u64,
+    pub timestamp_secs: i64,
+    pub version: u64,
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum KeyStoreVersion {
     Pre0_6,
     V0_6,
     V0_7,
+    V0_8,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -66,6 +91,14 @@ impl CommandKey {
         }
     }
 
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn timestamp_secs(&self) -> i64 {
+        self.timestamp_secs
+    }
+
     pub fn matches_crit(&self, crit: &CommandHistoryCriteria) -> bool {
         crit.matches_timestamp_secs(self.timestamp_secs) && crit.matches_label(&self.label)
     }
@@ -132,6 +165,13 @@ impl TryFrom<PathBuf> for CommandKey {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct CommandKeyError;
 
+/// The aggregate version of a stored command, used when truncating history
+/// without knowing the type of the command details.
+#[derive(Clone, Deserialize, Serialize)]
+struct StoredCommandVersion {
+    version: u64,
+}
+
 //------------ KeyStore ------------------------------------------------------
 
 /// Generic KeyStore for AggregateManager
@@ -143,6 +183,7 @@ pub trait KeyStore {
 
     fn key_for_info() -> Self::Key;
     fn key_for_snapshot() -> Self::Key;
+    fn key_for_previous_snapshot() -> Self::Key;
     fn key_for_event(version: u64) -> Self::Key;
     fn key_for_command<S: WithStorableDetails>(command: &StoredCommand<S>) -> CommandKey;
 
@@ -155,6 +196,42 @@ pub trait KeyStore {
     fn command_keys_ascending(&self, id: &Handle, crit: &CommandHistoryCriteria)
         -> Vec<CommandKey>;
 
+    /// Returns the sequence of the last command for an aggregate at the
+    /// given time, including archived commands, or 0 if there is none.
+    fn command_sequence_at(&self, id: &Handle, time_secs: i64) -> Result<u64, KeyStoreError> {
+        let stored = self
+            .command_keys_ascending(id, &CommandHistoryCriteria::default())
+            .into_iter()
+            .filter(|key| key.timestamp_secs <= time_secs)
+            .map(|key| key.sequence);
+        let info = self.get_info(id)?;
+        let archived = info
+            .archived_commands
+            .iter()
+            .filter(|cmd| cmd.timestamp_secs <= time_secs)
+            .map(|cmd| cmd.sequence);
+        Ok(stored.chain(archived).max().unwrap_or(0))
+    }
+
+    /// Returns the time of the command with the given sequence, including
+    /// archived commands, if there is such a command.
+    fn command_time(&self, id: &Handle, sequence: u64) -> Result<Option<i64>, KeyStoreError> {
+        let stored = self
+            .command_keys_ascending(id, &CommandHistoryCriteria::default())
+            .into_iter()
+            .find(|key| key.sequence == sequence)
+            .map(|key| key.timestamp_secs);
+        match stored {
+            Some(time) => Ok(Some(time)),
+            None => Ok(self
+                .get_info(id)?
+                .archived_commands
+                .iter()
+                .find(|cmd| cmd.sequence == sequence)
+                .map(|cmd| cmd.timestamp_secs)),
+        }
+    }
+
     /// Returns whether a key already exists.
     fn has_key(&self, id: &Handle, key: &Self::Key) -> bool;
 
@@ -192,6 +269,30 @@ pub trait KeyStore {
     /// Drop the value for this key
     fn drop(&self, id: &Handle, key: &Self::Key) -> Result<(), KeyStoreError>;
 
+    /// Returns the size in bytes of the value stored for a key, or zero if
+    /// there is no such value.
+    fn value_size(&self, id: &Handle, key: &Self::Key) -> Result<u64, KeyStoreError> {
+        let value: Option<serde_json::Value> = self.get(id, key)?;
+        match value {
+            Some(value) => Ok(serde_json::to_vec(&value)?.len() as u64),
+            None => Ok(0),
+        }
+    }
+
+    /// Moves the value for a key to another key, replacing any value there.
+    fn move_value(
+        &self,
+        id: &Handle,
+        from: &Self::Key,
+        to: &Self::Key,
+    ) -> Result<(), KeyStoreError> {
+        let value: serde_json::Value = self
+            .get(id, from)?
+            .ok_or_else(|| KeyStoreError::KeyUnknown("value to move".to_string()))?;
+        self.store(id, to, &value)?;
+        self.drop(id, from)
+    }
+
     /// Get the value for this key, if any exists.
     fn get_event<V: Event>(&self, id: &Handle, version: u64) -> Result<Option<V>, KeyStoreError>;
 
@@ -203,12 +304,303 @@ pub trait KeyStore {
         command: StoredCommand<S>,
     ) -> Result<(), KeyStoreError>;
 
+    /// Saves the latest snapshot, and keeps the snapshot it replaces as the
+    /// previous snapshot, in case the latest cannot be used.
+    fn store_snapshot<V: Aggregate>(
+        &self,
+        id: &Handle,
+        aggregate: &V,
+    ) -> Result<(), KeyStoreError> {
+        let key = Self::key_for_snapshot();
+        if self.has_key(id, &key) {
+            self.move_value(id, &key, &Self::key_for_previous_snapshot())?;
+        }
+        self.store(id, &key, aggregate)
+    }
+
+    /// Removes all values for an aggregate.
+    fn remove_aggregate(&self, id: &Handle) -> Result<(), KeyStoreError>;
+
+    /// Returns space freed up by removed values to the file system, if the
+    /// store does not do this by itself.
+    fn compact(&self) -> Result<(), KeyStoreError> {
+        Ok(())
+    }
+
+    /// Runs the operation as a single transaction, if the store supports
+    /// this: either all values it stores are kept, or, if it returns an
+    /// error, none are.
+    ///
+    /// Values stored in the transaction may be seen by other readers of the
+    /// store before it is committed, so callers must keep readers out while
+    /// it runs, as the aggregate store does with its lock.
+    fn transaction<T, E, F>(&self, op: F) -> Result<T, E>
+    where
+        E: From<KeyStoreError>,
+        F: FnOnce() -> Result<T, E>,
+    {
+        op()
+    }
+
     /// Get the latest aggregate
-    fn get_aggregate<V: Aggregate>(&self, id: &Handle) -> Result<Option<V>, KeyStoreError>;
+    fn get_aggregate<V: Aggregate>(&self, id: &Handle) -> Result<Option<V>, KeyStoreError> {
+        // Try to get the newest valid snapshot.
+        // If that fails, try to get the init event.
+        // Then replay all newer events that can be found.
+        let mut aggregate_opt = None;
+        for key in &[Self::key_for_snapshot(), Self::key_for_previous_snapshot()] {
+            if let Some(aggregate) = self.get::<V>(id, key)? {
+                if self.has_events_for_snapshot(id, &aggregate) {
+                    aggregate_opt = Some(aggregate);
+                    break;
+                }
+                warn!(
+                    "Ignoring snapshot for '{}' at version {}, which is ahead of its events.",
+                    id,
+                    aggregate.version()
+                );
+            }
+        }
+
+        if aggregate_opt.is_none() {
+            aggregate_opt = match self.get_event::<V::InitEvent>(id, 0)? {
+                Some(e) => Some(V::init(e).map_err(|_| KeyStoreError::InitError)?),
+                None => None,
+            };
+        }
+
+        match aggregate_opt {
+            None => Ok(None),
+            Some(mut aggregate) => {
+                self.update_aggregate(id, &mut aggregate)?;
+                Ok(Some(aggregate))
+            }
+        }
+    }
+
+    /// Returns whether the events that a snapshot was built from exist. If
+    /// not, the snapshot was saved for history which is no longer there.
+    fn has_events_for_snapshot<V: Aggregate>(&self, id: &Handle, snapshot: &V) -> bool {
+        match snapshot.version() {
+            0 => false,
+            version => self.has_key(id, &Self::key_for_event(version - 1)),
+        }
+    }
+
+    /// Rebuilds an aggregate from its init event, replaying events up to the
+    /// given version, or fewer if there are no more events.
+    fn replay<V: Aggregate>(&self, id: &Handle, version: u64) -> Result<Option<V>, KeyStoreError> {
+        let mut aggregate = match self.get_event::<V::InitEvent>(id, 0)? {
+            Some(e) => V::init(e).map_err(|_| KeyStoreError::InitError)?,
+            None => return Ok(None),
+        };
+        while aggregate.version() < version {
+            match self.get_event(id, aggregate.version())? {
+                Some(e) => aggregate.apply(e),
+                None => break,
+            }
+        }
+        Ok(Some(aggregate))
+    }
+
+    /// Checks the snapshots for an aggregate against a replay of all its
+    /// events up to the version of each snapshot.
+    fn verify_snapshots<V: Aggregate>(
+        &self,
+        id: &Handle,
+    ) -> Result<Vec<SnapshotCheck>, KeyStoreError> {
+        let mut res = vec![];
+        let snapshots = [
+            (SnapshotKind::Latest, Self::key_for_snapshot()),
+            (SnapshotKind::Previous, Self::key_for_previous_snapshot()),
+        ];
+        for (kind, key) in snapshots.iter() {
+            if !self.has_key(id, key) {
+                continue;
+            }
+            let status = match self.get::<V>(id, key)? {
+                None => SnapshotStatus::Corrupt,
+                Some(snapshot) => {
+                    let version = snapshot.version();
+                    match self.replay::<V>(id, version)? {
+                        Some(replayed) if replayed.version() == version => {
+                            if serde_json::to_value(&replayed)? == serde_json::to_value(&snapshot)?
+                            {
+                                SnapshotStatus::Valid(version)
+                            } else {
+                                SnapshotStatus::Mismatch(version)
+                            }
+                        }
+                        _ => SnapshotStatus::AheadOfEvents(version),
+                    }
+                }
+            };
+            res.push(SnapshotCheck {
+                handle: id.clone(),
+                kind: *kind,
+                status,
+            });
+        }
+        Ok(res)
+    }
+
+    /// Applies all events after the current version of the aggregate.
+    fn update_aggregate<A: Aggregate>(
+        &self,
+        id: &Handle,
+        aggregate: &mut A,
+    ) -> Result<(), KeyStoreError> {
+        while let Some(e) = self.get_event(id, aggregate.version())? {
+            aggregate.apply(e);
+        }
+        Ok(())
+    }
+
+    /// Removes all commands for an aggregate after the given command
+    /// sequence, and the events that followed from them, so that the
+    /// aggregate is rebuilt from its history up to and including that
+    /// command. The snapshots are removed as well, because they may include
+    /// later events. Returns the number of commands removed.
+    ///
+    /// Archived commands after the sequence are removed from the history
+    /// as well, but their archive segments are kept.
+    fn truncate_history(&self, id: &Handle, sequence: u64) -> Result<usize, KeyStoreError> {
+        let crit = CommandHistoryCriteria::default();
+        let removed: Vec<CommandKey> = self
+            .command_keys_ascending(id, &crit)
+            .into_iter()
+            .filter(|key| key.sequence > sequence)
+            .collect();
+
+        let mut info = self.get_info(id)?;
+        let archived_removed = info
+            .archived_commands
+            .iter()
+            .filter(|cmd| cmd.sequence > sequence)
+            .count();
+
+        if removed.is_empty() && archived_removed == 0 {
+            return Ok(0);
+        }
+
+        // The version of the aggregate that the first removed command was
+        // applied to is the version of the first event to remove.
+        let mut first_version = info
+            .archived_commands
+            .iter()
+            .filter(|cmd| cmd.sequence > sequence)
+            .map(|cmd| cmd.version)
+            .min();
+        for key in removed.iter().cloned() {
+            let key = Self::Key::from(key);
+            let command: StoredCommandVersion = self
+                .get(id, &key)?
+                .ok_or_else(|| KeyStoreError::CommandNotFound)?;
+            first_version = match first_version {
+                Some(v) if v <= command.version => Some(v),
+                _ => Some(command.version),
+            };
+            self.drop(id, &key)?;
+        }
+
+        let first_version = first_version.unwrap_or(0);
+        let mut version = first_version;
+        while self.has_key(id, &Self::key_for_event(version)) {
+            self.drop(id, &Self::key_for_event(version))?;
+            version += 1;
+        }
+
+        for snapshot in &[Self::key_for_snapshot(), Self::key_for_previous_snapshot()] {
+            if self.has_key(id, snapshot) {
+                self.drop(id, snapshot)?;
+            }
+        }
 
-    /// Saves the latest snapshot - overwrites any previous snapshot.
-    fn store_snapshot<V: Aggregate>(&self, id: &Handle, aggregate: &V)
-        -> Result<(), KeyStoreError>;
+        info.snapshot_version = 0;
+        info.last_event = first_version.saturating_sub(1);
+        info.last_command = sequence.max(info.last_archived_command);
+        info.last_update = Time::now();
+        info.archived_commands
+            .retain(|cmd| cmd.sequence <= sequence);
+        self.save_info(id, &info)?;
+
+        Ok(removed.len() + archived_removed)
+    }
+
+    /// Moves all commands matching the criteria out of the history of an
+    /// aggregate, into a gzip compressed json segment under the archive
+    /// directory. Returns None if there were no such commands.
+    ///
+    /// Only the commands are archived. Their events are still needed to
+    /// rebuild the aggregate, so they are kept. The sequence, time and
+    /// version of the archived commands are kept in the info for the
+    /// aggregate, so that its history can still be truncated to a point
+    /// in time. An existing segment is never replaced.
+    fn archive_commands(
+        &self,
+        id: &Handle,
+        crit: &CommandHistoryCriteria,
+        archive_dir: &Path,
+    ) -> Result<Option<ArchivedHistory>, KeyStoreError> {
+        let keys = self.command_keys_ascending(id, crit);
+        let segment = match (keys.first(), keys.last()) {
+            (Some(first), Some(last)) => {
+                format!("commands-{}-{}.json.gz", first.sequence, last.sequence)
+            }
+            _ => return Ok(None),
+        };
+
+        let path = archive_dir.join(id.to_path_buf()).join(&segment);
+        if path.exists() {
+            return Err(KeyStoreError::KeyExists(path.to_string_lossy().to_string()));
+        }
+
+        let mut commands: Vec<serde_json::Value> = Vec::with_capacity(keys.len());
+        let mut archived = Vec::with_capacity(keys.len());
+        let mut stored_bytes = 0;
+        for key in keys.iter() {
+            let stored_key = Self::Key::from(key.clone());
+            let command: serde_json::Value = self
+                .get(id, &stored_key)?
+                .ok_or_else(|| KeyStoreError::CommandNotFound)?;
+            let version: StoredCommandVersion = serde_json::from_value(command.clone())?;
+            archived.push(ArchivedCommand {
+                sequence: key.sequence,
+                timestamp_secs: key.timestamp_secs,
+                version: version.version,
+            });
+            commands.push(command);
+            stored_bytes += self.value_size(id, &stored_key)?;
+        }
+
+        // Write the segment in full before any command is removed, so that
+        // nothing is lost if this is interrupted.
+        let tmp = path.with_extension("tmp");
+        let mut encoder = Encoder::new(file::create_file_with_path(&tmp)?)?;
+        serde_json::to_writer(&mut encoder, &commands)?;
+        encoder.finish().into_result()?.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        let archived_bytes = fs::metadata(&path)?.len();
+
+        self.transaction(|| -> Result<(), KeyStoreError> {
+            for key in keys.iter().cloned() {
+                self.drop(id, &key.into())?;
+            }
+            let mut info = self.get_info(id)?;
+            let last = archived.iter().map(|cmd| cmd.sequence).max().unwrap_or(0);
+            info.last_archived_command = info.last_archived_command.max(last);
+            info.archived_commands.extend(archived);
+            self.save_info(id, &info)
+        })?;
+
+        Ok(Some(ArchivedHistory::new(
+            id.clone(),
+            segment,
+            keys.len(),
+            stored_bytes,
+            archived_bytes,
+        )))
+    }
 
     /// Find all commands that fit the criteria and return history
     fn command_history<A: Aggregate>(
@@ -240,6 +632,68 @@ pub trait KeyStore {
     }
 }
 
+//------------ SnapshotCheck -------------------------------------------------
+
+/// The outcome of checking a snapshot against a replay of all events.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SnapshotCheck {
+    handle: Handle,
+    kind: SnapshotKind,
+    status: SnapshotStatus,
+}
+
+impl SnapshotCheck {
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    pub fn kind(&self) -> SnapshotKind {
+        self.kind
+    }
+
+    pub fn status(&self) -> &SnapshotStatus {
+        &self.status
+    }
+
+    pub fn is_valid(&self) -> bool {
+        matches!(self.status, SnapshotStatus::Valid(_))
+    }
+}
+
+impl fmt::Display for SnapshotCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} snapshot for '{}': {}",
+            self.kind, self.handle, self.status
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, Display, Eq, PartialEq)]
+pub enum SnapshotKind {
+    #[display(fmt = "latest")]
+    Latest,
+
+    #[display(fmt = "previous")]
+    Previous,
+}
+
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum SnapshotStatus {
+    #[display(fmt = "valid at version {}", _0)]
+    Valid(u64),
+
+    #[display(fmt = "cannot be read")]
+    Corrupt,
+
+    #[display(fmt = "version {} is ahead of the events", _0)]
+    AheadOfEvents(u64),
+
+    #[display(fmt = "does not match replay of events up to version {}", _0)]
+    Mismatch(u64),
+}
+
 //------------ KeyStoreError -------------------------------------------------
 
 /// This type defines possible Errors for KeyStore
@@ -251,6 +705,9 @@ pub enum KeyStoreError {
     #[display(fmt = "{}", _0)]
     JsonError(serde_json::Error),
 
+    #[display(fmt = "{}", _0)]
+    SqliteError(rusqlite::Error),
+
     #[display(fmt = "Key '{}' already exists", _0)]
     KeyExists(String),
 
@@ -271,6 +728,14 @@ pub enum KeyStoreError {
 
     #[display(fmt = "StoredCommand offset out of bounds")]
     CommandOffSetError,
+
+    #[display(
+        fmt = "Found {} storage for '{}', but {} is configured. Use 'krill --migrate-storage' to convert it.",
+        _0,
+        _1,
+        _2
+    )]
+    StorageMismatch(StorageBackend, String, StorageBackend),
 }
 
 impl From<io::Error> for KeyStoreError {
@@ -285,6 +750,12 @@ impl From<serde_json::Error> for KeyStoreError {
     }
 }
 
+impl From<rusqlite::Error> for KeyStoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        KeyStoreError::SqliteError(e)
+    }
+}
+
 impl std::error::Error for KeyStoreError {}
 
 //------------ DiskKeyStore --------------------------------------------------
@@ -335,6 +806,10 @@ impl KeyStore for DiskKeyStore {
         PathBuf::from("snapshot.json")
     }
 
+    fn key_for_previous_snapshot() -> Self::Key {
+        PathBuf::from("snapshot-previous.json")
+    }
+
     fn key_for_event(version: u64) -> Self::Key {
         PathBuf::from(format!("delta-{}.json", version))
     }
@@ -466,6 +941,24 @@ impl KeyStore for DiskKeyStore {
         }
     }
 
+    fn value_size(&self, id: &Handle, key: &Self::Key) -> Result<u64, KeyStoreError> {
+        match fs::metadata(self.file_path(id, key)) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(KeyStoreError::IoError(e)),
+        }
+    }
+
+    fn move_value(
+        &self,
+        id: &Handle,
+        from: &Self::Key,
+        to: &Self::Key,
+    ) -> Result<(), KeyStoreError> {
+        fs::rename(self.file_path(id, from), self.file_path(id, to))?;
+        Ok(())
+    }
+
     /// Get the value for this key, if any exists.
     fn get_event<V: Event>(&self, id: &Handle, version: u64) -> Result<Option<V>, KeyStoreError> {
         let path = self.path_for_event(id, version);
@@ -516,35 +1009,9 @@ impl KeyStore for DiskKeyStore {
         }
     }
 
-    fn get_aggregate<V: Aggregate>(&self, id: &Handle) -> Result<Option<V>, KeyStoreError> {
-        // try to get a snapshot.
-        // If that fails, try to get the init event.
-        // Then replay all newer events that can be found.
-        let key = Self::key_for_snapshot();
-        let aggregate_opt = match self.get::<V>(id, &key)? {
-            Some(aggregate) => Some(aggregate),
-            None => match self.get_event::<V::InitEvent>(id, 0)? {
-                Some(e) => Some(V::init(e).map_err(|_| KeyStoreError::InitError)?),
-                None => None,
-            },
-        };
-
-        match aggregate_opt {
-            None => Ok(None),
-            Some(mut aggregate) => {
-                self.update_aggregate(id, &mut aggregate)?;
-                Ok(Some(aggregate))
-            }
-        }
-    }
-
-    fn store_snapshot<V: Aggregate>(
-        &self,
-        id: &Handle,
-        aggregate: &V,
-    ) -> Result<(), KeyStoreError> {
-        let key = Self::key_for_snapshot();
-        self.store(id, &key, aggregate)
+    fn remove_aggregate(&self, id: &Handle) -> Result<(), KeyStoreError> {
+        fs::remove_dir_all(self.dir_for_aggregate(id))?;
+        Ok(())
     }
 }
 
@@ -588,15 +1055,4 @@ impl DiskKeyStore {
         file_path.push(format!("delta-{}.json", version));
         file_path
     }
-
-    pub fn update_aggregate<A: Aggregate>(
-        &self,
-        id: &Handle,
-        aggregate: &mut A,
-    ) -> Result<(), KeyStoreError> {
-        while let Some(e) = self.get_event(id, aggregate.version())? {
-            aggregate.apply(e);
-        }
-        Ok(())
-    }
 }
@@ -113,6 +113,23 @@ pub async fn get_text(uri: &str, token: Option<&Token>) -> Result<String, Error>
     }
 }
 
+/// Performs a get request and returns the binary response body.
+pub async fn get_binary(uri: &str, token: Option<&Token>) -> Result<Bytes, Error> {
+    if env::var(KRILL_CLI_API_ENV).is_ok() {
+        report_get_and_exit(uri, token);
+    }
+
+    let headers = headers(None, token)?;
+    let res = client(uri).await?.get(uri).headers(headers).send().await?;
+    match res.status() {
+        StatusCode::OK => Ok(res.bytes().await?),
+        _ => {
+            opt_text_response(res).await?; // Will return nice errors with possible body.
+            Err(Error::EmptyResponse)
+        }
+    }
+}
+
 /// Checks that there is a 200 OK response at the given URI. Discards the
 /// response body.
 pub async fn get_ok(uri: &str, token: Option<&Token>) -> Result<(), Error> {
@@ -195,6 +212,21 @@ pub async fn post_empty(uri: &str, token: Option<&Token>) -> Result<(), Error> {
     }
 }
 
+/// Performs a POST with no data to the given URI and expects a json response
+/// that can be deserialized into the an owned value of the expected type.
+pub async fn post_empty_with_response<T: DeserializeOwned>(
+    uri: &str,
+    token: Option<&Token>,
+) -> Result<T, Error> {
+    if env::var(KRILL_CLI_API_ENV).is_ok() {
+        report_post_and_exit(uri, None, token, PostBody::String(&"<empty>".to_string()));
+    }
+
+    let headers = headers(Some(JSON_CONTENT), token)?;
+    let res = client(uri).await?.post(uri).headers(headers).send().await?;
+    process_json_response(res).await
+}
+
 /// Posts binary data, and expects a binary response.
 ///
 /// Note: Bytes may be empty if the post was successful, but the response was
@@ -0,0 +1,300 @@
+//! Encryption of the key files kept by the OpenSslSigner, using a key
+//! encryption key (KEK) derived from a passphrase.
+//!
+//! The KEK is derived using PBKDF2-HMAC-SHA256 with a random salt, and key
+//! files are encrypted using AES-256-GCM. The salt is kept in a file in the
+//! keys directory, together with a known value encrypted under the KEK, so
+//! that a wrong passphrase is detected when the key store is unlocked.
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs};
+
+use bytes::Bytes;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use crate::commons::util::ext_serde;
+use crate::commons::util::file;
+use crate::commons::util::softsigner::SignerError;
+
+/// The file in the keys directory which holds the KEK salt and check value.
+/// Its presence means that the key store is encrypted.
+pub const KEK_INFO_FILE: &str = "kek.json";
+
+const KEK_CHECK_VALUE: &[u8] = b"krill key store";
+const KEK_CHECK_AAD: &[u8] = b"check";
+const KEK_ITERATIONS: u32 = 100_000;
+const KEK_SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+//------------ KeyEncryptionConfig -------------------------------------------
+
+/// The key encryption configuration, as set in krill.conf:
+///
+/// ```toml
+/// [key_encryption]
+/// passphrase_env = "KRILL_KEY_PASSPHRASE"
+/// ```
+///
+/// The passphrase is taken from exactly one of 'passphrase', 'passphrase_file'
+/// (the first line of the file) or 'passphrase_env' (the named environment
+/// variable).
+#[derive(Clone, Default, Deserialize, Eq, PartialEq)]
+pub struct KeyEncryptionConfig {
+    #[serde(default)]
+    pub passphrase: Option<String>,
+
+    #[serde(default)]
+    pub passphrase_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub passphrase_env: Option<String>,
+}
+
+impl KeyEncryptionConfig {
+    pub fn from_file(passphrase_file: PathBuf) -> Self {
+        KeyEncryptionConfig {
+            passphrase_file: Some(passphrase_file),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_env(passphrase_env: String) -> Self {
+        KeyEncryptionConfig {
+            passphrase_env: Some(passphrase_env),
+            ..Default::default()
+        }
+    }
+
+    /// Checks that exactly one source for the passphrase is given.
+    pub fn verify(&self) -> Result<(), String> {
+        let sources = [
+            self.passphrase.is_some(),
+            self.passphrase_file.is_some(),
+            self.passphrase_env.is_some(),
+        ];
+        if sources.iter().filter(|set| **set).count() != 1 {
+            return Err(
+                "exactly one of passphrase, passphrase_file or passphrase_env must be set"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    fn passphrase(&self) -> Result<String, SignerError> {
+        let passphrase = if let Some(passphrase) = &self.passphrase {
+            passphrase.clone()
+        } else if let Some(path) = &self.passphrase_file {
+            let content = fs::read_to_string(path)?;
+            content.lines().next().unwrap_or("").to_string()
+        } else if let Some(var) = &self.passphrase_env {
+            env::var(var).map_err(|_| {
+                SignerError::KeyStoreError(format!("environment variable '{}' is not set", var))
+            })?
+        } else {
+            return Err(SignerError::KeyStoreError(
+                "no passphrase configured".to_string(),
+            ));
+        };
+
+        if passphrase.is_empty() {
+            Err(SignerError::KeyStoreError(
+                "passphrase is empty".to_string(),
+            ))
+        } else {
+            Ok(passphrase)
+        }
+    }
+}
+
+/// Do not show the passphrase in debug output.
+impl fmt::Debug for KeyEncryptionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeyEncryptionConfig")
+            .field("passphrase_file", &self.passphrase_file)
+            .field("passphrase_env", &self.passphrase_env)
+            .finish()
+    }
+}
+
+//------------ KekInfo -------------------------------------------------------
+
+/// The salt and check value for the KEK, saved in the keys directory.
+#[derive(Deserialize, Serialize)]
+struct KekInfo {
+    #[serde(deserialize_with = "ext_serde::de_bytes")]
+    #[serde(serialize_with = "ext_serde::ser_bytes")]
+    salt: Bytes,
+    iterations: u32,
+    check: EncryptedData,
+}
+
+//------------ KeyEncryptionKey ----------------------------------------------
+
+/// The key used to encrypt and decrypt key files.
+#[derive(Clone)]
+pub struct KeyEncryptionKey {
+    key: [u8; KEY_LEN],
+}
+
+impl KeyEncryptionKey {
+    /// Returns true if the key store in the keys directory is encrypted.
+    pub fn is_encrypted(keys_dir: &Path) -> bool {
+        keys_dir.join(KEK_INFO_FILE).exists()
+    }
+
+    /// Unlocks an encrypted key store, failing if the passphrase is wrong.
+    pub fn unlock(keys_dir: &Path, config: &KeyEncryptionConfig) -> Result<Self, SignerError> {
+        let info: KekInfo = file::load_json(&keys_dir.join(KEK_INFO_FILE))?;
+        let kek = Self::derive(&config.passphrase()?, &info.salt, info.iterations)?;
+
+        match kek.decrypt(KEK_CHECK_AAD, &info.check) {
+            Ok(ref value) if value.as_slice() == KEK_CHECK_VALUE => Ok(kek),
+            _ => Err(SignerError::KeyStoreError(
+                "incorrect passphrase for key store".to_string(),
+            )),
+        }
+    }
+
+    /// Creates a new KEK with a random salt, and saves its salt and check
+    /// value in the given directory.
+    pub fn create(dir: &Path, config: &KeyEncryptionConfig) -> Result<Self, SignerError> {
+        let mut salt = [0; KEK_SALT_LEN];
+        rand_bytes(&mut salt)?;
+
+        let kek = Self::derive(&config.passphrase()?, &salt, KEK_ITERATIONS)?;
+        let info = KekInfo {
+            salt: Bytes::copy_from_slice(&salt),
+            iterations: KEK_ITERATIONS,
+            check: kek.encrypt(KEK_CHECK_AAD, KEK_CHECK_VALUE)?,
+        };
+        file::save_json(&info, &dir.join(KEK_INFO_FILE))?;
+
+        Ok(kek)
+    }
+
+    fn derive(passphrase: &str, salt: &[u8], iterations: u32) -> Result<Self, SignerError> {
+        let mut key = [0; KEY_LEN];
+        pbkdf2_hmac(
+            passphrase.as_bytes(),
+            salt,
+            iterations as usize,
+            MessageDigest::sha256(),
+            &mut key,
+        )?;
+        Ok(KeyEncryptionKey { key })
+    }
+
+    /// Encrypts data. The additional authenticated data (e.g. the name of the
+    /// key file) is not included in the result, but must be given again to
+    /// decrypt it.
+    pub fn encrypt(&self, aad: &[u8], data: &[u8]) -> Result<EncryptedData, SignerError> {
+        let mut nonce = [0; NONCE_LEN];
+        rand_bytes(&mut nonce)?;
+
+        let mut tag = [0; TAG_LEN];
+        let data = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&nonce),
+            aad,
+            data,
+            &mut tag,
+        )?;
+
+        Ok(EncryptedData {
+            nonce: Bytes::copy_from_slice(&nonce),
+            data: Bytes::from(data),
+            tag: Bytes::copy_from_slice(&tag),
+        })
+    }
+
+    pub fn decrypt(&self, aad: &[u8], encrypted: &EncryptedData) -> Result<Vec<u8>, SignerError> {
+        decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&encrypted.nonce),
+            aad,
+            &encrypted.data,
+            &encrypted.tag,
+        )
+        .map_err(|_| SignerError::KeyStoreError("cannot decrypt key".to_string()))
+    }
+}
+
+/// Do not show the key in debug output.
+impl fmt::Debug for KeyEncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "KeyEncryptionKey")
+    }
+}
+
+//------------ EncryptedData -------------------------------------------------
+
+/// Data encrypted using AES-256-GCM, as saved in an encrypted key file.
+#[derive(Deserialize, Serialize)]
+pub struct EncryptedData {
+    #[serde(deserialize_with = "ext_serde::de_bytes")]
+    #[serde(serialize_with = "ext_serde::ser_bytes")]
+    nonce: Bytes,
+
+    #[serde(deserialize_with = "ext_serde::de_bytes")]
+    #[serde(serialize_with = "ext_serde::ser_bytes")]
+    data: Bytes,
+
+    #[serde(deserialize_with = "ext_serde::de_bytes")]
+    #[serde(serialize_with = "ext_serde::ser_bytes")]
+    tag: Bytes,
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::test;
+
+    use super::*;
+
+    #[test]
+    fn should_detect_wrong_passphrase() {
+        test::test_under_tmp(|d| {
+            let config = KeyEncryptionConfig {
+                passphrase: Some("secret".to_string()),
+                ..Default::default()
+            };
+            assert!(!KeyEncryptionKey::is_encrypted(&d));
+
+            let kek = KeyEncryptionKey::create(&d, &config).unwrap();
+            assert!(KeyEncryptionKey::is_encrypted(&d));
+
+            let encrypted = kek.encrypt(b"name", b"data").unwrap();
+            let unlocked = KeyEncryptionKey::unlock(&d, &config).unwrap();
+            assert_eq!(
+                b"data".to_vec(),
+                unlocked.decrypt(b"name", &encrypted).unwrap()
+            );
+            assert!(unlocked.decrypt(b"other", &encrypted).is_err());
+
+            let wrong = KeyEncryptionConfig {
+                passphrase: Some("wrong".to_string()),
+                ..Default::default()
+            };
+            assert!(KeyEncryptionKey::unlock(&d, &wrong).is_err());
+        })
+    }
+
+    #[test]
+    fn verify_config() {
+        let mut config = KeyEncryptionConfig::from_env("KRILL_KEY_PASSPHRASE".to_string());
+        assert!(config.verify().is_ok());
+
+        config.passphrase = Some("secret".to_string());
+        assert!(config.verify().is_err());
+
+        assert!(KeyEncryptionConfig::default().verify().is_err());
+    }
+}
@@ -5,7 +5,11 @@ use rpki::crypto::DigestAlgorithm;
 pub mod ext_serde;
 pub mod file;
 pub mod httpclient;
+pub mod keyencryption;
+pub mod pkcs11;
+pub mod signer;
 pub mod softsigner;
+pub mod tar;
 pub mod xml;
 
 pub fn sha256(object: &[u8]) -> Bytes {
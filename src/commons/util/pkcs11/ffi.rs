@@ -0,0 +1,223 @@
+//! Minimal bindings for the PKCS#11 (Cryptoki) v2.40 API, covering only the
+//! functions, types and constants used by the Pkcs11Signer.
+//!
+//! The module is loaded at run time, and all functions are called through
+//! the function list returned by C_GetFunctionList.
+#![allow(non_camel_case_types, non_snake_case)]
+
+use std::os::raw::{c_uchar, c_ulong, c_void};
+
+pub type CK_BYTE = c_uchar;
+pub type CK_BBOOL = CK_BYTE;
+pub type CK_ULONG = c_ulong;
+pub type CK_RV = CK_ULONG;
+pub type CK_FLAGS = CK_ULONG;
+pub type CK_SLOT_ID = CK_ULONG;
+pub type CK_SESSION_HANDLE = CK_ULONG;
+pub type CK_OBJECT_HANDLE = CK_ULONG;
+pub type CK_OBJECT_CLASS = CK_ULONG;
+pub type CK_KEY_TYPE = CK_ULONG;
+pub type CK_ATTRIBUTE_TYPE = CK_ULONG;
+pub type CK_MECHANISM_TYPE = CK_ULONG;
+pub type CK_USER_TYPE = CK_ULONG;
+
+pub const CK_TRUE: CK_BBOOL = 1;
+pub const CK_FALSE: CK_BBOOL = 0;
+
+pub const CKR_OK: CK_RV = 0x0000_0000;
+pub const CKR_PIN_INCORRECT: CK_RV = 0x0000_00A0;
+pub const CKR_USER_ALREADY_LOGGED_IN: CK_RV = 0x0000_0100;
+pub const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x0000_0191;
+
+pub const CKF_OS_LOCKING_OK: CK_FLAGS = 0x0000_0002;
+pub const CKF_RW_SESSION: CK_FLAGS = 0x0000_0002;
+pub const CKF_SERIAL_SESSION: CK_FLAGS = 0x0000_0004;
+
+pub const CKU_USER: CK_USER_TYPE = 1;
+
+pub const CKO_PUBLIC_KEY: CK_OBJECT_CLASS = 2;
+pub const CKO_PRIVATE_KEY: CK_OBJECT_CLASS = 3;
+
+pub const CKK_RSA: CK_KEY_TYPE = 0;
+
+pub const CKA_CLASS: CK_ATTRIBUTE_TYPE = 0x0000_0000;
+pub const CKA_TOKEN: CK_ATTRIBUTE_TYPE = 0x0000_0001;
+pub const CKA_PRIVATE: CK_ATTRIBUTE_TYPE = 0x0000_0002;
+pub const CKA_LABEL: CK_ATTRIBUTE_TYPE = 0x0000_0003;
+pub const CKA_KEY_TYPE: CK_ATTRIBUTE_TYPE = 0x0000_0100;
+pub const CKA_ID: CK_ATTRIBUTE_TYPE = 0x0000_0102;
+pub const CKA_SENSITIVE: CK_ATTRIBUTE_TYPE = 0x0000_0103;
+pub const CKA_SIGN: CK_ATTRIBUTE_TYPE = 0x0000_0108;
+pub const CKA_VERIFY: CK_ATTRIBUTE_TYPE = 0x0000_010A;
+pub const CKA_MODULUS: CK_ATTRIBUTE_TYPE = 0x0000_0120;
+pub const CKA_MODULUS_BITS: CK_ATTRIBUTE_TYPE = 0x0000_0121;
+pub const CKA_PUBLIC_EXPONENT: CK_ATTRIBUTE_TYPE = 0x0000_0122;
+pub const CKA_EXTRACTABLE: CK_ATTRIBUTE_TYPE = 0x0000_0162;
+
+pub const CKM_RSA_PKCS_KEY_PAIR_GEN: CK_MECHANISM_TYPE = 0x0000_0000;
+pub const CKM_SHA256_RSA_PKCS: CK_MECHANISM_TYPE = 0x0000_0040;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CK_VERSION {
+    pub major: CK_BYTE,
+    pub minor: CK_BYTE,
+}
+
+#[repr(C)]
+pub struct CK_ATTRIBUTE {
+    pub attr_type: CK_ATTRIBUTE_TYPE,
+    pub p_value: *mut c_void,
+    pub value_len: CK_ULONG,
+}
+
+#[repr(C)]
+pub struct CK_MECHANISM {
+    pub mechanism: CK_MECHANISM_TYPE,
+    pub p_parameter: *mut c_void,
+    pub parameter_len: CK_ULONG,
+}
+
+#[repr(C)]
+pub struct CK_C_INITIALIZE_ARGS {
+    pub create_mutex: *mut c_void,
+    pub destroy_mutex: *mut c_void,
+    pub lock_mutex: *mut c_void,
+    pub unlock_mutex: *mut c_void,
+    pub flags: CK_FLAGS,
+    pub p_reserved: *mut c_void,
+}
+
+/// Placeholder for entries in the function list which are not used.
+type Unused = Option<unsafe extern "C" fn() -> CK_RV>;
+
+pub type C_GetFunctionList = unsafe extern "C" fn(*mut *mut CK_FUNCTION_LIST) -> CK_RV;
+
+/// The function list as defined in PKCS#11 v2.40. The order of the entries
+/// is fixed by the standard.
+#[repr(C)]
+pub struct CK_FUNCTION_LIST {
+    pub version: CK_VERSION,
+    pub C_Initialize: Option<unsafe extern "C" fn(*mut c_void) -> CK_RV>,
+    pub C_Finalize: Option<unsafe extern "C" fn(*mut c_void) -> CK_RV>,
+    C_GetInfo: Unused,
+    C_GetFunctionList: Unused,
+    C_GetSlotList: Unused,
+    C_GetSlotInfo: Unused,
+    C_GetTokenInfo: Unused,
+    C_GetMechanismList: Unused,
+    C_GetMechanismInfo: Unused,
+    C_InitToken: Unused,
+    C_InitPIN: Unused,
+    C_SetPIN: Unused,
+    pub C_OpenSession: Option<
+        unsafe extern "C" fn(
+            CK_SLOT_ID,
+            CK_FLAGS,
+            *mut c_void,
+            Option<unsafe extern "C" fn()>,
+            *mut CK_SESSION_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub C_CloseSession: Option<unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    C_CloseAllSessions: Unused,
+    C_GetSessionInfo: Unused,
+    C_GetOperationState: Unused,
+    C_SetOperationState: Unused,
+    pub C_Login: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, CK_USER_TYPE, *const CK_BYTE, CK_ULONG) -> CK_RV,
+    >,
+    pub C_Logout: Option<unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    C_CreateObject: Unused,
+    C_CopyObject: Unused,
+    pub C_DestroyObject: Option<unsafe extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE) -> CK_RV>,
+    C_GetObjectSize: Unused,
+    pub C_GetAttributeValue: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_OBJECT_HANDLE,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_SetAttributeValue: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_OBJECT_HANDLE,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_FindObjectsInit:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_ATTRIBUTE, CK_ULONG) -> CK_RV>,
+    pub C_FindObjects: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_OBJECT_HANDLE,
+            CK_ULONG,
+            *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_FindObjectsFinal: Option<unsafe extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    C_EncryptInit: Unused,
+    C_Encrypt: Unused,
+    C_EncryptUpdate: Unused,
+    C_EncryptFinal: Unused,
+    C_DecryptInit: Unused,
+    C_Decrypt: Unused,
+    C_DecryptUpdate: Unused,
+    C_DecryptFinal: Unused,
+    C_DigestInit: Unused,
+    C_Digest: Unused,
+    C_DigestUpdate: Unused,
+    C_DigestKey: Unused,
+    C_DigestFinal: Unused,
+    pub C_SignInit: Option<
+        unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_MECHANISM, CK_OBJECT_HANDLE) -> CK_RV,
+    >,
+    pub C_Sign: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *const CK_BYTE,
+            CK_ULONG,
+            *mut CK_BYTE,
+            *mut CK_ULONG,
+        ) -> CK_RV,
+    >,
+    C_SignUpdate: Unused,
+    C_SignFinal: Unused,
+    C_SignRecoverInit: Unused,
+    C_SignRecover: Unused,
+    C_VerifyInit: Unused,
+    C_Verify: Unused,
+    C_VerifyUpdate: Unused,
+    C_VerifyFinal: Unused,
+    C_VerifyRecoverInit: Unused,
+    C_VerifyRecover: Unused,
+    C_DigestEncryptUpdate: Unused,
+    C_DecryptDigestUpdate: Unused,
+    C_SignEncryptUpdate: Unused,
+    C_DecryptVerifyUpdate: Unused,
+    C_GenerateKey: Unused,
+    pub C_GenerateKeyPair: Option<
+        unsafe extern "C" fn(
+            CK_SESSION_HANDLE,
+            *mut CK_MECHANISM,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_ATTRIBUTE,
+            CK_ULONG,
+            *mut CK_OBJECT_HANDLE,
+            *mut CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    C_WrapKey: Unused,
+    C_UnwrapKey: Unused,
+    C_DeriveKey: Unused,
+    C_SeedRandom: Unused,
+    pub C_GenerateRandom:
+        Option<unsafe extern "C" fn(CK_SESSION_HANDLE, *mut CK_BYTE, CK_ULONG) -> CK_RV>,
+    C_GetFunctionStatus: Unused,
+    C_CancelFunction: Unused,
+    C_WaitForSlotEvent: Unused,
+}
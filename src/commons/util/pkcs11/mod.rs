@@ -0,0 +1,681 @@
+//! Support for signing things using keys kept in a Hardware Security Module
+//! (HSM), which is accessed through its PKCS#11 interface.
+//!
+//! Private keys are generated in, and never leave, the HSM. Keys are found
+//! by their CKA_ID attribute, which is set to the key identifier of the
+//! public key when the key is created.
+use std::mem::size_of;
+use std::os::raw::c_void;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::{env, fmt, fs, ptr};
+
+use bytes::Bytes;
+use libloading::{Library, Symbol};
+use openssl::bn::BigNum;
+use openssl::rsa::Rsa;
+
+use rpki::crypto::signer::KeyError;
+use rpki::crypto::{
+    KeyIdentifier, PublicKey, PublicKeyFormat, Signature, SignatureAlgorithm, Signer, SigningError,
+};
+
+use crate::commons::util::softsigner::SignerError;
+
+mod ffi;
+
+use self::ffi::*;
+
+//------------ Calling the module --------------------------------------------
+
+/// Calls a function from the module's function list, and returns its return
+/// value. Only fails if the module does not provide the function.
+macro_rules! call_rv {
+    ( $context:expr, $function:ident ( $( $arg:expr ),* ) ) => {{
+        match $context.functions().$function {
+            None => Err(SignerError::Pkcs11Error(format!(
+                "module does not provide {}",
+                stringify!($function)
+            ))),
+            Some(function) => Ok(unsafe { function($( $arg ),*) }),
+        }
+    }};
+}
+
+/// Calls a function from the module's function list, and maps any return
+/// value other than CKR_OK to an error.
+macro_rules! call {
+    ( $context:expr, $function:ident ( $( $arg:expr ),* ) ) => {{
+        call_rv!($context, $function($( $arg ),*))
+            .and_then(|rv| Pkcs11Context::check(rv, stringify!($function)))
+    }};
+}
+
+//------------ Pkcs11Config --------------------------------------------------
+
+/// The PKCS#11 signer configuration, as set in krill.conf:
+///
+/// ```toml
+/// [signer]
+/// type = "pkcs11"
+/// module = "/usr/lib/softhsm/libsofthsm2.so"
+/// slot = 0
+/// pin_env = "KRILL_HSM_PIN"
+/// ```
+///
+/// The user PIN is taken from exactly one of 'pin', 'pin_file' (the first
+/// line of the file) or 'pin_env' (the named environment variable).
+#[derive(Clone, Deserialize, Eq, PartialEq)]
+pub struct Pkcs11Config {
+    pub module: PathBuf,
+    pub slot: u64,
+
+    #[serde(default)]
+    pub pin: Option<String>,
+
+    #[serde(default)]
+    pub pin_file: Option<PathBuf>,
+
+    #[serde(default)]
+    pub pin_env: Option<String>,
+}
+
+impl Pkcs11Config {
+    /// Checks that the module exists, and that exactly one source for the
+    /// PIN is given.
+    pub fn verify(&self) -> Result<(), String> {
+        if !self.module.is_file() {
+            return Err(format!("cannot find module '{}'", self.module.display()));
+        }
+        let sources = [
+            self.pin.is_some(),
+            self.pin_file.is_some(),
+            self.pin_env.is_some(),
+        ];
+        if sources.iter().filter(|set| **set).count() != 1 {
+            return Err("exactly one of pin, pin_file or pin_env must be set".to_string());
+        }
+        Ok(())
+    }
+
+    fn pin(&self) -> Result<String, SignerError> {
+        if let Some(pin) = &self.pin {
+            Ok(pin.clone())
+        } else if let Some(path) = &self.pin_file {
+            let content = fs::read_to_string(path)?;
+            Ok(content.lines().next().unwrap_or("").to_string())
+        } else if let Some(var) = &self.pin_env {
+            env::var(var).map_err(|_| {
+                SignerError::Pkcs11Error(format!("environment variable '{}' is not set", var))
+            })
+        } else {
+            Err(SignerError::Pkcs11Error("no PIN configured".to_string()))
+        }
+    }
+}
+
+/// Do not show the PIN in debug output.
+impl fmt::Debug for Pkcs11Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pkcs11Config")
+            .field("module", &self.module)
+            .field("slot", &self.slot)
+            .field("pin_file", &self.pin_file)
+            .field("pin_env", &self.pin_env)
+            .finish()
+    }
+}
+
+//------------ Pkcs11Signer --------------------------------------------------
+
+/// A signer which uses RSA keys in an HSM, through a PKCS#11 module.
+///
+/// All operations use a single logged in session, so clones of this signer
+/// share the session. Each operation holds the lock on the session until it
+/// is done, as operations like finding objects and signing take multiple
+/// calls using the session state.
+#[derive(Clone)]
+pub struct Pkcs11Signer {
+    context: Arc<Pkcs11Context>,
+}
+
+impl Pkcs11Signer {
+    /// Loads the module, and logs in to the configured slot. Fails if the
+    /// module cannot be used, or if the PIN is wrong.
+    pub fn build(config: &Pkcs11Config) -> Result<Self, SignerError> {
+        let pin = config.pin()?;
+        let context = Pkcs11Context::initialize(config)?;
+        context.login(&pin)?;
+
+        info!(
+            "Using PKCS#11 module {} with slot {}",
+            config.module.display(),
+            config.slot
+        );
+
+        Ok(Pkcs11Signer {
+            context: Arc::new(context),
+        })
+    }
+}
+
+impl fmt::Debug for Pkcs11Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pkcs11Signer")
+            .field("module", &self.context.module)
+            .field("slot", &self.context.slot)
+            .finish()
+    }
+}
+
+impl Signer for Pkcs11Signer {
+    type KeyId = KeyIdentifier;
+    type Error = SignerError;
+
+    fn create_key(&mut self, _algorithm: PublicKeyFormat) -> Result<Self::KeyId, Self::Error> {
+        let guard = self.context.session.lock().unwrap();
+        let session = *guard;
+
+        // The key identifier is only known once the key exists, so the
+        // CKA_ID and CKA_LABEL are set afterwards. If that fails, the key
+        // pair is destroyed, as it could never be found again.
+        let (public, private) = self.context.generate_key_pair(session, true)?;
+        let res = self.context.public_key(session, public).and_then(|key| {
+            let key_id = key.key_identifier();
+            let label = key_id.to_string();
+            for handle in &[public, private] {
+                let mut template = [
+                    attr_bytes(CKA_ID, key_id.as_slice()),
+                    attr_bytes(CKA_LABEL, label.as_bytes()),
+                ];
+                call!(
+                    self.context,
+                    C_SetAttributeValue(
+                        session,
+                        *handle,
+                        template.as_mut_ptr(),
+                        template.len() as CK_ULONG
+                    )
+                )?;
+            }
+            Ok(key_id)
+        });
+
+        if res.is_err() {
+            let _ = call!(self.context, C_DestroyObject(session, private));
+            let _ = call!(self.context, C_DestroyObject(session, public));
+        }
+        res
+    }
+
+    fn get_key_info(&self, key_id: &Self::KeyId) -> Result<PublicKey, KeyError<Self::Error>> {
+        let guard = self.context.session.lock().unwrap();
+        let session = *guard;
+
+        match self.context.find_key(session, CKO_PUBLIC_KEY, key_id)? {
+            Some(handle) => Ok(self.context.public_key(session, handle)?),
+            None => Err(KeyError::KeyNotFound),
+        }
+    }
+
+    fn destroy_key(&mut self, key_id: &Self::KeyId) -> Result<(), KeyError<Self::Error>> {
+        let guard = self.context.session.lock().unwrap();
+        let session = *guard;
+
+        for class in &[CKO_PRIVATE_KEY, CKO_PUBLIC_KEY] {
+            if let Some(handle) = self.context.find_key(session, *class, key_id)? {
+                call!(self.context, C_DestroyObject(session, handle))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn sign<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        key_id: &Self::KeyId,
+        _algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<Signature, SigningError<Self::Error>> {
+        let guard = self.context.session.lock().unwrap();
+        let session = *guard;
+
+        match self.context.find_key(session, CKO_PRIVATE_KEY, key_id)? {
+            Some(handle) => Ok(self.context.sign(session, handle, data.as_ref())?),
+            None => Err(SigningError::KeyNotFound),
+        }
+    }
+
+    fn sign_one_off<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        _algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<(Signature, PublicKey), SignerError> {
+        let guard = self.context.session.lock().unwrap();
+        let session = *guard;
+
+        // Use a session key pair, which the HSM discards when the session
+        // is closed. Destroy it explicitly as well, since the session lives
+        // as long as Krill runs.
+        let (public, private) = self.context.generate_key_pair(session, false)?;
+
+        let res = self
+            .context
+            .sign(session, private, data.as_ref())
+            .and_then(|signature| {
+                let key = self.context.public_key(session, public)?;
+                Ok((signature, key))
+            });
+
+        call!(self.context, C_DestroyObject(session, private))?;
+        call!(self.context, C_DestroyObject(session, public))?;
+
+        res
+    }
+
+    fn rand(&self, target: &mut [u8]) -> Result<(), SignerError> {
+        let session = self.context.session.lock().unwrap();
+        call!(
+            self.context,
+            C_GenerateRandom(*session, target.as_mut_ptr(), target.len() as CK_ULONG)
+        )
+    }
+}
+
+//------------ Pkcs11Context -------------------------------------------------
+
+/// The loaded module, and the logged in session.
+struct Pkcs11Context {
+    module: PathBuf,
+    slot: u64,
+
+    // Points into the loaded library, so it must not outlive it.
+    functions: *const CK_FUNCTION_LIST,
+
+    // The session used for all operations, or 0 (CK_INVALID_HANDLE).
+    session: Mutex<CK_SESSION_HANDLE>,
+
+    // Whether the module was initialized by this context, rather than
+    // already initialized in this process. Only then is it finalized when
+    // the context is dropped, as finalizing affects all users of the module.
+    initialized: bool,
+
+    // Declared last, so that it is unloaded after the context is dropped.
+    _library: Library,
+}
+
+// The function list is never changed after loading, and all calls using the
+// session are serialized through its mutex. The module is initialized with
+// CKF_OS_LOCKING_OK, so it may be called from any thread.
+unsafe impl Send for Pkcs11Context {}
+unsafe impl Sync for Pkcs11Context {}
+
+impl Pkcs11Context {
+    fn initialize(config: &Pkcs11Config) -> Result<Self, SignerError> {
+        let library = Library::new(&config.module).map_err(|e| {
+            SignerError::Pkcs11Error(format!(
+                "cannot load module '{}': {}",
+                config.module.display(),
+                e
+            ))
+        })?;
+
+        let functions = unsafe {
+            let get_function_list: Symbol<C_GetFunctionList> = library
+                .get(b"C_GetFunctionList\0")
+                .map_err(|e| SignerError::Pkcs11Error(format!("not a PKCS#11 module: {}", e)))?;
+
+            let mut functions: *mut CK_FUNCTION_LIST = ptr::null_mut();
+            Self::check(get_function_list(&mut functions), "C_GetFunctionList")?;
+            if functions.is_null() {
+                return Err(SignerError::Pkcs11Error(
+                    "module returned no function list".to_string(),
+                ));
+            }
+            functions as *const CK_FUNCTION_LIST
+        };
+
+        let mut context = Pkcs11Context {
+            module: config.module.clone(),
+            slot: config.slot,
+            functions,
+            session: Mutex::new(0),
+            initialized: false,
+            _library: library,
+        };
+
+        let mut args = CK_C_INITIALIZE_ARGS {
+            create_mutex: ptr::null_mut(),
+            destroy_mutex: ptr::null_mut(),
+            lock_mutex: ptr::null_mut(),
+            unlock_mutex: ptr::null_mut(),
+            flags: CKF_OS_LOCKING_OK,
+            p_reserved: ptr::null_mut(),
+        };
+        let args_ptr = &mut args as *mut CK_C_INITIALIZE_ARGS as *mut c_void;
+        match call_rv!(context, C_Initialize(args_ptr))? {
+            CKR_OK => context.initialized = true,
+            CKR_CRYPTOKI_ALREADY_INITIALIZED => {}
+            rv => Self::check(rv, "C_Initialize")?,
+        }
+
+        let mut session = 0;
+        call!(
+            context,
+            C_OpenSession(
+                config.slot as CK_SLOT_ID,
+                CKF_SERIAL_SESSION | CKF_RW_SESSION,
+                ptr::null_mut(),
+                None,
+                &mut session
+            )
+        )?;
+        *context.session.lock().unwrap() = session;
+
+        Ok(context)
+    }
+
+    fn functions(&self) -> &CK_FUNCTION_LIST {
+        unsafe { &*self.functions }
+    }
+
+    fn check(rv: CK_RV, function: &str) -> Result<(), SignerError> {
+        if rv == CKR_OK {
+            Ok(())
+        } else {
+            Err(SignerError::Pkcs11Error(format!(
+                "{} failed with error {:#x}",
+                function, rv
+            )))
+        }
+    }
+
+    fn login(&self, pin: &str) -> Result<(), SignerError> {
+        let session = *self.session.lock().unwrap();
+        let rv = call_rv!(
+            self,
+            C_Login(session, CKU_USER, pin.as_ptr(), pin.len() as CK_ULONG)
+        )?;
+        match rv {
+            CKR_OK | CKR_USER_ALREADY_LOGGED_IN => Ok(()),
+            CKR_PIN_INCORRECT => Err(SignerError::Pkcs11Error(format!(
+                "incorrect PIN for slot {}",
+                self.slot
+            ))),
+            rv => Self::check(rv, "C_Login"),
+        }
+    }
+
+    /// Generates an RSA key pair, either on the token, or for the session
+    /// only. The private key can only be used for signing, and cannot be
+    /// extracted from the HSM.
+    fn generate_key_pair(
+        &self,
+        session: CK_SESSION_HANDLE,
+        token: bool,
+    ) -> Result<(CK_OBJECT_HANDLE, CK_OBJECT_HANDLE), SignerError> {
+        let token: CK_BBOOL = if token { CK_TRUE } else { CK_FALSE };
+        let yes = CK_TRUE;
+        let no = CK_FALSE;
+        let key_type = CKK_RSA;
+        let bits: CK_ULONG = 2048;
+        let exponent: [u8; 3] = [0x01, 0x00, 0x01];
+
+        let mut public_template = [
+            attr(CKA_TOKEN, &token),
+            attr(CKA_KEY_TYPE, &key_type),
+            attr(CKA_VERIFY, &yes),
+            attr(CKA_MODULUS_BITS, &bits),
+            attr_bytes(CKA_PUBLIC_EXPONENT, &exponent),
+        ];
+        let mut private_template = [
+            attr(CKA_TOKEN, &token),
+            attr(CKA_KEY_TYPE, &key_type),
+            attr(CKA_SIGN, &yes),
+            attr(CKA_PRIVATE, &yes),
+            attr(CKA_SENSITIVE, &yes),
+            attr(CKA_EXTRACTABLE, &no),
+        ];
+        let mut mechanism = mechanism(CKM_RSA_PKCS_KEY_PAIR_GEN);
+
+        let mut public = 0;
+        let mut private = 0;
+        call!(
+            self,
+            C_GenerateKeyPair(
+                session,
+                &mut mechanism,
+                public_template.as_mut_ptr(),
+                public_template.len() as CK_ULONG,
+                private_template.as_mut_ptr(),
+                private_template.len() as CK_ULONG,
+                &mut public,
+                &mut private
+            )
+        )?;
+
+        Ok((public, private))
+    }
+
+    /// Finds the public or private key object for the key identifier.
+    fn find_key(
+        &self,
+        session: CK_SESSION_HANDLE,
+        class: CK_OBJECT_CLASS,
+        key_id: &KeyIdentifier,
+    ) -> Result<Option<CK_OBJECT_HANDLE>, SignerError> {
+        let mut template = [
+            attr(CKA_CLASS, &class),
+            attr_bytes(CKA_ID, key_id.as_slice()),
+        ];
+        call!(
+            self,
+            C_FindObjectsInit(session, template.as_mut_ptr(), template.len() as CK_ULONG)
+        )?;
+
+        let mut handle = 0;
+        let mut count = 0;
+        let res = call!(self, C_FindObjects(session, &mut handle, 1, &mut count));
+        call!(self, C_FindObjectsFinal(session))?;
+        res?;
+
+        if count == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(handle))
+        }
+    }
+
+    /// Reads the modulus and exponent of the public key object, and returns
+    /// the key as a SubjectPublicKeyInfo.
+    fn public_key(
+        &self,
+        session: CK_SESSION_HANDLE,
+        handle: CK_OBJECT_HANDLE,
+    ) -> Result<PublicKey, SignerError> {
+        // Get the lengths first, then the values.
+        let mut template = [
+            attr_out(CKA_MODULUS, ptr::null_mut(), 0),
+            attr_out(CKA_PUBLIC_EXPONENT, ptr::null_mut(), 0),
+        ];
+        call!(
+            self,
+            C_GetAttributeValue(session, handle, template.as_mut_ptr(), 2)
+        )?;
+
+        let mut modulus = vec![0u8; template[0].value_len as usize];
+        let mut exponent = vec![0u8; template[1].value_len as usize];
+        let mut template = [
+            attr_out(CKA_MODULUS, modulus.as_mut_ptr(), modulus.len()),
+            attr_out(CKA_PUBLIC_EXPONENT, exponent.as_mut_ptr(), exponent.len()),
+        ];
+        call!(
+            self,
+            C_GetAttributeValue(session, handle, template.as_mut_ptr(), 2)
+        )?;
+
+        let rsa = Rsa::from_public_components(
+            BigNum::from_slice(&modulus)?,
+            BigNum::from_slice(&exponent)?,
+        )?;
+        let mut der = Bytes::from(rsa.public_key_to_der()?);
+        PublicKey::decode(&mut der).map_err(|_| SignerError::DecodeError)
+    }
+
+    /// Signs the data using SHA-256 with RSA (PKCS #1 v1.5), as required
+    /// for RPKI (RFC 7935).
+    fn sign(
+        &self,
+        session: CK_SESSION_HANDLE,
+        handle: CK_OBJECT_HANDLE,
+        data: &[u8],
+    ) -> Result<Signature, SignerError> {
+        let mut mechanism = mechanism(CKM_SHA256_RSA_PKCS);
+        call!(self, C_SignInit(session, &mut mechanism, handle))?;
+
+        // A 4096 bit key would need 512 bytes, generated keys are 2048 bits.
+        let mut signature = vec![0u8; 512];
+        let mut len = signature.len() as CK_ULONG;
+        call!(
+            self,
+            C_Sign(
+                session,
+                data.as_ptr(),
+                data.len() as CK_ULONG,
+                signature.as_mut_ptr(),
+                &mut len
+            )
+        )?;
+        signature.truncate(len as usize);
+
+        Ok(Signature::new(
+            SignatureAlgorithm::default(),
+            Bytes::from(signature),
+        ))
+    }
+}
+
+impl Drop for Pkcs11Context {
+    fn drop(&mut self) {
+        let session = *self.session.lock().unwrap();
+        if session != 0 {
+            let _ = call!(self, C_Logout(session));
+            let _ = call!(self, C_CloseSession(session));
+        }
+        if self.initialized {
+            let _ = call!(self, C_Finalize(ptr::null_mut()));
+        }
+    }
+}
+
+//------------ Attribute helpers ---------------------------------------------
+
+fn attr<T>(attr_type: CK_ATTRIBUTE_TYPE, value: &T) -> CK_ATTRIBUTE {
+    CK_ATTRIBUTE {
+        attr_type,
+        p_value: value as *const T as *mut c_void,
+        value_len: size_of::<T>() as CK_ULONG,
+    }
+}
+
+fn attr_bytes(attr_type: CK_ATTRIBUTE_TYPE, value: &[u8]) -> CK_ATTRIBUTE {
+    CK_ATTRIBUTE {
+        attr_type,
+        p_value: value.as_ptr() as *mut c_void,
+        value_len: value.len() as CK_ULONG,
+    }
+}
+
+fn attr_out(attr_type: CK_ATTRIBUTE_TYPE, buf: *mut u8, len: usize) -> CK_ATTRIBUTE {
+    CK_ATTRIBUTE {
+        attr_type,
+        p_value: buf as *mut c_void,
+        value_len: len as CK_ULONG,
+    }
+}
+
+fn mechanism(mechanism: CK_MECHANISM_TYPE) -> CK_MECHANISM {
+    CK_MECHANISM {
+        mechanism,
+        p_parameter: ptr::null_mut(),
+        parameter_len: 0,
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the signer against a real PKCS#11 module. This is ignored by
+    /// default. To run it against SoftHSMv2:
+    ///
+    /// ```sh
+    /// softhsm2-util --init-token --free --label krill --so-pin 1234 --pin 1234
+    /// KRILL_TEST_PKCS11_MODULE=/usr/lib/softhsm/libsofthsm2.so \
+    /// KRILL_TEST_PKCS11_SLOT=<slot reported by softhsm2-util> \
+    /// KRILL_TEST_PKCS11_PIN=1234 \
+    /// cargo test pkcs11 -- --ignored
+    /// ```
+    #[test]
+    #[ignore]
+    fn sign_with_pkcs11_module() {
+        let config = Pkcs11Config {
+            module: PathBuf::from(env::var("KRILL_TEST_PKCS11_MODULE").unwrap()),
+            slot: env::var("KRILL_TEST_PKCS11_SLOT").unwrap().parse().unwrap(),
+            pin: None,
+            pin_file: None,
+            pin_env: Some("KRILL_TEST_PKCS11_PIN".to_string()),
+        };
+        config.verify().unwrap();
+
+        let mut signer = Pkcs11Signer::build(&config).unwrap();
+
+        let key_id = signer.create_key(PublicKeyFormat::default()).unwrap();
+        let key = signer.get_key_info(&key_id).unwrap();
+        assert_eq!(key_id, key.key_identifier());
+
+        let data = b"some data to sign";
+        let signature = signer
+            .sign(&key_id, SignatureAlgorithm::default(), data)
+            .unwrap();
+        key.verify(data, &signature).unwrap();
+
+        let (signature, one_off_key) = signer
+            .sign_one_off(SignatureAlgorithm::default(), data)
+            .unwrap();
+        one_off_key.verify(data, &signature).unwrap();
+
+        signer.destroy_key(&key_id).unwrap();
+        assert!(signer.get_key_info(&key_id).is_err());
+
+        // The login state is shared by all sessions with the token in this
+        // process, so log out and finalize before trying another PIN.
+        drop(signer);
+
+        let mut wrong_pin = config;
+        wrong_pin.pin_env = None;
+        wrong_pin.pin = Some("wrong".to_string());
+        assert!(Pkcs11Signer::build(&wrong_pin).is_err());
+    }
+
+    #[test]
+    fn verify_config() {
+        let config = Pkcs11Config {
+            module: PathBuf::from("/no/such/module.so"),
+            slot: 0,
+            pin: Some("1234".to_string()),
+            pin_file: None,
+            pin_env: None,
+        };
+        assert!(config.verify().is_err());
+
+        let mut config = config;
+        config.module = PathBuf::from("./Cargo.toml");
+        config.verify().unwrap();
+
+        config.pin_env = Some("KRILL_HSM_PIN".to_string());
+        assert!(config.verify().is_err());
+        assert!(!format!("{:?}", config).contains("1234"));
+    }
+}
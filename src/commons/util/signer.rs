@@ -0,0 +1,126 @@
+//! The signer used for the keys of CAs, as selected in krill.conf.
+use std::path::PathBuf;
+
+use rpki::crypto::signer::KeyError;
+use rpki::crypto::{
+    KeyIdentifier, PublicKey, PublicKeyFormat, Signature, SignatureAlgorithm, Signer, SigningError,
+};
+
+use crate::commons::util::keyencryption::KeyEncryptionConfig;
+use crate::commons::util::pkcs11::{Pkcs11Config, Pkcs11Signer};
+use crate::commons::util::softsigner::{OpenSslSigner, SignerError};
+
+//------------ SignerConfig --------------------------------------------------
+
+/// Selects the signer for CA keys in krill.conf. If there is no [signer]
+/// section, keys are kept on disk by the OpenSslSigner:
+///
+/// ```toml
+/// [signer]
+/// type = "openssl"
+/// ```
+///
+/// See Pkcs11Config for using keys in an HSM.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum SignerConfig {
+    #[serde(rename = "openssl")]
+    OpenSsl,
+
+    #[serde(rename = "pkcs11")]
+    Pkcs11(Pkcs11Config),
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        SignerConfig::OpenSsl
+    }
+}
+
+impl SignerConfig {
+    pub fn verify(&self) -> Result<(), String> {
+        match self {
+            SignerConfig::OpenSsl => Ok(()),
+            SignerConfig::Pkcs11(config) => config.verify(),
+        }
+    }
+}
+
+//------------ KrillSigner ---------------------------------------------------
+
+/// The signer for CA keys, using whichever implementation is configured.
+#[derive(Clone, Debug)]
+pub enum KrillSigner {
+    OpenSsl(OpenSslSigner),
+    Pkcs11(Pkcs11Signer),
+}
+
+impl KrillSigner {
+    pub fn build(
+        config: &SignerConfig,
+        work_dir: &PathBuf,
+        key_encryption: Option<&KeyEncryptionConfig>,
+    ) -> Result<Self, SignerError> {
+        match config {
+            SignerConfig::OpenSsl => OpenSslSigner::build_with_encryption(work_dir, key_encryption)
+                .map(KrillSigner::OpenSsl),
+            SignerConfig::Pkcs11(config) => Pkcs11Signer::build(config).map(KrillSigner::Pkcs11),
+        }
+    }
+}
+
+impl Signer for KrillSigner {
+    type KeyId = KeyIdentifier;
+    type Error = SignerError;
+
+    fn create_key(&mut self, algorithm: PublicKeyFormat) -> Result<Self::KeyId, Self::Error> {
+        match self {
+            KrillSigner::OpenSsl(signer) => signer.create_key(algorithm),
+            KrillSigner::Pkcs11(signer) => signer.create_key(algorithm),
+        }
+    }
+
+    fn get_key_info(&self, key_id: &Self::KeyId) -> Result<PublicKey, KeyError<Self::Error>> {
+        match self {
+            KrillSigner::OpenSsl(signer) => signer.get_key_info(key_id),
+            KrillSigner::Pkcs11(signer) => signer.get_key_info(key_id),
+        }
+    }
+
+    fn destroy_key(&mut self, key_id: &Self::KeyId) -> Result<(), KeyError<Self::Error>> {
+        match self {
+            KrillSigner::OpenSsl(signer) => signer.destroy_key(key_id),
+            KrillSigner::Pkcs11(signer) => signer.destroy_key(key_id),
+        }
+    }
+
+    fn sign<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        key_id: &Self::KeyId,
+        algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<Signature, SigningError<Self::Error>> {
+        match self {
+            KrillSigner::OpenSsl(signer) => signer.sign(key_id, algorithm, data),
+            KrillSigner::Pkcs11(signer) => signer.sign(key_id, algorithm, data),
+        }
+    }
+
+    fn sign_one_off<D: AsRef<[u8]> + ?Sized>(
+        &self,
+        algorithm: SignatureAlgorithm,
+        data: &D,
+    ) -> Result<(Signature, PublicKey), Self::Error> {
+        match self {
+            KrillSigner::OpenSsl(signer) => signer.sign_one_off(algorithm, data),
+            KrillSigner::Pkcs11(signer) => signer.sign_one_off(algorithm, data),
+        }
+    }
+
+    fn rand(&self, target: &mut [u8]) -> Result<(), Self::Error> {
+        match self {
+            KrillSigner::OpenSsl(signer) => signer.rand(target),
+            KrillSigner::Pkcs11(signer) => signer.rand(target),
+        }
+    }
+}
@@ -1,8 +1,8 @@
 //! Support for signing things using software keys (through openssl) and
-//! storing them unencrypted on disk.
+//! storing them on disk, optionally encrypted using a passphrase.
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 
 use bytes::Bytes;
@@ -18,18 +18,37 @@ use rpki::crypto::{
     KeyIdentifier, PublicKey, PublicKeyFormat, Signature, SignatureAlgorithm, Signer, SigningError,
 };
 
+use crate::commons::util::keyencryption::{
+    EncryptedData, KeyEncryptionConfig, KeyEncryptionKey, KEK_INFO_FILE,
+};
+
 //------------ OpenSslSigner -------------------------------------------------
 
 /// An openssl based signer.
 ///
-/// Keeps the keys in memory (for now).
+/// Keeps the keys in files under 'keys' in the work dir. If a key encryption
+/// key is used, the key files are encrypted.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct OpenSslSigner {
     keys_dir: PathBuf,
+
+    #[serde(skip)]
+    kek: Option<KeyEncryptionKey>,
 }
 
 impl OpenSslSigner {
     pub fn build(work_dir: &PathBuf) -> Result<Self, SignerError> {
+        Self::build_with_encryption(work_dir, None)
+    }
+
+    /// Builds a signer using the key store in the work dir. If key encryption
+    /// is configured the key store is unlocked, which fails if the passphrase
+    /// is wrong. A new key store is encrypted if key encryption is configured,
+    /// but an existing key store is only ever (re-)encrypted by 'rekey'.
+    pub fn build_with_encryption(
+        work_dir: &PathBuf,
+        key_encryption: Option<&KeyEncryptionConfig>,
+    ) -> Result<Self, SignerError> {
         let meta_data = fs::metadata(&work_dir)?;
         if meta_data.is_dir() {
             let mut keys_dir = PathBuf::from(work_dir);
@@ -38,11 +57,100 @@ impl OpenSslSigner {
                 fs::create_dir_all(&keys_dir)?;
             }
 
-            Ok(OpenSslSigner { keys_dir })
+            let kek = match key_encryption {
+                None => {
+                    if KeyEncryptionKey::is_encrypted(&keys_dir) {
+                        return Err(SignerError::KeyStoreError(
+                            "key store is encrypted, but no key_encryption is configured"
+                                .to_string(),
+                        ));
+                    }
+                    None
+                }
+                Some(config) => {
+                    if KeyEncryptionKey::is_encrypted(&keys_dir) {
+                        Some(KeyEncryptionKey::unlock(&keys_dir, config)?)
+                    } else if Self::key_file_names(&keys_dir)?.is_empty() {
+                        Some(KeyEncryptionKey::create(&keys_dir, config)?)
+                    } else {
+                        return Err(SignerError::KeyStoreError(
+                            "key store is not encrypted, use 'krill --rekey' to encrypt it"
+                                .to_string(),
+                        ));
+                    }
+                }
+            };
+
+            Ok(OpenSslSigner { keys_dir, kek })
         } else {
             Err(SignerError::InvalidWorkDir(work_dir.clone()))
         }
     }
+
+    /// Re-encrypts all keys in the key store in the work dir, which is
+    /// unlocked using the old key encryption config (if any), with a new
+    /// key encryption key derived from the new config (if any). Returns the
+    /// number of keys.
+    ///
+    /// All keys are written to a new directory first, which then replaces
+    /// the current keys directory.
+    pub fn rekey(
+        work_dir: &PathBuf,
+        old: Option<&KeyEncryptionConfig>,
+        new: Option<&KeyEncryptionConfig>,
+    ) -> Result<usize, SignerError> {
+        let old_dir = work_dir.join("keys.old");
+        if old_dir.exists() {
+            return Err(SignerError::KeyStoreError(format!(
+                "a previous rekey did not complete, check the keys in '{}'",
+                old_dir.display()
+            )));
+        }
+
+        let current = Self::build_with_encryption(work_dir, old)?;
+
+        let new_dir = work_dir.join("keys.rekey");
+        if new_dir.exists() {
+            fs::remove_dir_all(&new_dir)?;
+        }
+        fs::create_dir_all(&new_dir)?;
+
+        let kek = match new {
+            Some(config) => Some(KeyEncryptionKey::create(&new_dir, config)?),
+            None => None,
+        };
+        let rekeyed = OpenSslSigner {
+            keys_dir: new_dir.clone(),
+            kek,
+        };
+
+        let names = Self::key_file_names(&current.keys_dir)?;
+        for name in names.iter() {
+            let key_pair = current.read_key_file(name)?;
+            rekeyed.write_key_file(name, &key_pair)?;
+        }
+
+        fs::rename(&current.keys_dir, &old_dir)?;
+        fs::rename(&new_dir, &current.keys_dir)?;
+        fs::remove_dir_all(&old_dir)?;
+
+        Ok(names.len())
+    }
+
+    /// Returns the names of all key files in the keys directory.
+    fn key_file_names(keys_dir: &Path) -> Result<Vec<String>, SignerError> {
+        let mut names = vec![];
+        for entry in fs::read_dir(keys_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name != KEK_INFO_FILE {
+                    names.push(name);
+                }
+            }
+        }
+        Ok(names)
+    }
 }
 
 impl OpenSslSigner {
@@ -64,14 +172,42 @@ impl OpenSslSigner {
     fn load_key(&self, id: &KeyIdentifier) -> Result<OpenSslKeyPair, SignerError> {
         let path = self.key_path(id);
         if path.exists() {
-            let f = File::open(path)?;
-            let kp: OpenSslKeyPair = serde_json::from_reader(f)?;
-            Ok(kp)
+            self.read_key_file(&id.to_string())
         } else {
             Err(SignerError::KeyNotFound)
         }
     }
 
+    /// Reads a key file, decrypting it if a key encryption key is used. The
+    /// name of the file is authenticated as well, so that encrypted key files
+    /// cannot be swapped.
+    fn read_key_file(&self, name: &str) -> Result<OpenSslKeyPair, SignerError> {
+        let f = File::open(self.keys_dir.join(name))?;
+        match &self.kek {
+            None => Ok(serde_json::from_reader(f)?),
+            Some(kek) => {
+                let encrypted: EncryptedData = serde_json::from_reader(f)?;
+                let der = kek.decrypt(name.as_bytes(), &encrypted)?;
+                let pkey = PKey::private_key_from_der(&der)?;
+                Ok(OpenSslKeyPair { pkey })
+            }
+        }
+    }
+
+    fn write_key_file(&self, name: &str, key_pair: &OpenSslKeyPair) -> Result<(), SignerError> {
+        let json = match &self.kek {
+            None => serde_json::to_string(key_pair)?,
+            Some(kek) => {
+                let der = key_pair.pkey.private_key_to_der()?;
+                serde_json::to_string(&kek.encrypt(name.as_bytes(), &der)?)?
+            }
+        };
+
+        let mut f = File::create(self.keys_dir.join(name))?;
+        f.write_all(json.as_ref())?;
+        Ok(())
+    }
+
     fn key_path(&self, key_id: &KeyIdentifier) -> PathBuf {
         let mut path = self.keys_dir.clone();
         path.push(&key_id.to_string());
@@ -89,17 +225,16 @@ impl Signer for OpenSslSigner {
         let pk = &kp.subject_public_key_info()?;
         let key_id = pk.key_identifier();
 
-        let path = self.key_path(&key_id);
-        let json = serde_json::to_string(&kp)?;
-
-        let mut f = File::create(path)?;
-        f.write_all(json.as_ref())?;
+        self.write_key_file(&key_id.to_string(), &kp)?;
 
         Ok(key_id)
     }
 
     fn get_key_info(&self, key_id: &Self::KeyId) -> Result<PublicKey, KeyError<Self::Error>> {
-        let key_pair = self.load_key(key_id)?;
+        let key_pair = match self.load_key(key_id) {
+            Err(SignerError::KeyNotFound) => return Err(KeyError::KeyNotFound),
+            res => res?,
+        };
         Ok(key_pair.subject_public_key_info()?)
     }
 
@@ -218,6 +353,12 @@ pub enum SignerError {
 
     #[display(fmt = "Could not decode key")]
     DecodeError,
+
+    #[display(fmt = "PKCS#11 error: {}", _0)]
+    Pkcs11Error(String),
+
+    #[display(fmt = "Key store error: {}", _0)]
+    KeyStoreError(String),
 }
 
 impl From<ErrorStack> for SignerError {
@@ -267,4 +408,43 @@ pub mod tests {
         // not implement Eq and PartialEq.
         assert_eq!(json, json_from_des);
     }
+
+    #[test]
+    fn should_rekey_key_store() {
+        test::test_under_tmp(|d| {
+            let first = KeyEncryptionConfig {
+                passphrase: Some("first".to_string()),
+                ..Default::default()
+            };
+            let second = KeyEncryptionConfig {
+                passphrase: Some("second".to_string()),
+                ..Default::default()
+            };
+
+            let mut s = OpenSslSigner::build(&d).unwrap();
+            let ki = s.create_key(PublicKeyFormat::default()).unwrap();
+            let key_info = s.get_key_info(&ki).unwrap();
+
+            // An existing unencrypted key store is not encrypted implicitly
+            assert!(OpenSslSigner::build_with_encryption(&d, Some(&first)).is_err());
+
+            assert_eq!(1, OpenSslSigner::rekey(&d, None, Some(&first)).unwrap());
+            assert!(OpenSslSigner::build(&d).is_err());
+            let s = OpenSslSigner::build_with_encryption(&d, Some(&first)).unwrap();
+            assert_eq!(key_info, s.get_key_info(&ki).unwrap());
+
+            assert_eq!(
+                1,
+                OpenSslSigner::rekey(&d, Some(&first), Some(&second)).unwrap()
+            );
+            assert!(OpenSslSigner::build_with_encryption(&d, Some(&first)).is_err());
+            let s = OpenSslSigner::build_with_encryption(&d, Some(&second)).unwrap();
+            s.sign(&ki, SignatureAlgorithm::default(), b"data").unwrap();
+
+            assert!(OpenSslSigner::rekey(&d, Some(&first), None).is_err());
+            assert_eq!(1, OpenSslSigner::rekey(&d, Some(&second), None).unwrap());
+            let s = OpenSslSigner::build(&d).unwrap();
+            assert_eq!(key_info, s.get_key_info(&ki).unwrap());
+        })
+    }
 }
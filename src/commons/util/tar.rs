@@ -0,0 +1,271 @@
+//! A minimal implementation of the ustar archive format, as used for backups
+//! of the data directory. Only regular files are supported.
+use std::io::{self, Read, Write};
+
+use bytes::Bytes;
+
+const BLOCK_SIZE: usize = 512;
+const NAME_LEN: usize = 100;
+const PREFIX_LEN: usize = 155;
+
+//------------ TarEntry ------------------------------------------------------
+
+/// A regular file in a tar archive.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TarEntry {
+    path: String,
+    mtime: u64,
+    content: Bytes,
+}
+
+impl TarEntry {
+    pub fn new(path: String, mtime: u64, content: Bytes) -> Self {
+        TarEntry {
+            path,
+            mtime,
+            content,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The modification time in seconds since the epoch.
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn content(&self) -> &Bytes {
+        &self.content
+    }
+
+    pub fn unpack(self) -> (String, u64, Bytes) {
+        (self.path, self.mtime, self.content)
+    }
+}
+
+//------------ TarWriter -----------------------------------------------------
+
+/// Writes regular files to a ustar archive.
+pub struct TarWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(out: W) -> Self {
+        TarWriter { out }
+    }
+
+    pub fn append(&mut self, path: &str, mtime: u64, content: &[u8]) -> io::Result<()> {
+        let mut header = [0u8; BLOCK_SIZE];
+
+        let (prefix, name) = split_path(path)?;
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        write_octal(&mut header[100..108], 0o644);
+        write_octal(&mut header[108..116], 0);
+        write_octal(&mut header[116..124], 0);
+        write_octal(&mut header[124..136], content.len() as u64);
+        write_octal(&mut header[136..148], mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+        let checksum = checksum(&header);
+        write_octal(&mut header[148..155], checksum);
+        header[155] = b' ';
+
+        self.out.write_all(&header)?;
+        self.out.write_all(content)?;
+        let padding = padding(content.len());
+        self.out.write_all(&[0u8; BLOCK_SIZE][..padding])
+    }
+
+    /// Writes the end of archive marker, and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(&[0u8; 2 * BLOCK_SIZE])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Splits a path into the ustar prefix and name fields.
+fn split_path(path: &str) -> io::Result<(&str, &str)> {
+    if path.len() <= NAME_LEN {
+        return Ok(("", path));
+    }
+    for (i, c) in path.char_indices() {
+        if c == '/' && i <= PREFIX_LEN && path.len() - i - 1 <= NAME_LEN {
+            return Ok((&path[..i], &path[i + 1..]));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("path too long for tar archive: {}", path),
+    ))
+}
+
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let octal = format!("{:0width$o}", value, width = digits);
+    field[..digits].copy_from_slice(octal.as_bytes());
+    field[digits] = 0;
+}
+
+fn read_octal(field: &[u8]) -> io::Result<u64> {
+    let text: String = field
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect();
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).map_err(|_| invalid("invalid number in tar header"))
+}
+
+/// The checksum of a header, with the checksum field itself taken as spaces.
+fn checksum(header: &[u8]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                *b as u64
+            }
+        })
+        .sum()
+}
+
+fn padding(len: usize) -> usize {
+    (BLOCK_SIZE - len % BLOCK_SIZE) % BLOCK_SIZE
+}
+
+fn read_str(field: &[u8]) -> io::Result<String> {
+    let bytes: Vec<u8> = field.iter().take_while(|b| **b != 0).cloned().collect();
+    String::from_utf8(bytes).map_err(|_| invalid("invalid path in tar header"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+//------------ TarReader -----------------------------------------------------
+
+/// Reads the entries of a ustar archive one at a time, so that only a single
+/// file needs to be kept in memory. Fails if a header checksum does not
+/// match, if the archive is truncated, or if it contains anything other than
+/// regular files.
+pub struct TarReader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(input: R) -> Self {
+        TarReader { input }
+    }
+
+    /// Returns the next entry, or None at the end of the archive.
+    pub fn next_entry(&mut self) -> io::Result<Option<TarEntry>> {
+        let mut header = [0u8; BLOCK_SIZE];
+        self.read_exact(&mut header)?;
+        if header.iter().all(|b| *b == 0) {
+            return Ok(None);
+        }
+
+        if read_octal(&header[148..156])? != checksum(&header) {
+            return Err(invalid("tar header checksum mismatch"));
+        }
+        if header[156] != b'0' && header[156] != 0 {
+            return Err(invalid("tar archive contains entry which is not a file"));
+        }
+
+        let name = read_str(&header[..NAME_LEN])?;
+        let prefix = read_str(&header[345..345 + PREFIX_LEN])?;
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let size = read_octal(&header[124..136])?;
+        let mtime = read_octal(&header[136..148])?;
+
+        let mut content = vec![];
+        (&mut self.input).take(size).read_to_end(&mut content)?;
+        if content.len() as u64 != size {
+            return Err(invalid("tar archive is truncated"));
+        }
+        let mut padding_block = [0u8; BLOCK_SIZE];
+        self.read_exact(&mut padding_block[..padding(content.len())])?;
+
+        Ok(Some(TarEntry::new(path, mtime, Bytes::from(content))))
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.input.read_exact(buf).map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                invalid("tar archive is truncated")
+            } else {
+                e
+            }
+        })
+    }
+}
+
+/// Reads all entries from a ustar archive in memory, see TarReader.
+pub fn read_entries(archive: &[u8]) -> io::Result<Vec<TarEntry>> {
+    let mut reader = TarReader::new(archive);
+    let mut entries = vec![];
+    while let Some(entry) = reader.next_entry()? {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read_archive() {
+        let long_path = format!("{}/{}", "d".repeat(120), "f".repeat(90));
+        let files = vec![
+            TarEntry::new(
+                "cas/ta/info.json".to_string(),
+                1_580_000_000,
+                Bytes::from("{}"),
+            ),
+            TarEntry::new("empty".to_string(), 0, Bytes::new()),
+            TarEntry::new(long_path, 1, Bytes::from(vec![7u8; 1500])),
+        ];
+
+        let mut writer = TarWriter::new(vec![]);
+        for entry in &files {
+            writer
+                .append(entry.path(), entry.mtime(), entry.content())
+                .unwrap();
+        }
+        let archive = writer.finish().unwrap();
+        assert_eq!(0, archive.len() % BLOCK_SIZE);
+
+        assert_eq!(files, read_entries(&archive).unwrap());
+
+        let mut corrupt = archive.clone();
+        corrupt[0] = b'x';
+        assert!(read_entries(&corrupt).is_err());
+
+        assert!(read_entries(&archive[..1000]).is_err());
+    }
+
+    #[test]
+    fn reject_path_too_long() {
+        let path = "x".repeat(300);
+        assert!(TarWriter::new(vec![]).append(&path, 0, b"").is_err());
+    }
+}
@@ -17,14 +17,25 @@ pub const CASERVER_DIR: &str = "cas";
 pub const PUBSERVER_DFLT: &str = "0";
 pub const PUBSERVER_DIR: &str = "pubd";
 
+pub const HISTORY_ARCHIVE_DIR: &str = "archive";
+
+pub const WEBHOOKS_DIR: &str = "webhooks";
+
+pub const EVENT_QUEUE_DIR: &str = "mq";
+
+pub const STATUS_DIR: &str = "status";
+pub const TMP_DIR: &str = "tmp";
+
 pub const PUBLISH_VALID_DAYS: i64 = 7; // mft is valid for 7 days
 pub const PUBLISH_NEXT_HOURS: i64 = 24; // next update in 24 hours (otherwise mft and crl will become stale)
 pub const PUBLISH_THRESHOLD_HOURS: i64 = 8; // republish 8 hours before stale
+pub const PUBLISH_WARN_HOURS: i64 = 4; // warn if published objects go stale within 4 hours
 
 pub const REPOSITORY_DIR: &str = "repo";
 pub const REPOSITORY_RRDP_DIR: &str = "rrdp";
 pub const REPOSITORY_RSYNC_DIR: &str = "rsync";
-pub const REPOSITORY_RRDP_SNAPSHOT_RETAIN_MINS: u64 = 10;
+pub const RSYNC_CURRENT: &str = "current";
+pub const REPOSITORY_RRDP_FILES_RETAIN_MINS: i64 = 10;
 
 pub const KRILL_CLI_SERVER_ARG: &str = "server";
 pub const KRILL_CLI_SERVER_ENV: &str = "KRILL_CLI_SERVER";
@@ -45,6 +56,12 @@ pub const CHILD_CERTIFICATE_VALIDITY_YEARS: i32 = 1;
 pub const CHILD_CERTIFICATE_REISSUE_WEEKS: i64 = 4;
 pub const ROA_CERTIFICATE_VALIDITY_YEARS: i32 = 1;
 pub const ROA_CERTIFICATE_REISSUE_WEEKS: i64 = 4;
+pub const ASPA_CERTIFICATE_VALIDITY_YEARS: i32 = 1;
+pub const ASPA_CERTIFICATE_REISSUE_WEEKS: i64 = 4;
+pub const ROUTER_CERTIFICATE_VALIDITY_YEARS: i32 = 1;
+pub const ROUTER_CERTIFICATE_REISSUE_WEEKS: i64 = 4;
+pub const GHOSTBUSTER_VALIDITY_YEARS: i32 = 1;
+pub const GHOSTBUSTER_REISSUE_WEEKS: i64 = 4;
 pub const ID_CERTIFICATE_VALIDITY_YEARS: i32 = 15;
 
 pub const BGP_RIS_REFRESH_MINUTES: i64 = 60;
@@ -0,0 +1,544 @@
+//! Authorization for the API
+
+use std::fmt;
+use std::path::PathBuf;
+
+use openssl::memcmp;
+use openssl::rand::rand_bytes;
+use openssl::sha::Sha256;
+
+use crate::commons::api::{CaToken, CaTokenInfo, CaTokenList, CaTokenRequest, Handle, Token};
+use crate::commons::error::Error;
+use crate::commons::util::file;
+use crate::commons::{KrillEmptyResult, KrillResult};
+use crate::daemon::auth::oidc::LoginSessions;
+
+pub mod oidc;
+
+const CA_TOKENS_FILE: &str = "ca_tokens.json";
+
+//------------ Authorizer ----------------------------------------------------
+
+/// This type is responsible for checking authorizations when the API is
+/// accessed.
+///
+/// The master token (`auth_token` in the config) always resolves to an
+/// [`Actor`] with the [`Role::Admin`] role. Additional named users, each
+/// with their own token and role, can be configured as [`ApiUser`]s.
+///
+/// Tokens which can only manage specific CAs can also be added and removed
+/// at runtime. These are kept in a JSON file in the `auth` directory under
+/// the data directory, so that they survive restarts. Only a salted hash of
+/// their secret is kept, see StoredCaToken.
+///
+/// Finally, users who logged in through OpenID Connect are identified by
+/// their session.
+#[derive(Clone, Debug)]
+pub struct Authorizer {
+    krill_auth_token: Token,
+    users: Vec<ApiUser>,
+    ca_tokens: Vec<StoredCaToken>,
+    ca_tokens_file: Option<PathBuf>,
+    sessions: LoginSessions,
+}
+
+impl Authorizer {
+    /// Creates an Authorizer which does not persist any runtime tokens.
+    pub fn new(krill_auth_token: &Token, users: &[ApiUser]) -> Self {
+        Authorizer {
+            krill_auth_token: krill_auth_token.clone(),
+            users: users.to_vec(),
+            ca_tokens: vec![],
+            ca_tokens_file: None,
+            sessions: LoginSessions::default(),
+        }
+    }
+
+    /// Creates an Authorizer which loads, and saves, its runtime CA tokens
+    /// under the given work directory.
+    pub fn build(
+        krill_auth_token: &Token,
+        users: &[ApiUser],
+        work_dir: &PathBuf,
+    ) -> KrillResult<Self> {
+        let dir = file::sub_dir(work_dir, "auth")?;
+        let mut path = dir;
+        path.push(CA_TOKENS_FILE);
+
+        let ca_tokens = if path.exists() {
+            file::load_json(&path)?
+        } else {
+            vec![]
+        };
+
+        Ok(Authorizer {
+            krill_auth_token: krill_auth_token.clone(),
+            users: users.to_vec(),
+            ca_tokens,
+            ca_tokens_file: Some(path),
+            sessions: LoginSessions::default(),
+        })
+    }
+
+    /// Returns the actor for the given authentication, or None if the
+    /// authentication does not match any known token. Tokens are compared
+    /// in constant time.
+    pub fn actor(&self, auth: &Auth) -> Option<Actor> {
+        match auth {
+            Auth::Bearer(token) => {
+                if tokens_eq(&self.krill_auth_token, token) {
+                    Some(Actor::master())
+                } else {
+                    self.users
+                        .iter()
+                        .find(|user| tokens_eq(&user.token, token))
+                        .map(Actor::from)
+                        .or_else(|| {
+                            self.ca_tokens
+                                .iter()
+                                .find(|ca_token| ca_token.matches(token))
+                                .map(Actor::from)
+                        })
+                }
+            }
+            Auth::Session(id) => self.sessions.actor(id),
+        }
+    }
+
+    /// Gives access to the login sessions, to add or remove logins.
+    pub fn sessions_mut(&mut self) -> &mut LoginSessions {
+        &mut self.sessions
+    }
+
+    /// Returns the names and CAs of all runtime CA tokens. The secret
+    /// tokens themselves are not included.
+    pub fn ca_tokens(&self) -> CaTokenList {
+        CaTokenList::new(self.ca_tokens.iter().map(StoredCaToken::info).collect())
+    }
+
+    /// Adds a new CA token using the given secret. Names must be unique
+    /// across configured users and CA tokens, and the token must be bound
+    /// to at least one CA.
+    pub fn add_ca_token(&mut self, req: CaTokenRequest, token: Token) -> KrillResult<CaToken> {
+        let (name, cas) = req.unpack();
+
+        if self.users.iter().any(|u| u.name == name)
+            || self.ca_tokens.iter().any(|t| t.name == name)
+        {
+            return Err(Error::ApiTokenDuplicate(name));
+        }
+        if cas.is_empty() {
+            return Err(Error::ApiTokenNoCas(name));
+        }
+
+        let ca_token = CaToken::new(name, token, cas);
+        self.ca_tokens.push(StoredCaToken::new(&ca_token)?);
+        self.save()?;
+
+        Ok(ca_token)
+    }
+
+    /// Removes (revokes) a CA token, so that it can no longer be used.
+    pub fn remove_ca_token(&mut self, name: &str) -> KrillEmptyResult {
+        let before = self.ca_tokens.len();
+        self.ca_tokens.retain(|t| t.name != name);
+        if self.ca_tokens.len() == before {
+            return Err(Error::ApiTokenUnknown(name.to_string()));
+        }
+        self.save()
+    }
+
+    fn save(&self) -> KrillEmptyResult {
+        if let Some(path) = &self.ca_tokens_file {
+            file::save_json(&self.ca_tokens, path)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ StoredCaToken -------------------------------------------------
+
+/// A CA token as it is kept on disk and in memory. The secret itself is not
+/// kept, only a salted SHA-256 hash of it. Secrets are random 160 bit values,
+/// so a slow password hash is not needed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct StoredCaToken {
+    name: String,
+    cas: Vec<Handle>,
+    salt: String,
+    hash: String,
+}
+
+impl StoredCaToken {
+    fn new(ca_token: &CaToken) -> KrillResult<Self> {
+        let mut salt = [0; 16];
+        rand_bytes(&mut salt).map_err(Error::signer)?;
+        let hash = Self::hash(&salt, ca_token.token());
+
+        Ok(StoredCaToken {
+            name: ca_token.name().to_string(),
+            cas: ca_token.cas().to_vec(),
+            salt: hex::encode(salt),
+            hash: hex::encode(hash),
+        })
+    }
+
+    fn hash(salt: &[u8], token: &Token) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(token.as_ref().as_bytes());
+        hasher.finish()
+    }
+
+    /// Returns whether the given token is the secret of this CA token. The
+    /// hashes are compared in constant time.
+    fn matches(&self, token: &Token) -> bool {
+        match (hex::decode(&self.salt), hex::decode(&self.hash)) {
+            (Ok(salt), Ok(hash)) if hash.len() == 32 => {
+                memcmp::eq(&hash, &Self::hash(&salt, token))
+            }
+            _ => false,
+        }
+    }
+
+    fn info(&self) -> CaTokenInfo {
+        CaTokenInfo::new(self.name.clone(), self.cas.clone())
+    }
+}
+
+/// Compares the tokens in constant time, so that the time taken does not
+/// reveal how much of a token was guessed right.
+fn tokens_eq(known: &Token, token: &Token) -> bool {
+    let known = known.as_ref().as_bytes();
+    let token = token.as_ref().as_bytes();
+    known.len() == token.len() && memcmp::eq(known, token)
+}
+
+pub enum Auth {
+    Bearer(Token),
+    Session(String),
+}
+
+impl Auth {
+    pub fn bearer(token: Token) -> Self {
+        Auth::Bearer(token)
+    }
+
+    pub fn session(id: String) -> Self {
+        Auth::Session(id)
+    }
+}
+
+//------------ Role ----------------------------------------------------------
+
+/// The role of an API user, determining what the user is permitted to do.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Full access to everything.
+    Admin,
+
+    /// May read everything, and may update the ROAs of the listed CAs.
+    RoaEditor,
+
+    /// May read everything, but not change anything.
+    ReadOnly,
+
+    /// May read and manage the listed CAs only. Other CAs are invisible.
+    CaAdmin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Admin => write!(f, "admin"),
+            Role::RoaEditor => write!(f, "roa_editor"),
+            Role::ReadOnly => write!(f, "read_only"),
+            Role::CaAdmin => write!(f, "ca_admin"),
+        }
+    }
+}
+
+//------------ ApiUser -------------------------------------------------------
+
+/// A named user of the API, as configured in krill.conf:
+///
+/// ```toml
+/// [[api_users]]
+/// name = "alice"
+/// token = "alice-secret"
+/// role = "roa_editor"
+/// cas = [ "ca1", "ca2" ]
+/// ```
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ApiUser {
+    name: String,
+    token: Token,
+    role: Role,
+    #[serde(default)]
+    cas: Vec<Handle>,
+}
+
+impl ApiUser {
+    pub fn new(name: &str, token: Token, role: Role, cas: Vec<Handle>) -> Self {
+        ApiUser {
+            name: name.to_string(),
+            token,
+            role,
+            cas,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    pub fn role(&self) -> &Role {
+        &self.role
+    }
+
+    pub fn cas(&self) -> &[Handle] {
+        &self.cas
+    }
+}
+
+//------------ Permission ----------------------------------------------------
+
+/// Describes what is needed to access a part of the API.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Permission {
+    /// Any authenticated actor, e.g. to list the CAs it can see.
+    Authenticated,
+
+    /// Read (GET) access to anything not specific to a CA.
+    Read,
+
+    /// Read (GET) access to a CA.
+    CaRead(Handle),
+
+    /// Update the route authorizations (ROAs) for a CA.
+    RoutesUpdate(Handle),
+
+    /// Any other change to a CA.
+    CaUpdate(Handle),
+
+    /// Any other change.
+    Admin,
+}
+
+//------------ Actor ---------------------------------------------------------
+
+/// The resolved identity of whoever is using the API, or Krill itself for
+/// background operations. The name of the actor is recorded with the
+/// commands it sends, so that it shows up in the CA history.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Actor {
+    name: String,
+    role: Role,
+    cas: Vec<Handle>,
+}
+
+impl Actor {
+    pub fn new(name: &str, role: Role, cas: Vec<Handle>) -> Self {
+        Actor {
+            name: name.to_string(),
+            role,
+            cas,
+        }
+    }
+
+    /// The actor used for background operations done by Krill itself.
+    pub fn krill() -> Self {
+        Actor {
+            name: "krill".to_string(),
+            role: Role::Admin,
+            cas: vec![],
+        }
+    }
+
+    /// The actor using the master API token.
+    pub fn master() -> Self {
+        Actor {
+            name: "admin-token".to_string(),
+            role: Role::Admin,
+            cas: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn role(&self) -> &Role {
+        &self.role
+    }
+
+    /// Returns whether this actor has the given permission.
+    pub fn is_allowed(&self, permission: &Permission) -> bool {
+        match (&self.role, permission) {
+            (Role::Admin, _) => true,
+            (_, Permission::Authenticated) => true,
+            (Role::CaAdmin, Permission::CaRead(ca))
+            | (Role::CaAdmin, Permission::RoutesUpdate(ca))
+            | (Role::CaAdmin, Permission::CaUpdate(ca)) => self.cas.contains(ca),
+            (Role::CaAdmin, _) => false,
+            (_, Permission::Read) | (_, Permission::CaRead(_)) => true,
+            (Role::RoaEditor, Permission::RoutesUpdate(ca)) => self.cas.contains(ca),
+            _ => false,
+        }
+    }
+
+    /// Returns whether this actor may see the given CA.
+    pub fn can_see(&self, ca: &Handle) -> bool {
+        self.is_allowed(&Permission::CaRead(ca.clone()))
+    }
+}
+
+impl From<&ApiUser> for Actor {
+    fn from(user: &ApiUser) -> Self {
+        Actor {
+            name: user.name.clone(),
+            role: user.role.clone(),
+            cas: user.cas.clone(),
+        }
+    }
+}
+
+impl From<&StoredCaToken> for Actor {
+    fn from(ca_token: &StoredCaToken) -> Self {
+        Actor {
+            name: ca_token.name.clone(),
+            role: Role::CaAdmin,
+            cas: ca_token.cas.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.role)
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::test;
+
+    fn authorizer() -> Authorizer {
+        let users = vec![
+            ApiUser::new("reader", Token::from("r"), Role::ReadOnly, vec![]),
+            ApiUser::new(
+                "editor",
+                Token::from("e"),
+                Role::RoaEditor,
+                vec![Handle::from_str("ca1").unwrap()],
+            ),
+        ];
+        Authorizer::new(&Token::from("secret"), &users)
+    }
+
+    #[test]
+    fn resolve_actors() {
+        let authorizer = authorizer();
+
+        let master = authorizer.actor(&Auth::bearer(Token::from("secret")));
+        assert_eq!(Some(Actor::master()), master);
+
+        let reader = authorizer.actor(&Auth::bearer(Token::from("r"))).unwrap();
+        assert_eq!("reader", reader.name());
+
+        assert!(authorizer.actor(&Auth::bearer(Token::from("x"))).is_none());
+        assert!(authorizer
+            .actor(&Auth::bearer(Token::from("secret2")))
+            .is_none());
+    }
+
+    #[test]
+    fn check_permissions() {
+        let authorizer = authorizer();
+        let ca1 = Handle::from_str("ca1").unwrap();
+        let ca2 = Handle::from_str("ca2").unwrap();
+
+        let reader = authorizer.actor(&Auth::bearer(Token::from("r"))).unwrap();
+        assert!(reader.is_allowed(&Permission::Read));
+        assert!(!reader.is_allowed(&Permission::RoutesUpdate(ca1.clone())));
+        assert!(!reader.is_allowed(&Permission::Admin));
+
+        let editor = authorizer.actor(&Auth::bearer(Token::from("e"))).unwrap();
+        assert!(editor.is_allowed(&Permission::Read));
+        assert!(editor.is_allowed(&Permission::RoutesUpdate(ca1)));
+        assert!(!editor.is_allowed(&Permission::RoutesUpdate(ca2)));
+        assert!(!editor.is_allowed(&Permission::Admin));
+
+        let master = Actor::master();
+        assert!(master.is_allowed(&Permission::Admin));
+    }
+
+    #[test]
+    fn ca_tokens() {
+        let mut authorizer = authorizer();
+        let ca1 = Handle::from_str("ca1").unwrap();
+        let ca2 = Handle::from_str("ca2").unwrap();
+
+        let req = CaTokenRequest::new("unit-a", vec![ca1.clone()]);
+        authorizer.add_ca_token(req, Token::from("a")).unwrap();
+
+        let unit_a = authorizer.actor(&Auth::bearer(Token::from("a"))).unwrap();
+        assert!(unit_a.can_see(&ca1));
+        assert!(!unit_a.can_see(&ca2));
+        assert!(unit_a.is_allowed(&Permission::CaUpdate(ca1.clone())));
+        assert!(unit_a.is_allowed(&Permission::RoutesUpdate(ca1.clone())));
+        assert!(!unit_a.is_allowed(&Permission::CaUpdate(ca2.clone())));
+        assert!(!unit_a.is_allowed(&Permission::Read));
+        assert!(!unit_a.is_allowed(&Permission::Admin));
+        assert!(unit_a.is_allowed(&Permission::Authenticated));
+
+        // names must be unique, also with regards to configured users
+        let req = CaTokenRequest::new("editor", vec![ca2.clone()]);
+        assert!(authorizer.add_ca_token(req, Token::from("b")).is_err());
+
+        // tokens must be bound to a CA
+        let req = CaTokenRequest::new("unit-b", vec![]);
+        assert!(authorizer.add_ca_token(req, Token::from("b")).is_err());
+
+        assert_eq!(1, authorizer.ca_tokens().tokens().len());
+
+        authorizer.remove_ca_token("unit-a").unwrap();
+        assert!(authorizer.actor(&Auth::bearer(Token::from("a"))).is_none());
+        assert!(authorizer.remove_ca_token("unit-a").is_err());
+    }
+
+    #[test]
+    fn ca_tokens_are_hashed_on_disk() {
+        test::test_under_tmp(|d| {
+            let master = Token::from("secret");
+            let ca1 = Handle::from_str("ca1").unwrap();
+            let path = d.join("auth").join(CA_TOKENS_FILE);
+
+            let mut authorizer = Authorizer::build(&master, &[], &d).unwrap();
+            let req = CaTokenRequest::new("unit-a", vec![ca1]);
+            authorizer
+                .add_ca_token(req, Token::from("a-secret"))
+                .unwrap();
+
+            let saved = std::fs::read_to_string(&path).unwrap();
+            assert!(!saved.contains("a-secret"));
+
+            let authorizer = Authorizer::build(&master, &[], &d).unwrap();
+            let unit_a = authorizer.actor(&Auth::bearer(Token::from("a-secret")));
+            assert_eq!("unit-a", unit_a.unwrap().name());
+            assert!(authorizer
+                .actor(&Auth::bearer(Token::from("a-secre")))
+                .is_none());
+        })
+    }
+}
@@ -0,0 +1,715 @@
+//! Support for logging in through an OpenID Connect (OIDC) provider.
+//!
+//! Krill acts as a relying party using the authorization code flow. Once
+//! a user has logged in, the claims in their ID token are mapped to a Krill
+//! [`Role`], and a session is started. The session id is kept in a cookie
+//! so that the UI (and API) can be used without the need for a token.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::Utc;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use reqwest::Url;
+use serde_json::{Map, Value};
+
+use crate::commons::api::Handle;
+use crate::commons::error::Error;
+use crate::commons::KrillResult;
+use crate::constants::HTTTP_CLIENT_TIMEOUT_SECS;
+use crate::daemon::auth::{Actor, Role};
+
+/// The name of the cookie holding the session id after login.
+pub const SESSION_COOKIE: &str = "krill_session";
+
+/// The name of the cookie binding the login state to the browser.
+pub const LOGIN_STATE_COOKIE: &str = "krill_login_state";
+
+/// The number of seconds a user has to complete a login at the provider.
+const PENDING_LOGIN_SECS: i64 = 600;
+
+//------------ OidcConfig ----------------------------------------------------
+
+/// The OpenID Connect configuration, as set in krill.conf:
+///
+/// ```toml
+/// [auth_openidconnect]
+/// issuer_url = "https://login.example.com/realms/krill"
+/// client_id = "krill"
+/// client_secret = "secret"
+///
+/// [auth_openidconnect.role_map]
+/// "krill-admins" = "admin"
+/// "noc" = "roa_editor"
+/// ```
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+
+    #[serde(default = "OidcConfig::default_scopes")]
+    pub scopes: Vec<String>,
+
+    /// The claim used for the name of the user, falls back to 'sub'.
+    #[serde(default = "OidcConfig::default_name_claim")]
+    pub name_claim: String,
+
+    /// The claim containing the role, or roles, of the user.
+    #[serde(default = "OidcConfig::default_role_claim")]
+    pub role_claim: String,
+
+    /// The claim containing the CAs for roles bound to CAs.
+    #[serde(default = "OidcConfig::default_cas_claim")]
+    pub cas_claim: String,
+
+    /// Maps values of the role claim to Krill roles. If this is empty the
+    /// claim values are expected to be Krill role names.
+    #[serde(default)]
+    pub role_map: HashMap<String, Role>,
+
+    /// The number of seconds a session stays valid after login.
+    #[serde(default = "OidcConfig::default_session_ttl")]
+    pub session_ttl: i64,
+}
+
+impl OidcConfig {
+    fn default_scopes() -> Vec<String> {
+        vec![
+            "openid".to_string(),
+            "profile".to_string(),
+            "email".to_string(),
+        ]
+    }
+
+    fn default_name_claim() -> String {
+        "email".to_string()
+    }
+
+    fn default_role_claim() -> String {
+        "krill_role".to_string()
+    }
+
+    fn default_cas_claim() -> String {
+        "krill_cas".to_string()
+    }
+
+    fn default_session_ttl() -> i64 {
+        8 * 3600
+    }
+
+    /// Checks that the issuer is a valid https URI. Plain http is only
+    /// allowed for localhost, so that a local mock provider can be used
+    /// for testing.
+    pub fn verify(&self) -> Result<(), String> {
+        let uri = Url::parse(&self.issuer_url)
+            .map_err(|e| format!("invalid issuer_url '{}': {}", self.issuer_url, e))?;
+        let local = uri.host_str() == Some("localhost") || uri.host_str() == Some("127.0.0.1");
+        if uri.scheme() != "https" && !(uri.scheme() == "http" && local) {
+            return Err("issuer_url must use https".to_string());
+        }
+        if self.client_id.is_empty() {
+            return Err("client_id must be set".to_string());
+        }
+        if self.session_ttl <= 0 {
+            return Err("session_ttl must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+//------------ ProviderMetadata ----------------------------------------------
+
+/// The parts of the provider's discovery document which Krill uses.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+//------------ JwkSet --------------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+//------------ OidcClient ----------------------------------------------------
+
+/// Talks to the OpenID Connect provider. The discovery document and keys
+/// are fetched for every login, so that key rollovers at the provider are
+/// picked up without the need to restart Krill.
+#[derive(Clone, Debug)]
+pub struct OidcClient {
+    config: OidcConfig,
+    redirect_uri: String,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig, redirect_uri: String) -> Self {
+        OidcClient {
+            config,
+            redirect_uri,
+        }
+    }
+
+    pub fn session_ttl(&self) -> i64 {
+        self.config.session_ttl
+    }
+
+    fn http(&self) -> KrillResult<reqwest::Client> {
+        reqwest::ClientBuilder::new()
+            .timeout(Duration::from_secs(HTTTP_CLIENT_TIMEOUT_SECS))
+            .build()
+            .map_err(Error::login_failed)
+    }
+
+    /// Fetches the provider's discovery document.
+    pub async fn metadata(&self) -> KrillResult<ProviderMetadata> {
+        let uri = format!(
+            "{}/.well-known/openid-configuration",
+            self.config.issuer_url.trim_end_matches('/')
+        );
+        let meta: ProviderMetadata = self
+            .http()?
+            .get(&uri)
+            .send()
+            .await
+            .map_err(Error::login_failed)?
+            .json()
+            .await
+            .map_err(Error::login_failed)?;
+
+        if meta.issuer.trim_end_matches('/') != self.config.issuer_url.trim_end_matches('/') {
+            return Err(Error::login_failed(format!(
+                "provider uses issuer '{}', expected '{}'",
+                meta.issuer, self.config.issuer_url
+            )));
+        }
+        Ok(meta)
+    }
+
+    /// Returns the URI at the provider to which the user must be sent.
+    pub fn authorization_uri(
+        &self,
+        meta: &ProviderMetadata,
+        state: &str,
+        nonce: &str,
+    ) -> KrillResult<String> {
+        let scopes = self.config.scopes.join(" ");
+        let uri = Url::parse_with_params(
+            &meta.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.config.client_id.as_str()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("scope", scopes.as_str()),
+                ("state", state),
+                ("nonce", nonce),
+            ],
+        )
+        .map_err(Error::login_failed)?;
+        Ok(uri.to_string())
+    }
+
+    /// Exchanges the authorization code for an ID token, verifies it, and
+    /// returns the actor for the user.
+    pub async fn login(&self, code: &str, nonce: &str) -> KrillResult<Actor> {
+        let meta = self.metadata().await?;
+
+        let params = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_uri.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("client_secret", self.config.client_secret.as_str()),
+        ];
+        let res = self
+            .http()?
+            .post(&meta.token_endpoint)
+            .form(&params)
+            .send()
+            .await
+            .map_err(Error::login_failed)?;
+        if !res.status().is_success() {
+            return Err(Error::login_failed(format!(
+                "token endpoint returned {}",
+                res.status()
+            )));
+        }
+        let token: TokenResponse = res.json().await.map_err(Error::login_failed)?;
+
+        let jwks: JwkSet = self
+            .http()?
+            .get(&meta.jwks_uri)
+            .send()
+            .await
+            .map_err(Error::login_failed)?
+            .json()
+            .await
+            .map_err(Error::login_failed)?;
+
+        let claims = verify_id_token(
+            &token.id_token,
+            &jwks,
+            &meta.issuer,
+            &self.config.client_id,
+            nonce,
+            Utc::now().timestamp(),
+        )?;
+
+        self.actor(&claims)
+    }
+
+    /// Maps the claims of a verified ID token to an actor.
+    fn actor(&self, claims: &Map<String, Value>) -> KrillResult<Actor> {
+        let name = claims
+            .get(&self.config.name_claim)
+            .or_else(|| claims.get("sub"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::login_failed("no name or subject in ID token"))?;
+
+        let role = claim_values(claims, &self.config.role_claim)
+            .into_iter()
+            .filter_map(|value| {
+                if self.config.role_map.is_empty() {
+                    serde_json::from_value(Value::String(value)).ok()
+                } else {
+                    self.config.role_map.get(&value).cloned()
+                }
+            })
+            .next()
+            .ok_or_else(|| {
+                Error::login_failed(format!(
+                    "no Krill role for user '{}' in claim '{}'",
+                    name, self.config.role_claim
+                ))
+            })?;
+
+        let mut cas = vec![];
+        for value in claim_values(claims, &self.config.cas_claim) {
+            let ca: Handle = value
+                .parse()
+                .map_err(|_| Error::login_failed(format!("invalid CA name '{}'", value)))?;
+            cas.push(ca);
+        }
+
+        Ok(Actor::new(name, role, cas))
+    }
+}
+
+/// Returns the string value(s) of a claim, which may be a single string
+/// or an array of strings.
+fn claim_values(claims: &Map<String, Value>, claim: &str) -> Vec<String> {
+    match claims.get(claim) {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn decode_b64(s: &str) -> KrillResult<Vec<u8>> {
+    base64::decode_config(s, base64::URL_SAFE_NO_PAD).map_err(Error::login_failed)
+}
+
+/// Verifies an RS256 signed ID token, and returns its claims. The token
+/// must be issued by the issuer for the client, must not be expired, and
+/// must contain the nonce which was sent to the provider at login.
+fn verify_id_token(
+    id_token: &str,
+    jwks: &JwkSet,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: i64,
+) -> KrillResult<Map<String, Value>> {
+    let parts: Vec<&str> = id_token.split('.').collect();
+    if parts.len() != 3 {
+        return Err(Error::login_failed("malformed ID token"));
+    }
+
+    let header: JwtHeader =
+        serde_json::from_slice(&decode_b64(parts[0])?).map_err(Error::login_failed)?;
+    if header.alg != "RS256" {
+        return Err(Error::login_failed(format!(
+            "unsupported ID token algorithm '{}'",
+            header.alg
+        )));
+    }
+
+    let key = jwks
+        .keys
+        .iter()
+        .find(|key| key.kty == "RSA" && (header.kid.is_none() || key.kid == header.kid))
+        .ok_or_else(|| Error::login_failed("no matching key for ID token"))?;
+
+    let (n, e) = match (&key.n, &key.e) {
+        (Some(n), Some(e)) => (n, e),
+        _ => return Err(Error::login_failed("invalid RSA key in provider key set")),
+    };
+    let n = BigNum::from_slice(&decode_b64(n)?).map_err(Error::login_failed)?;
+    let e = BigNum::from_slice(&decode_b64(e)?).map_err(Error::login_failed)?;
+    let rsa = Rsa::from_public_components(n, e).map_err(Error::login_failed)?;
+    let pkey = PKey::from_rsa(rsa).map_err(Error::login_failed)?;
+
+    let signature = decode_b64(parts[2])?;
+    let mut verifier =
+        Verifier::new(MessageDigest::sha256(), &pkey).map_err(Error::login_failed)?;
+    verifier
+        .update(format!("{}.{}", parts[0], parts[1]).as_bytes())
+        .map_err(Error::login_failed)?;
+    if !verifier.verify(&signature).map_err(Error::login_failed)? {
+        return Err(Error::login_failed("invalid ID token signature"));
+    }
+
+    let claims: Map<String, Value> =
+        serde_json::from_slice(&decode_b64(parts[1])?).map_err(Error::login_failed)?;
+
+    if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+        return Err(Error::login_failed("ID token has wrong issuer"));
+    }
+    if !claim_values(&claims, "aud")
+        .iter()
+        .any(|aud| aud == client_id)
+    {
+        return Err(Error::login_failed("ID token is not meant for Krill"));
+    }
+    match claims.get("exp").and_then(Value::as_i64) {
+        Some(exp) if exp > now => {}
+        _ => return Err(Error::login_failed("ID token has expired")),
+    }
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err(Error::login_failed("ID token nonce does not match"));
+    }
+
+    Ok(claims)
+}
+
+//------------ LoginSessions -------------------------------------------------
+
+/// Keeps track of logins in progress, and of the sessions of users who
+/// logged in. These are kept in memory only, so users will need to log in
+/// again after Krill is restarted.
+#[derive(Clone, Debug, Default)]
+pub struct LoginSessions {
+    pending: HashMap<String, (String, i64)>,
+    sessions: HashMap<String, (Actor, i64)>,
+}
+
+impl LoginSessions {
+    /// Remembers the nonce for a login in progress, identified by state.
+    pub fn add_pending(&mut self, state: String, nonce: String) {
+        let now = Utc::now().timestamp();
+        self.pending.retain(|_, (_, expires)| *expires > now);
+        self.pending
+            .insert(state, (nonce, now + PENDING_LOGIN_SECS));
+    }
+
+    /// Returns the nonce for a login in progress, if it did not expire.
+    /// A state can only be used once.
+    pub fn take_pending(&mut self, state: &str) -> Option<String> {
+        let now = Utc::now().timestamp();
+        match self.pending.remove(state) {
+            Some((nonce, expires)) if expires > now => Some(nonce),
+            _ => None,
+        }
+    }
+
+    pub fn add_session(&mut self, id: String, actor: Actor, ttl: i64) {
+        let now = Utc::now().timestamp();
+        self.sessions.retain(|_, (_, expires)| *expires > now);
+        self.sessions.insert(id, (actor, now + ttl));
+    }
+
+    pub fn remove_session(&mut self, id: &str) {
+        self.sessions.remove(id);
+    }
+
+    /// Returns the actor for a session, if it exists and did not expire.
+    pub fn actor(&self, id: &str) -> Option<Actor> {
+        let now = Utc::now().timestamp();
+        match self.sessions.get(id) {
+            Some((actor, expires)) if *expires > now => Some(actor.clone()),
+            _ => None,
+        }
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Method, Request, Response};
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+    use serde_json::json;
+
+    use crate::daemon::auth::Permission;
+
+    const CLIENT_ID: &str = "krill";
+    const CLIENT_SECRET: &str = "client-secret";
+
+    fn b64(bytes: &[u8]) -> String {
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    }
+
+    fn sign(key: &PKey<Private>, claims: &Value) -> String {
+        let header = b64(br#"{"alg":"RS256","kid":"test"}"#);
+        let payload = b64(claims.to_string().as_bytes());
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer
+            .update(format!("{}.{}", header, payload).as_bytes())
+            .unwrap();
+        let signature = b64(&signer.sign_to_vec().unwrap());
+        format!("{}.{}.{}", header, payload, signature)
+    }
+
+    fn jwks(key: &PKey<Private>) -> Value {
+        let rsa = key.rsa().unwrap();
+        json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": "test",
+                "n": b64(&rsa.n().to_vec()),
+                "e": b64(&rsa.e().to_vec()),
+            }]
+        })
+    }
+
+    fn claims_json(issuer: &str, nonce: &str) -> Value {
+        json!({
+            "iss": issuer,
+            "sub": "1234",
+            "aud": CLIENT_ID,
+            "exp": Utc::now().timestamp() + 300,
+            "nonce": nonce,
+            "email": "alice@example.com",
+            "groups": ["staff", "noc"],
+            "krill_cas": ["ca1"],
+        })
+    }
+
+    /// A minimal OpenID Connect provider, which hands out ID tokens for
+    /// 'alice'. The authorization code is expected to be the nonce used
+    /// in the login, so that the test can skip the browser interaction.
+    async fn mock_provider(
+        req: Request<Body>,
+        key: PKey<Private>,
+        issuer: String,
+    ) -> Result<Response<Body>, Infallible> {
+        let body = match (req.method(), req.uri().path()) {
+            (&Method::GET, "/.well-known/openid-configuration") => json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "jwks_uri": format!("{}/jwks", issuer),
+            }),
+            (&Method::GET, "/jwks") => jwks(&key),
+            (&Method::POST, "/token") => {
+                let bytes = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                let form: HashMap<String, String> = Url::parse("http://localhost/")
+                    .unwrap()
+                    .join(&format!("?{}", String::from_utf8_lossy(&bytes)))
+                    .unwrap()
+                    .query_pairs()
+                    .into_owned()
+                    .collect();
+                if form.get("client_secret").map(String::as_str) != Some(CLIENT_SECRET) {
+                    return Ok(Response::builder().status(401).body(Body::empty()).unwrap());
+                }
+                let nonce = form.get("code").unwrap();
+                json!({ "id_token": sign(&key, &claims_json(&issuer, nonce)) })
+            }
+            _ => return Ok(Response::builder().status(404).body(Body::empty()).unwrap()),
+        };
+        Ok(Response::new(Body::from(body.to_string())))
+    }
+
+    async fn start_mock_provider(key: PKey<Private>) -> String {
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = std::net::TcpListener::bind(addr).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let service_issuer = issuer.clone();
+        let make_svc = make_service_fn(move |_| {
+            let key = key.clone();
+            let issuer = service_issuer.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    mock_provider(req, key.clone(), issuer.clone())
+                }))
+            }
+        });
+        let server = hyper::Server::from_tcp(listener).unwrap().serve(make_svc);
+        tokio::spawn(server);
+
+        issuer
+    }
+
+    fn config(issuer: &str) -> OidcConfig {
+        let mut role_map = HashMap::new();
+        role_map.insert("noc".to_string(), Role::RoaEditor);
+
+        OidcConfig {
+            issuer_url: issuer.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: CLIENT_SECRET.to_string(),
+            scopes: OidcConfig::default_scopes(),
+            name_claim: OidcConfig::default_name_claim(),
+            role_claim: "groups".to_string(),
+            cas_claim: OidcConfig::default_cas_claim(),
+            role_map,
+            session_ttl: OidcConfig::default_session_ttl(),
+        }
+    }
+
+    fn test_key() -> PKey<Private> {
+        PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn login_with_mock_provider() {
+        let key = test_key();
+        let issuer = start_mock_provider(key).await;
+
+        let client = OidcClient::new(
+            config(&issuer),
+            "https://localhost:3000/auth/callback".to_string(),
+        );
+
+        let meta = client.metadata().await.unwrap();
+        let uri = client.authorization_uri(&meta, "state", "nonce").unwrap();
+        assert!(uri.starts_with(&format!("{}/authorize?response_type=code", issuer)));
+        assert!(uri.contains("state=state"));
+        assert!(uri.contains("nonce=nonce"));
+
+        let actor = client.login("nonce", "nonce").await.unwrap();
+        assert_eq!("alice@example.com", actor.name());
+        assert_eq!(&Role::RoaEditor, actor.role());
+
+        let ca1 = Handle::from_str("ca1").unwrap();
+        let ca2 = Handle::from_str("ca2").unwrap();
+        assert!(actor.is_allowed(&Permission::RoutesUpdate(ca1)));
+        assert!(!actor.is_allowed(&Permission::RoutesUpdate(ca2)));
+
+        // The ID token is bound to the nonce of the login
+        assert!(client.login("other", "nonce").await.is_err());
+    }
+
+    #[test]
+    fn map_claims_to_actor() {
+        let issuer = "https://idp.example.com";
+        let redirect_uri = "https://localhost:3000/auth/callback".to_string();
+        let claims = |value: Value| value.as_object().unwrap().clone();
+
+        // Groups are mapped to roles using the role map
+        let client = OidcClient::new(config(issuer), redirect_uri.clone());
+        let actor = client.actor(&claims(claims_json(issuer, "n"))).unwrap();
+        assert_eq!("alice@example.com", actor.name());
+        assert_eq!(&Role::RoaEditor, actor.role());
+
+        // Without a role map the claim must contain a Krill role name
+        let mut config = config(issuer);
+        config.role_map.clear();
+        config.role_claim = "krill_role".to_string();
+        let client = OidcClient::new(config, redirect_uri);
+
+        let mut with_role = claims_json(issuer, "n");
+        with_role["krill_role"] = json!("read_only");
+        with_role.as_object_mut().unwrap().remove("email");
+        let actor = client.actor(&claims(with_role)).unwrap();
+        assert_eq!("1234", actor.name());
+        assert_eq!(&Role::ReadOnly, actor.role());
+
+        // Users without a role cannot log in
+        assert!(client.actor(&claims(claims_json(issuer, "n"))).is_err());
+
+        // CA names must be valid
+        let mut invalid_ca = claims_json(issuer, "n");
+        invalid_ca["krill_role"] = json!("roa_editor");
+        invalid_ca["krill_cas"] = json!(["not a handle!"]);
+        assert!(client.actor(&claims(invalid_ca)).is_err());
+    }
+
+    #[test]
+    fn verify_id_tokens() {
+        let key = test_key();
+        let jwks: JwkSet = serde_json::from_value(jwks(&key)).unwrap();
+        let issuer = "https://idp.example.com";
+        let now = Utc::now().timestamp();
+
+        let verify = |claims: &Value| {
+            verify_id_token(&sign(&key, claims), &jwks, issuer, CLIENT_ID, "n", now)
+        };
+
+        assert!(verify(&claims_json(issuer, "n")).is_ok());
+        assert!(verify(&claims_json(issuer, "other")).is_err());
+        assert!(verify(&claims_json("https://evil.example.com", "n")).is_err());
+
+        let mut wrong_aud = claims_json(issuer, "n");
+        wrong_aud["aud"] = json!(["other-client"]);
+        assert!(verify(&wrong_aud).is_err());
+
+        let mut expired = claims_json(issuer, "n");
+        expired["exp"] = json!(now - 1);
+        assert!(verify(&expired).is_err());
+
+        let other_key = test_key();
+        let forged = sign(&other_key, &claims_json(issuer, "n"));
+        assert!(verify_id_token(&forged, &jwks, issuer, CLIENT_ID, "n", now).is_err());
+    }
+
+    #[test]
+    fn sessions() {
+        let mut sessions = LoginSessions::default();
+
+        sessions.add_pending("state".to_string(), "nonce".to_string());
+        assert_eq!(Some("nonce".to_string()), sessions.take_pending("state"));
+        assert_eq!(None, sessions.take_pending("state"));
+
+        sessions.add_session("id".to_string(), Actor::master(), 60);
+        assert_eq!(Some(Actor::master()), sessions.actor("id"));
+        sessions.remove_session("id");
+        assert_eq!(None, sessions.actor("id"));
+
+        sessions.add_session("id".to_string(), Actor::master(), -1);
+        assert_eq!(None, sessions.actor("id"));
+    }
+}
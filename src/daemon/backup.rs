@@ -0,0 +1,740 @@
+//! Online backups of the Krill data directory, and restoring them.
+//!
+//! A backup is a tar archive of the directories which hold the
+//! state of Krill. The derived repository content (the 'repo' directory) is
+//! not included: it is written again when the backup is restored. The first
+//! entry in the archive is a manifest listing all files with their size and
+//! hash, so that the integrity of the archive can be checked.
+//!
+//! The manifest also lists the highest manifest and CRL number used by each
+//! CA. Relying parties may have seen these numbers, and they will not accept
+//! lower numbers, so new manifests and CRLs with higher numbers are published
+//! when Krill starts after a restore. The CAs may have published more often
+//! after the backup was made, so the numbers are raised by a margin which
+//! grows with the age of the backup, see RESTORE_NUMBERS_PER_MINUTE.
+use std::collections::HashMap;
+use std::io::{BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::{fmt, fs};
+
+use rpki::x509::Time;
+
+use crate::commons::api::{Handle, HexEncodedHash};
+use crate::commons::error::Error;
+use crate::commons::eventsourcing::{
+    AggregateStore, AggregateStoreError, AnyKeyStore, DiskAggregateStore, KeyStore,
+};
+use crate::commons::util::file;
+use crate::commons::util::tar::{TarEntry, TarReader, TarWriter};
+use crate::commons::KrillResult;
+use crate::constants::*;
+use crate::pubd::{CmdDet, Repository, SessionReset};
+
+/// The directories under the data directory which are included in a backup.
+pub const BACKUP_DIRS: &[&str] = &[
+    CASERVER_DIR,
+    PUBSERVER_DIR,
+    HISTORY_ARCHIVE_DIR,
+    "keys",
+    "auth",
+];
+
+/// The name of the manifest, which is the first entry in the archive.
+pub const BACKUP_MANIFEST: &str = "backup.json";
+
+/// The file in the data directory which keeps the publication numbers from
+/// a restored backup, until new manifests and CRLs were published.
+pub const RESTORED_NUMBERS_FILE: &str = "restored_publication_numbers.json";
+
+/// The number by which manifest and CRL numbers are raised after a restore,
+/// for each minute that passed since the backup was made. This allows a CA
+/// to have published once every second since then, which is far more than
+/// Krill does. Numbers can go up to 2^159, so a large margin does no harm.
+pub const RESTORE_NUMBERS_PER_MINUTE: u64 = 60;
+
+//------------ BackupManifest ------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BackupManifest {
+    krill_version: String,
+    created: Time,
+    data_dir: PathBuf,
+    files: Vec<BackupFile>,
+
+    /// The highest manifest and CRL number used by each CA.
+    #[serde(default)]
+    publication_numbers: HashMap<Handle, u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BackupFile {
+    path: String,
+    size: usize,
+    hash: HexEncodedHash,
+}
+
+impl BackupManifest {
+    pub fn created(&self) -> Time {
+        self.created
+    }
+
+    pub fn krill_version(&self) -> &str {
+        &self.krill_version
+    }
+
+    pub fn publication_numbers(&self) -> &HashMap<Handle, u64> {
+        &self.publication_numbers
+    }
+}
+
+//------------ Backup --------------------------------------------------------
+
+/// Creates backups of the data directory.
+pub struct Backup;
+
+impl Backup {
+    /// Writes a tar archive of the state in the data directory to the
+    /// target file. Files are read one at a time, so that the archive does
+    /// not need to fit in memory: first to find their hashes for the
+    /// manifest, and then to add them to the archive.
+    ///
+    /// The caller must make sure that the stores are not changed while the
+    /// backup is made, and provide the highest publication number of each
+    /// CA, see KrillServer::backup.
+    pub fn create(
+        data_dir: &Path,
+        publication_numbers: HashMap<Handle, u64>,
+        target: &Path,
+    ) -> KrillResult<BackupManifest> {
+        let mut paths = vec![];
+        for dir in BACKUP_DIRS {
+            let path = data_dir.join(dir);
+            if path.is_dir() {
+                Self::read_dir(&path, dir, &mut paths)?;
+            }
+        }
+        paths.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut files = Vec::with_capacity(paths.len());
+        for (rel, path) in &paths {
+            let content = file::read(path)?;
+            files.push(BackupFile {
+                path: rel.clone(),
+                size: content.len(),
+                hash: HexEncodedHash::from_content(&content),
+            });
+        }
+
+        let manifest = BackupManifest {
+            krill_version: KRILL_VERSION.to_string(),
+            created: Time::now(),
+            data_dir: data_dir.to_path_buf(),
+            files,
+            publication_numbers,
+        };
+        let manifest_json = serde_json::to_string_pretty(&manifest).map_err(Error::JsonError)?;
+
+        let out = file::create_file_with_path(&target.to_path_buf())?;
+        let mut tar = TarWriter::new(BufWriter::new(&out));
+        tar.append(
+            BACKUP_MANIFEST,
+            manifest.created.timestamp() as u64,
+            manifest_json.as_bytes(),
+        )?;
+        for (rel, path) in &paths {
+            let content = file::read(path)?;
+            tar.append(rel, Self::mtime(path)?, &content)?;
+        }
+        tar.finish()?;
+        out.sync_all()?;
+
+        Ok(manifest)
+    }
+
+    /// Adds the relative and full paths of all files under a directory.
+    fn read_dir(dir: &PathBuf, rel: &str, paths: &mut Vec<(String, PathBuf)>) -> KrillResult<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let rel = format!("{}/{}", rel, entry.file_name().to_string_lossy());
+            if path.is_dir() {
+                Self::read_dir(&path, &rel, paths)?;
+            } else {
+                paths.push((rel, path));
+            }
+        }
+        Ok(())
+    }
+
+    fn mtime(path: &Path) -> KrillResult<u64> {
+        Ok(fs::metadata(path)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0))
+    }
+}
+
+//------------ RestorePoint --------------------------------------------------
+
+/// The point in history to restore to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RestorePoint {
+    /// Restore the state at the time of the backup.
+    Latest,
+
+    /// Restore the state at the given time. Commands after this time are
+    /// removed, and so are CAs and publication servers created after it.
+    Time(Time),
+
+    /// Restore the given CA (or publication server) up to and including the
+    /// command with the given sequence, and everything else to the time of
+    /// that command.
+    Command(Handle, u64),
+}
+
+impl fmt::Display for RestorePoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RestorePoint::Latest => write!(f, "latest"),
+            RestorePoint::Time(time) => write!(f, "{}", time.to_rfc3339()),
+            RestorePoint::Command(handle, seq) => write!(f, "{}:{}", handle, seq),
+        }
+    }
+}
+
+//------------ Restore -------------------------------------------------------
+
+/// Restores a backup into a data directory. The archive is read as a stream,
+/// once to check its integrity, and once more to write the files.
+pub struct Restore<R> {
+    manifest: BackupManifest,
+    archive: R,
+}
+
+impl<R: Read + Seek> Restore<R> {
+    /// Reads a backup archive, and checks its integrity: all files in the
+    /// manifest must be present with the right size and hash, and there may
+    /// not be any other files.
+    pub fn read(mut archive: R) -> KrillResult<Self> {
+        let (manifest, mut reader) = Self::read_manifest(&mut archive)?;
+
+        let mut files = manifest.files.iter();
+        while let Some(entry) = Self::next_entry(&mut reader)? {
+            match files.next() {
+                Some(file) => Self::check_entry(file, &entry)?,
+                None => {
+                    return Err(Error::BackupInvalid(format!(
+                        "unexpected file in archive: {}",
+                        entry.path()
+                    )))
+                }
+            }
+        }
+        if let Some(file) = files.next() {
+            return Err(Error::BackupInvalid(format!(
+                "missing file in archive: {}",
+                file.path
+            )));
+        }
+
+        archive.seek(SeekFrom::Start(0))?;
+        Ok(Restore { manifest, archive })
+    }
+
+    fn read_manifest(archive: &mut R) -> KrillResult<(BackupManifest, TarReader<&mut R>)> {
+        let mut reader = TarReader::new(archive);
+        let manifest = match Self::next_entry(&mut reader)? {
+            Some(entry) if entry.path() == BACKUP_MANIFEST => {
+                serde_json::from_slice(entry.content())
+                    .map_err(|e| Error::BackupInvalid(format!("cannot parse manifest: {}", e)))?
+            }
+            _ => {
+                return Err(Error::BackupInvalid(format!(
+                    "archive does not start with {}",
+                    BACKUP_MANIFEST
+                )))
+            }
+        };
+        Ok((manifest, reader))
+    }
+
+    fn next_entry(reader: &mut TarReader<&mut R>) -> KrillResult<Option<TarEntry>> {
+        reader
+            .next_entry()
+            .map_err(|e| Error::BackupInvalid(e.to_string()))
+    }
+
+    fn check_entry(file: &BackupFile, entry: &TarEntry) -> KrillResult<()> {
+        if file.path != entry.path() {
+            return Err(Error::BackupInvalid(format!(
+                "unexpected file in archive: {}",
+                entry.path()
+            )));
+        }
+        if file.size != entry.content().len()
+            || file.hash != HexEncodedHash::from_content(entry.content())
+        {
+            return Err(Error::BackupInvalid(format!(
+                "hash mismatch for file: {}",
+                file.path
+            )));
+        }
+        if file
+            .path
+            .split('/')
+            .any(|part| part == ".." || part.is_empty())
+            || !BACKUP_DIRS
+                .iter()
+                .any(|dir| file.path.starts_with(&format!("{}/", dir)))
+        {
+            return Err(Error::BackupInvalid(format!(
+                "file outside of backup directories: {}",
+                file.path
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn manifest(&self) -> &BackupManifest {
+        &self.manifest
+    }
+
+    /// Writes the backup to the data directory, and rewinds history to the
+    /// restore point. If there is a publication server, then it will start
+    /// a new RRDP session, because relying parties may have seen later
+    /// serials than the one restored. The RRDP and rsync files are written.
+    ///
+    /// The publication numbers in the manifest are saved in the data
+    /// directory, so that the CAs publish new manifests and CRLs with higher
+    /// numbers when Krill starts, see restored_publication_numbers.
+    ///
+    /// The data directory must be the same as the one used when the backup
+    /// was made, because paths under it are kept in the state, and it may
+    /// not contain any of the restored directories yet.
+    pub fn restore(
+        mut self,
+        data_dir: &PathBuf,
+        point: &RestorePoint,
+    ) -> KrillResult<RestoreReport> {
+        if data_dir != &self.manifest.data_dir {
+            return Err(Error::RestoreNotPossible(format!(
+                "backup was made for data directory '{}'",
+                self.manifest.data_dir.to_string_lossy()
+            )));
+        }
+        for dir in BACKUP_DIRS.iter().chain(&[REPOSITORY_DIR]) {
+            if data_dir.join(dir).exists() {
+                return Err(Error::RestoreNotPossible(format!(
+                    "data directory already contains '{}'",
+                    dir
+                )));
+            }
+        }
+
+        // Keep the modification times of the init events, to find out
+        // which aggregates were created after a point in time. A database
+        // keeps these times itself.
+        // The entries are checked again, in case the archive was changed
+        // after it was read.
+        let mut created: HashMap<String, u64> = HashMap::new();
+        let (_, mut reader) = Self::read_manifest(&mut self.archive)?;
+        let mut files = 0;
+        for file in &self.manifest.files {
+            let entry = Self::next_entry(&mut reader)?.ok_or_else(|| {
+                Error::BackupInvalid(format!("missing file in archive: {}", file.path))
+            })?;
+            Self::check_entry(file, &entry)?;
+            files += 1;
+
+            let (path, mtime, content) = entry.unpack();
+            if let Some(dir) = path.strip_suffix("/delta-0.json") {
+                created.insert(dir.to_string(), mtime);
+            }
+            file::save(&content, &data_dir.join(&path))?;
+        }
+
+        let mut report = RestoreReport {
+            created: self.manifest.created,
+            point: point.clone(),
+            files,
+            removed: vec![],
+            truncated: vec![],
+            numbers: 0,
+            session: None,
+        };
+
+        let stores = [CASERVER_DIR, PUBSERVER_DIR];
+        let time = match point {
+            RestorePoint::Latest => None,
+            RestorePoint::Time(time) => Some(time.timestamp()),
+            RestorePoint::Command(handle, sequence) => {
+                let mut time = None;
+                for name in &stores {
+                    let store = AnyKeyStore::existing(data_dir, name)
+                        .map_err(AggregateStoreError::KeyStoreError)?;
+                    if store.has_aggregate(handle) {
+                        time = store
+                            .command_time(handle, *sequence)
+                            .map_err(AggregateStoreError::KeyStoreError)?;
+                        break;
+                    }
+                }
+                Some(time.ok_or_else(|| {
+                    Error::RestoreNotPossible(format!("unknown command {}", point))
+                })?)
+            }
+        };
+
+        if let Some(time) = time {
+            for name in &stores {
+                let store = AnyKeyStore::existing(data_dir, name)
+                    .map_err(AggregateStoreError::KeyStoreError)?;
+                for handle in store.aggregates() {
+                    let dir = format!("{}/{}", name, handle);
+                    let created = match &store {
+                        AnyKeyStore::Disk(_) => created.get(&dir).map(|t| *t as i64),
+                        AnyKeyStore::Sqlite(db) => db
+                            .created(&handle)
+                            .map_err(AggregateStoreError::KeyStoreError)?,
+                    };
+                    if created.map(|t| t > time).unwrap_or(false) {
+                        store
+                            .remove_aggregate(&handle)
+                            .map_err(AggregateStoreError::KeyStoreError)?;
+                        report.removed.push(dir);
+                        continue;
+                    }
+
+                    let sequence = match point {
+                        RestorePoint::Command(h, seq) if h == &handle => *seq,
+                        _ => store
+                            .command_sequence_at(&handle, time)
+                            .map_err(AggregateStoreError::KeyStoreError)?,
+                    };
+
+                    let removed = store
+                        .truncate_history(&handle, sequence)
+                        .map_err(AggregateStoreError::KeyStoreError)?;
+                    if removed > 0 {
+                        report.truncated.push((dir, removed));
+                    }
+                }
+            }
+        }
+
+        if !self.manifest.publication_numbers.is_empty() {
+            let restored = RestoredNumbers {
+                created: self.manifest.created,
+                numbers: self.manifest.publication_numbers.clone(),
+            };
+            file::save_json(&restored, &data_dir.join(RESTORED_NUMBERS_FILE))?;
+            report.numbers = self.manifest.publication_numbers.len();
+        }
+
+        let pubd = DiskAggregateStore::<Repository>::new(data_dir, PUBSERVER_DIR)?;
+        let handle = unsafe { Handle::from_str_unsafe(PUBSERVER_DFLT) };
+        if pubd.has(&handle) {
+            let old = pubd.get_latest(&handle)?.stats().clone();
+            let repository = pubd.command(CmdDet::reset_session(&handle))?;
+            repository.write(0)?; // no earlier rsync versions to keep
+
+            report.session = Some(repository.session_reset_report(&old));
+        } else {
+            let _empty = fs::remove_dir_all(data_dir.join(PUBSERVER_DIR));
+        }
+
+        Ok(report)
+    }
+}
+
+//------------ RestoredNumbers -----------------------------------------------
+
+/// The publication numbers saved by a restore, until Krill starts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct RestoredNumbers {
+    created: Time,
+    numbers: HashMap<Handle, u64>,
+}
+
+/// Returns the publication numbers saved by a restore, if any, raised by
+/// RESTORE_NUMBERS_PER_MINUTE for every started minute since the backup
+/// was made. The caller must publish new manifests and CRLs with higher
+/// numbers for the CAs, and then remove the numbers.
+pub fn restored_publication_numbers(data_dir: &Path) -> KrillResult<Option<HashMap<Handle, u64>>> {
+    let path = data_dir.join(RESTORED_NUMBERS_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let restored: RestoredNumbers = file::load_json(&path)?;
+
+    let age = (Time::now().timestamp() - restored.created.timestamp()).max(0) as u64;
+    let margin = (age / 60 + 1) * RESTORE_NUMBERS_PER_MINUTE;
+
+    let numbers = restored
+        .numbers
+        .into_iter()
+        .map(|(handle, number)| (handle, number.saturating_add(margin)))
+        .collect();
+    Ok(Some(numbers))
+}
+
+pub fn remove_restored_publication_numbers(data_dir: &Path) -> KrillResult<()> {
+    fs::remove_file(data_dir.join(RESTORED_NUMBERS_FILE))?;
+    Ok(())
+}
+
+//------------ RestoreReport -------------------------------------------------
+
+/// Describes what was restored.
+#[derive(Clone, Debug)]
+pub struct RestoreReport {
+    created: Time,
+    point: RestorePoint,
+    files: usize,
+    removed: Vec<String>,
+    truncated: Vec<(String, usize)>,
+    numbers: usize,
+    session: Option<SessionReset>,
+}
+
+impl RestoreReport {
+    pub fn session(&self) -> Option<&SessionReset> {
+        self.session.as_ref()
+    }
+}
+
+impl fmt::Display for RestoreReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Restored {} files from backup made at {}",
+            self.files,
+            self.created.to_rfc3339()
+        )?;
+        writeln!(f, "Restore point: {}", self.point)?;
+        for dir in &self.removed {
+            writeln!(f, "Removed '{}', created after the restore point", dir)?;
+        }
+        for (dir, commands) in &self.truncated {
+            writeln!(f, "Removed {} later commands for '{}'", commands, dir)?;
+        }
+        if self.numbers > 0 {
+            writeln!(
+                f,
+                "Manifest and CRL numbers for {} CAs will be raised when Krill starts",
+                self.numbers
+            )?;
+        }
+        if let Some(reset) = &self.session {
+            write!(f, "{}", reset)?;
+        }
+        Ok(())
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::{Arc, RwLock};
+
+    use bytes::Bytes;
+    use chrono::Duration;
+
+    use super::*;
+
+    use crate::commons::eventsourcing::StorageBackend;
+    use crate::commons::util::softsigner::OpenSslSigner;
+    use crate::commons::util::tar;
+    use crate::daemon::eventstream::EventStream;
+    use crate::pubd::{PubServer, RrdpRetention};
+    use crate::test;
+
+    fn backup(d: &Path, data_dir: &Path, numbers: HashMap<Handle, u64>) -> Bytes {
+        let target = d.join("backup.tar");
+        Backup::create(data_dir, numbers, &target).unwrap();
+        file::read(&target).unwrap()
+    }
+
+    fn read(archive: &Bytes) -> KrillResult<Restore<Cursor<Bytes>>> {
+        Restore::read(Cursor::new(archive.clone()))
+    }
+
+    /// Rewrites the archive with the manifest changed by the closure.
+    fn change_manifest<F: FnOnce(&mut BackupManifest)>(archive: &Bytes, op: F) -> Bytes {
+        let mut entries = tar::read_entries(archive).unwrap();
+        let (path, mtime, content) = entries.remove(0).unpack();
+        let mut manifest: BackupManifest = serde_json::from_slice(&content).unwrap();
+        op(&mut manifest);
+
+        let mut writer = TarWriter::new(vec![]);
+        writer
+            .append(&path, mtime, &serde_json::to_vec(&manifest).unwrap())
+            .unwrap();
+        for entry in &entries {
+            writer
+                .append(entry.path(), entry.mtime(), entry.content())
+                .unwrap();
+        }
+        Bytes::from(writer.finish().unwrap())
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        test::test_under_tmp(|d| {
+            let data_dir = d.join("data");
+            file::save(b"{}", &data_dir.join("cas/ta/info.json")).unwrap();
+            file::save(b"key", &data_dir.join("keys/abcd")).unwrap();
+            file::save(b"derived", &data_dir.join("repo/rrdp/notification.xml")).unwrap();
+
+            let ta = unsafe { Handle::from_str_unsafe("ta") };
+            let mut numbers = HashMap::new();
+            numbers.insert(ta.clone(), 5);
+            let archive = backup(&d, &data_dir, numbers.clone());
+
+            let restore = read(&archive).unwrap();
+            assert_eq!(2, restore.manifest().files.len());
+            assert_eq!(KRILL_VERSION, restore.manifest().krill_version());
+            assert_eq!(&numbers, restore.manifest().publication_numbers());
+
+            // cannot restore over existing state
+            match restore.restore(&data_dir, &RestorePoint::Latest) {
+                Err(Error::RestoreNotPossible(_)) => {}
+                _ => panic!("Expected error"),
+            }
+
+            fs::remove_dir_all(&data_dir).unwrap();
+            let restore = read(&archive).unwrap();
+            let report = restore.restore(&data_dir, &RestorePoint::Latest).unwrap();
+            assert!(report.session().is_none());
+            assert_eq!(
+                Bytes::from("key"),
+                file::read(&data_dir.join("keys/abcd")).unwrap()
+            );
+            assert!(!data_dir.join("repo").exists());
+
+            // the numbers are kept until Krill starts, with a margin for
+            // publications after the backup
+            let restored = restored_publication_numbers(&data_dir).unwrap().unwrap();
+            assert_eq!(5 + RESTORE_NUMBERS_PER_MINUTE, restored[&ta]);
+            remove_restored_publication_numbers(&data_dir).unwrap();
+            assert_eq!(None, restored_publication_numbers(&data_dir).unwrap());
+        })
+    }
+
+    #[test]
+    fn restore_older_backup() {
+        test::test_under_tmp(|d| {
+            let data_dir = d.join("data");
+            file::save(b"{}", &data_dir.join("cas/ta/info.json")).unwrap();
+
+            let ta = unsafe { Handle::from_str_unsafe("ta") };
+            let mut numbers = HashMap::new();
+            numbers.insert(ta.clone(), 5);
+            let archive = backup(&d, &data_dir, numbers);
+
+            // the CA has published for a day after this backup was made
+            let archive = change_manifest(&archive, |manifest| {
+                manifest.created = Time::now() - Duration::days(1);
+            });
+            let published_since = 24 * 60 * 60;
+
+            fs::remove_dir_all(&data_dir).unwrap();
+            read(&archive)
+                .unwrap()
+                .restore(&data_dir, &RestorePoint::Latest)
+                .unwrap();
+
+            let restored = restored_publication_numbers(&data_dir).unwrap().unwrap();
+            assert!(restored[&ta] > 5 + published_since);
+        })
+    }
+
+    #[test]
+    fn reject_corrupt_backup() {
+        test::test_under_tmp(|d| {
+            file::save(b"{}", &d.join("cas/ta/info.json")).unwrap();
+            let archive = backup(&d, &d, HashMap::new());
+
+            let mut entries = tar::read_entries(&archive).unwrap();
+            let (path, mtime, _) = entries.pop().unwrap().unpack();
+            let mut writer = TarWriter::new(vec![]);
+            for entry in &entries {
+                writer
+                    .append(entry.path(), entry.mtime(), entry.content())
+                    .unwrap();
+            }
+            writer.append(&path, mtime, b"{ }").unwrap();
+            let tampered = writer.finish().unwrap();
+
+            match read(&Bytes::from(tampered)) {
+                Err(Error::BackupInvalid(_)) => {}
+                _ => panic!("Expected error"),
+            }
+
+            let missing = change_manifest(&archive, |manifest| {
+                manifest.files.push(BackupFile {
+                    path: "cas/ta/extra.json".to_string(),
+                    size: 0,
+                    hash: HexEncodedHash::from_content(b""),
+                })
+            });
+            match read(&missing) {
+                Err(Error::BackupInvalid(_)) => {}
+                _ => panic!("Expected error"),
+            }
+
+            let truncated = archive.slice(..archive.len() - 2048);
+            match read(&truncated) {
+                Err(Error::BackupInvalid(_)) => {}
+                _ => panic!("Expected error"),
+            }
+        })
+    }
+
+    #[test]
+    fn restore_resets_rrdp_session() {
+        test::test_under_tmp(|d| {
+            let data_dir = d.join("data");
+            fs::create_dir_all(&data_dir).unwrap();
+            let signer = OpenSslSigner::build(&data_dir).unwrap();
+            let server = PubServer::build(
+                &test::rsync("rsync://localhost/repo/"),
+                test::https("https://localhost/rrdp/"),
+                &data_dir,
+                None,
+                StorageBackend::Disk,
+                RrdpRetention::new(50, 24),
+                10,
+                vec![],
+                Arc::new(EventStream::default()),
+                Arc::new(RwLock::new(signer)),
+            )
+            .unwrap();
+            server.write_repository().unwrap();
+            let stats = server.repo_stats().unwrap();
+
+            let archive = {
+                let _lock = server.lock_for_backup();
+                backup(&d, &data_dir, HashMap::new())
+            };
+            fs::remove_dir_all(&data_dir).unwrap();
+
+            let report = read(&archive)
+                .unwrap()
+                .restore(&data_dir, &RestorePoint::Latest)
+                .unwrap();
+
+            let reset = report.session().unwrap();
+            assert_ne!(stats.session(), reset.new_session());
+            assert_eq!(1, reset.new_serial());
+
+            let session_dir = data_dir.join(format!("repo/rrdp/{}", reset.new_session()));
+            assert!(data_dir.join("repo/rrdp/notification.xml").exists());
+            assert!(session_dir.join("1/snapshot.xml").exists());
+        })
+    }
+}
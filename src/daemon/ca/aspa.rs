@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use bcder::encode::{PrimitiveContent, Values};
+use bcder::{encode, Mode, Oid, Tag};
+use bytes::Bytes;
+
+use rpki::resources::{AsBlocksBuilder, AsId, AsResources};
+use rpki::sigobj::{SignedObject, SignedObjectBuilder};
+use rpki::uri;
+use rpki::x509::{Serial, Time};
+
+use crate::commons::api::{
+    AsNumber, AspaDefinition, AspaDefinitionList, CurrentObject, ObjectName, ReplacedObject,
+};
+use crate::commons::KrillResult;
+use crate::constants::ASPA_CERTIFICATE_VALIDITY_YEARS;
+use crate::daemon::ca::events::AspaObjectsUpdates;
+use crate::daemon::ca::{self, CertifiedKey, SignSupport, Signer};
+
+/// The content type for ASPA objects, id-ct-ASPA: 1.2.840.113549.1.9.16.1.49
+const ASPA_CONTENT_TYPE: [u8; 11] = [42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 49];
+
+/// The version of the ASPA profile used for the objects we make.
+const ASPA_VERSION: u8 = 1;
+
+//------------ AspaDefinitions ---------------------------------------------
+
+/// The ASPA definitions configured for a CA, keyed by customer AS.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaDefinitions {
+    attestations: HashMap<AsNumber, AspaDefinition>,
+}
+
+impl AspaDefinitions {
+    pub fn get(&self, customer: AsNumber) -> Option<&AspaDefinition> {
+        self.attestations.get(&customer)
+    }
+
+    pub fn has(&self, customer: AsNumber) -> bool {
+        self.attestations.contains_key(&customer)
+    }
+
+    /// Adds a new definition, or replaces the existing definition for
+    /// the same customer AS.
+    pub fn add_or_replace(&mut self, definition: AspaDefinition) {
+        self.attestations.insert(definition.customer(), definition);
+    }
+
+    pub fn remove(&mut self, customer: AsNumber) {
+        self.attestations.remove(&customer);
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &AspaDefinition> {
+        self.attestations.values()
+    }
+
+    pub fn as_list(&self) -> AspaDefinitionList {
+        AspaDefinitionList::new(self.all().cloned().collect())
+    }
+}
+
+//------------ AspaInfo ----------------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaInfo {
+    definition: AspaDefinition,       // the definition for the ASPA
+    object: CurrentObject,            // actual ASPA
+    since: Time,                      // first ASPA in RC created
+    replaces: Option<ReplacedObject>, // for revoking when re-newing
+}
+
+impl AspaInfo {
+    pub fn new_aspa(definition: AspaDefinition, aspa: &SignedObject) -> Self {
+        AspaInfo {
+            definition,
+            object: CurrentObject::from(aspa),
+            since: Time::now(),
+            replaces: None,
+        }
+    }
+
+    pub fn updated_aspa(old: &AspaInfo, definition: AspaDefinition, aspa: &SignedObject) -> Self {
+        AspaInfo {
+            definition,
+            object: CurrentObject::from(aspa),
+            since: old.since,
+            replaces: Some(ReplacedObject::from(old.object())),
+        }
+    }
+
+    pub fn definition(&self) -> &AspaDefinition {
+        &self.definition
+    }
+
+    pub fn object(&self) -> &CurrentObject {
+        &self.object
+    }
+
+    pub fn name(&self) -> ObjectName {
+        ObjectName::aspa(self.definition.customer())
+    }
+
+    pub fn since(&self) -> Time {
+        self.since
+    }
+
+    pub fn replaces(&self) -> Option<&ReplacedObject> {
+        self.replaces.as_ref()
+    }
+}
+
+//------------ AspaObjects -------------------------------------------------
+
+/// ASPA objects held by a resource class in a CA.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaObjects {
+    inner: HashMap<AsNumber, AspaInfo>,
+}
+
+impl AspaObjects {
+    pub fn get(&self, customer: AsNumber) -> Option<&AspaInfo> {
+        self.inner.get(&customer)
+    }
+
+    pub fn updated(&mut self, updates: AspaObjectsUpdates) {
+        let (updated, removed) = updates.unpack();
+
+        for (customer, info) in updated.into_iter() {
+            self.inner.insert(customer, info);
+        }
+
+        for customer in removed.keys() {
+            self.inner.remove(customer);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&AsNumber, &AspaInfo)> {
+        self.inner.iter()
+    }
+
+    pub fn current(&self) -> impl Iterator<Item = &AspaInfo> {
+        self.inner.values()
+    }
+
+    pub fn definitions(&self) -> impl Iterator<Item = &AspaDefinition> {
+        self.inner.values().map(|info| info.definition())
+    }
+
+    pub fn make_aspa<S: Signer>(
+        definition: &AspaDefinition,
+        certified_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<SignedObject> {
+        let name = ObjectName::aspa(definition.customer());
+
+        let incoming_cert = certified_key.incoming_cert();
+        let crl_uri = match &new_repo {
+            None => incoming_cert.crl_uri(),
+            Some(base_uri) => base_uri.join(incoming_cert.crl_name().as_bytes()),
+        };
+
+        let aspa_uri = match &new_repo {
+            None => incoming_cert.uri_for_object(name),
+            Some(base_uri) => base_uri.join(name.as_bytes()),
+        };
+
+        let aia = incoming_cert.uri();
+
+        let signing_key = certified_key.key_id();
+
+        let mut object_builder = SignedObjectBuilder::new(
+            Serial::random(signer).map_err(ca::Error::signer)?,
+            SignSupport::sign_validity_years(ASPA_CERTIFICATE_VALIDITY_YEARS),
+            crl_uri,
+            aia.clone(),
+            aspa_uri,
+        );
+        object_builder.set_issuer(Some(incoming_cert.cert().subject().clone()));
+        object_builder.set_signing_time(Some(Time::now()));
+
+        // The EE certificate holds the customer AS, and no IP resources.
+        let mut as_blocks = AsBlocksBuilder::new();
+        as_blocks.push(AsId::from(definition.customer()));
+        object_builder.set_as_resources(Some(AsResources::blocks(as_blocks.finalize())));
+
+        object_builder
+            .finalize(
+                Oid(Bytes::from_static(&ASPA_CONTENT_TYPE)),
+                Self::encode_content(definition),
+                signer,
+                signing_key,
+            )
+            .map_err(ca::Error::signer)
+    }
+
+    /// Encodes the ASPA content:
+    ///
+    /// ```text
+    /// ASProviderAttestation ::= SEQUENCE {
+    ///   version [0] INTEGER DEFAULT 0,
+    ///   customerASID ASID,
+    ///   providers ProviderASSet }
+    ///
+    /// ProviderASSet ::= SEQUENCE (SIZE(1..MAX)) OF ASID
+    /// ```
+    fn encode_content(definition: &AspaDefinition) -> Bytes {
+        encode::sequence((
+            encode::sequence_as(Tag::CTX_0, ASPA_VERSION.encode()),
+            AsId::from(definition.customer()).encode(),
+            encode::sequence(encode::slice(definition.providers(), |provider| {
+                AsId::from(*provider).encode()
+            })),
+        ))
+        .to_captured(Mode::Der)
+        .into_bytes()
+    }
+}
+
+//------------ Tests -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    #[test]
+    fn encode_aspa_content() {
+        let definition = AspaDefinition::from_str("64496 => 64498, 64497").unwrap();
+        let encoded = AspaObjects::encode_content(&definition);
+
+        let expected: &[u8] = &[
+            0x30, 0x16, // ASProviderAttestation
+            0xa0, 0x03, 0x02, 0x01, 0x01, // version [0] 1
+            0x02, 0x03, 0x00, 0xfb, 0xf0, // customer 64496
+            0x30, 0x0a, // providers
+            0x02, 0x03, 0x00, 0xfb, 0xf1, // 64497
+            0x02, 0x03, 0x00, 0xfb, 0xf2, // 64498
+        ];
+        assert_eq!(expected, encoded.as_ref());
+    }
+
+    #[test]
+    fn serde_aspa_definitions() {
+        let mut definitions = AspaDefinitions::default();
+        definitions.add_or_replace(AspaDefinition::from_str("64496 => 64497").unwrap());
+        definitions.add_or_replace(AspaDefinition::from_str("64500 => 64501").unwrap());
+
+        let json = serde_json::to_string(&definitions).unwrap();
+        let des: AspaDefinitions = serde_json::from_str(&json).unwrap();
+        assert_eq!(definitions, des);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use bcder::{BitString, Captured, Mode};
+use bytes::Bytes;
+use chrono::Duration;
+use openssl::nid::Nid;
+use openssl::x509::X509Req;
+
+use rpki::crypto::KeyIdentifier;
+use rpki::x509::Time;
+
+use crate::commons::api::{
+    AsNumber, Base64, CurrentObject, ObjectName, ReplacedObject, ResourceSet, RouterCertInfo,
+};
+use crate::constants::ROUTER_CERTIFICATE_REISSUE_WEEKS;
+use crate::daemon::ca::events::RouterCertUpdates;
+
+//------------ RouterKey ---------------------------------------------------
+
+/// The public key of a BGPsec router, as taken from its PKCS#10 request.
+///
+/// Router keys are ECDSA P-256 keys (RFC 8208). The rpki crate only
+/// supports RSA keys, so we keep the DER encoded SubjectPublicKeyInfo
+/// and include it as is in the router certificate.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RouterKey {
+    key_id: KeyIdentifier,
+    info: Base64,
+}
+
+impl RouterKey {
+    /// Parses a DER encoded router PKCS#10 request, verifies its signature
+    /// and returns the router key. Returns a description of the problem if
+    /// the request is invalid.
+    pub fn from_csr(csr: &[u8]) -> Result<Self, String> {
+        let req = X509Req::from_der(csr).map_err(|_| "cannot parse CSR".to_string())?;
+        let pub_key = req
+            .public_key()
+            .map_err(|_| "cannot parse public key in CSR".to_string())?;
+
+        let ec_key = pub_key
+            .ec_key()
+            .map_err(|_| "router key must be an ECDSA P-256 key".to_string())?;
+        if ec_key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+            return Err("router key must be an ECDSA P-256 key".to_string());
+        }
+
+        if !req.verify(&pub_key).unwrap_or(false) {
+            return Err("invalid signature".to_string());
+        }
+
+        let info = pub_key
+            .public_key_to_der()
+            .map_err(|_| "cannot encode public key".to_string())?;
+
+        Self::from_info(&info)
+    }
+
+    /// Makes a router key from a DER encoded SubjectPublicKeyInfo.
+    fn from_info(info: &[u8]) -> Result<Self, String> {
+        let bits = Mode::Der
+            .decode(Bytes::copy_from_slice(info), |cons| {
+                cons.take_sequence(|cons| {
+                    cons.take_sequence(|cons| cons.skip_all())?;
+                    BitString::take_from(cons)
+                })
+            })
+            .map_err(|_| "cannot parse public key info".to_string())?;
+
+        // The key identifier is the SHA-1 hash of the public key bits (RFC 6487)
+        let key_bits = bits
+            .octet_slice()
+            .ok_or_else(|| "cannot parse public key bits".to_string())?;
+        let key_id = KeyIdentifier::try_from(openssl::sha::sha1(key_bits).as_ref())
+            .map_err(|_| "cannot derive key identifier".to_string())?;
+
+        Ok(RouterKey {
+            key_id,
+            info: Base64::from_content(info),
+        })
+    }
+
+    pub fn key_identifier(&self) -> &KeyIdentifier {
+        &self.key_id
+    }
+
+    /// Returns the SubjectPublicKeyInfo for encoding in a certificate.
+    pub fn info(&self) -> Captured {
+        Mode::Der
+            .decode(self.info.to_bytes(), |cons| cons.capture_one())
+            .unwrap() // parsed when the key was made
+    }
+}
+
+//------------ RouterCertDefinition ----------------------------------------
+
+/// A router key and the ASNs which it may sign for, for which a CA should
+/// issue a BGPsec router certificate.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RouterCertDefinition {
+    key: RouterKey,
+    asns: Vec<AsNumber>,
+}
+
+impl RouterCertDefinition {
+    pub fn new(key: RouterKey, mut asns: Vec<AsNumber>) -> Self {
+        asns.sort();
+        asns.dedup();
+        RouterCertDefinition { key, asns }
+    }
+
+    pub fn key(&self) -> &RouterKey {
+        &self.key
+    }
+
+    pub fn key_identifier(&self) -> &KeyIdentifier {
+        self.key.key_identifier()
+    }
+
+    pub fn asns(&self) -> &Vec<AsNumber> {
+        &self.asns
+    }
+
+    pub fn name(&self) -> ObjectName {
+        ObjectName::router_cert(self.key_identifier())
+    }
+
+    /// Returns `true` if all ASNs are held in the given resources.
+    pub fn held_in(&self, resources: &ResourceSet) -> bool {
+        self.asns
+            .iter()
+            .all(|asn| resources.contains(&(*asn).into()))
+    }
+}
+
+//------------ RouterCertDefinitions ---------------------------------------
+
+/// The router certificates configured for a CA, keyed by router key.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RouterCertDefinitions {
+    definitions: HashMap<KeyIdentifier, RouterCertDefinition>,
+}
+
+impl RouterCertDefinitions {
+    pub fn get(&self, key: &KeyIdentifier) -> Option<&RouterCertDefinition> {
+        self.definitions.get(key)
+    }
+
+    pub fn has(&self, key: &KeyIdentifier) -> bool {
+        self.definitions.contains_key(key)
+    }
+
+    /// Adds a new definition, or replaces the existing definition for
+    /// the same router key.
+    pub fn add_or_replace(&mut self, definition: RouterCertDefinition) {
+        self.definitions
+            .insert(definition.key_identifier().clone(), definition);
+    }
+
+    pub fn remove(&mut self, key: &KeyIdentifier) {
+        self.definitions.remove(key);
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &RouterCertDefinition> {
+        self.definitions.values()
+    }
+}
+
+//------------ IssuedRouterCert --------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct IssuedRouterCert {
+    definition: RouterCertDefinition, // the key and ASNs certified
+    object: CurrentObject,            // actual certificate
+    replaces: Option<ReplacedObject>, // for revoking when re-newing
+}
+
+impl IssuedRouterCert {
+    pub fn new(definition: RouterCertDefinition, object: CurrentObject) -> Self {
+        IssuedRouterCert {
+            definition,
+            object,
+            replaces: None,
+        }
+    }
+
+    pub fn updated(
+        old: &IssuedRouterCert,
+        definition: RouterCertDefinition,
+        object: CurrentObject,
+    ) -> Self {
+        IssuedRouterCert {
+            definition,
+            object,
+            replaces: Some(ReplacedObject::from(old.object())),
+        }
+    }
+
+    pub fn definition(&self) -> &RouterCertDefinition {
+        &self.definition
+    }
+
+    pub fn key_identifier(&self) -> &KeyIdentifier {
+        self.definition.key_identifier()
+    }
+
+    pub fn object(&self) -> &CurrentObject {
+        &self.object
+    }
+
+    pub fn name(&self) -> ObjectName {
+        self.definition.name()
+    }
+
+    pub fn replaces(&self) -> Option<&ReplacedObject> {
+        self.replaces.as_ref()
+    }
+
+    pub fn as_info(&self) -> RouterCertInfo {
+        RouterCertInfo::new(
+            self.key_identifier().clone(),
+            self.definition.asns().clone(),
+            self.name(),
+            self.object.expires(),
+        )
+    }
+}
+
+//------------ RouterCertificates ------------------------------------------
+
+/// BGPsec router certificates issued by a resource class in a CA.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RouterCertificates {
+    inner: HashMap<KeyIdentifier, IssuedRouterCert>,
+}
+
+impl RouterCertificates {
+    pub fn get(&self, key: &KeyIdentifier) -> Option<&IssuedRouterCert> {
+        self.inner.get(key)
+    }
+
+    pub fn updated(&mut self, updates: RouterCertUpdates) {
+        let (issued, removed) = updates.unpack();
+
+        for (key, cert) in issued.into_iter() {
+            self.inner.insert(key, cert);
+        }
+
+        for key in removed.keys() {
+            self.inner.remove(key);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&KeyIdentifier, &IssuedRouterCert)> {
+        self.inner.iter()
+    }
+
+    pub fn current(&self) -> impl Iterator<Item = &IssuedRouterCert> {
+        self.inner.values()
+    }
+
+    pub fn definitions(&self) -> impl Iterator<Item = &RouterCertDefinition> {
+        self.inner.values().map(|issued| issued.definition())
+    }
+
+    pub fn expiring(&self) -> Vec<&IssuedRouterCert> {
+        self.inner
+            .values()
+            .filter(|issued| {
+                issued.object().expires()
+                    < Time::now() + Duration::weeks(ROUTER_CERTIFICATE_REISSUE_WEEKS)
+            })
+            .collect()
+    }
+}
+
+//------------ Tests -------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::X509ReqBuilder;
+
+    fn make_csr(pkey: &PKey<openssl::pkey::Private>) -> Vec<u8> {
+        let mut builder = X509ReqBuilder::new().unwrap();
+        builder.set_pubkey(pkey).unwrap();
+        builder.sign(pkey, MessageDigest::sha256()).unwrap();
+        builder.build().to_der().unwrap()
+    }
+
+    fn ec_point_bytes(pkey: &PKey<openssl::pkey::Private>) -> Vec<u8> {
+        let ec_key = pkey.ec_key().unwrap();
+        let mut ctx = openssl::bn::BigNumContext::new().unwrap();
+        ec_key
+            .public_key()
+            .to_bytes(
+                ec_key.group(),
+                openssl::ec::PointConversionForm::UNCOMPRESSED,
+                &mut ctx,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn router_key_from_csr() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let ec_key = EcKey::generate(&group).unwrap();
+        let pkey = PKey::from_ec_key(ec_key).unwrap();
+
+        let key = RouterKey::from_csr(&make_csr(&pkey)).unwrap();
+
+        let info = pkey.public_key_to_der().unwrap();
+        assert_eq!(info.as_slice(), key.info().as_slice());
+        assert_eq!(
+            &KeyIdentifier::try_from(openssl::sha::sha1(&ec_point_bytes(&pkey)).as_ref()).unwrap(),
+            key.key_identifier()
+        );
+
+        let json = serde_json::to_string(&key).unwrap();
+        let des: RouterKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(key, des);
+    }
+
+    #[test]
+    fn refuse_rsa_router_key() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let pkey = PKey::from_rsa(rsa).unwrap();
+        assert!(RouterKey::from_csr(&make_csr(&pkey)).is_err());
+        assert!(RouterKey::from_csr(b"not a csr").is_err());
+    }
+}
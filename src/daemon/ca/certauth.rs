@@ -15,11 +15,12 @@ use rpki::x509::{Serial, Time, Validity};
 
 use crate::commons::api::rrdp::PublishElement;
 use crate::commons::api::{
-    self, CertAuthInfo, ChildHandle, EntitlementClass, Entitlements, Handle, IdCertPem,
+    self, AsNumber, AspaDefinition, AspaDefinitionList, AspaDefinitionUpdates, CertAuthInfo,
+    ChildHandle, EntitlementClass, Entitlements, GhostbusterRecord, Handle, IdCertPem,
     IssuanceRequest, IssuedCert, ObjectsDelta, ParentCaContact, ParentHandle, RcvdCert,
     RepositoryContact, RequestResourceLimit, ResourceClassName, ResourceSet, RevocationRequest,
-    RevocationResponse, RoaDefinition, SigningCert, StorableCaCommand, TaCertDetails,
-    TrustAnchorLocator,
+    RevocationResponse, RoaDefinition, RouterCertList, SigningCert, StorableCaCommand,
+    TaCertDetails, TrustAnchorLocator,
 };
 use crate::commons::error::Error;
 use crate::commons::eventsourcing::{Aggregate, StoredEvent};
@@ -36,8 +37,9 @@ use crate::daemon::ca::events::ChildCertificateUpdates;
 use crate::daemon::ca::rc::PublishMode;
 use crate::daemon::ca::signing::CsrInfo;
 use crate::daemon::ca::{
-    ta_handle, ChildDetails, Cmd, CmdDet, CurrentObjectSetDelta, Evt, EvtDet, Ini, ResourceClass,
-    RouteAuthorization, RouteAuthorizationUpdates, Routes, Signer,
+    ta_handle, AspaDefinitions, ChildDetails, Cmd, CmdDet, CurrentObjectSetDelta, Evt, EvtDet, Ini,
+    ResourceClass, RoaAggregation, RouteAuthorization, RouteAuthorizationUpdates,
+    RouterCertDefinition, RouterCertDefinitions, Routes, Signer,
 };
 
 //------------ Rfc8183Id ---------------------------------------------------
@@ -88,6 +90,15 @@ pub struct CertAuth<S: Signer> {
 
     routes: Routes,
 
+    #[serde(default)]
+    aspas: AspaDefinitions,
+
+    #[serde(default)]
+    router_certs: RouterCertDefinitions,
+
+    #[serde(default)]
+    ghostbuster: Option<GhostbusterRecord>,
+
     phantom_signer: PhantomData<S>,
 }
 
@@ -137,6 +148,9 @@ impl<S: Signer> Aggregate for CertAuth<S> {
             children,
 
             routes,
+            aspas: AspaDefinitions::default(),
+            router_certs: RouterCertDefinitions::default(),
+            ghostbuster: None,
 
             phantom_signer: PhantomData,
         })
@@ -215,6 +229,12 @@ impl<S: Signer> Aggregate for CertAuth<S> {
                 .unwrap()
                 .set_resources(resources),
 
+            EvtDet::ChildResourcesReduced(child, time) => self
+                .children
+                .get_mut(&child)
+                .unwrap()
+                .set_resources_reduced(time),
+
             EvtDet::ChildRemoved(child) => {
                 self.children.remove(&child);
             }
@@ -302,6 +322,39 @@ impl<S: Signer> Aggregate for CertAuth<S> {
                 self.resources.get_mut(&rcn).unwrap().roas_updated(updates)
             }
 
+            //-----------------------------------------------------------------------
+            // ASPA
+            //-----------------------------------------------------------------------
+            EvtDet::AspaDefinitionAdded(definition) => self.aspas.add_or_replace(definition),
+            EvtDet::AspaDefinitionRemoved(customer) => self.aspas.remove(customer),
+            EvtDet::AspaObjectsUpdated(rcn, updates) => {
+                self.resources.get_mut(&rcn).unwrap().aspas_updated(updates)
+            }
+
+            //-----------------------------------------------------------------------
+            // BGPsec router certificates
+            //-----------------------------------------------------------------------
+            EvtDet::RouterCertDefinitionAdded(definition) => {
+                self.router_certs.add_or_replace(definition)
+            }
+            EvtDet::RouterCertDefinitionRemoved(key) => self.router_certs.remove(&key),
+            EvtDet::RouterCertsUpdated(rcn, updates) => self
+                .resources
+                .get_mut(&rcn)
+                .unwrap()
+                .router_certs_updated(updates),
+
+            //-----------------------------------------------------------------------
+            // Ghostbusters
+            //-----------------------------------------------------------------------
+            EvtDet::GhostbusterRecordUpdated(record) => self.ghostbuster = Some(record),
+            EvtDet::GhostbusterRecordRemoved => self.ghostbuster = None,
+            EvtDet::GhostbusterObjectUpdated(rcn, updates) => self
+                .resources
+                .get_mut(&rcn)
+                .unwrap()
+                .ghostbuster_updated(updates),
+
             //-----------------------------------------------------------------------
             // Publication
             //-----------------------------------------------------------------------
@@ -346,6 +399,9 @@ impl<S: Signer> Aggregate for CertAuth<S> {
                 self.child_revoke_key(child, request, signer)
             }
             CmdDet::ChildRemove(child, signer) => self.child_remove(&child, signer),
+            CmdDet::ChildShrinkCertificates(grace, roa_aggregation, signer) => {
+                self.child_shrink_certificates(grace, roa_aggregation, signer)
+            }
 
             // being a child
             CmdDet::GenerateNewIdKey(signer) => self.generate_new_id_key(signer),
@@ -356,23 +412,41 @@ impl<S: Signer> Aggregate for CertAuth<S> {
             CmdDet::UpdateResourceClasses(parent, entitlements, signer) => {
                 self.update_resource_classes(parent, entitlements, signer)
             }
-            CmdDet::UpdateRcvdCert(class_name, rcvd_cert, signer) => {
-                self.update_received_cert(class_name, rcvd_cert, signer)
+            CmdDet::UpdateRcvdCert(class_name, rcvd_cert, roa_aggregation, signer) => {
+                self.update_received_cert(class_name, rcvd_cert, roa_aggregation, signer)
             }
 
             // Key rolls
             CmdDet::KeyRollInitiate(duration, signer) => self.keyroll_initiate(duration, signer),
-            CmdDet::KeyRollActivate(duration, signer) => self.keyroll_activate(duration, signer),
+            CmdDet::KeyRollActivate(duration, roa_aggregation, signer) => {
+                self.keyroll_activate(duration, roa_aggregation, signer)
+            }
             CmdDet::KeyRollFinish(rcn, response) => self.keyroll_finish(rcn, response),
 
             // Route Authorizations
-            CmdDet::RouteAuthorizationsUpdate(updates, signer) => {
-                self.route_authorizations_update(updates, signer)
+            CmdDet::RouteAuthorizationsUpdate(updates, roa_aggregation, signer) => {
+                self.route_authorizations_update(updates, roa_aggregation, signer)
             }
 
+            // ASPA
+            CmdDet::AspasUpdate(updates, signer) => self.aspas_update(updates, signer),
+
+            // BGPsec router certificates
+            CmdDet::RouterCertify(definition, signer) => self.router_certify(definition, signer),
+            CmdDet::RouterRevoke(key, signer) => self.router_revoke(key, signer),
+
+            // Ghostbusters
+            CmdDet::GhostbusterUpdate(record, signer) => self.ghostbuster_update(record, signer),
+            CmdDet::GhostbusterRemove(signer) => self.ghostbuster_remove(signer),
+
             // Republish
-            CmdDet::Republish(signer) => self.republish(signer),
-            CmdDet::RepoUpdate(new_contact, signer) => self.update_repo(new_contact, signer),
+            CmdDet::Republish(roa_aggregation, signer) => self.republish(roa_aggregation, signer),
+            CmdDet::RaisePublicationNumbers(number, signer) => {
+                self.raise_publication_numbers(number, signer)
+            }
+            CmdDet::RepoUpdate(new_contact, roa_aggregation, signer) => {
+                self.update_repo(new_contact, roa_aggregation, signer)
+            }
             CmdDet::RepoRemoveOld(signer) => self.clean_repo(signer),
         }
     }
@@ -410,6 +484,24 @@ impl<S: Signer> CertAuth<S> {
             .collect()
     }
 
+    pub fn aspa_definitions(&self) -> AspaDefinitionList {
+        self.aspas.as_list()
+    }
+
+    pub fn router_certs(&self) -> RouterCertList {
+        let certs = self
+            .resources
+            .values()
+            .flat_map(|rc| rc.router_certs().current())
+            .map(|issued| issued.as_info())
+            .collect();
+        RouterCertList::new(certs)
+    }
+
+    pub fn ghostbuster(&self) -> Option<&GhostbusterRecord> {
+        self.ghostbuster.as_ref()
+    }
+
     pub fn child_request(&self) -> rfc8183::ChildRequest {
         rfc8183::ChildRequest::new(self.handle.clone(), self.id.cert.clone())
     }
@@ -452,6 +544,16 @@ impl<S: Signer> CertAuth<S> {
         res
     }
 
+    /// Returns the earliest next update time of the manifests for the
+    /// current keys, if there are any.
+    pub fn next_update(&self) -> Option<Time> {
+        self.resources
+            .values()
+            .filter_map(|rc| rc.current_key())
+            .map(|key| key.current_set().next_update())
+            .min()
+    }
+
     pub fn get_repository_contact(&self) -> KrillResult<&RepositoryContact> {
         self.repository.as_ref().ok_or(Error::RepoNotSet)
     }
@@ -777,7 +879,10 @@ impl<S: Signer> CertAuth<S> {
 
     /// Updates child Resource entitlements.
     ///
-    /// This does not yet revoke / reissue / republish anything.
+    /// This does not yet revoke / reissue / republish anything. If the child
+    /// holds certificates for resources it is no longer entitled to, this is
+    /// recorded, so that these certificates can be shrunk if the child does
+    /// not request new certificates itself within the grace period.
     /// Also, this is a no-op if the child already has these resources.
     fn child_update_resources(
         &self,
@@ -793,8 +898,114 @@ impl<S: Signer> CertAuth<S> {
                 &self.handle,
                 self.version,
                 child_handle.clone(),
-                resources,
+                resources.clone(),
             ));
+
+            if !self.child_overclaiming_keys(child, &resources).is_empty() {
+                res.push(EvtDet::child_resources_reduced(
+                    &self.handle,
+                    self.version + 1,
+                    child_handle.clone(),
+                    Time::now(),
+                ));
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Returns the keys of the certificates issued to the child, per resource
+    /// class, which claim resources not included in the given set.
+    fn child_overclaiming_keys(
+        &self,
+        child: &ChildDetails,
+        resources: &ResourceSet,
+    ) -> HashMap<ResourceClassName, Vec<KeyIdentifier>> {
+        let mut res = HashMap::new();
+
+        for (rcn, rc) in self.resources.iter() {
+            let keys: Vec<KeyIdentifier> = child
+                .issued(rcn)
+                .into_iter()
+                .filter(|ki| match rc.issued(ki) {
+                    Some(issued) => !resources.contains(issued.resource_set()),
+                    None => false,
+                })
+                .collect();
+
+            if !keys.is_empty() {
+                res.insert(rcn.clone(), keys);
+            }
+        }
+
+        res
+    }
+
+    /// Returns the children which hold certificates for resources they are
+    /// no longer entitled to, and the time when their resources were reduced.
+    pub fn children_overclaiming(&self) -> Vec<(ChildHandle, Time)> {
+        let mut res = vec![];
+
+        for (handle, child) in self.children.iter() {
+            if let Some(reduced) = child.resources_reduced() {
+                if !self
+                    .child_overclaiming_keys(child, child.resources())
+                    .is_empty()
+                {
+                    res.push((handle.clone(), reduced));
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Shrinks the certificates of children which claim resources they are
+    /// no longer entitled to, if their resources were reduced longer ago than
+    /// the grace period. Certificates for which no resources remain are
+    /// revoked.
+    fn child_shrink_certificates(
+        &self,
+        grace: Duration,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let signer = signer.read().unwrap();
+        let signer = signer.deref();
+
+        let deadline = Time::now() - grace;
+
+        let mut shrink: HashMap<ResourceClassName, HashMap<KeyIdentifier, ResourceSet>> =
+            HashMap::new();
+
+        for child in self.children.values() {
+            match child.resources_reduced() {
+                Some(reduced) if reduced <= deadline => {}
+                _ => continue,
+            }
+            for (rcn, keys) in self.child_overclaiming_keys(child, child.resources()) {
+                let entitlements = shrink.entry(rcn).or_default();
+                for ki in keys {
+                    entitlements.insert(ki, child.resources().clone());
+                }
+            }
+        }
+
+        let mut version = self.version;
+        let mut res = vec![];
+
+        for (rcn, entitlements) in shrink {
+            if let Some(rc) = self.resources.get(&rcn) {
+                if rc.current_key().is_some() {
+                    let mode = PublishMode::ShrinkChildren(entitlements);
+                    for evt_det in
+                        self.republish_resource_class(rc, &mode, roa_aggregation, signer)?
+                    {
+                        res.push(StoredEvent::new(&self.handle, version, evt_det));
+                        version += 1;
+                    }
+                }
+            }
         }
 
         Ok(res)
@@ -1245,6 +1456,7 @@ impl<S: Signer> CertAuth<S> {
         &self,
         rcn: ResourceClassName,
         rcvd_cert: RcvdCert,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> KrillResult<Vec<Evt>> {
         debug!(
@@ -1261,7 +1473,8 @@ impl<S: Signer> CertAuth<S> {
 
         let repo = self.get_repository_contact()?;
 
-        let evt_details = rc.update_received_cert(rcvd_cert, repo.repo_info(), signer.deref())?;
+        let evt_details =
+            rc.update_received_cert(rcvd_cert, repo.repo_info(), roa_aggregation, signer.deref())?;
 
         let mut res = vec![];
         let mut version = self.version;
@@ -1311,7 +1524,12 @@ impl<S: Signer> CertAuth<S> {
         Ok(res)
     }
 
-    fn keyroll_activate(&self, staging: Duration, signer: Arc<RwLock<S>>) -> KrillResult<Vec<Evt>> {
+    fn keyroll_activate(
+        &self,
+        staging: Duration,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
         if self.is_ta() {
             return Ok(vec![]);
         }
@@ -1326,7 +1544,7 @@ impl<S: Signer> CertAuth<S> {
             let repo = self.get_repository_contact()?;
 
             for details in rc
-                .keyroll_activate(repo.repo_info(), staging, signer.deref())?
+                .keyroll_activate(repo.repo_info(), staging, roa_aggregation, signer.deref())?
                 .into_iter()
             {
                 activated = true;
@@ -1373,14 +1591,20 @@ impl<S: Signer> CertAuth<S> {
 ///
 impl<S: Signer> CertAuth<S> {
     /// Republish objects for this CA
-    pub fn republish(&self, signer: Arc<RwLock<S>>) -> KrillResult<Vec<Evt>> {
+    pub fn republish(
+        &self,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
         let signer = signer.read().unwrap();
         let signer = signer.deref();
 
         let mut version = self.version;
         let mut res = vec![];
 
-        for evt_det in self.republish_resource_classes(&PublishMode::Normal, signer)? {
+        for evt_det in
+            self.republish_resource_classes(&PublishMode::Normal, roa_aggregation, signer)?
+        {
             res.push(StoredEvent::new(&self.handle, version, evt_det));
             version += 1;
         }
@@ -1388,31 +1612,106 @@ impl<S: Signer> CertAuth<S> {
         Ok(res)
     }
 
+    /// Returns the identifiers of all keys used by this CA, i.e. its ID key
+    /// and the keys in its resource classes.
+    pub fn key_ids(&self) -> Vec<&KeyIdentifier> {
+        let mut res = vec![self.id_key()];
+        for rc in self.resources.values() {
+            res.append(&mut rc.key_ids());
+        }
+        res
+    }
+
+    /// Returns the highest manifest and CRL number used by this CA.
+    pub fn highest_publication_number(&self) -> u64 {
+        self.resources
+            .values()
+            .map(|rc| rc.highest_publication_number())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Publishes new manifests and CRLs with numbers above the given number,
+    /// for all resource classes. See ResourceClass::raise_publication_numbers.
+    fn raise_publication_numbers(
+        &self,
+        number: u64,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let signer = signer.read().unwrap();
+        let signer = signer.deref();
+
+        // Nothing was published without a repository
+        let repo_info = match &self.repository {
+            Some(contact) => contact.repo_info(),
+            None => return Ok(vec![]),
+        };
+
+        let mut version = self.version;
+        let mut res = vec![];
+
+        for rc in self.resources.values() {
+            if let Some(evt_det) = rc.raise_publication_numbers(number, repo_info, signer)? {
+                res.push(StoredEvent::new(&self.handle, version, evt_det));
+                version += 1;
+            }
+        }
+
+        Ok(res)
+    }
+
     fn republish_resource_classes(
         &self,
         mode: &PublishMode,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<Vec<EvtDet>> {
         let mut res = vec![];
 
         for rc in self.resources.values() {
             if rc.current_key().is_some() {
-                let auths: Vec<RouteAuthorization> =
-                    self.routes.authorizations().cloned().collect();
-
-                let repo_info = if let PublishMode::NewRepo(info) = mode {
-                    info
-                } else {
-                    self.get_repository_contact()?.repo_info()
-                };
-
-                res.append(&mut rc.republish(auths.as_slice(), repo_info, mode, signer)?);
+                res.append(&mut self.republish_resource_class(
+                    rc,
+                    mode,
+                    roa_aggregation,
+                    signer,
+                )?);
             }
         }
 
         Ok(res)
     }
 
+    fn republish_resource_class(
+        &self,
+        rc: &ResourceClass,
+        mode: &PublishMode,
+        roa_aggregation: RoaAggregation,
+        signer: &S,
+    ) -> KrillResult<Vec<EvtDet>> {
+        let auths: Vec<RouteAuthorization> = self.routes.authorizations().cloned().collect();
+        let aspas: Vec<AspaDefinition> = self.aspas.all().cloned().collect();
+        let router_certs: Vec<RouterCertDefinition> = self.router_certs.all().cloned().collect();
+        let ghostbuster = self.ghostbuster.as_ref();
+
+        let repo_info = if let PublishMode::NewRepo(info) = mode {
+            info
+        } else {
+            self.get_repository_contact()?.repo_info()
+        };
+
+        rc.republish(
+            auths.as_slice(),
+            roa_aggregation,
+            aspas.as_slice(),
+            router_certs.as_slice(),
+            ghostbuster,
+            repo_info,
+            mode,
+            signer,
+        )
+    }
+
     /// Update repository:
     /// - check that it is indeed different
     /// - regenerate all objects under the new URI (CRL URIs updated)
@@ -1424,6 +1723,7 @@ impl<S: Signer> CertAuth<S> {
     pub fn update_repo(
         &self,
         new_contact: RepositoryContact,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> KrillResult<Vec<Evt>> {
         let signer = signer.read().unwrap();
@@ -1444,9 +1744,11 @@ impl<S: Signer> CertAuth<S> {
         evt_dts.push(EvtDet::RepoUpdated(new_contact));
 
         // issue new things => will trigger publication at the new location
-        evt_dts.append(
-            &mut self.republish_resource_classes(&PublishMode::NewRepo(info.clone()), signer)?,
-        );
+        evt_dts.append(&mut self.republish_resource_classes(
+            &PublishMode::NewRepo(info.clone()),
+            roa_aggregation,
+            signer,
+        )?);
 
         // request new certs => when received will trigger unpublishing at old location
         for rc in self.resources.values() {
@@ -1487,6 +1789,7 @@ impl<S: Signer> CertAuth<S> {
     fn route_authorizations_update(
         &self,
         updates: RouteAuthorizationUpdates,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> KrillResult<Vec<Evt>> {
         let (added, removed) = updates.unpack();
@@ -1548,7 +1851,12 @@ impl<S: Signer> CertAuth<S> {
 
         // Update ROAs, and derive deltas and revocations for publishing.
         for (rcn, rc) in self.resources.iter() {
-            let updates = rc.update_roas(current_auths.as_slice(), &mode, signer.deref())?;
+            let updates = rc.update_roas(
+                current_auths.as_slice(),
+                roa_aggregation,
+                &mode,
+                signer.deref(),
+            )?;
             if updates.contains_changes() {
                 let mut delta = ObjectsDelta::new(repo.repo_info().ca_repository(rc.name_space()));
 
@@ -1590,6 +1898,357 @@ impl<S: Signer> CertAuth<S> {
     }
 }
 
+/// # Managing ASPA
+///
+impl<S: Signer> CertAuth<S> {
+    /// Updates the ASPA definitions for this CA, and update ASPA objects. Will
+    /// return an error in case a definition is added for a customer AS which is
+    /// not held by this CA, or in case an unknown definition is removed.
+    fn aspas_update(
+        &self,
+        updates: AspaDefinitionUpdates,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let (add_or_replace, remove) = updates.unpack();
+        let signer = signer.read().unwrap();
+        let mode = PublishMode::Normal;
+
+        let repo = self.get_repository_contact()?;
+
+        let mut res = vec![];
+        let mut version = self.version;
+        let all_resources = self.all_resources();
+
+        let mut current: HashMap<AsNumber, AspaDefinition> = self
+            .aspas
+            .all()
+            .map(|def| (def.customer(), def.clone()))
+            .collect();
+
+        for customer in remove {
+            if current.remove(&customer).is_some() {
+                res.push(StoredEvent::new(
+                    self.handle(),
+                    version,
+                    EvtDet::AspaDefinitionRemoved(customer),
+                ));
+                version += 1;
+            } else {
+                return Err(Error::CaAspaUnknown(self.handle.clone(), customer));
+            }
+        }
+
+        for definition in add_or_replace {
+            let customer = definition.customer();
+            let definition = AspaDefinition::new(customer, definition.providers().clone());
+
+            if definition.providers().is_empty() {
+                return Err(Error::CaAspaNoProviders(self.handle.clone(), customer));
+            } else if definition.customer_used_as_provider() {
+                return Err(Error::CaAspaCustomerAsProvider(
+                    self.handle.clone(),
+                    customer,
+                ));
+            } else if !all_resources.contains(&customer.into()) {
+                return Err(Error::CaAspaNotEntitled(self.handle.clone(), customer));
+            } else if current.get(&customer) != Some(&definition) {
+                current.insert(customer, definition.clone());
+                res.push(StoredEvent::new(
+                    self.handle(),
+                    version,
+                    EvtDet::AspaDefinitionAdded(definition),
+                ));
+                version += 1;
+            }
+        }
+
+        let current: Vec<AspaDefinition> = current.into_iter().map(|(_, def)| def).collect();
+
+        let mut deltas = HashMap::new();
+
+        // Update ASPA objects, and derive deltas and revocations for publishing.
+        for (rcn, rc) in self.resources.iter() {
+            if rc.current_key().is_none() {
+                continue;
+            }
+            let updates = rc.update_aspas(current.as_slice(), &mode, signer.deref())?;
+            if updates.contains_changes() {
+                let mut delta = ObjectsDelta::new(repo.repo_info().ca_repository(rc.name_space()));
+
+                for added in updates.added().into_iter() {
+                    delta.add(added);
+                }
+                for update in updates.updated().into_iter() {
+                    delta.update(update);
+                }
+                for withdraw in updates.withdrawn().into_iter() {
+                    delta.withdraw(withdraw);
+                }
+
+                let revocations = updates.revocations();
+
+                deltas.insert(rcn, (delta, revocations));
+
+                res.push(StoredEvent::new(
+                    self.handle(),
+                    version,
+                    EvtDet::AspaObjectsUpdated(rcn.clone(), updates),
+                ));
+                version += 1;
+            }
+        }
+
+        // Create publication delta with all additions/updates/withdraws as a single delta
+        for (rcn, (delta, revocations)) in deltas.into_iter() {
+            let rc = self.resources.get(&rcn).unwrap();
+
+            let pub_detail =
+                rc.publish_objects(repo.repo_info(), delta, revocations, &mode, signer.deref())?;
+
+            res.push(StoredEvent::new(&self.handle, version, pub_detail));
+            version += 1;
+        }
+
+        Ok(res)
+    }
+}
+
+/// # Managing BGPsec router certificates
+///
+impl<S: Signer> CertAuth<S> {
+    /// Adds or replaces the definition for a router key, and issues (or
+    /// re-issues) its router certificate. Will return an error in case no
+    /// ASNs are given, or if any ASN is not held by this CA.
+    fn router_certify(
+        &self,
+        definition: RouterCertDefinition,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        if definition.asns().is_empty() {
+            return Err(Error::CaBgpSecNoAsns(self.handle.clone()));
+        }
+
+        let all_resources = self.all_resources();
+        for asn in definition.asns() {
+            if !all_resources.contains(&(*asn).into()) {
+                return Err(Error::CaBgpSecNotEntitled(self.handle.clone(), *asn));
+            }
+        }
+
+        if self.router_certs.get(definition.key_identifier()) == Some(&definition) {
+            return Ok(vec![]);
+        }
+
+        let mut current: Vec<RouterCertDefinition> = self
+            .router_certs
+            .all()
+            .filter(|def| def.key_identifier() != definition.key_identifier())
+            .cloned()
+            .collect();
+        current.push(definition.clone());
+
+        let event = StoredEvent::new(
+            self.handle(),
+            self.version,
+            EvtDet::RouterCertDefinitionAdded(definition),
+        );
+
+        self.router_certs_publish(event, current.as_slice(), signer)
+    }
+
+    /// Removes the definition for a router key, and revokes and withdraws its
+    /// router certificate.
+    fn router_revoke(&self, key: KeyIdentifier, signer: Arc<RwLock<S>>) -> KrillResult<Vec<Evt>> {
+        if !self.router_certs.has(&key) {
+            return Err(Error::CaBgpSecUnknown(self.handle.clone(), key));
+        }
+
+        let current: Vec<RouterCertDefinition> = self
+            .router_certs
+            .all()
+            .filter(|def| def.key_identifier() != &key)
+            .cloned()
+            .collect();
+
+        let event = StoredEvent::new(
+            self.handle(),
+            self.version,
+            EvtDet::RouterCertDefinitionRemoved(key),
+        );
+
+        self.router_certs_publish(event, current.as_slice(), signer)
+    }
+
+    /// Updates the router certificates in all resource classes after the
+    /// definition event, and publishes the changes.
+    fn router_certs_publish(
+        &self,
+        definition_event: Evt,
+        current: &[RouterCertDefinition],
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let signer = signer.read().unwrap();
+        let mode = PublishMode::Normal;
+
+        let repo = self.get_repository_contact()?;
+
+        let mut res = vec![definition_event];
+        let mut version = self.version + 1;
+
+        let mut deltas = HashMap::new();
+
+        // Update router certificates, and derive deltas and revocations for publishing.
+        for (rcn, rc) in self.resources.iter() {
+            if rc.current_key().is_none() {
+                continue;
+            }
+            let updates = rc.update_router_certs(current, &mode, signer.deref())?;
+            if updates.contains_changes() {
+                let mut delta = ObjectsDelta::new(repo.repo_info().ca_repository(rc.name_space()));
+
+                for added in updates.added().into_iter() {
+                    delta.add(added);
+                }
+                for update in updates.updated().into_iter() {
+                    delta.update(update);
+                }
+                for withdraw in updates.withdrawn().into_iter() {
+                    delta.withdraw(withdraw);
+                }
+
+                let revocations = updates.revocations();
+
+                deltas.insert(rcn, (delta, revocations));
+
+                res.push(StoredEvent::new(
+                    self.handle(),
+                    version,
+                    EvtDet::RouterCertsUpdated(rcn.clone(), updates),
+                ));
+                version += 1;
+            }
+        }
+
+        // Create publication delta with all additions/updates/withdraws as a single delta
+        for (rcn, (delta, revocations)) in deltas.into_iter() {
+            let rc = self.resources.get(&rcn).unwrap();
+
+            let pub_detail =
+                rc.publish_objects(repo.repo_info(), delta, revocations, &mode, signer.deref())?;
+
+            res.push(StoredEvent::new(&self.handle, version, pub_detail));
+            version += 1;
+        }
+
+        Ok(res)
+    }
+}
+
+/// # Managing Ghostbusters
+///
+impl<S: Signer> CertAuth<S> {
+    /// Sets or replaces the vCard for this CA, and publishes it in a
+    /// Ghostbusters Record under each resource class. The vCard is expected
+    /// to be validated before it is passed to the CA.
+    fn ghostbuster_update(
+        &self,
+        record: GhostbusterRecord,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        if self.ghostbuster.as_ref() == Some(&record) {
+            return Ok(vec![]);
+        }
+
+        let event = StoredEvent::new(
+            self.handle(),
+            self.version,
+            EvtDet::GhostbusterRecordUpdated(record.clone()),
+        );
+
+        self.ghostbuster_publish(event, Some(&record), signer)
+    }
+
+    /// Removes the vCard for this CA, and revokes and withdraws its
+    /// Ghostbusters Records.
+    fn ghostbuster_remove(&self, signer: Arc<RwLock<S>>) -> KrillResult<Vec<Evt>> {
+        if self.ghostbuster.is_none() {
+            return Err(Error::CaGhostbusterUnknown(self.handle.clone()));
+        }
+
+        let event = StoredEvent::new(
+            self.handle(),
+            self.version,
+            EvtDet::GhostbusterRecordRemoved,
+        );
+
+        self.ghostbuster_publish(event, None, signer)
+    }
+
+    /// Updates the Ghostbusters Record in all resource classes after the
+    /// vCard event, and publishes the changes.
+    fn ghostbuster_publish(
+        &self,
+        record_event: Evt,
+        record: Option<&GhostbusterRecord>,
+        signer: Arc<RwLock<S>>,
+    ) -> KrillResult<Vec<Evt>> {
+        let signer = signer.read().unwrap();
+        let mode = PublishMode::Normal;
+
+        let repo = self.get_repository_contact()?;
+
+        let mut res = vec![record_event];
+        let mut version = self.version + 1;
+
+        let mut deltas = HashMap::new();
+
+        // Update the objects, and derive deltas and revocations for publishing.
+        for (rcn, rc) in self.resources.iter() {
+            if rc.current_key().is_none() {
+                continue;
+            }
+            let updates = rc.update_ghostbuster(record, &mode, signer.deref())?;
+            if updates.contains_changes() {
+                let mut delta = ObjectsDelta::new(repo.repo_info().ca_repository(rc.name_space()));
+
+                for added in updates.added().into_iter() {
+                    delta.add(added);
+                }
+                for update in updates.updated().into_iter() {
+                    delta.update(update);
+                }
+                for withdraw in updates.withdrawn().into_iter() {
+                    delta.withdraw(withdraw);
+                }
+
+                let revocations = updates.revocations();
+
+                deltas.insert(rcn, (delta, revocations));
+
+                res.push(StoredEvent::new(
+                    self.handle(),
+                    version,
+                    EvtDet::GhostbusterObjectUpdated(rcn.clone(), updates),
+                ));
+                version += 1;
+            }
+        }
+
+        // Create publication delta with all additions/updates/withdraws as a single delta
+        for (rcn, (delta, revocations)) in deltas.into_iter() {
+            let rc = self.resources.get(&rcn).unwrap();
+
+            let pub_detail =
+                rc.publish_objects(repo.repo_info(), delta, revocations, &mode, signer.deref())?;
+
+            res.push(StoredEvent::new(&self.handle, version, pub_detail));
+            version += 1;
+        }
+
+        Ok(res)
+    }
+}
+
 //------------ Tests ---------------------------------------------------------
 
 #[cfg(test)]
@@ -32,6 +32,13 @@ pub struct ChildDetails {
     id_cert: Option<IdCert>,
     resources: ResourceSet,
     used_keys: HashMap<KeyIdentifier, LastResponse>,
+
+    /// The time when the resources of the child were last reduced, while it
+    /// held certificates for resources no longer entitled to. Such
+    /// certificates are shrunk after a grace period, if the child did not
+    /// request new certificates itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resources_reduced: Option<Time>,
 }
 
 impl ChildDetails {
@@ -40,6 +47,7 @@ impl ChildDetails {
             id_cert,
             resources,
             used_keys: HashMap::new(),
+            resources_reduced: None,
         }
     }
 
@@ -59,6 +67,14 @@ impl ChildDetails {
         self.resources = resources;
     }
 
+    pub fn resources_reduced(&self) -> Option<Time> {
+        self.resources_reduced
+    }
+
+    pub fn set_resources_reduced(&mut self, time: Time) {
+        self.resources_reduced = Some(time);
+    }
+
     pub fn issued(&self, rcn: &ResourceClassName) -> Vec<KeyIdentifier> {
         let mut res = vec![];
 
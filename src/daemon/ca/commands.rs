@@ -4,16 +4,19 @@ use std::sync::{Arc, RwLock};
 
 use chrono::Duration;
 
+use rpki::crypto::KeyIdentifier;
 use rpki::uri;
 
 use crate::commons::api::{
-    ChildHandle, Entitlements, Handle, IssuanceRequest, ParentCaContact, ParentHandle, RcvdCert,
-    RepositoryContact, ResourceClassName, ResourceSet, RevocationRequest, RevocationResponse,
-    StorableCaCommand,
+    AspaDefinitionUpdates, ChildHandle, Entitlements, GhostbusterRecord, Handle, IssuanceRequest,
+    ParentCaContact, ParentHandle, RcvdCert, RepositoryContact, ResourceClassName, ResourceSet,
+    RevocationRequest, RevocationResponse, StorableCaCommand,
 };
 use crate::commons::eventsourcing;
 use crate::commons::remote::id::IdCert;
-use crate::daemon::ca::{Evt, RouteAuthorizationUpdates, Signer};
+use crate::daemon::ca::{
+    Evt, RoaAggregation, RouteAuthorizationUpdates, RouterCertDefinition, Signer,
+};
 
 //------------ Command -----------------------------------------------------
 
@@ -45,6 +48,9 @@ pub enum CmdDet<S: Signer> {
     ChildRevokeKey(ChildHandle, RevocationRequest, Arc<RwLock<S>>),
     // Remove child (also revokes, and removes issued certs, and republishes)
     ChildRemove(ChildHandle, Arc<RwLock<S>>),
+    // Shrink (or revoke) certificates of children which still claim resources
+    // that were taken away from them longer ago than the grace period.
+    ChildShrinkCertificates(Duration, RoaAggregation, Arc<RwLock<S>>),
 
     // ------------------------------------------------------------
     // Being a child (only allowed if this CA is not self-signed)
@@ -70,7 +76,7 @@ pub enum CmdDet<S: Signer> {
     // as needed.
     UpdateResourceClasses(ParentHandle, Entitlements, Arc<RwLock<S>>),
     // Process a new certificate received from a parent.
-    UpdateRcvdCert(ResourceClassName, RcvdCert, Arc<RwLock<S>>),
+    UpdateRcvdCert(ResourceClassName, RcvdCert, RoaAggregation, Arc<RwLock<S>>),
 
     // ------------------------------------------------------------
     // Key rolls
@@ -90,7 +96,7 @@ pub enum CmdDet<S: Signer> {
     //
     // RFC6489 dictates that 24 hours MUST be observed. However, shorter time frames can
     // be used for testing, and in case of emergency rolls.
-    KeyRollActivate(Duration, Arc<RwLock<S>>),
+    KeyRollActivate(Duration, RoaAggregation, Arc<RwLock<S>>),
 
     // Finish the keyroll after the parent confirmed that a key for a parent and resource
     // class has been revoked. I.e. remove the old key, and withdraw the crl and mft for it.
@@ -99,17 +105,40 @@ pub enum CmdDet<S: Signer> {
     // ------------------------------------------------------------
     // ROA Support
     // ------------------------------------------------------------
-    RouteAuthorizationsUpdate(RouteAuthorizationUpdates, Arc<RwLock<S>>),
+    // ROAs are aggregated per origin ASN, or not, as determined by the
+    // RoaAggregation passed in commands which may (re-)issue ROAs.
+    RouteAuthorizationsUpdate(RouteAuthorizationUpdates, RoaAggregation, Arc<RwLock<S>>),
+
+    // ------------------------------------------------------------
+    // ASPA Support
+    // ------------------------------------------------------------
+    AspasUpdate(AspaDefinitionUpdates, Arc<RwLock<S>>),
+
+    // ------------------------------------------------------------
+    // BGPsec Support
+    // ------------------------------------------------------------
+    RouterCertify(RouterCertDefinition, Arc<RwLock<S>>),
+    RouterRevoke(KeyIdentifier, Arc<RwLock<S>>),
+
+    // ------------------------------------------------------------
+    // Ghostbusters Support
+    // ------------------------------------------------------------
+    GhostbusterUpdate(GhostbusterRecord, Arc<RwLock<S>>),
+    GhostbusterRemove(Arc<RwLock<S>>),
 
     // ------------------------------------------------------------
     // Publishing
     // ------------------------------------------------------------
 
     // Republish, if needed, may be a no-op if everything is still fresh.
-    Republish(Arc<RwLock<S>>),
+    Republish(RoaAggregation, Arc<RwLock<S>>),
+
+    // Publish new manifests and CRLs with numbers above the given number,
+    // after restoring a backup.
+    RaisePublicationNumbers(u64, Arc<RwLock<S>>),
 
     // Update the repository where this CA publishes
-    RepoUpdate(RepositoryContact, Arc<RwLock<S>>),
+    RepoUpdate(RepositoryContact, RoaAggregation, Arc<RwLock<S>>),
 
     // Clean up the old pending to withdraw repo.
     RepoRemoveOld(Arc<RwLock<S>>),
@@ -150,6 +179,9 @@ impl<S: Signer> From<CmdDet<S>> for StorableCaCommand {
             }
             CmdDet::ChildRevokeKey(child, req, _) => StorableCaCommand::ChildRevokeKey(child, req),
             CmdDet::ChildRemove(child, _) => StorableCaCommand::ChildRemove(child),
+            CmdDet::ChildShrinkCertificates(grace, _, _) => {
+                StorableCaCommand::ChildShrinkCertificates(grace.num_seconds())
+            }
             CmdDet::GenerateNewIdKey(_) => StorableCaCommand::GenerateNewIdKey,
             CmdDet::AddParent(parent, contact) => {
                 StorableCaCommand::AddParent(parent, contact.into())
@@ -169,21 +201,32 @@ impl<S: Signer> From<CmdDet<S>> for StorableCaCommand {
 
                 StorableCaCommand::UpdateResourceClasses(parent, classes)
             }
-            CmdDet::UpdateRcvdCert(rcn, rcvd_cert, _) => {
+            CmdDet::UpdateRcvdCert(rcn, rcvd_cert, _, _) => {
                 StorableCaCommand::UpdateRcvdCert(rcn, rcvd_cert.resources().clone())
             }
             CmdDet::KeyRollInitiate(duration, _) => {
                 StorableCaCommand::KeyRollInitiate(duration.num_seconds())
             }
-            CmdDet::KeyRollActivate(duration, _) => {
+            CmdDet::KeyRollActivate(duration, _, _) => {
                 StorableCaCommand::KeyRollActivate(duration.num_seconds())
             }
             CmdDet::KeyRollFinish(rcn, _) => StorableCaCommand::KeyRollFinish(rcn),
-            CmdDet::RouteAuthorizationsUpdate(updates, _) => {
+            CmdDet::RouteAuthorizationsUpdate(updates, _, _) => {
                 StorableCaCommand::RoaDefinitionUpdates(updates.into())
             }
-            CmdDet::Republish(_) => StorableCaCommand::Republish,
-            CmdDet::RepoUpdate(update, _) => {
+            CmdDet::AspasUpdate(updates, _) => StorableCaCommand::AspasUpdate(updates),
+            CmdDet::RouterCertify(definition, _) => StorableCaCommand::RouterCertify(
+                definition.key_identifier().clone(),
+                definition.asns().clone(),
+            ),
+            CmdDet::RouterRevoke(key, _) => StorableCaCommand::RouterRevoke(key),
+            CmdDet::GhostbusterUpdate(_, _) => StorableCaCommand::GhostbusterUpdate,
+            CmdDet::GhostbusterRemove(_) => StorableCaCommand::GhostbusterRemove,
+            CmdDet::Republish(_, _) => StorableCaCommand::Republish,
+            CmdDet::RaisePublicationNumbers(number, _) => {
+                StorableCaCommand::RaisePublicationNumbers(number)
+            }
+            CmdDet::RepoUpdate(update, _, _) => {
                 let service_uri_opt = match update {
                     RepositoryContact::Embedded(_) => None,
                     RepositoryContact::Rfc8181(res) => Some(res.service_uri().clone()),
@@ -273,6 +316,19 @@ impl<S: Signer> CmdDet<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::ChildRemove(child_handle, signer))
     }
 
+    pub fn child_shrink_certificates(
+        handle: &Handle,
+        grace: Duration,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::ChildShrinkCertificates(grace, roa_aggregation, signer),
+        )
+    }
+
     pub fn update_id(handle: &Handle, signer: Arc<RwLock<S>>) -> Cmd<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::GenerateNewIdKey(signer))
     }
@@ -306,12 +362,13 @@ impl<S: Signer> CmdDet<S> {
         handle: &Handle,
         class_name: ResourceClassName,
         cert: RcvdCert,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> Cmd<S> {
         eventsourcing::SentCommand::new(
             handle,
             None,
-            CmdDet::UpdateRcvdCert(class_name, cert, signer),
+            CmdDet::UpdateRcvdCert(class_name, cert, roa_aggregation, signer),
         )
     }
 
@@ -323,8 +380,17 @@ impl<S: Signer> CmdDet<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::KeyRollInitiate(duration, signer))
     }
 
-    pub fn key_roll_activate(handle: &Handle, staging: Duration, signer: Arc<RwLock<S>>) -> Cmd<S> {
-        eventsourcing::SentCommand::new(handle, None, CmdDet::KeyRollActivate(staging, signer))
+    pub fn key_roll_activate(
+        handle: &Handle,
+        staging: Duration,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::KeyRollActivate(staging, roa_aggregation, signer),
+        )
     }
 
     pub fn key_roll_finish(
@@ -335,16 +401,37 @@ impl<S: Signer> CmdDet<S> {
         eventsourcing::SentCommand::new(handle, None, CmdDet::KeyRollFinish(rcn, res))
     }
 
-    pub fn publish(handle: &Handle, signer: Arc<RwLock<S>>) -> Cmd<S> {
-        eventsourcing::SentCommand::new(handle, None, CmdDet::Republish(signer))
+    pub fn publish(
+        handle: &Handle,
+        roa_aggregation: RoaAggregation,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::Republish(roa_aggregation, signer))
+    }
+
+    pub fn raise_publication_numbers(
+        handle: &Handle,
+        number: u64,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::RaisePublicationNumbers(number, signer),
+        )
     }
 
     pub fn update_repo(
         handle: &Handle,
         contact: RepositoryContact,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> Cmd<S> {
-        eventsourcing::SentCommand::new(handle, None, CmdDet::RepoUpdate(contact, signer))
+        eventsourcing::SentCommand::new(
+            handle,
+            None,
+            CmdDet::RepoUpdate(contact, roa_aggregation, signer),
+        )
     }
 
     pub fn remove_old_repo(handle: &Handle, signer: Arc<RwLock<S>>) -> Cmd<S> {
@@ -357,12 +444,54 @@ impl<S: Signer> CmdDet<S> {
     pub fn route_authorizations_update(
         handle: &Handle,
         updates: RouteAuthorizationUpdates,
+        roa_aggregation: RoaAggregation,
         signer: Arc<RwLock<S>>,
     ) -> Cmd<S> {
         eventsourcing::SentCommand::new(
             handle,
             None,
-            CmdDet::RouteAuthorizationsUpdate(updates, signer),
+            CmdDet::RouteAuthorizationsUpdate(updates, roa_aggregation, signer),
         )
     }
+
+    //-------------------------------------------------------------------------------
+    // ASPA
+    //-------------------------------------------------------------------------------
+    pub fn aspas_update(
+        handle: &Handle,
+        updates: AspaDefinitionUpdates,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::AspasUpdate(updates, signer))
+    }
+
+    //-------------------------------------------------------------------------------
+    // BGPsec
+    //-------------------------------------------------------------------------------
+    pub fn router_certify(
+        handle: &Handle,
+        definition: RouterCertDefinition,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::RouterCertify(definition, signer))
+    }
+
+    pub fn router_revoke(handle: &Handle, key: KeyIdentifier, signer: Arc<RwLock<S>>) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::RouterRevoke(key, signer))
+    }
+
+    //-------------------------------------------------------------------------------
+    // Ghostbusters
+    //-------------------------------------------------------------------------------
+    pub fn ghostbuster_update(
+        handle: &Handle,
+        record: GhostbusterRecord,
+        signer: Arc<RwLock<S>>,
+    ) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::GhostbusterUpdate(record, signer))
+    }
+
+    pub fn ghostbuster_remove(handle: &Handle, signer: Arc<RwLock<S>>) -> Cmd<S> {
+        eventsourcing::SentCommand::new(handle, None, CmdDet::GhostbusterRemove(signer))
+    }
 }
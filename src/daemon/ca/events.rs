@@ -4,20 +4,21 @@ use std::ops::DerefMut;
 use std::sync::{Arc, RwLock};
 
 use rpki::crypto::KeyIdentifier;
+use rpki::x509::Time;
 
 use crate::commons::api::{
-    AddedObject, ChildHandle, Handle, IssuanceRequest, IssuedCert, ObjectName, ObjectsDelta,
-    ParentCaContact, ParentHandle, RcvdCert, RepoInfo, RepositoryContact, ResourceClassName,
-    ResourceSet, Revocation, RevocationRequest, RevokedObject, TaCertDetails, UpdatedObject,
-    WithdrawnObject,
+    AddedObject, AsNumber, AspaDefinition, ChildHandle, GhostbusterRecord, Handle, IssuanceRequest,
+    IssuedCert, ObjectName, ObjectsDelta, ParentCaContact, ParentHandle, RcvdCert, RepoInfo,
+    RepositoryContact, ResourceClassName, ResourceSet, Revocation, RevocationRequest,
+    RevokedObject, TaCertDetails, UpdatedObject, WithdrawnObject,
 };
 use crate::commons::eventsourcing::StoredEvent;
 use crate::commons::remote::id::IdCert;
 use crate::commons::KrillResult;
 use crate::daemon::ca::signing::Signer;
 use crate::daemon::ca::{
-    CertifiedKey, ChildDetails, CurrentObjectSetDelta, ResourceClass, Rfc8183Id, RoaInfo,
-    RouteAuthorization,
+    AggregateRoaInfo, AspaInfo, CertifiedKey, ChildDetails, CurrentObjectSetDelta, GhostbusterInfo, IssuedRouterCert,
+    ResourceClass, Rfc8183Id, RoaInfo, RouteAuthorization, RouterCertDefinition,
 };
 
 //------------ Ini -----------------------------------------------------------
@@ -78,6 +79,10 @@ impl fmt::Display for IniDet {
 pub struct RoaUpdates {
     updated: HashMap<RouteAuthorization, RoaInfo>,
     removed: HashMap<RouteAuthorization, RevokedObject>,
+    #[serde(default)]
+    aggregate_updated: HashMap<AsNumber, AggregateRoaInfo>,
+    #[serde(default)]
+    aggregate_removed: HashMap<AsNumber, RevokedObject>,
 }
 
 impl Default for RoaUpdates {
@@ -85,6 +90,8 @@ impl Default for RoaUpdates {
         RoaUpdates {
             updated: HashMap::new(),
             removed: HashMap::new(),
+            aggregate_updated: HashMap::new(),
+            aggregate_removed: HashMap::new(),
         }
     }
 }
@@ -94,11 +101,19 @@ impl RoaUpdates {
         updated: HashMap<RouteAuthorization, RoaInfo>,
         removed: HashMap<RouteAuthorization, RevokedObject>,
     ) -> Self {
-        RoaUpdates { updated, removed }
+        RoaUpdates {
+            updated,
+            removed,
+            aggregate_updated: HashMap::new(),
+            aggregate_removed: HashMap::new(),
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.updated.is_empty() && self.removed.is_empty()
+        self.updated.is_empty()
+            && self.removed.is_empty()
+            && self.aggregate_updated.is_empty()
+            && self.aggregate_removed.is_empty()
     }
 
     pub fn contains_changes(&self) -> bool {
@@ -113,9 +128,25 @@ impl RoaUpdates {
         self.removed.insert(auth, revoke);
     }
 
+    pub fn update_aggregate(&mut self, asn: AsNumber, aggregate: AggregateRoaInfo) {
+        self.aggregate_updated.insert(asn, aggregate);
+    }
+
+    pub fn remove_aggregate(&mut self, asn: AsNumber, revoke: RevokedObject) {
+        self.aggregate_removed.insert(asn, revoke);
+    }
+
+    fn all_updated(&self) -> impl Iterator<Item = &RoaInfo> {
+        self.updated.values().chain(
+            self.aggregate_updated
+                .values()
+                .map(|aggregate| aggregate.roa_info()),
+        )
+    }
+
     pub fn added(&self) -> Vec<AddedObject> {
         let mut res = vec![];
-        for (_auth, info) in self.updated.iter() {
+        for info in self.all_updated() {
             if info.replaces().is_none() {
                 let object = info.object().clone();
                 let name = info.name().clone();
@@ -127,7 +158,7 @@ impl RoaUpdates {
 
     pub fn updated(&self) -> Vec<UpdatedObject> {
         let mut res = vec![];
-        for (_auth, info) in self.updated.iter() {
+        for info in self.all_updated() {
             if let Some(replaced) = info.replaces() {
                 let object = info.object().clone();
                 let name = info.name().clone();
@@ -144,18 +175,23 @@ impl RoaUpdates {
             let hash = revoked.hash().clone();
             res.push(WithdrawnObject::new(name, hash));
         }
+        for (asn, revoked) in self.aggregate_removed.iter() {
+            let name = ObjectName::aggregate_roa(*asn);
+            let hash = revoked.hash().clone();
+            res.push(WithdrawnObject::new(name, hash));
+        }
         res
     }
 
     pub fn revocations(&self) -> Vec<Revocation> {
         let mut res = vec![];
-        for info in self.updated.values() {
+        for info in self.all_updated() {
             if let Some(old) = info.replaces() {
                 res.push(old.revocation())
             }
         }
 
-        for revoked in self.removed.values() {
+        for revoked in self.removed.values().chain(self.aggregate_removed.values()) {
             res.push(revoked.revocation())
         }
 
@@ -167,11 +203,268 @@ impl RoaUpdates {
     ) -> (
         HashMap<RouteAuthorization, RoaInfo>,
         HashMap<RouteAuthorization, RevokedObject>,
+        HashMap<AsNumber, AggregateRoaInfo>,
+        HashMap<AsNumber, RevokedObject>,
+    ) {
+        (
+            self.updated,
+            self.removed,
+            self.aggregate_updated,
+            self.aggregate_removed,
+        )
+    }
+}
+
+//------------ AspaObjectsUpdates ------------------------------------------
+
+/// Describes an update to the set of ASPA objects under a ResourceClass.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AspaObjectsUpdates {
+    updated: HashMap<AsNumber, AspaInfo>,
+    removed: HashMap<AsNumber, RevokedObject>,
+}
+
+impl AspaObjectsUpdates {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn contains_changes(&self) -> bool {
+        !self.is_empty()
+    }
+
+    pub fn update(&mut self, customer: AsNumber, aspa: AspaInfo) {
+        self.updated.insert(customer, aspa);
+    }
+
+    pub fn remove(&mut self, customer: AsNumber, revoke: RevokedObject) {
+        self.removed.insert(customer, revoke);
+    }
+
+    pub fn added(&self) -> Vec<AddedObject> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if info.replaces().is_none() {
+                res.push(AddedObject::new(info.name(), info.object().clone()));
+            }
+        }
+        res
+    }
+
+    pub fn updated(&self) -> Vec<UpdatedObject> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if let Some(replaced) = info.replaces() {
+                let object = info.object().clone();
+                res.push(UpdatedObject::new(
+                    info.name(),
+                    object,
+                    replaced.hash().clone(),
+                ));
+            }
+        }
+        res
+    }
+
+    pub fn withdrawn(&self) -> Vec<WithdrawnObject> {
+        let mut res = vec![];
+        for (customer, revoked) in self.removed.iter() {
+            let name = ObjectName::aspa(*customer);
+            let hash = revoked.hash().clone();
+            res.push(WithdrawnObject::new(name, hash));
+        }
+        res
+    }
+
+    pub fn revocations(&self) -> Vec<Revocation> {
+        let mut res = vec![];
+        for info in self.updated.values() {
+            if let Some(old) = info.replaces() {
+                res.push(old.revocation())
+            }
+        }
+
+        for revoked in self.removed.values() {
+            res.push(revoked.revocation())
+        }
+
+        res
+    }
+
+    pub fn unpack(
+        self,
+    ) -> (
+        HashMap<AsNumber, AspaInfo>,
+        HashMap<AsNumber, RevokedObject>,
     ) {
         (self.updated, self.removed)
     }
 }
 
+//------------ RouterCertUpdates -------------------------------------------
+
+/// Describes an update to the set of BGPsec router certificates under a
+/// ResourceClass.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RouterCertUpdates {
+    issued: HashMap<KeyIdentifier, IssuedRouterCert>,
+    removed: HashMap<KeyIdentifier, IssuedRouterCert>,
+}
+
+impl RouterCertUpdates {
+    pub fn is_empty(&self) -> bool {
+        self.issued.is_empty() && self.removed.is_empty()
+    }
+
+    pub fn contains_changes(&self) -> bool {
+        !self.is_empty()
+    }
+
+    pub fn issue(&mut self, cert: IssuedRouterCert) {
+        self.issued.insert(cert.key_identifier().clone(), cert);
+    }
+
+    pub fn remove(&mut self, cert: IssuedRouterCert) {
+        self.removed.insert(cert.key_identifier().clone(), cert);
+    }
+
+    pub fn added(&self) -> Vec<AddedObject> {
+        let mut res = vec![];
+        for cert in self.issued.values() {
+            if cert.replaces().is_none() {
+                res.push(AddedObject::new(cert.name(), cert.object().clone()));
+            }
+        }
+        res
+    }
+
+    pub fn updated(&self) -> Vec<UpdatedObject> {
+        let mut res = vec![];
+        for cert in self.issued.values() {
+            if let Some(replaced) = cert.replaces() {
+                let object = cert.object().clone();
+                res.push(UpdatedObject::new(
+                    cert.name(),
+                    object,
+                    replaced.hash().clone(),
+                ));
+            }
+        }
+        res
+    }
+
+    pub fn withdrawn(&self) -> Vec<WithdrawnObject> {
+        let mut res = vec![];
+        for cert in self.removed.values() {
+            let hash = cert.object().to_hex_hash();
+            res.push(WithdrawnObject::new(cert.name(), hash));
+        }
+        res
+    }
+
+    pub fn revocations(&self) -> Vec<Revocation> {
+        let mut res = vec![];
+        for cert in self.issued.values() {
+            if let Some(old) = cert.replaces() {
+                res.push(old.revocation())
+            }
+        }
+
+        for cert in self.removed.values() {
+            res.push(RevokedObject::from(cert.object()).revocation())
+        }
+
+        res
+    }
+
+    pub fn unpack(
+        self,
+    ) -> (
+        HashMap<KeyIdentifier, IssuedRouterCert>,
+        HashMap<KeyIdentifier, IssuedRouterCert>,
+    ) {
+        (self.issued, self.removed)
+    }
+}
+
+//------------ GhostbusterUpdates ------------------------------------------
+
+/// Describes an update to the Ghostbusters Record under a ResourceClass.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GhostbusterUpdates {
+    updated: Option<GhostbusterInfo>,
+    removed: Option<RevokedObject>,
+}
+
+impl GhostbusterUpdates {
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_none() && self.removed.is_none()
+    }
+
+    pub fn contains_changes(&self) -> bool {
+        !self.is_empty()
+    }
+
+    pub fn update(&mut self, gbr: GhostbusterInfo) {
+        self.updated = Some(gbr);
+    }
+
+    pub fn remove(&mut self, revoke: RevokedObject) {
+        self.removed = Some(revoke);
+    }
+
+    pub fn added(&self) -> Vec<AddedObject> {
+        let mut res = vec![];
+        if let Some(info) = &self.updated {
+            if info.replaces().is_none() {
+                res.push(AddedObject::new(info.name(), info.object().clone()));
+            }
+        }
+        res
+    }
+
+    pub fn updated(&self) -> Vec<UpdatedObject> {
+        let mut res = vec![];
+        if let Some(info) = &self.updated {
+            if let Some(replaced) = info.replaces() {
+                let object = info.object().clone();
+                res.push(UpdatedObject::new(
+                    info.name(),
+                    object,
+                    replaced.hash().clone(),
+                ));
+            }
+        }
+        res
+    }
+
+    pub fn withdrawn(&self) -> Vec<WithdrawnObject> {
+        let mut res = vec![];
+        if let Some(revoked) = &self.removed {
+            let hash = revoked.hash().clone();
+            res.push(WithdrawnObject::new(ObjectName::ghostbuster(), hash));
+        }
+        res
+    }
+
+    pub fn revocations(&self) -> Vec<Revocation> {
+        let mut res = vec![];
+        if let Some(old) = self.updated.as_ref().and_then(|info| info.replaces()) {
+            res.push(old.revocation())
+        }
+
+        if let Some(revoked) = &self.removed {
+            res.push(revoked.revocation())
+        }
+
+        res
+    }
+
+    pub fn unpack(self) -> (Option<GhostbusterInfo>, Option<RevokedObject>) {
+        (self.updated, self.removed)
+    }
+}
+
 //------------ ChildCertificateUpdates -------------------------------------
 
 /// Describes an update to the set of ROAs under a ResourceClass.
@@ -223,6 +516,7 @@ pub enum EvtDet {
     ChildCertificatesUpdated(ResourceClassName, ChildCertificateUpdates),
     ChildUpdatedIdCert(ChildHandle, IdCert),
     ChildUpdatedResources(ChildHandle, ResourceSet),
+    ChildResourcesReduced(ChildHandle, Time),
     ChildRemoved(ChildHandle),
 
     // Being a child Events
@@ -254,6 +548,21 @@ pub enum EvtDet {
     RouteAuthorizationRemoved(RouteAuthorization),
     RoasUpdated(ResourceClassName, RoaUpdates),
 
+    // ASPA
+    AspaDefinitionAdded(AspaDefinition),
+    AspaDefinitionRemoved(AsNumber),
+    AspaObjectsUpdated(ResourceClassName, AspaObjectsUpdates),
+
+    // BGPsec router certificates
+    RouterCertDefinitionAdded(RouterCertDefinition),
+    RouterCertDefinitionRemoved(KeyIdentifier),
+    RouterCertsUpdated(ResourceClassName, RouterCertUpdates),
+
+    // Ghostbusters
+    GhostbusterRecordUpdated(GhostbusterRecord),
+    GhostbusterRecordRemoved,
+    GhostbusterObjectUpdated(ResourceClassName, GhostbusterUpdates),
+
     // Publishing
     ObjectSetUpdated(
         ResourceClassName,
@@ -364,6 +673,19 @@ impl EvtDet {
         )
     }
 
+    pub(super) fn child_resources_reduced(
+        handle: &Handle,
+        version: u64,
+        child: ChildHandle,
+        time: Time,
+    ) -> Evt {
+        StoredEvent::new(
+            handle,
+            version,
+            EvtDet::ChildResourcesReduced(child, time),
+        )
+    }
+
     pub(super) fn child_certificate_issued(
         handle: &Handle,
         version: u64,
@@ -481,6 +803,12 @@ impl fmt::Display for EvtDet {
             EvtDet::ChildUpdatedResources(child, resources) => {
                 write!(f, "updated child '{}' resources to '{}'", child, resources)
             }
+            EvtDet::ChildResourcesReduced(child, time) => write!(
+                f,
+                "child '{}' holds certificates exceeding its resources since {}",
+                child,
+                time.to_rfc3339()
+            ),
             EvtDet::ChildRemoved(child) => {
                 write!(f, "removed child '{}'", child)
             }
@@ -587,6 +915,90 @@ impl fmt::Display for EvtDet {
                         write!(f, "{} ", auth)?;
                     }
                 }
+                if ! roa_updates.aggregate_updated.is_empty() {
+                    write!(f, " added aggregates for ASNs: ")?;
+                    for asn in roa_updates.aggregate_updated.keys() {
+                        write!(f, "{} ", asn)?;
+                    }
+                }
+                if ! roa_updates.aggregate_removed.is_empty() {
+                    write!(f, " removed aggregates for ASNs: ")?;
+                    for asn in roa_updates.aggregate_removed.keys() {
+                        write!(f, "{} ", asn)?;
+                    }
+                }
+                Ok(())
+            },
+
+            // ASPA
+            EvtDet::AspaDefinitionAdded(definition) => write!(
+                f,
+                "added ASPA: '{}'",
+                definition
+            ),
+            EvtDet::AspaDefinitionRemoved(customer) => write!(
+                f,
+                "removed ASPA for customer AS: '{}'",
+                customer
+            ),
+            EvtDet::AspaObjectsUpdated(rcn, aspa_updates) => {
+                write!(f, "updated ASPA objects under resource class '{}'", rcn)?;
+                if ! aspa_updates.updated.is_empty() {
+                    write!(f, " updated customers: ")?;
+                    for customer in aspa_updates.updated.keys() {
+                        write!(f, "{} ", customer)?;
+                    }
+                }
+                if ! aspa_updates.removed.is_empty() {
+                    write!(f, " removed customers: ")?;
+                    for customer in aspa_updates.removed.keys() {
+                        write!(f, "{} ", customer)?;
+                    }
+                }
+                Ok(())
+            },
+
+            // BGPsec router certificates
+            EvtDet::RouterCertDefinitionAdded(definition) => {
+                write!(f, "added router key '{}' for ASNs:", definition.key_identifier())?;
+                for asn in definition.asns() {
+                    write!(f, " {}", asn)?;
+                }
+                Ok(())
+            },
+            EvtDet::RouterCertDefinitionRemoved(key) => write!(
+                f,
+                "removed router key '{}'",
+                key
+            ),
+            EvtDet::RouterCertsUpdated(rcn, router_updates) => {
+                write!(f, "updated router certificates under resource class '{}'", rcn)?;
+                if ! router_updates.issued.is_empty() {
+                    write!(f, " issued for keys: ")?;
+                    for key in router_updates.issued.keys() {
+                        write!(f, "{} ", key)?;
+                    }
+                }
+                if ! router_updates.removed.is_empty() {
+                    write!(f, " revoked for keys: ")?;
+                    for key in router_updates.removed.keys() {
+                        write!(f, "{} ", key)?;
+                    }
+                }
+                Ok(())
+            },
+
+            // Ghostbusters
+            EvtDet::GhostbusterRecordUpdated(_) => write!(f, "updated Ghostbusters vCard"),
+            EvtDet::GhostbusterRecordRemoved => write!(f, "removed Ghostbusters vCard"),
+            EvtDet::GhostbusterObjectUpdated(rcn, gbr_updates) => {
+                write!(f, "updated Ghostbusters Record under resource class '{}'", rcn)?;
+                if gbr_updates.updated.is_some() {
+                    write!(f, " published new object")?;
+                }
+                if gbr_updates.removed.is_some() {
+                    write!(f, " withdrew object")?;
+                }
                 Ok(())
             },
 
@@ -0,0 +1,139 @@
+use bcder::Oid;
+use bytes::Bytes;
+
+use rpki::sigobj::{SignedObject, SignedObjectBuilder};
+use rpki::uri;
+use rpki::x509::{Serial, Time};
+
+use crate::commons::api::{CurrentObject, GhostbusterRecord, ObjectName, ReplacedObject};
+use crate::commons::KrillResult;
+use crate::constants::GHOSTBUSTER_VALIDITY_YEARS;
+use crate::daemon::ca::events::GhostbusterUpdates;
+use crate::daemon::ca::{self, CertifiedKey, SignSupport, Signer};
+
+/// The content type for Ghostbusters Records, id-ct-rpkiGhostbusters:
+/// 1.2.840.113549.1.9.16.1.35
+const GHOSTBUSTERS_CONTENT_TYPE: [u8; 11] = [42, 134, 72, 134, 247, 13, 1, 9, 16, 1, 35];
+
+//------------ GhostbusterInfo ---------------------------------------------
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GhostbusterInfo {
+    record: GhostbusterRecord,        // the vCard in the object
+    object: CurrentObject,            // actual Ghostbusters Record
+    replaces: Option<ReplacedObject>, // for revoking when re-newing
+}
+
+impl GhostbusterInfo {
+    pub fn new_gbr(record: GhostbusterRecord, gbr: &SignedObject) -> Self {
+        GhostbusterInfo {
+            record,
+            object: CurrentObject::from(gbr),
+            replaces: None,
+        }
+    }
+
+    pub fn updated_gbr(
+        old: &GhostbusterInfo,
+        record: GhostbusterRecord,
+        gbr: &SignedObject,
+    ) -> Self {
+        GhostbusterInfo {
+            record,
+            object: CurrentObject::from(gbr),
+            replaces: Some(ReplacedObject::from(old.object())),
+        }
+    }
+
+    pub fn record(&self) -> &GhostbusterRecord {
+        &self.record
+    }
+
+    pub fn object(&self) -> &CurrentObject {
+        &self.object
+    }
+
+    pub fn name(&self) -> ObjectName {
+        ObjectName::ghostbuster()
+    }
+
+    pub fn replaces(&self) -> Option<&ReplacedObject> {
+        self.replaces.as_ref()
+    }
+}
+
+//------------ GhostbusterObject -------------------------------------------
+
+/// The Ghostbusters Record published by a resource class in a CA, if any.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GhostbusterObject {
+    current: Option<GhostbusterInfo>,
+}
+
+impl GhostbusterObject {
+    pub fn current(&self) -> Option<&GhostbusterInfo> {
+        self.current.as_ref()
+    }
+
+    pub fn record(&self) -> Option<&GhostbusterRecord> {
+        self.current.as_ref().map(|info| info.record())
+    }
+
+    pub fn updated(&mut self, updates: GhostbusterUpdates) {
+        let (updated, removed) = updates.unpack();
+
+        if let Some(info) = updated {
+            self.current = Some(info);
+        } else if removed.is_some() {
+            self.current = None;
+        }
+    }
+
+    /// Makes a Ghostbusters Record (RFC 6493). The EE certificate inherits
+    /// all resources from the signing certificate.
+    pub fn make_gbr<S: Signer>(
+        record: &GhostbusterRecord,
+        certified_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<SignedObject> {
+        let name = ObjectName::ghostbuster();
+
+        let incoming_cert = certified_key.incoming_cert();
+        let crl_uri = match &new_repo {
+            None => incoming_cert.crl_uri(),
+            Some(base_uri) => base_uri.join(incoming_cert.crl_name().as_bytes()),
+        };
+
+        let gbr_uri = match &new_repo {
+            None => incoming_cert.uri_for_object(name),
+            Some(base_uri) => base_uri.join(name.as_bytes()),
+        };
+
+        let aia = incoming_cert.uri();
+
+        let signing_key = certified_key.key_id();
+
+        let mut object_builder = SignedObjectBuilder::new(
+            Serial::random(signer).map_err(ca::Error::signer)?,
+            SignSupport::sign_validity_years(GHOSTBUSTER_VALIDITY_YEARS),
+            crl_uri,
+            aia.clone(),
+            gbr_uri,
+        );
+        object_builder.set_issuer(Some(incoming_cert.cert().subject().clone()));
+        object_builder.set_signing_time(Some(Time::now()));
+        object_builder.set_as_resources_inherit();
+        object_builder.set_v4_resources_inherit();
+        object_builder.set_v6_resources_inherit();
+
+        object_builder
+            .finalize(
+                Oid(Bytes::from_static(&GHOSTBUSTERS_CONTENT_TYPE)),
+                Bytes::copy_from_slice(record.vcard().as_bytes()),
+                signer,
+                signing_key,
+            )
+            .map_err(ca::Error::signer)
+    }
+}
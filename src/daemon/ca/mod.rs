@@ -22,6 +22,15 @@ pub use self::publishing::*;
 mod routes;
 pub use self::routes::*;
 
+mod aspa;
+pub use self::aspa::*;
+
+mod bgpsec;
+pub use self::bgpsec::*;
+
+mod ghostbuster;
+pub use self::ghostbuster::*;
+
 mod commands;
 pub use self::commands::*;
 
@@ -34,6 +43,9 @@ pub use self::server::CaServer;
 mod signing;
 pub use self::signing::*;
 
+mod status;
+pub use self::status::StatusStore;
+
 pub const TA_NAME: &str = "ta"; // reserved for TA
 
 pub fn ta_handle() -> Handle {
@@ -10,20 +10,27 @@ use rpki::x509::Time;
 use crate::commons::api::rrdp::PublishElement;
 use crate::commons::api::Base64;
 use crate::commons::api::{
-    AddedObject, CurrentObject, CurrentObjects, EntitlementClass, HexEncodedHash, IssuanceRequest,
-    IssuedCert, ObjectName, ObjectsDelta, ParentHandle, RcvdCert, ReplacedObject, RepoInfo,
-    RequestResourceLimit, ResourceClassInfo, ResourceClassName, ResourceSet, Revocation,
-    RevocationRequest, RevokedObject, UpdatedObject, WithdrawnObject,
+    AddedObject, AsNumber, AspaDefinition, CurrentObject, CurrentObjects, EntitlementClass,
+    GhostbusterRecord, HexEncodedHash, IssuanceRequest, IssuedCert, ObjectName, ObjectsDelta,
+    ParentHandle, RcvdCert, ReplacedObject, RepoInfo, RequestResourceLimit, ResourceClassInfo,
+    ResourceClassName, ResourceSet, Revocation, RevocationRequest, RevokedObject, UpdatedObject,
+    WithdrawnObject,
 };
 use crate::commons::error::Error;
 use crate::commons::KrillResult;
-use crate::constants::ROA_CERTIFICATE_REISSUE_WEEKS;
-use crate::daemon::ca::events::{ChildCertificateUpdates, RoaUpdates};
+use crate::constants::{
+    ASPA_CERTIFICATE_REISSUE_WEEKS, GHOSTBUSTER_REISSUE_WEEKS, ROA_CERTIFICATE_REISSUE_WEEKS,
+};
+use crate::daemon::ca::events::{
+    AspaObjectsUpdates, ChildCertificateUpdates, GhostbusterUpdates, RoaUpdates, RouterCertUpdates,
+};
 use crate::daemon::ca::signing::CsrInfo;
 use crate::daemon::ca::{
-    self, ta_handle, AddedOrUpdated, CertifiedKey, ChildCertificates, CrlBuilder, CurrentKey,
-    CurrentObjectSetDelta, EvtDet, KeyState, ManifestBuilder, NewKey, OldKey, PendingKey, RoaInfo,
-    Roas, RouteAuthorization, SignSupport, Signer,
+    self, ta_handle, AddedOrUpdated, AggregateRoaInfo, AspaInfo, AspaObjects, CertifiedKey,
+    ChildCertificates, CrlBuilder, CurrentKey, CurrentObjectSetDelta, EvtDet, GhostbusterInfo,
+    GhostbusterObject, IssuedRouterCert, KeyState, ManifestBuilder, NewKey, OldKey, PendingKey,
+    RoaAggregation, RoaInfo, Roas, RouteAuthorization, RouterCertDefinition, RouterCertificates,
+    SignSupport, Signer,
 };
 
 //------------ ResourceClass -----------------------------------------------
@@ -48,6 +55,12 @@ pub struct ResourceClass {
     parent_rc_name: ResourceClassName,
 
     roas: Roas,
+    #[serde(default)]
+    aspas: AspaObjects,
+    #[serde(default)]
+    router_certs: RouterCertificates,
+    #[serde(default)]
+    ghostbuster: GhostbusterObject,
     certificates: ChildCertificates,
 
     last_key_change: Time,
@@ -71,6 +84,9 @@ impl ResourceClass {
             parent_handle,
             parent_rc_name,
             roas: Roas::default(),
+            aspas: AspaObjects::default(),
+            router_certs: RouterCertificates::default(),
+            ghostbuster: GhostbusterObject::default(),
             certificates: ChildCertificates::default(),
             last_key_change: Time::now(),
             key_state: KeyState::create(pending_key),
@@ -84,6 +100,9 @@ impl ResourceClass {
             parent_handle: ta_handle(),
             parent_rc_name,
             roas: Roas::default(),
+            aspas: AspaObjects::default(),
+            router_certs: RouterCertificates::default(),
+            ghostbuster: GhostbusterObject::default(),
             certificates: ChildCertificates::default(),
             last_key_change: Time::now(),
             key_state: KeyState::create(pending_key),
@@ -154,6 +173,18 @@ impl ResourceClass {
             current_objects.insert(roa_info.name().clone(), roa_info.object().clone());
         }
 
+        for aspa_info in self.aspas.current() {
+            current_objects.insert(aspa_info.name(), aspa_info.object().clone());
+        }
+
+        for router_cert in self.router_certs.current() {
+            current_objects.insert(router_cert.name(), router_cert.object().clone());
+        }
+
+        if let Some(gbr_info) = self.ghostbuster.current() {
+            current_objects.insert(gbr_info.name(), gbr_info.object().clone());
+        }
+
         for issued in self.certificates.current() {
             let cert = issued.cert();
             current_objects.insert(ObjectName::from(cert), CurrentObject::from(cert));
@@ -208,6 +239,7 @@ impl ResourceClass {
         &self,
         rcvd_cert: RcvdCert,
         repo_info: &RepoInfo,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<Vec<EvtDet>> {
         // If this is for a pending key, then we need to promote this key
@@ -253,9 +285,13 @@ impl ResourceClass {
                     )])
                 }
             }
-            KeyState::Active(current) => {
-                self.update_rcvd_cert_current(current, rcvd_cert, repo_info, signer)
-            }
+            KeyState::Active(current) => self.update_rcvd_cert_current(
+                current,
+                rcvd_cert,
+                repo_info,
+                roa_aggregation,
+                signer,
+            ),
             KeyState::RollPending(pending, current) => {
                 if rcvd_cert_ki == pending.key_id() {
                     let (active_key, delta) = create_active_key_and_delta(
@@ -270,7 +306,13 @@ impl ResourceClass {
                         delta,
                     )])
                 } else {
-                    self.update_rcvd_cert_current(current, rcvd_cert, repo_info, signer)
+                    self.update_rcvd_cert_current(
+                        current,
+                        rcvd_cert,
+                        repo_info,
+                        roa_aggregation,
+                        signer,
+                    )
                 }
             }
             KeyState::RollNew(new, current) => {
@@ -281,12 +323,24 @@ impl ResourceClass {
                         rcvd_cert,
                     )])
                 } else {
-                    self.update_rcvd_cert_current(current, rcvd_cert, repo_info, signer)
+                    self.update_rcvd_cert_current(
+                        current,
+                        rcvd_cert,
+                        repo_info,
+                        roa_aggregation,
+                        signer,
+                    )
                 }
             }
             KeyState::RollOld(current, _old) => {
                 // We will never request a new certificate for an old key
-                self.update_rcvd_cert_current(current, rcvd_cert, repo_info, signer)
+                self.update_rcvd_cert_current(
+                    current,
+                    rcvd_cert,
+                    repo_info,
+                    roa_aggregation,
+                    signer,
+                )
             }
         }
     }
@@ -296,6 +350,7 @@ impl ResourceClass {
         current: &CurrentKey,
         rcvd_cert: RcvdCert,
         repo_info: &RepoInfo,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<Vec<EvtDet>> {
         let rcvd_cert_ki = rcvd_cert.cert().subject_key_identifier();
@@ -316,8 +371,16 @@ impl ResourceClass {
             let publish_mode = PublishMode::UpdatedResources(rcvd_resources);
             let authorizations: Vec<RouteAuthorization> =
                 self.roas.authorizations().cloned().collect();
+            let aspas: Vec<AspaDefinition> = self.aspas.definitions().cloned().collect();
+            let router_certs: Vec<RouterCertDefinition> =
+                self.router_certs.definitions().cloned().collect();
+            let ghostbuster = self.ghostbuster.record().cloned();
             res.append(&mut self.republish(
                 authorizations.as_slice(),
+                roa_aggregation,
+                aspas.as_slice(),
+                router_certs.as_slice(),
+                ghostbuster.as_ref(),
                 repo_info,
                 &publish_mode,
                 signer,
@@ -393,6 +456,67 @@ impl ResourceClass {
         new_revocations: Vec<Revocation>,
         mode: &PublishMode,
         signer: &S,
+    ) -> KrillResult<EvtDet> {
+        self.publish_objects_numbered(repo_info, objects_delta, new_revocations, mode, 0, signer)
+    }
+
+    /// Returns the identifiers of all keys in this resource class.
+    pub fn key_ids(&self) -> Vec<&KeyIdentifier> {
+        match &self.key_state {
+            KeyState::Pending(pending) => vec![pending.key_id()],
+            KeyState::Active(current) => vec![current.key_id()],
+            KeyState::RollPending(pending, current) => vec![pending.key_id(), current.key_id()],
+            KeyState::RollNew(new, current) => vec![new.key_id(), current.key_id()],
+            KeyState::RollOld(current, old) => vec![current.key_id(), old.key().key_id()],
+        }
+    }
+
+    /// Returns the highest manifest and CRL number used by any key in this
+    /// resource class, or 0 if there is no certified key.
+    pub fn highest_publication_number(&self) -> u64 {
+        let keys: Vec<&CertifiedKey> = match &self.key_state {
+            KeyState::Pending(_) => vec![],
+            KeyState::Active(current) | KeyState::RollPending(_, current) => vec![current],
+            KeyState::RollNew(new, current) => vec![new, current],
+            KeyState::RollOld(current, old) => vec![current, old.key()],
+        };
+        keys.iter()
+            .map(|key| key.current_set().number())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Publishes a new manifest and CRL for the keys in this resource class,
+    /// with a number above the given number. This is used after restoring
+    /// a backup, because relying parties may already have seen manifests
+    /// and CRLs with numbers up to the given number, and they would not
+    /// accept lower numbers. Returns None if the numbers are above the
+    /// given number already.
+    pub fn raise_publication_numbers<S: Signer>(
+        &self,
+        number: u64,
+        repo_info: &RepoInfo,
+        signer: &S,
+    ) -> KrillResult<Option<EvtDet>> {
+        if self.current_key().is_none() || self.highest_publication_number() > number {
+            return Ok(None);
+        }
+        let delta = ObjectsDelta::new(repo_info.ca_repository(self.name_space()));
+        let mode = PublishMode::Normal;
+        self.publish_objects_numbered(repo_info, delta, vec![], &mode, number + 1, signer)
+            .map(Some)
+    }
+
+    /// Publishes objects as publish_objects does, using at least the given
+    /// number for the new manifests and CRLs.
+    fn publish_objects_numbered<S: Signer>(
+        &self,
+        repo_info: &RepoInfo,
+        objects_delta: ObjectsDelta,
+        new_revocations: Vec<Revocation>,
+        mode: &PublishMode,
+        min_number: u64,
+        signer: &S,
     ) -> KrillResult<EvtDet> {
         let mut key_pub_map = HashMap::new();
 
@@ -419,6 +543,7 @@ impl ResourceClass {
                 repo_info,
                 objects_delta,
                 publish_key_revocations,
+                min_number,
                 signer,
             )
             .map_err(Error::signer)?;
@@ -430,7 +555,14 @@ impl ResourceClass {
             let delta = ObjectsDelta::new(repo_info.ca_repository(ns));
 
             let other_delta = self
-                .make_current_set_delta(other_key, repo_info, delta, other_key_revocations, signer)
+                .make_current_set_delta(
+                    other_key,
+                    repo_info,
+                    delta,
+                    other_key_revocations,
+                    min_number,
+                    signer,
+                )
                 .map_err(ca::Error::signer)?;
 
             key_pub_map.insert(other_key.key_id().clone(), other_delta);
@@ -453,10 +585,16 @@ impl ResourceClass {
     }
 
     /// Republish all keys in this class (that want it). Also update
-    /// ROAs as needed.
+    /// ROAs, ASPA objects, BGPsec router certificates and the Ghostbusters
+    /// Record as needed.
+    #[allow(clippy::too_many_arguments)]
     pub fn republish<S: Signer>(
         &self,
         authorizations: &[RouteAuthorization],
+        roa_aggregation: RoaAggregation,
+        aspas: &[AspaDefinition],
+        router_certs: &[RouterCertDefinition],
+        ghostbuster: Option<&GhostbusterRecord>,
         repo_info: &RepoInfo,
         mode: &PublishMode,
         signer: &S,
@@ -467,7 +605,7 @@ impl ResourceClass {
         let mut delta = ObjectsDelta::new(repo_info.ca_repository(ns));
         let mut revocations = vec![];
 
-        let roa_updates = self.update_roas(authorizations, mode, signer)?;
+        let roa_updates = self.update_roas(authorizations, roa_aggregation, mode, signer)?;
         if roa_updates.contains_changes() {
             for added in roa_updates.added().into_iter() {
                 delta.add(added);
@@ -483,6 +621,60 @@ impl ResourceClass {
             res.push(EvtDet::RoasUpdated(self.name.clone(), roa_updates));
         }
 
+        let aspa_updates = self.update_aspas(aspas, mode, signer)?;
+        if aspa_updates.contains_changes() {
+            for added in aspa_updates.added().into_iter() {
+                delta.add(added);
+            }
+            for update in aspa_updates.updated().into_iter() {
+                delta.update(update);
+            }
+            for withdraw in aspa_updates.withdrawn().into_iter() {
+                delta.withdraw(withdraw);
+            }
+            revocations.append(&mut aspa_updates.revocations());
+
+            res.push(EvtDet::AspaObjectsUpdated(self.name.clone(), aspa_updates));
+        }
+
+        let router_cert_updates = self.update_router_certs(router_certs, mode, signer)?;
+        if router_cert_updates.contains_changes() {
+            for added in router_cert_updates.added().into_iter() {
+                delta.add(added);
+            }
+            for update in router_cert_updates.updated().into_iter() {
+                delta.update(update);
+            }
+            for withdraw in router_cert_updates.withdrawn().into_iter() {
+                delta.withdraw(withdraw);
+            }
+            revocations.append(&mut router_cert_updates.revocations());
+
+            res.push(EvtDet::RouterCertsUpdated(
+                self.name.clone(),
+                router_cert_updates,
+            ));
+        }
+
+        let gbr_updates = self.update_ghostbuster(ghostbuster, mode, signer)?;
+        if gbr_updates.contains_changes() {
+            for added in gbr_updates.added().into_iter() {
+                delta.add(added);
+            }
+            for update in gbr_updates.updated().into_iter() {
+                delta.update(update);
+            }
+            for withdraw in gbr_updates.withdrawn().into_iter() {
+                delta.withdraw(withdraw);
+            }
+            revocations.append(&mut gbr_updates.revocations());
+
+            res.push(EvtDet::GhostbusterObjectUpdated(
+                self.name.clone(),
+                gbr_updates,
+            ));
+        }
+
         let child_cert_updates = self.update_child_certificates(mode, signer)?;
         if !child_cert_updates.is_empty() {
             for issued in child_cert_updates.issued() {
@@ -551,7 +743,14 @@ impl ResourceClass {
         }
 
         let set_delta = self
-            .make_current_set_delta(issuing_key, repo_info, objects_delta, revocations, signer)
+            .make_current_set_delta(
+                issuing_key,
+                repo_info,
+                objects_delta,
+                revocations,
+                0,
+                signer,
+            )
             .map_err(Error::signer)?;
 
         let mut res = HashMap::new();
@@ -565,12 +764,13 @@ impl ResourceClass {
         repo_info: &RepoInfo,
         mut objects_delta: ObjectsDelta,
         mut new_revocations: Vec<Revocation>,
+        min_number: u64,
         signer: &S,
     ) -> KrillResult<CurrentObjectSetDelta> {
         let signing_cert = signing_key.incoming_cert();
         let current_set = signing_key.current_set();
         let current_revocations = current_set.revocations().clone();
-        let number = current_set.number() + 1;
+        let number = (current_set.number() + 1).max(min_number);
 
         let current_mft = current_set.manifest_info();
         let current_mft_hash = current_mft.current().to_hex_hash();
@@ -599,12 +799,27 @@ impl ResourceClass {
         // List all current files, i.e.
         //  - the new CRL
         //  - current ROAs
+        //  - current ASPA objects
+        //  - current BGPsec router certificates
+        //  - current Ghostbusters Record
         //  - current Certs
         //  - applying the delta - which may update the current ROAs and Certs on the MFT
         let issued = self.certificates.current();
-        let roas = self.roas.iter();
-
-        let manifest_info = ManifestBuilder::new(&crl_info, issued, roas, &objects_delta).build(
+        let roas = self.roas.current();
+        let aspas = self.aspas.current();
+        let router_certs = self.router_certs.current();
+        let ghostbuster = self.ghostbuster.current();
+
+        let manifest_info = ManifestBuilder::new(
+            &crl_info,
+            issued,
+            roas,
+            aspas,
+            router_certs,
+            ghostbuster,
+            &objects_delta,
+        )
+        .build(
             signing_cert,
             repo_info,
             self.name_space(),
@@ -638,6 +853,24 @@ impl ResourceClass {
             let uri = base_repo.resolve(ns, object_name.as_str());
             res.push(PublishElement::new(base64, uri));
         }
+        // ASPA objects
+        for info in self.aspas.current() {
+            let base64 = info.object().content().clone();
+            let uri = base_repo.resolve(ns, info.name().as_str());
+            res.push(PublishElement::new(base64, uri));
+        }
+        // BGPsec router certificates
+        for router_cert in self.router_certs.current() {
+            let base64 = router_cert.object().content().clone();
+            let uri = base_repo.resolve(ns, router_cert.name().as_str());
+            res.push(PublishElement::new(base64, uri));
+        }
+        // Ghostbusters Record
+        if let Some(gbr_info) = self.ghostbuster.current() {
+            let base64 = gbr_info.object().content().clone();
+            let uri = base_repo.resolve(ns, gbr_info.name().as_str());
+            res.push(PublishElement::new(base64, uri));
+        }
         // Certs
         for cert in self.certificates.current() {
             let base64 = Base64::from_content(cert.to_captured().as_slice());
@@ -799,6 +1032,7 @@ impl ResourceClass {
         &self,
         repo_info: &RepoInfo,
         staging: Duration,
+        roa_aggregation: RoaAggregation,
         signer: &S,
     ) -> KrillResult<Vec<EvtDet>> {
         if !self.key_state.has_new_key() || self.last_key_change + staging > Time::now() {
@@ -808,6 +1042,10 @@ impl ResourceClass {
         let mut res = vec![];
 
         let authorizations: Vec<RouteAuthorization> = self.roas.authorizations().cloned().collect();
+        let aspas: Vec<AspaDefinition> = self.aspas.definitions().cloned().collect();
+        let router_certs: Vec<RouterCertDefinition> =
+            self.router_certs.definitions().cloned().collect();
+        let ghostbuster = self.ghostbuster.record().cloned();
 
         res.push(self.key_state.keyroll_activate(
             self.name.clone(),
@@ -817,6 +1055,10 @@ impl ResourceClass {
 
         res.append(&mut self.republish(
             authorizations.as_slice(),
+            roa_aggregation,
+            aspas.as_slice(),
+            router_certs.as_slice(),
+            ghostbuster.as_ref(),
             repo_info,
             &PublishMode::KeyRollActivation,
             signer,
@@ -951,6 +1193,31 @@ impl ResourceClass {
                     updates.issue(re_issued);
                 }
             }
+            PublishMode::ShrinkChildren(entitlements) => {
+                //    re-issue: overclaiming with remaining
+                //    revoke: overclaiming without remaining
+                let resources = signing_key.incoming_cert().resources();
+                for (ki, entitled) in entitlements.iter() {
+                    if let Some(issued) = self.certificates.get(ki) {
+                        let remaining_resources = issued
+                            .resource_set()
+                            .intersection(entitled)
+                            .intersection(resources);
+                        if remaining_resources.is_empty() {
+                            updates.remove(*ki);
+                        } else if &remaining_resources != issued.resource_set() {
+                            let re_issued = self.re_issue(
+                                issued,
+                                Some(remaining_resources),
+                                signing_key,
+                                None,
+                                signer,
+                            )?;
+                            updates.issue(re_issued);
+                        }
+                    }
+                }
+            }
             PublishMode::NewRepo(info) => {
                 for issued in self.certificates.iter() {
                     let csr_info_update = CsrInfo::new(
@@ -994,6 +1261,7 @@ impl ResourceClass {
     pub fn update_roas<S: Signer>(
         &self,
         auths: &[RouteAuthorization],
+        aggregation: RoaAggregation,
         mode: &PublishMode,
         signer: &S,
     ) -> KrillResult<RoaUpdates> {
@@ -1005,7 +1273,9 @@ impl ResourceClass {
         };
 
         let resources = match mode {
-            PublishMode::Normal | PublishMode::NewRepo(_) => key.incoming_cert().resources(),
+            PublishMode::Normal | PublishMode::NewRepo(_) | PublishMode::ShrinkChildren(_) => {
+                key.incoming_cert().resources()
+            }
             PublishMode::UpdatedResources(resources) => resources,
             PublishMode::KeyRollActivation => self.get_current_key()?.incoming_cert().resources(),
         };
@@ -1015,37 +1285,108 @@ impl ResourceClass {
             _ => None,
         };
 
-        // Remove any ROAs no longer in auths, or no longer in resources.
-        for (current_auth, roa_info) in self.roas.iter() {
-            if !auths.contains(current_auth) || !resources.contains(&current_auth.prefix().into()) {
+        // Only auths for prefixes in this resource class are relevant.
+        let relevant: Vec<&RouteAuthorization> = auths
+            .iter()
+            .filter(|auth| resources.contains(&auth.prefix().into()))
+            .collect();
+
+        let aggregate = aggregation.should_aggregate(relevant.len(), self.roas.is_aggregating());
+
+        if aggregate {
+            // Remove all ROAs for single authorizations, these are replaced by
+            // the aggregated ROAs in the same update so that there is no gap.
+            for (current_auth, roa_info) in self.roas.iter() {
                 updates.remove(*current_auth, RevokedObject::from(roa_info.object()));
             }
-        }
 
-        for auth in auths {
-            // if the auth is not in this resource class, just skip it.
-            if !resources.contains(&auth.prefix().into()) {
-                continue;
+            let mut by_asn: HashMap<AsNumber, Vec<RouteAuthorization>> = HashMap::new();
+            for auth in relevant {
+                by_asn.entry(auth.asn()).or_default().push(*auth);
             }
 
-            match self.roas.get(auth) {
-                None => {
-                    // NO ROA yet, so create one.
-                    let roa = Roas::make_roa(auth, key, new_repo.as_ref(), signer)?;
-                    let name = ObjectName::from(auth);
-                    updates.update(*auth, RoaInfo::new_roa(&roa, name));
+            // Remove aggregated ROAs for ASNs which no longer have any auths.
+            for (asn, aggregate_info) in self.roas.iter_aggregate() {
+                if !by_asn.contains_key(asn) {
+                    let revoked = RevokedObject::from(aggregate_info.roa_info().object());
+                    updates.remove_aggregate(*asn, revoked);
                 }
-                Some(roa) => {
-                    // Re-issue if the ROA is getting close to its expiration time, or if we are
-                    //  activating the new key.
-                    let expiring = roa.object().expires()
-                        < Time::now() + Duration::weeks(ROA_CERTIFICATE_REISSUE_WEEKS);
-                    let activating = mode == &PublishMode::KeyRollActivation;
+            }
+
+            for (asn, mut asn_auths) in by_asn.into_iter() {
+                asn_auths.sort();
 
-                    if expiring || activating || new_repo.is_some() {
-                        let new_roa = Roas::make_roa(auth, key, new_repo.as_ref(), signer)?;
+                match self.roas.get_aggregate(asn) {
+                    None => {
+                        let roa = Roas::make_aggregate_roa(
+                            asn,
+                            &asn_auths,
+                            key,
+                            new_repo.as_ref(),
+                            signer,
+                        )?;
+                        let name = ObjectName::aggregate_roa(asn);
+                        let info = RoaInfo::new_roa(&roa, name);
+                        updates.update_aggregate(asn, AggregateRoaInfo::new(asn_auths, info));
+                    }
+                    Some(current) => {
+                        // Re-issue if any auth for the ASN changed, if the ROA is getting
+                        // close to its expiration time, or if we are activating the new key.
+                        let changed = current.authorizations() != &asn_auths;
+                        let expiring = current.roa_info().object().expires()
+                            < Time::now() + Duration::weeks(ROA_CERTIFICATE_REISSUE_WEEKS);
+                        let activating = mode == &PublishMode::KeyRollActivation;
+
+                        if changed || expiring || activating || new_repo.is_some() {
+                            let roa = Roas::make_aggregate_roa(
+                                asn,
+                                &asn_auths,
+                                key,
+                                new_repo.as_ref(),
+                                signer,
+                            )?;
+                            let name = ObjectName::aggregate_roa(asn);
+                            let info = RoaInfo::updated_roa(current.roa_info(), &roa, name);
+                            updates.update_aggregate(asn, AggregateRoaInfo::new(asn_auths, info));
+                        }
+                    }
+                }
+            }
+        } else {
+            // Remove all aggregated ROAs, these are replaced by ROAs for single
+            // authorizations in the same update so that there is no gap.
+            for (asn, aggregate_info) in self.roas.iter_aggregate() {
+                let revoked = RevokedObject::from(aggregate_info.roa_info().object());
+                updates.remove_aggregate(*asn, revoked);
+            }
+
+            // Remove any ROAs no longer in auths, or no longer in resources.
+            for (current_auth, roa_info) in self.roas.iter() {
+                if !relevant.contains(&current_auth) {
+                    updates.remove(*current_auth, RevokedObject::from(roa_info.object()));
+                }
+            }
+
+            for auth in relevant {
+                match self.roas.get(auth) {
+                    None => {
+                        // NO ROA yet, so create one.
+                        let roa = Roas::make_roa(auth, key, new_repo.as_ref(), signer)?;
                         let name = ObjectName::from(auth);
-                        updates.update(*auth, RoaInfo::updated_roa(roa, &new_roa, name));
+                        updates.update(*auth, RoaInfo::new_roa(&roa, name));
+                    }
+                    Some(roa) => {
+                        // Re-issue if the ROA is getting close to its expiration time, or if we
+                        // are activating the new key.
+                        let expiring = roa.object().expires()
+                            < Time::now() + Duration::weeks(ROA_CERTIFICATE_REISSUE_WEEKS);
+                        let activating = mode == &PublishMode::KeyRollActivation;
+
+                        if expiring || activating || new_repo.is_some() {
+                            let new_roa = Roas::make_roa(auth, key, new_repo.as_ref(), signer)?;
+                            let name = ObjectName::from(auth);
+                            updates.update(*auth, RoaInfo::updated_roa(roa, &new_roa, name));
+                        }
                     }
                 }
             }
@@ -1060,6 +1401,251 @@ impl ResourceClass {
     }
 }
 
+/// # ASPA
+///
+impl ResourceClass {
+    /// Updates the ASPA objects in accordance with the current definitions,
+    /// and the target resources and key determined by the PublishMode. An
+    /// ASPA object is only made in this class if it holds the customer AS.
+    pub fn update_aspas<S: Signer>(
+        &self,
+        definitions: &[AspaDefinition],
+        mode: &PublishMode,
+        signer: &S,
+    ) -> KrillResult<AspaObjectsUpdates> {
+        let mut updates = AspaObjectsUpdates::default();
+
+        let key = match mode {
+            PublishMode::KeyRollActivation => self.get_new_key()?,
+            _ => self.get_current_key()?,
+        };
+
+        let resources = match mode {
+            PublishMode::Normal | PublishMode::NewRepo(_) | PublishMode::ShrinkChildren(_) => {
+                key.incoming_cert().resources()
+            }
+            PublishMode::UpdatedResources(resources) => resources,
+            PublishMode::KeyRollActivation => self.get_current_key()?.incoming_cert().resources(),
+        };
+
+        let new_repo = match &mode {
+            PublishMode::NewRepo(info) => Some(info.ca_repository(self.name_space())),
+            _ => None,
+        };
+
+        // Remove any ASPA objects no longer defined, or for customers no longer held.
+        for (customer, aspa_info) in self.aspas.iter() {
+            let defined = definitions.iter().any(|def| def.customer() == *customer);
+            if !defined || !resources.contains(&(*customer).into()) {
+                updates.remove(*customer, RevokedObject::from(aspa_info.object()));
+            }
+        }
+
+        for definition in definitions {
+            let customer = definition.customer();
+
+            // if the customer AS is not in this resource class, just skip it.
+            if !resources.contains(&customer.into()) {
+                continue;
+            }
+
+            match self.aspas.get(customer) {
+                None => {
+                    let aspa = AspaObjects::make_aspa(definition, key, new_repo.as_ref(), signer)?;
+                    updates.update(customer, AspaInfo::new_aspa(definition.clone(), &aspa));
+                }
+                Some(aspa_info) => {
+                    // Re-issue if the providers changed, if the object is getting close to
+                    // its expiration time, or if we are activating the new key.
+                    let changed = aspa_info.definition() != definition;
+                    let expiring = aspa_info.object().expires()
+                        < Time::now() + Duration::weeks(ASPA_CERTIFICATE_REISSUE_WEEKS);
+                    let activating = mode == &PublishMode::KeyRollActivation;
+
+                    if changed || expiring || activating || new_repo.is_some() {
+                        let aspa =
+                            AspaObjects::make_aspa(definition, key, new_repo.as_ref(), signer)?;
+                        updates.update(
+                            customer,
+                            AspaInfo::updated_aspa(aspa_info, definition.clone(), &aspa),
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Marks the ASPA objects as updated from an AspaObjectsUpdated event.
+    pub fn aspas_updated(&mut self, updates: AspaObjectsUpdates) {
+        self.aspas.updated(updates);
+    }
+}
+
+/// # BGPsec router certificates
+///
+impl ResourceClass {
+    /// Updates the BGPsec router certificates in accordance with the current
+    /// definitions, and the target resources and key determined by the
+    /// PublishMode. A router certificate is only issued in this class if it
+    /// holds all ASNs in the definition.
+    pub fn update_router_certs<S: Signer>(
+        &self,
+        definitions: &[RouterCertDefinition],
+        mode: &PublishMode,
+        signer: &S,
+    ) -> KrillResult<RouterCertUpdates> {
+        let mut updates = RouterCertUpdates::default();
+
+        let key = match mode {
+            PublishMode::KeyRollActivation => self.get_new_key()?,
+            _ => self.get_current_key()?,
+        };
+
+        let resources = match mode {
+            PublishMode::Normal | PublishMode::NewRepo(_) | PublishMode::ShrinkChildren(_) => {
+                key.incoming_cert().resources()
+            }
+            PublishMode::UpdatedResources(resources) => resources,
+            PublishMode::KeyRollActivation => self.get_current_key()?.incoming_cert().resources(),
+        };
+
+        let new_repo = match &mode {
+            PublishMode::NewRepo(info) => Some(info.ca_repository(self.name_space())),
+            _ => None,
+        };
+
+        // Remove any certificates no longer defined, or for ASNs no longer held.
+        for (router_key, issued) in self.router_certs.iter() {
+            let held = definitions
+                .iter()
+                .any(|def| def.key_identifier() == router_key && def.held_in(resources));
+            if !held {
+                updates.remove(issued.clone());
+            }
+        }
+
+        let expiring: Vec<&KeyIdentifier> = self
+            .router_certs
+            .expiring()
+            .into_iter()
+            .map(|issued| issued.key_identifier())
+            .collect();
+
+        for definition in definitions {
+            // if the ASNs are not all in this resource class, just skip it.
+            if !definition.held_in(resources) {
+                continue;
+            }
+
+            match self.router_certs.get(definition.key_identifier()) {
+                None => {
+                    let object =
+                        SignSupport::make_router_cert(definition, key, new_repo.as_ref(), signer)?;
+                    updates.issue(IssuedRouterCert::new(definition.clone(), object));
+                }
+                Some(issued) => {
+                    // Re-issue if the ASNs changed, if the certificate is getting close
+                    // to its expiration time, or if we are activating the new key.
+                    let changed = issued.definition() != definition;
+                    let expiring = expiring.contains(&definition.key_identifier());
+                    let activating = mode == &PublishMode::KeyRollActivation;
+
+                    if changed || expiring || activating || new_repo.is_some() {
+                        let object = SignSupport::make_router_cert(
+                            definition,
+                            key,
+                            new_repo.as_ref(),
+                            signer,
+                        )?;
+                        updates.issue(IssuedRouterCert::updated(
+                            issued,
+                            definition.clone(),
+                            object,
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Marks the router certificates as updated from a RouterCertsUpdated event.
+    pub fn router_certs_updated(&mut self, updates: RouterCertUpdates) {
+        self.router_certs.updated(updates);
+    }
+
+    /// Returns the BGPsec router certificates issued under this class.
+    pub fn router_certs(&self) -> &RouterCertificates {
+        &self.router_certs
+    }
+}
+
+/// # Ghostbusters
+///
+impl ResourceClass {
+    /// Updates the Ghostbusters Record in accordance with the vCard for the
+    /// CA, and the key determined by the PublishMode. The object is removed
+    /// if the CA no longer has a vCard.
+    pub fn update_ghostbuster<S: Signer>(
+        &self,
+        record: Option<&GhostbusterRecord>,
+        mode: &PublishMode,
+        signer: &S,
+    ) -> KrillResult<GhostbusterUpdates> {
+        let mut updates = GhostbusterUpdates::default();
+
+        let key = match mode {
+            PublishMode::KeyRollActivation => self.get_new_key()?,
+            _ => self.get_current_key()?,
+        };
+
+        let new_repo = match &mode {
+            PublishMode::NewRepo(info) => Some(info.ca_repository(self.name_space())),
+            _ => None,
+        };
+
+        match (record, self.ghostbuster.current()) {
+            (None, None) => {}
+            (None, Some(gbr_info)) => {
+                updates.remove(RevokedObject::from(gbr_info.object()));
+            }
+            (Some(record), None) => {
+                let gbr = GhostbusterObject::make_gbr(record, key, new_repo.as_ref(), signer)?;
+                updates.update(GhostbusterInfo::new_gbr(record.clone(), &gbr));
+            }
+            (Some(record), Some(gbr_info)) => {
+                // Re-issue if the vCard changed, if the object is getting close to
+                // its expiration time, or if we are activating the new key.
+                let changed = gbr_info.record() != record;
+                let expiring = gbr_info.object().expires()
+                    < Time::now() + Duration::weeks(GHOSTBUSTER_REISSUE_WEEKS);
+                let activating = mode == &PublishMode::KeyRollActivation;
+
+                if changed || expiring || activating || new_repo.is_some() {
+                    let gbr = GhostbusterObject::make_gbr(record, key, new_repo.as_ref(), signer)?;
+                    updates.update(GhostbusterInfo::updated_gbr(gbr_info, record.clone(), &gbr));
+                }
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Marks the Ghostbusters Record as updated from a GhostbusterObjectUpdated
+    /// event.
+    pub fn ghostbuster_updated(&mut self, updates: GhostbusterUpdates) {
+        self.ghostbuster.updated(updates);
+    }
+
+    /// Returns the Ghostbusters Record published under this class, if any.
+    pub fn ghostbuster(&self) -> &GhostbusterObject {
+        &self.ghostbuster
+    }
+}
+
 //------------ PublishMode -------------------------------------------------
 
 /// Describes which kind of publication we're after:
@@ -1076,6 +1662,10 @@ impl ResourceClass {
 /// KeyActivation: Publish ROAs and certificates under the new key, and revoke
 ///         them under the old key - which will be revoked shortly.
 ///
+/// ShrinkChildren: Use the current key and resources, and shrink the child
+///         certificates for the given keys to the given entitlements - or
+///         revoke them if nothing remains.
+///
 #[derive(Clone, Eq, PartialEq)]
 #[allow(clippy::large_enum_variant)]
 pub enum PublishMode {
@@ -1083,4 +1673,5 @@ pub enum PublishMode {
     UpdatedResources(ResourceSet),
     KeyRollActivation,
     NewRepo(RepoInfo),
+    ShrinkChildren(HashMap<KeyIdentifier, ResourceSet>),
 }
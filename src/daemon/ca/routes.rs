@@ -11,7 +11,7 @@ use rpki::uri;
 use rpki::x509::{Serial, Time};
 
 use crate::commons::api::{
-    CurrentObject, ObjectName, ReplacedObject, RoaDefinition, RoaDefinitionUpdates,
+    AsNumber, CurrentObject, ObjectName, ReplacedObject, RoaDefinition, RoaDefinitionUpdates,
 };
 use crate::commons::KrillResult;
 use crate::constants::ROA_CERTIFICATE_VALIDITY_YEARS;
@@ -22,7 +22,7 @@ use crate::daemon::ca::{self, CertifiedKey, SignSupport, Signer};
 
 /// This type defines a prefix and optional maximum length (other than the
 /// prefix length) which is to be authorized for the given origin ASN.
-#[derive(Clone, Copy, Debug, Display, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Display, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct RouteAuthorization(RoaDefinition);
 
 impl RouteAuthorization {
@@ -186,6 +186,40 @@ impl Default for RouteInfo {
     }
 }
 
+//------------ RoaAggregation ----------------------------------------------
+
+/// Determines when the ROAs in a resource class are aggregated into one ROA
+/// per origin ASN, rather than one ROA per authorization.
+///
+/// ROAs are aggregated when the number of authorizations in a resource class
+/// exceeds the aggregate threshold. They go back to one ROA per authorization
+/// only when the number drops below the (lower) de-aggregate threshold, so
+/// that a CA hovering around the threshold does not flip between layouts.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RoaAggregation {
+    aggregate_threshold: usize,
+    deaggregate_threshold: usize,
+}
+
+impl RoaAggregation {
+    pub fn new(aggregate_threshold: usize, deaggregate_threshold: usize) -> Self {
+        RoaAggregation {
+            aggregate_threshold,
+            deaggregate_threshold,
+        }
+    }
+
+    /// Returns whether ROAs should be aggregated for the given number of
+    /// authorizations, taking into account whether they are aggregated now.
+    pub fn should_aggregate(&self, authorizations: usize, aggregating: bool) -> bool {
+        if aggregating {
+            authorizations >= self.deaggregate_threshold
+        } else {
+            authorizations > self.aggregate_threshold
+        }
+    }
+}
+
 //------------ RoaInfo -----------------------------------------------------
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
@@ -235,18 +269,49 @@ impl RoaInfo {
     }
 }
 
+//------------ AggregateRoaInfo --------------------------------------------
+
+/// An aggregated ROA, which includes all authorizations for one origin ASN.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AggregateRoaInfo {
+    authorizations: Vec<RouteAuthorization>, // sorted
+    roa: RoaInfo,
+}
+
+impl AggregateRoaInfo {
+    pub fn new(authorizations: Vec<RouteAuthorization>, roa: RoaInfo) -> Self {
+        AggregateRoaInfo {
+            authorizations,
+            roa,
+        }
+    }
+
+    pub fn authorizations(&self) -> &Vec<RouteAuthorization> {
+        &self.authorizations
+    }
+
+    pub fn roa_info(&self) -> &RoaInfo {
+        &self.roa
+    }
+}
+
 //------------ Roas --------------------------------------------------------
 
-/// ROAs held by a resource class in a CA.
+/// ROAs held by a resource class in a CA. These are either one ROA per
+/// authorization, or aggregated into one ROA per origin ASN, depending on
+/// the RoaAggregation used when they were last updated.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Roas {
     inner: HashMap<RouteAuthorization, RoaInfo>,
+    #[serde(default)]
+    aggregate: HashMap<AsNumber, AggregateRoaInfo>,
 }
 
 impl Default for Roas {
     fn default() -> Self {
         Roas {
             inner: HashMap::new(),
+            aggregate: HashMap::new(),
         }
     }
 }
@@ -256,8 +321,17 @@ impl Roas {
         self.inner.get(auth)
     }
 
+    pub fn get_aggregate(&self, asn: AsNumber) -> Option<&AggregateRoaInfo> {
+        self.aggregate.get(&asn)
+    }
+
+    /// Returns whether the ROAs are currently aggregated per origin ASN.
+    pub fn is_aggregating(&self) -> bool {
+        !self.aggregate.is_empty()
+    }
+
     pub fn updated(&mut self, updates: RoaUpdates) {
-        let (updated, removed) = updates.unpack();
+        let (updated, removed, aggregate_updated, aggregate_removed) = updates.unpack();
 
         for (auth, info) in updated.into_iter() {
             self.inner.insert(auth, info);
@@ -266,18 +340,36 @@ impl Roas {
         for auth in removed.keys() {
             self.inner.remove(auth);
         }
+
+        for (asn, info) in aggregate_updated.into_iter() {
+            self.aggregate.insert(asn, info);
+        }
+
+        for asn in aggregate_removed.keys() {
+            self.aggregate.remove(asn);
+        }
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (&RouteAuthorization, &RoaInfo)> {
         self.inner.iter()
     }
 
+    pub fn iter_aggregate(&self) -> impl Iterator<Item = (&AsNumber, &AggregateRoaInfo)> {
+        self.aggregate.iter()
+    }
+
+    /// Returns all current ROAs, both simple and aggregated.
     pub fn current(&self) -> impl Iterator<Item = &RoaInfo> {
-        self.inner.values()
+        self.inner
+            .values()
+            .chain(self.aggregate.values().map(|agg| agg.roa_info()))
     }
 
+    /// Returns all authorizations for which ROAs are published.
     pub fn authorizations(&self) -> impl Iterator<Item = &RouteAuthorization> {
-        self.inner.keys()
+        self.inner
+            .keys()
+            .chain(self.aggregate.values().flat_map(|agg| agg.authorizations()))
     }
 
     pub fn make_roa<S: Signer>(
@@ -286,8 +378,31 @@ impl Roas {
         new_repo: Option<&uri::Rsync>,
         signer: &S,
     ) -> KrillResult<Roa> {
-        let prefix = auth.prefix();
+        let name = ObjectName::from(auth);
+        Self::make_roa_for_asn(auth.asn(), &[*auth], name, certified_key, new_repo, signer)
+    }
+
+    /// Makes a single ROA for all given authorizations, which must all be for
+    /// the given origin ASN.
+    pub fn make_aggregate_roa<S: Signer>(
+        asn: AsNumber,
+        auths: &[RouteAuthorization],
+        certified_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<Roa> {
+        let name = ObjectName::aggregate_roa(asn);
+        Self::make_roa_for_asn(asn, auths, name, certified_key, new_repo, signer)
+    }
 
+    fn make_roa_for_asn<S: Signer>(
+        asn: AsNumber,
+        auths: &[RouteAuthorization],
+        name: ObjectName,
+        certified_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<Roa> {
         let incoming_cert = certified_key.incoming_cert();
         let crl_uri = match &new_repo {
             None => incoming_cert.crl_uri(),
@@ -295,20 +410,23 @@ impl Roas {
         };
 
         let roa_uri = match &new_repo {
-            None => incoming_cert.uri_for_object(auth),
-            Some(base_uri) => base_uri.join(ObjectName::from(auth).as_bytes()),
+            None => incoming_cert.uri_for_object(name),
+            Some(base_uri) => base_uri.join(name.as_bytes()),
         };
 
         let aia = incoming_cert.uri();
 
         let signing_key = certified_key.key_id();
 
-        let mut roa_builder = RoaBuilder::new(auth.asn().into());
+        let mut roa_builder = RoaBuilder::new(asn.into());
 
-        if auth.effective_max_length() > auth.prefix().prefix().addr_len() {
-            roa_builder.push_addr(prefix.ip_addr(), prefix.addr_len(), auth.max_length());
-        } else {
-            roa_builder.push_addr(prefix.ip_addr(), prefix.addr_len(), None);
+        for auth in auths {
+            let prefix = auth.prefix();
+            if auth.effective_max_length() > prefix.prefix().addr_len() {
+                roa_builder.push_addr(prefix.ip_addr(), prefix.addr_len(), auth.max_length());
+            } else {
+                roa_builder.push_addr(prefix.ip_addr(), prefix.addr_len(), None);
+            }
         }
 
         let mut object_builder = SignedObjectBuilder::new(
@@ -352,4 +470,25 @@ mod tests {
         parse_encode_authorization("2001:db8::/32 => 64496");
         parse_encode_authorization("2001:db8::/32-48 => 64496");
     }
+
+    #[test]
+    fn roa_aggregation_threshold() {
+        let aggregation = RoaAggregation::new(100, 90);
+
+        assert!(!aggregation.should_aggregate(100, false));
+        assert!(aggregation.should_aggregate(101, false));
+
+        // Stay aggregated until dropping below the de-aggregate threshold
+        assert!(aggregation.should_aggregate(95, true));
+        assert!(aggregation.should_aggregate(90, true));
+        assert!(!aggregation.should_aggregate(89, true));
+    }
+
+    #[test]
+    fn deserialize_roas_without_aggregates() {
+        let json = r#"{"inner":{}}"#;
+        let roas: Roas = serde_json::from_str(json).unwrap();
+        assert_eq!(Roas::default(), roas);
+        assert!(!roas.is_aggregating());
+    }
 }
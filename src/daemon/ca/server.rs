@@ -1,24 +1,29 @@
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 
 use bytes::Bytes;
 use chrono::Duration;
 
+use rpki::crypto::signer::KeyError;
 use rpki::crypto::KeyIdentifier;
 use rpki::uri;
+use rpki::x509::Time;
 
 use crate::commons::api::{
-    self, AddChildRequest, Base64, CaCommandDetails, CaCommandResult, CertAuthList,
-    CertAuthSummary, ChildAuthRequest, ChildCaInfo, ChildHandle, CommandHistory,
-    CommandHistoryCriteria, Entitlements, Handle, IssuanceRequest, IssuanceResponse, IssuedCert,
-    ListReply, ParentCaContact, ParentCaReq, ParentHandle, PublishDelta, RcvdCert, RepoInfo,
+    self, AddChildRequest, AspaDefinitionUpdates, Base64, CaCommandDetails, CaCommandResult,
+    CertAuthList, CertAuthSummary, ChildAuthRequest, ChildCaInfo, ChildHandle, CommandHistory,
+    CommandHistoryCriteria, Entitlements, GhostbusterRecord, Handle, HistoryArchiveReport,
+    IssuanceRequest, IssuanceResponse, IssuedCert, ListReply, ParentCaContact, ParentCaReq,
+    ParentHandle, ParentStatus, PublishDelta, RcvdCert, RepoInfo, RepoStatus,
     RepositoryContact, ResourceClassName, ResourceSet, RevocationRequest, RevocationResponse,
-    StoredEffect, UpdateChildRequest,
+    RouterCertRequest, StoredEffect, UpdateChildRequest,
 };
 use crate::commons::error::Error;
-use crate::commons::eventsourcing::{Aggregate, AggregateStore, CommandKey, DiskAggregateStore};
+use crate::commons::eventsourcing::{
+    Aggregate, AggregateStore, CommandKey, DiskAggregateStore, StorageBackend,
+};
 use crate::commons::remote::builder::SignedMessageBuilder;
 use crate::commons::remote::cmslogger::CmsLogger;
 use crate::commons::remote::id::IdCert;
@@ -26,11 +31,15 @@ use crate::commons::remote::sigmsg::SignedMessage;
 use crate::commons::remote::{rfc6492, rfc8181, rfc8183};
 use crate::commons::util::httpclient;
 use crate::commons::KrillResult;
-use crate::constants::CASERVER_DIR;
+use crate::constants::{CASERVER_DIR, HISTORY_ARCHIVE_DIR};
+use crate::daemon::auth::Actor;
 use crate::daemon::ca::{
-    self, ta_handle, CertAuth, Cmd, CmdDet, IniDet, RouteAuthorizationUpdates, Signer,
+    self, ta_handle, CertAuth, Cmd, CmdDet, IniDet, RoaAggregation, RouteAuthorizationUpdates,
+    RouterCertDefinition, RouterKey, Signer, StatusStore,
 };
+use crate::daemon::eventstream::EventStream;
 use crate::daemon::mq::EventQueueListener;
+use crate::daemon::webhooks::WebhookQueue;
 
 //------------ CaServer ------------------------------------------------------
 
@@ -38,8 +47,11 @@ use crate::daemon::mq::EventQueueListener;
 pub struct CaServer<S: Signer> {
     signer: Arc<RwLock<S>>,
     ca_store: Arc<DiskAggregateStore<CertAuth<S>>>,
+    archive_dir: PathBuf,
     rfc8181_log_dir: Option<PathBuf>,
     rfc6492_log_dir: Option<PathBuf>,
+    roa_aggregation: RoaAggregation,
+    status: Arc<StatusStore>,
 }
 
 impl<S: Signer> CaServer<S> {
@@ -50,19 +62,37 @@ impl<S: Signer> CaServer<S> {
         rfc8181_log_dir: Option<&PathBuf>,
         rfc6492_log_dir: Option<&PathBuf>,
         events_queue: Arc<EventQueueListener>,
+        event_stream: Arc<EventStream>,
+        webhooks: Arc<WebhookQueue>,
+        roa_aggregation: RoaAggregation,
+        storage_backend: StorageBackend,
         signer: Arc<RwLock<S>>,
     ) -> KrillResult<Self> {
-        let mut ca_store = DiskAggregateStore::<CertAuth<S>>::new(work_dir, CASERVER_DIR)?;
+        let mut ca_store = DiskAggregateStore::<CertAuth<S>>::with_backend(
+            work_dir,
+            CASERVER_DIR,
+            storage_backend,
+        )?;
         ca_store.add_listener(events_queue);
+        ca_store.add_listener(event_stream);
+        ca_store.add_listener(webhooks);
 
         Ok(CaServer {
             signer,
             ca_store: Arc::new(ca_store),
+            archive_dir: work_dir.join(HISTORY_ARCHIVE_DIR).join(CASERVER_DIR),
             rfc6492_log_dir: rfc6492_log_dir.cloned(),
             rfc8181_log_dir: rfc8181_log_dir.cloned(),
+            roa_aggregation,
+            status: Arc::new(StatusStore::build(work_dir)?),
         })
     }
 
+    /// Blocks all changes to CAs until the returned guard is dropped.
+    pub fn lock_for_backup(&self) -> RwLockReadGuard<'_, ()> {
+        self.ca_store.lock_for_backup()
+    }
+
     /// Gets the TrustAnchor, if present. Returns an error if the TA is uninitialized.
     pub fn get_trust_anchor(&self) -> KrillResult<Arc<CertAuth<S>>> {
         self.ca_store
@@ -87,7 +117,8 @@ impl<S: Signer> CaServer<S> {
 
             // add embedded repo
             let embedded = RepositoryContact::embedded(info);
-            let upd_repo_cmd = CmdDet::update_repo(&handle, embedded, self.signer.clone());
+            let upd_repo_cmd =
+                CmdDet::update_repo(&handle, embedded, self.roa_aggregation, self.signer.clone());
             self.ca_store.command(upd_repo_cmd)?;
 
             // make trust anchor
@@ -102,6 +133,7 @@ impl<S: Signer> CaServer<S> {
                 &handle,
                 ResourceClassName::default(),
                 rcvd_cert,
+                self.roa_aggregation,
                 self.signer.clone(),
             );
             self.ca_store.command(rcv_cert)?;
@@ -133,13 +165,86 @@ impl<S: Signer> CaServer<S> {
 
     /// Republish a CA, this is a no-op when there is nothing to publish.
     pub fn republish(&self, handle: &Handle) -> KrillResult<()> {
-        let cmd = CmdDet::publish(handle, self.signer.clone());
+        let cmd = CmdDet::publish(handle, self.roa_aggregation, self.signer.clone());
         self.send_command(cmd)
     }
 
+    /// Verifies that the configured signer has the keys of all CAs. This
+    /// fails if the signer was changed, e.g. to an HSM, while the CAs still
+    /// use keys which were created by the previous signer.
+    pub fn verify_signer_keys(&self) -> KrillResult<()> {
+        let signer = self.signer.read().unwrap();
+        let mut missing = vec![];
+        for ca in self.ca_list().cas() {
+            let ca = self.ca_store.get_latest(ca.handle())?;
+            for key_id in ca.key_ids() {
+                match signer.get_key_info(key_id) {
+                    Ok(_) => {}
+                    Err(KeyError::KeyNotFound) => {
+                        error!(
+                            "Signer does not have key '{}' of CA '{}'",
+                            key_id,
+                            ca.handle()
+                        );
+                        if !missing.contains(ca.handle()) {
+                            missing.push(ca.handle().clone());
+                        }
+                    }
+                    Err(KeyError::Signer(e)) => return Err(Error::signer(e)),
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            let cas: Vec<String> = missing.iter().map(|h| h.to_string()).collect();
+            Err(Error::SignerKeysMissing(cas.join(", ")))
+        }
+    }
+
+    /// Returns the highest manifest and CRL number used by each CA.
+    pub fn publication_numbers(&self) -> KrillResult<HashMap<Handle, u64>> {
+        let mut res = HashMap::new();
+        for ca in self.ca_list().cas() {
+            let ca = self.ca_store.get_latest(ca.handle())?;
+            res.insert(ca.handle().clone(), ca.highest_publication_number());
+        }
+        Ok(res)
+    }
+
+    /// Publishes new manifests and CRLs for a CA with numbers above the
+    /// given number, which relying parties may have seen before a backup
+    /// was restored.
+    pub fn raise_publication_numbers(&self, handle: &Handle, number: u64) -> KrillResult<()> {
+        let cmd = CmdDet::raise_publication_numbers(handle, number, self.signer.clone());
+        self.send_command(cmd)
+    }
+
+    /// Moves republish commands older than the given number of days out of
+    /// the history of all CAs, into compressed segments in the archive dir.
+    pub fn archive_history(&self, days: u32) -> KrillResult<HistoryArchiveReport> {
+        let before = Time::now() - Duration::days(i64::from(days));
+        let mut crit = CommandHistoryCriteria::default();
+        crit.set_include(&["cmd-ca-publish"]);
+        crit.set_before(before.timestamp());
+        Ok(self.ca_store.archive_history(&crit, &self.archive_dir)?)
+    }
+
     /// Update repository where a CA publishes.
-    pub fn update_repo(&self, handle: Handle, new_contact: RepositoryContact) -> KrillResult<()> {
-        let cmd = CmdDet::update_repo(&handle, new_contact, self.signer.clone());
+    pub fn update_repo(
+        &self,
+        handle: Handle,
+        new_contact: RepositoryContact,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::update_repo(
+            &handle,
+            new_contact,
+            self.roa_aggregation,
+            self.signer.clone(),
+        )
+        .with_actor(actor.name());
         self.send_command(cmd)
     }
 
@@ -156,12 +261,45 @@ impl<S: Signer> CaServer<S> {
         }
     }
 
-    /// Refresh all CAs: ask for updates and shrink as needed.
-    pub async fn refresh_all(&self) {
+    /// Refresh all CAs: ask for updates and shrink as needed. Returns an
+    /// error if any CA could not get updates from any of its parents. Parents
+    /// which are backed off after failures are skipped, unless forced.
+    pub async fn refresh_all(&self, force: bool) -> KrillResult<()> {
         info!("Refreshing all CAs");
-        if let Err(e) = self.get_updates_for_all_cas().await {
+        self.get_updates_for_all_cas(force).await.map_err(|e| {
             error!("Failed to refresh CA certificates: {}", e);
+            e
+        })
+    }
+
+    /// Shrinks the certificates of children of all CAs, which still claim
+    /// resources that were taken away from them longer ago than the grace
+    /// period. Certificates without any remaining resources are revoked.
+    pub fn shrink_children_all(&self, grace_hours: u32) -> KrillResult<()> {
+        let grace = Duration::hours(i64::from(grace_hours));
+        let deadline = Time::now() - grace;
+
+        for ca in self.ca_list().cas() {
+            let handle = ca.handle();
+            let due = self
+                .get_ca(handle)?
+                .children_overclaiming()
+                .iter()
+                .any(|(_, reduced)| *reduced <= deadline);
+
+            if due {
+                let cmd = CmdDet::child_shrink_certificates(
+                    handle,
+                    grace,
+                    self.roa_aggregation,
+                    self.signer.clone(),
+                );
+                if let Err(e) = self.send_command(cmd) {
+                    error!("Could not shrink child certificates of CA '{}': {}", handle, e)
+                }
+            }
         }
+        Ok(())
     }
 
     /// Adds a child under an embedded CA
@@ -170,6 +308,7 @@ impl<S: Signer> CaServer<S> {
         parent: &ParentHandle,
         req: AddChildRequest,
         service_uri: &uri::Https,
+        actor: &Actor,
     ) -> KrillResult<ParentCaContact> {
         info!("CA '{}' process add child request: {}", &parent, &req);
         let (child_handle, child_res, child_auth) = req.unwrap();
@@ -179,7 +318,8 @@ impl<S: Signer> CaServer<S> {
             ChildAuthRequest::Rfc8183(req) => Some(req.id_cert().clone()),
         };
 
-        let add_child = CmdDet::child_add(&parent, child_handle.clone(), id_cert, child_res);
+        let add_child = CmdDet::child_add(&parent, child_handle.clone(), id_cert, child_res)
+            .with_actor(actor.name());
         self.ca_store.command(add_child)?;
 
         let tag = match child_auth {
@@ -248,6 +388,7 @@ impl<S: Signer> CaServer<S> {
         handle: &Handle,
         child: ChildHandle,
         req: UpdateChildRequest,
+        actor: &Actor,
     ) -> KrillResult<()> {
         let (id_opt, resources_opt) = req.unpack();
 
@@ -256,17 +397,26 @@ impl<S: Signer> CaServer<S> {
         {
             Err(Error::CaChildUpdateOneThing(handle.clone(), child))
         } else if let Some(id) = id_opt {
-            self.send_command(CmdDet::child_update_id(handle, child, id))
+            let cmd = CmdDet::child_update_id(handle, child, id).with_actor(actor.name());
+            self.send_command(cmd)
         } else {
             let resources = resources_opt.unwrap();
-            self.send_command(CmdDet::child_update_resources(handle, child, resources))
+            let cmd = CmdDet::child_update_resources(handle, child, resources)
+                .with_actor(actor.name());
+            self.send_command(cmd)
         }
     }
 
     /// Update a child under this CA.
-    pub fn ca_child_remove(&self, handle: &Handle, child: ChildHandle) -> KrillResult<()> {
+    pub fn ca_child_remove(
+        &self,
+        handle: &Handle,
+        child: ChildHandle,
+        actor: &Actor,
+    ) -> KrillResult<()> {
         let signer = self.signer.clone();
-        self.send_command(CmdDet::child_remove(handle, child, signer))
+        let cmd = CmdDet::child_remove(handle, child, signer).with_actor(actor.name());
+        self.send_command(cmd)
     }
 }
 
@@ -331,6 +481,22 @@ impl<S: Signer> CaServer<S> {
         }
     }
 
+    /// Returns the stored events for a CA, starting at the given version.
+    pub fn get_ca_events(&self, handle: &Handle, from: u64) -> KrillResult<Vec<ca::Evt>> {
+        let ca = self.get_ca(handle)?;
+        let mut events = vec![];
+        for version in from..ca.version() {
+            let evt = self.ca_store.stored_event(handle, version)?.ok_or_else(|| {
+                Error::Custom(format!(
+                    "Cannot find evt: {} in history for CA: {}",
+                    version, handle
+                ))
+            })?;
+            events.push(evt);
+        }
+        Ok(events)
+    }
+
     /// Checks whether a CA by the given handle exists.
     pub fn has_ca(&self, handle: &Handle) -> bool {
         self.ca_store.has(handle)
@@ -473,16 +639,22 @@ impl<S: Signer> CaServer<S> {
         }
     }
 
-    pub fn ca_update_id(&self, handle: Handle) -> KrillResult<()> {
-        let cmd = CmdDet::update_id(&handle, self.signer.clone());
+    pub fn ca_update_id(&self, handle: Handle, actor: &Actor) -> KrillResult<()> {
+        let cmd = CmdDet::update_id(&handle, self.signer.clone()).with_actor(actor.name());
         self.send_command(cmd)
     }
 
     /// Adds a parent to a CA
-    pub fn ca_parent_add(&self, handle: Handle, parent: ParentCaReq) -> KrillResult<()> {
+    pub fn ca_parent_add(
+        &self,
+        handle: Handle,
+        parent: ParentCaReq,
+        actor: &Actor,
+    ) -> KrillResult<()> {
         let (parent_handle, parent_contact) = parent.unpack();
 
-        let add = CmdDet::add_parent(&handle, parent_handle, parent_contact);
+        let add = CmdDet::add_parent(&handle, parent_handle, parent_contact)
+            .with_actor(actor.name());
         self.send_command(add)
     }
 
@@ -492,20 +664,70 @@ impl<S: Signer> CaServer<S> {
         handle: Handle,
         parent: ParentHandle,
         contact: ParentCaContact,
+        actor: &Actor,
     ) -> KrillResult<()> {
-        let upd = CmdDet::update_parent(&handle, parent, contact);
-        self.send_command(upd)
+        let upd = CmdDet::update_parent(&handle, parent.clone(), contact)
+            .with_actor(actor.name());
+        self.send_command(upd)?;
+
+        // Past failures were for the old contact, so start afresh.
+        self.status.remove_parent(&handle, &parent);
+        Ok(())
     }
 
     /// Removes a parent from a CA
-    pub fn ca_parent_remove(&self, handle: Handle, parent: ParentHandle) -> KrillResult<()> {
-        let upd = CmdDet::remove_parent(&handle, parent);
-        self.send_command(upd)
+    pub fn ca_parent_remove(
+        &self,
+        handle: Handle,
+        parent: ParentHandle,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let upd = CmdDet::remove_parent(&handle, parent.clone()).with_actor(actor.name());
+        self.send_command(upd)?;
+        self.status.remove_parent(&handle, &parent);
+        Ok(())
+    }
+
+    /// Returns the known synchronisation status for each parent of a CA.
+    pub fn get_parent_statuses(&self, handle: &Handle) -> HashMap<ParentHandle, ParentStatus> {
+        self.status.get_parent_statuses(handle)
+    }
+
+    /// Returns the publication status for each CA which tried to publish.
+    pub fn get_repo_statuses(&self) -> HashMap<Handle, RepoStatus> {
+        self.status.get_repo_statuses()
+    }
+
+    pub fn get_repo_status(&self, handle: &Handle) -> Option<RepoStatus> {
+        self.status.get_repo_status(handle)
+    }
+
+    /// Records that a CA published objects with the given earliest manifest
+    /// next update time.
+    pub fn set_repo_success(&self, handle: &Handle, next_update: Option<Time>) {
+        self.status.set_repo_success(handle, next_update);
+    }
+
+    /// Records that a CA failed to publish, with the number of objects which
+    /// could not be published, if known.
+    pub fn set_repo_failure(
+        &self,
+        handle: &Handle,
+        error: &Error,
+        pending: Option<usize>,
+    ) -> RepoStatus {
+        self.status.set_repo_failure(handle, error, pending)
     }
 
     /// Perform a key roll for all active keys in a CA older than the specified duration.
-    pub fn ca_keyroll_init(&self, handle: Handle, max_age: Duration) -> KrillResult<()> {
-        let init_key_roll = CmdDet::key_roll_init(&handle, max_age, self.signer.clone());
+    pub fn ca_keyroll_init(
+        &self,
+        handle: Handle,
+        max_age: Duration,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let init_key_roll = CmdDet::key_roll_init(&handle, max_age, self.signer.clone())
+            .with_actor(actor.name());
         self.send_command(init_key_roll)
     }
 
@@ -513,29 +735,56 @@ impl<S: Signer> CaServer<S> {
     /// have an age equal to or greater than the staging period are promoted. The RFC mandates
     /// a staging period of 24 hours, but we may use a shorter period for testing and/or emergency
     /// manual key rolls.
-    pub fn ca_keyroll_activate(&self, handle: Handle, staging: Duration) -> KrillResult<()> {
-        let activate_cmd = CmdDet::key_roll_activate(&handle, staging, self.signer.clone());
+    pub fn ca_keyroll_activate(
+        &self,
+        handle: Handle,
+        staging: Duration,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let activate_cmd =
+            CmdDet::key_roll_activate(&handle, staging, self.roa_aggregation, self.signer.clone())
+                .with_actor(actor.name());
         self.send_command(activate_cmd)
     }
 
     /// Try to get updates for all embedded CAs, will skip the TA and/or CAs that
     /// have no parents. Will try to process all and log possible errors, i.e. do
-    /// not bail out because of issues with one CA.
-    pub async fn get_updates_for_all_cas(&self) -> KrillResult<()> {
+    /// not bail out because of issues with one CA. Returns an error listing
+    /// the CAs and parents for which getting updates failed, if any.
+    ///
+    /// Parents which are backed off after failures are skipped, unless forced.
+    pub async fn get_updates_for_all_cas(&self, force: bool) -> KrillResult<()> {
+        let mut failures = vec![];
+
         for handle in self.ca_store.list() {
             if let Ok(ca) = self.get_ca(&handle) {
                 for parent in ca.parents() {
+                    if !force && !self.status.is_parent_due(&handle, parent) {
+                        debug!(
+                            "Skipping refresh for '{}' under backed off parent '{}'",
+                            handle, parent
+                        );
+                        continue;
+                    }
                     if let Err(e) = self.get_updates_from_parent(&handle, &parent).await {
                         error!(
                             "Failed to refresh CA certificates for {}, error: {}",
                             &handle, e
                         );
+                        failures.push(format!("'{}' under '{}': {}", handle, parent, e));
                     }
                 }
             }
         }
 
-        Ok(())
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Custom(format!(
+                "Could not get updates for {}",
+                failures.join(", ")
+            )))
+        }
     }
 
     /// Try to get update for parents, if they were delayed because there was no repository configured
@@ -559,7 +808,8 @@ impl<S: Signer> CaServer<S> {
         }
     }
 
-    /// Try to update a specific CA
+    /// Try to update a specific CA. The outcome is recorded in the status
+    /// of the parent.
     pub async fn get_updates_from_parent(
         &self,
         handle: &Handle,
@@ -573,13 +823,26 @@ impl<S: Signer> CaServer<S> {
                 // No repo set, yet. So, skip updating.
                 Ok(())
             } else {
-                let entitlements = self.get_entitlements_from_parent(handle, parent).await?;
-
-                if !self.update_resource_classes(handle, parent.clone(), entitlements)? {
-                    return Ok(()); // Nothing to do
+                match self.get_entitlements_from_parent(handle, parent).await {
+                    Ok(entitlements) => {
+                        self.status.set_parent_success(handle, parent);
+                        // Pending requests, if any, will be picked up by the scheduler.
+                        self.update_resource_classes(handle, parent.clone(), entitlements)?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        let status = self.status.set_parent_failure(handle, parent, &e);
+                        warn!(
+                            "Could not reach parent '{}' of CA '{}', {} consecutive failure(s), \
+                             backing off until {}",
+                            parent,
+                            handle,
+                            status.failures(),
+                            status.next_attempt().unwrap_or_default()
+                        );
+                        Err(e)
+                    }
                 }
-
-                Ok(()) // Pending requests will be picked up by the scheduler.
             }
         }
     }
@@ -751,6 +1014,7 @@ impl<S: Signer> CaServer<S> {
                     handle,
                     class_name.clone(),
                     received,
+                    self.roa_aggregation,
                     self.signer.clone(),
                 );
 
@@ -1072,8 +1336,94 @@ impl<S: Signer> CaServer<S> {
         &self,
         handle: Handle,
         updates: RouteAuthorizationUpdates,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::route_authorizations_update(
+            &handle,
+            updates,
+            self.roa_aggregation,
+            self.signer.clone(),
+        )
+        .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+}
+
+/// # Support ASPA functions
+///
+impl<S: Signer> CaServer<S> {
+    /// Update the ASPA definitions of a CA
+    pub fn ca_aspas_update(
+        &self,
+        handle: Handle,
+        updates: AspaDefinitionUpdates,
+        actor: &Actor,
     ) -> KrillResult<()> {
-        let cmd = CmdDet::route_authorizations_update(&handle, updates, self.signer.clone());
+        let cmd =
+            CmdDet::aspas_update(&handle, updates, self.signer.clone()).with_actor(actor.name());
+        self.send_command(cmd)
+    }
+}
+
+/// # Support BGPsec functions
+///
+impl<S: Signer> CaServer<S> {
+    /// Certify a BGPsec router key for the ASNs in the request
+    pub fn ca_router_certify(
+        &self,
+        handle: Handle,
+        request: RouterCertRequest,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let (asns, csr) = request.unpack();
+
+        let csr = base64::decode(csr.as_ref())
+            .map_err(|_| Error::CaBgpSecInvalidCsr(handle.clone(), "invalid base64".to_string()))?;
+        let key = RouterKey::from_csr(&csr)
+            .map_err(|cause| Error::CaBgpSecInvalidCsr(handle.clone(), cause))?;
+
+        let definition = RouterCertDefinition::new(key, asns);
+
+        let cmd = CmdDet::router_certify(&handle, definition, self.signer.clone())
+            .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+
+    /// Revoke the BGPsec router certificate for a router key
+    pub fn ca_router_revoke(
+        &self,
+        handle: Handle,
+        key: KeyIdentifier,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        let cmd = CmdDet::router_revoke(&handle, key, self.signer.clone()).with_actor(actor.name());
+        self.send_command(cmd)
+    }
+}
+
+/// # Support Ghostbusters functions
+///
+impl<S: Signer> CaServer<S> {
+    /// Set the Ghostbusters vCard for a CA, after checking it against the
+    /// RFC 6493 profile
+    pub fn ca_ghostbuster_update(
+        &self,
+        handle: Handle,
+        record: GhostbusterRecord,
+        actor: &Actor,
+    ) -> KrillResult<()> {
+        record
+            .validate()
+            .map_err(|cause| Error::CaGhostbusterInvalid(handle.clone(), cause))?;
+
+        let cmd = CmdDet::ghostbuster_update(&handle, record, self.signer.clone())
+            .with_actor(actor.name());
+        self.send_command(cmd)
+    }
+
+    /// Remove the Ghostbusters vCard for a CA
+    pub fn ca_ghostbuster_remove(&self, handle: Handle, actor: &Actor) -> KrillResult<()> {
+        let cmd = CmdDet::ghostbuster_remove(&handle, self.signer.clone()).with_actor(actor.name());
         self.send_command(cmd)
     }
 }
@@ -1085,22 +1435,44 @@ mod tests {
 
     use super::*;
 
+    use std::str::FromStr;
     use std::sync::{Arc, RwLock};
 
-    use crate::commons::api::RepoInfo;
+    use crate::commons::api::{RepoInfo, UpdateChildRequest};
     use crate::commons::util::softsigner::OpenSslSigner;
     use crate::test;
 
+    fn server(d: &PathBuf) -> CaServer<OpenSslSigner> {
+        server_with_signer_dir(d, d)
+    }
+
+    fn server_with_signer_dir(d: &PathBuf, signer_dir: &PathBuf) -> CaServer<OpenSslSigner> {
+        let signer = OpenSslSigner::build(signer_dir).unwrap();
+        let signer = Arc::new(RwLock::new(signer));
+
+        let event_queue = Arc::new(EventQueueListener::build(d).unwrap());
+        let event_stream = Arc::new(EventStream::default());
+        let webhooks = Arc::new(WebhookQueue::build(d, vec![]).unwrap());
+        let roa_aggregation = RoaAggregation::new(100, 90);
+
+        CaServer::<OpenSslSigner>::build(
+            d,
+            None,
+            None,
+            event_queue,
+            event_stream,
+            webhooks,
+            roa_aggregation,
+            StorageBackend::Disk,
+            signer,
+        )
+        .unwrap()
+    }
+
     #[test]
     fn add_ta() {
         test::test_under_tmp(|d| {
-            let signer = OpenSslSigner::build(&d).unwrap();
-            let signer = Arc::new(RwLock::new(signer));
-
-            let event_queue = Arc::new(EventQueueListener::in_mem());
-
-            let server =
-                CaServer::<OpenSslSigner>::build(&d, None, None, event_queue, signer).unwrap();
+            let server = server(&d);
 
             let repo_info = {
                 let base_uri = test::rsync("rsync://localhost/repo/ta/");
@@ -1118,4 +1490,118 @@ mod tests {
             assert!(server.get_trust_anchor().is_ok());
         })
     }
+
+    #[test]
+    fn verify_signer_keys() {
+        test::test_under_tmp(|d| {
+            let server = server(&d);
+
+            let repo_info = {
+                let base_uri = test::rsync("rsync://localhost/repo/ta/");
+                let rrdp_uri = test::https("https://localhost/repo/notification.xml");
+                RepoInfo::new(base_uri, rrdp_uri)
+            };
+            let ta_uri = test::https("https://localhost/ta/ta.cer");
+            let ta_aia = test::rsync("rsync://localhost/repo/ta.cer");
+            server.init_ta(repo_info, ta_aia, vec![ta_uri]).unwrap();
+            server.verify_signer_keys().unwrap();
+
+            // a signer which does not have the keys of the TA
+            let other_dir = d.join("other-signer");
+            std::fs::create_dir_all(&other_dir).unwrap();
+            let server = server_with_signer_dir(&d, &other_dir);
+            match server.verify_signer_keys() {
+                Err(Error::SignerKeysMissing(cas)) => assert_eq!(ta_handle().to_string(), cas),
+                res => panic!("Expected missing keys, got: {:?}", res),
+            }
+        })
+    }
+
+    #[test]
+    fn raise_publication_numbers() {
+        test::test_under_tmp(|d| {
+            let server = server(&d);
+
+            let repo_info = {
+                let base_uri = test::rsync("rsync://localhost/repo/ta/");
+                let rrdp_uri = test::https("https://localhost/repo/notification.xml");
+                RepoInfo::new(base_uri, rrdp_uri)
+            };
+            let ta_uri = test::https("https://localhost/ta/ta.cer");
+            let ta_aia = test::rsync("rsync://localhost/repo/ta.cer");
+            server.init_ta(repo_info, ta_aia, vec![ta_uri]).unwrap();
+
+            let ta = ta_handle();
+            let number = server.publication_numbers().unwrap()[&ta];
+
+            server.raise_publication_numbers(&ta, number + 40).unwrap();
+            assert_eq!(number + 41, server.publication_numbers().unwrap()[&ta]);
+
+            // numbers are never lowered
+            server.raise_publication_numbers(&ta, number).unwrap();
+            assert_eq!(number + 41, server.publication_numbers().unwrap()[&ta]);
+        })
+    }
+
+    #[tokio::test]
+    async fn shrink_child_certificates() {
+        let d = test::tmp_dir();
+        let server = server(&d);
+        let actor = Actor::master();
+
+        let repo_info = |ns: &str| {
+            let base_uri = test::rsync(&format!("rsync://example.org/repo/{}/", ns));
+            let rrdp_uri = test::https("https://example.org/repo/notification.xml");
+            RepoInfo::new(base_uri, rrdp_uri)
+        };
+
+        let ta = ta_handle();
+        let ta_uri = test::https("https://example.org/ta/ta.cer");
+        let ta_aia = test::rsync("rsync://example.org/repo/ta.cer");
+        server.init_ta(repo_info("ta"), ta_aia, vec![ta_uri]).unwrap();
+
+        let child = Handle::from_str("child").unwrap();
+        let embedded = RepositoryContact::embedded(repo_info("child"));
+        server.init_ca(&child).unwrap();
+        server.update_repo(child.clone(), embedded, &actor).unwrap();
+
+        let resources = ResourceSet::from_strs("AS65000", "10.0.0.0/16", "").unwrap();
+        let auth = ChildAuthRequest::Embedded;
+        let req = AddChildRequest::new(child.clone(), resources.clone(), auth);
+        let service_uri = test::https("https://example.org/");
+        let contact = server.ca_add_child(&ta, req, &service_uri, &actor).unwrap();
+        let parent = ParentCaReq::new(ta.clone(), contact);
+        server.ca_parent_add(child.clone(), parent, &actor).unwrap();
+
+        server.get_updates_from_parent(&child, &ta).await.unwrap();
+        server.send_requests(&child, &ta).await.unwrap();
+
+        let issued = || -> Vec<ResourceSet> {
+            let entitlements = server.list(&ta, &child).unwrap();
+            entitlements
+                .classes()
+                .iter()
+                .flat_map(|class| class.issued().iter())
+                .map(|issued| issued.resource_set().clone())
+                .collect()
+        };
+        assert_eq!(vec![resources.clone()], issued());
+
+        // Reducing the resources does not affect the issued certificate yet
+        let reduced = ResourceSet::from_strs("AS65000", "10.0.0.0/24", "").unwrap();
+        let update = UpdateChildRequest::resources(reduced.clone());
+        server.ca_child_update(&ta, child.clone(), update, &actor).unwrap();
+        assert_eq!(vec![resources.clone()], issued());
+        assert_eq!(1, server.get_ca(&ta).unwrap().children_overclaiming().len());
+
+        // The certificate is not shrunk within the grace period
+        server.shrink_children_all(1).unwrap();
+        assert_eq!(vec![resources], issued());
+
+        server.shrink_children_all(0).unwrap();
+        assert_eq!(vec![reduced], issued());
+        assert!(server.get_ca(&ta).unwrap().children_overclaiming().is_empty());
+
+        let _ = std::fs::remove_dir_all(d);
+    }
 }
@@ -2,20 +2,31 @@
 //! Common objects for TAs and CAs
 use std::convert::TryFrom;
 
+use bcder::encode::{PrimitiveContent, Values};
+use bcder::{encode, BitString, Captured, Mode, OctetString, Oid, Tag};
 use bytes::Bytes;
 
 use rpki::cert::{Cert, KeyUsage, Overclaim, TbsCert};
 use rpki::crl::Crl;
-use rpki::crypto::{self, DigestAlgorithm, KeyIdentifier, PublicKey};
+use rpki::crypto::{self, DigestAlgorithm, KeyIdentifier, PublicKey, SignatureAlgorithm};
 use rpki::csr::Csr;
 use rpki::manifest::FileAndHash;
+use rpki::oid;
+use rpki::resources::{AsBlocksBuilder, AsId, AsResources};
 use rpki::uri;
-use rpki::x509::{Name, Serial, Time, Validity};
+use rpki::x509::{encode_extension, Name, Serial, Time, Validity};
 
-use crate::commons::api::{IssuedCert, ReplacedObject, RequestResourceLimit, ResourceSet};
+use crate::commons::api::{
+    Base64, CurrentObject, IssuedCert, ReplacedObject, RequestResourceLimit, ResourceSet,
+};
 use crate::commons::error::Error;
 use crate::commons::KrillResult;
-use crate::daemon::ca::{self, CertifiedKey};
+use crate::constants::ROUTER_CERTIFICATE_VALIDITY_YEARS;
+use crate::daemon::ca::{self, CertifiedKey, RouterCertDefinition};
+
+/// The extended key usage for BGPsec router certificates,
+/// id-kp-bgpsec-router: 1.3.6.1.5.5.7.3.30 (RFC 8209)
+const KP_BGPSEC_ROUTER: Oid<&[u8]> = Oid(&[43, 6, 1, 5, 5, 7, 3, 30]);
 
 //------------ Signer --------------------------------------------------------
 
@@ -198,6 +209,139 @@ impl SignSupport {
         Ok(IssuedCert::new(cert_uri, limit, resources, cert, replaces))
     }
 
+    /// Create a BGPsec router certificate (RFC 8209) for the router key and
+    /// ASNs in the definition.
+    ///
+    /// The rpki crate can only include RSA keys in certificates, so we encode
+    /// the certificate here, including the router's SubjectPublicKeyInfo as is.
+    pub fn make_router_cert<S: Signer>(
+        definition: &RouterCertDefinition,
+        signing_key: &CertifiedKey,
+        new_repo: Option<&uri::Rsync>,
+        signer: &S,
+    ) -> KrillResult<CurrentObject> {
+        let signing_cert = signing_key.incoming_cert();
+
+        let crl_uri = match &new_repo {
+            None => signing_cert.crl_uri(),
+            Some(base_uri) => base_uri.join(signing_cert.crl_name().as_bytes()),
+        };
+        let ca_issuer = signing_cert.uri();
+
+        let serial = Serial::random(signer).map_err(ca::Error::signer)?;
+        let algorithm = SignatureAlgorithm::default();
+        let issuer = signing_cert.cert().subject();
+        let validity = Self::sign_validity_years(ROUTER_CERTIFICATE_VALIDITY_YEARS);
+
+        let ski = definition.key_identifier();
+
+        // The subject uses "ROUTER-" followed by the hex encoded (first) ASN
+        // as the common name, and the hex encoded key identifier as the
+        // serial number, so that routers for the same ASN can be told apart.
+        let common_name = match definition.asns().first() {
+            Some(asn) => format!("ROUTER-{:08X}", asn.into_u32()),
+            None => "ROUTER".to_string(),
+        };
+        let serial_number = ski.to_string();
+
+        let aki = signing_cert.cert().subject_key_identifier();
+
+        let mut as_blocks = AsBlocksBuilder::new();
+        for asn in definition.asns() {
+            as_blocks.push(AsId::from(*asn));
+        }
+        let as_resources = AsResources::blocks(as_blocks.finalize());
+
+        let spki = definition.key().info();
+
+        let tbs = Captured::from_values(
+            Mode::Der,
+            encode::sequence((
+                encode::sequence_as(Tag::CTX_0, 2.encode()), // version
+                serial.encode(),
+                algorithm.x509_encode(),
+                issuer.encode_ref(),
+                validity.encode(),
+                encode::sequence((
+                    encode::set(encode::sequence((
+                        oid::AT_COMMON_NAME.encode(),
+                        OctetString::encode_slice_as(common_name.as_bytes(), Tag::PRINTABLE_STRING),
+                    ))),
+                    encode::set(encode::sequence((
+                        oid::AT_SERIAL_NUMBER.encode(),
+                        OctetString::encode_slice_as(
+                            serial_number.as_bytes(),
+                            Tag::PRINTABLE_STRING,
+                        ),
+                    ))),
+                )),
+                &spki,
+                encode::sequence_as(
+                    Tag::CTX_3,
+                    encode::sequence((
+                        encode_extension(&oid::CE_SUBJECT_KEY_IDENTIFIER, false, ski.encode_ref()),
+                        encode_extension(
+                            &oid::CE_AUTHORITY_KEY_IDENTIFIER,
+                            false,
+                            encode::sequence(aki.encode_ref_as(Tag::CTX_0)),
+                        ),
+                        encode_extension(&oid::CE_KEY_USAGE, true, KeyUsage::Ee.encode()),
+                        encode_extension(
+                            &oid::CE_EXTENDED_KEY_USAGE,
+                            false,
+                            encode::sequence(KP_BGPSEC_ROUTER.encode()),
+                        ),
+                        encode_extension(
+                            &oid::CE_CRL_DISTRIBUTION_POINTS,
+                            false,
+                            encode::sequence(encode::sequence(encode::sequence_as(
+                                Tag::CTX_0,
+                                encode::sequence_as(Tag::CTX_0, crl_uri.encode_general_name()),
+                            ))),
+                        ),
+                        encode_extension(
+                            &oid::PE_AUTHORITY_INFO_ACCESS,
+                            false,
+                            encode::sequence(encode::sequence((
+                                oid::AD_CA_ISSUERS.encode(),
+                                ca_issuer.encode_general_name(),
+                            ))),
+                        ),
+                        encode_extension(
+                            &oid::CE_CERTIFICATE_POLICIES,
+                            true,
+                            encode::sequence(encode::sequence(
+                                Overclaim::Refuse.policy_id().encode(),
+                            )),
+                        ),
+                        encode_extension(
+                            Overclaim::Refuse.as_res_id(),
+                            true,
+                            as_resources.encode_ref(),
+                        ),
+                    )),
+                ),
+            )),
+        );
+
+        let signature = signer
+            .sign(&signing_key.key_id(), algorithm, tbs.as_slice())
+            .map_err(ca::Error::signer)?;
+
+        let cert = encode::sequence((
+            &tbs,
+            algorithm.x509_encode(),
+            BitString::encode_slice(signature.value().as_ref(), 0),
+        ))
+        .to_captured(Mode::Der);
+
+        Ok(CurrentObject::new(
+            Base64::from_content(cert.as_slice()),
+            serial,
+            validity.not_after(),
+        ))
+    }
+
     /// Returns a validity period from 5 minutes ago (in case of NTP mess-up), to
     /// X year from now.
     pub fn sign_validity_years(years: i32) -> Validity {
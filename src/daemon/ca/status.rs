@@ -0,0 +1,329 @@
+//! Keeps track of the synchronisation status of CAs with their parents,
+//! and of their publication at their repository.
+//!
+//! This status changes with every attempt to contact a parent or repository,
+//! so it is not kept as events in the CA history, but in separate files in
+//! the data directory. Parents which keep failing are backed off, so that an
+//! outage at a parent does not cause a storm of retries.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use rand::{thread_rng, Rng};
+use rpki::x509::Time;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::commons::api::{Handle, ParentHandle, ParentStatus, RepoStatus};
+use crate::commons::error::Error;
+use crate::commons::util::file;
+use crate::commons::KrillResult;
+use crate::constants::STATUS_DIR;
+
+/// The delay before a failing parent is contacted again after its first
+/// failure. It is doubled for every further consecutive failure, up to
+/// PARENT_BACKOFF_MAX_SECS. A random jitter of up to a quarter of the delay
+/// is added, so that CAs under the same parent do not retry in lockstep.
+pub const PARENT_BACKOFF_BASE_SECS: i64 = 300;
+pub const PARENT_BACKOFF_MAX_SECS: i64 = 14400;
+
+const PARENTS_FILE: &str = "parents.json";
+const REPOS_FILE: &str = "repos.json";
+
+type ParentStatuses = HashMap<Handle, HashMap<ParentHandle, ParentStatus>>;
+type RepoStatuses = HashMap<Handle, RepoStatus>;
+
+/// Returns the delay in seconds, without jitter, before the next attempt
+/// after the given number of consecutive failures.
+fn backoff_delay(failures: u32) -> i64 {
+    let exp = failures.saturating_sub(1).min(16);
+    (PARENT_BACKOFF_BASE_SECS << exp).min(PARENT_BACKOFF_MAX_SECS)
+}
+
+fn jitter(delay: i64) -> i64 {
+    thread_rng().gen_range(0, delay / 4 + 1)
+}
+
+//------------ StatusStore ---------------------------------------------------
+
+pub struct StatusStore {
+    dir: PathBuf,
+    parents: RwLock<ParentStatuses>,
+    repos: RwLock<RepoStatuses>,
+}
+
+impl StatusStore {
+    pub fn build(work_dir: &PathBuf) -> KrillResult<Self> {
+        let mut dir = work_dir.clone();
+        dir.push(STATUS_DIR);
+        fs::create_dir_all(&dir)?;
+
+        let parents = Self::load(&dir.join(PARENTS_FILE));
+        let repos = Self::load(&dir.join(REPOS_FILE));
+
+        Ok(StatusStore {
+            dir,
+            parents: RwLock::new(parents),
+            repos: RwLock::new(repos),
+        })
+    }
+
+    /// Returns the known status of each parent of the CA.
+    pub fn get_parent_statuses(&self, ca: &Handle) -> HashMap<ParentHandle, ParentStatus> {
+        let parents = self.parents.read().unwrap();
+        parents.get(ca).cloned().unwrap_or_default()
+    }
+
+    /// Returns whether the parent may be contacted now, i.e. whether it is
+    /// not backed off after failures.
+    pub fn is_parent_due(&self, ca: &Handle, parent: &ParentHandle) -> bool {
+        let parents = self.parents.read().unwrap();
+        parents
+            .get(ca)
+            .and_then(|statuses| statuses.get(parent))
+            .map(|status| status.is_due(Time::now().timestamp()))
+            .unwrap_or(true)
+    }
+
+    pub fn set_parent_success(&self, ca: &Handle, parent: &ParentHandle) {
+        self.update(ca, parent, |status| {
+            status.set_success(Time::now().timestamp())
+        });
+    }
+
+    /// Records a failure to synchronise with the parent, and backs it off.
+    /// Returns the updated status.
+    pub fn set_parent_failure(
+        &self,
+        ca: &Handle,
+        parent: &ParentHandle,
+        error: &Error,
+    ) -> ParentStatus {
+        self.update(ca, parent, |status| {
+            let now = Time::now().timestamp();
+            let delay = backoff_delay(status.failures() + 1);
+            let next = now + delay + jitter(delay);
+            status.set_failure(now, error.to_error_response(), next);
+        })
+    }
+
+    pub fn remove_parent(&self, ca: &Handle, parent: &ParentHandle) {
+        let mut parents = self.parents.write().unwrap();
+        if let Some(statuses) = parents.get_mut(ca) {
+            statuses.remove(parent);
+            if statuses.is_empty() {
+                parents.remove(ca);
+            }
+            self.save(PARENTS_FILE, &*parents);
+        }
+    }
+
+    fn update<F>(&self, ca: &Handle, parent: &ParentHandle, op: F) -> ParentStatus
+    where
+        F: FnOnce(&mut ParentStatus),
+    {
+        let mut parents = self.parents.write().unwrap();
+        let status = parents
+            .entry(ca.clone())
+            .or_default()
+            .entry(parent.clone())
+            .or_default();
+        op(status);
+        let status = status.clone();
+        self.save(PARENTS_FILE, &*parents);
+        status
+    }
+}
+
+/// # Publication status
+///
+impl StatusStore {
+    /// Returns the publication status for each CA which tried to publish.
+    pub fn get_repo_statuses(&self) -> RepoStatuses {
+        self.repos.read().unwrap().clone()
+    }
+
+    pub fn get_repo_status(&self, ca: &Handle) -> Option<RepoStatus> {
+        self.repos.read().unwrap().get(ca).cloned()
+    }
+
+    /// Records a successful publication, of objects with the given earliest
+    /// manifest next update time.
+    pub fn set_repo_success(&self, ca: &Handle, next_update: Option<Time>) {
+        self.update_repo(ca, |status| {
+            let next_update = next_update.map(|time| time.timestamp());
+            status.set_success(Time::now().timestamp(), next_update)
+        });
+    }
+
+    /// Records a failure to publish, with the number of objects which could
+    /// not be published, if known. Returns the updated status.
+    pub fn set_repo_failure(
+        &self,
+        ca: &Handle,
+        error: &Error,
+        pending: Option<usize>,
+    ) -> RepoStatus {
+        self.update_repo(ca, |status| {
+            status.set_failure(Time::now().timestamp(), error.to_error_response(), pending)
+        })
+    }
+
+    fn update_repo<F>(&self, ca: &Handle, op: F) -> RepoStatus
+    where
+        F: FnOnce(&mut RepoStatus),
+    {
+        let mut repos = self.repos.write().unwrap();
+        let status = repos.entry(ca.clone()).or_default();
+        op(status);
+        let status = status.clone();
+        self.save(REPOS_FILE, &*repos);
+        status
+    }
+}
+
+impl StatusStore {
+    /// Loads the statuses. The statuses are only used to back off and to
+    /// report, so if they cannot be read Krill starts without them.
+    fn load<T: DeserializeOwned + Default>(path: &Path) -> T {
+        if !path.exists() {
+            return T::default();
+        }
+        match file::load_json(&path.to_path_buf()) {
+            Ok(statuses) => statuses,
+            Err(e) => {
+                warn!(
+                    "Could not read statuses from '{}', starting without them: {}",
+                    path.display(),
+                    e
+                );
+                T::default()
+            }
+        }
+    }
+
+    /// Saves the statuses to a temporary file first, and then renames it,
+    /// so that they are never left partially written. Failing to do so is
+    /// logged, but does not fail the synchronisation which is being recorded.
+    fn save<T: Serialize>(&self, name: &str, statuses: &T) {
+        let path = self.dir.join(name);
+        let tmp = path.with_extension("tmp");
+        if let Err(e) = file::save_json(statuses, &tmp).and_then(|_| fs::rename(&tmp, &path)) {
+            error!("Could not save statuses to '{}': {}", name, e);
+        }
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::test;
+
+    #[test]
+    fn back_off_failing_parents() {
+        assert_eq!(300, backoff_delay(1));
+        assert_eq!(600, backoff_delay(2));
+        assert_eq!(9600, backoff_delay(6));
+        assert_eq!(PARENT_BACKOFF_MAX_SECS, backoff_delay(7));
+        assert_eq!(PARENT_BACKOFF_MAX_SECS, backoff_delay(u32::MAX));
+
+        for _ in 0..100 {
+            let jitter = jitter(PARENT_BACKOFF_MAX_SECS);
+            assert!((0..=PARENT_BACKOFF_MAX_SECS / 4).contains(&jitter));
+        }
+    }
+
+    #[test]
+    fn track_parent_status() {
+        test::test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let parent = Handle::from_str("parent").unwrap();
+            let error = Error::Custom("connection refused".to_string());
+
+            let store = StatusStore::build(&d).unwrap();
+            assert!(store.is_parent_due(&ca, &parent));
+
+            store.set_parent_failure(&ca, &parent, &error);
+            let status = store.set_parent_failure(&ca, &parent, &error);
+            assert_eq!(2, status.failures());
+            assert_eq!(None, status.last_success());
+            assert_eq!(Some(&error.to_error_response()), status.last_error());
+            let next = status.next_attempt().unwrap() - status.last_attempt().unwrap();
+            assert!(next >= backoff_delay(2) && next <= backoff_delay(2) * 5 / 4);
+            assert!(!store.is_parent_due(&ca, &parent));
+
+            // The status survives a restart
+            let store = StatusStore::build(&d).unwrap();
+            assert_eq!(Some(&status), store.get_parent_statuses(&ca).get(&parent));
+
+            store.set_parent_success(&ca, &parent);
+            let status = store
+                .get_parent_statuses(&ca)
+                .get(&parent)
+                .cloned()
+                .unwrap();
+            assert!(!status.is_failing());
+            assert!(status.last_success().is_some());
+            assert_eq!(None, status.last_error());
+            assert!(store.is_parent_due(&ca, &parent));
+
+            store.remove_parent(&ca, &parent);
+            assert!(store.get_parent_statuses(&ca).is_empty());
+        })
+    }
+
+    #[test]
+    fn track_repo_status() {
+        test::test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let error = Error::Custom("connection refused".to_string());
+            let next_update = Time::now();
+
+            let store = StatusStore::build(&d).unwrap();
+            assert_eq!(None, store.get_repo_status(&ca));
+
+            store.set_repo_success(&ca, Some(next_update));
+            store.set_repo_failure(&ca, &error, Some(3));
+            let status = store.set_repo_failure(&ca, &error, None);
+            assert_eq!(2, status.failures());
+            assert_eq!(3, status.pending());
+            assert_eq!(Some(&error.to_error_response()), status.last_error());
+            assert_eq!(Some(next_update.timestamp()), status.next_update());
+            assert!(status.last_success().is_some());
+
+            // The status survives a restart
+            let store = StatusStore::build(&d).unwrap();
+            assert_eq!(Some(status), store.get_repo_status(&ca));
+
+            store.set_repo_success(&ca, None);
+            let status = store.get_repo_status(&ca).unwrap();
+            assert!(!status.is_failing());
+            assert_eq!(0, status.pending());
+            assert_eq!(None, status.next_update());
+        })
+    }
+
+    #[test]
+    fn start_without_unreadable_status() {
+        test::test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let status_dir = d.join(STATUS_DIR);
+            file::save(b"{ \"ca\": ", &status_dir.join(REPOS_FILE)).unwrap();
+
+            let store = StatusStore::build(&d).unwrap();
+            assert_eq!(None, store.get_repo_status(&ca));
+
+            store.set_repo_success(&ca, None);
+            let store = StatusStore::build(&d).unwrap();
+            assert!(store.get_repo_status(&ca).is_some());
+            assert!(!status_dir.join(REPOS_FILE).with_extension("tmp").exists());
+        })
+    }
+}
@@ -6,7 +6,8 @@ use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use clap::{App, Arg};
+use chrono::{DateTime, Utc};
+use clap::{App, Arg, ArgMatches};
 use log::{error, LevelFilter};
 use serde::de;
 use serde::{Deserialize, Deserializer};
@@ -15,11 +16,21 @@ use syslog::Facility;
 use toml;
 
 use rpki::uri;
+use rpki::x509::Time;
 
-use crate::commons::api::Token;
+use crate::commons::api::{Handle, Token};
+use crate::commons::eventsourcing::StorageBackend;
 use crate::commons::util::ext_serde;
+use crate::commons::util::keyencryption::KeyEncryptionConfig;
+use crate::commons::util::signer::SignerConfig;
 use crate::constants::*;
+use crate::daemon::auth::oidc::OidcConfig;
+use crate::daemon::auth::{ApiUser, Role};
+use crate::daemon::backup::RestorePoint;
+use crate::daemon::ca::RoaAggregation;
 use crate::daemon::http::tls_keys;
+use crate::daemon::webhooks::WebhookConfig;
+use crate::pubd::RrdpRetention;
 
 //------------ ConfigDefaults ------------------------------------------------
 
@@ -82,6 +93,58 @@ impl ConfigDefaults {
         600
     }
 
+    fn child_shrink_grace_hours() -> u32 {
+        24
+    }
+
+    fn roa_aggregate_threshold() -> usize {
+        100
+    }
+
+    fn roa_deaggregate_threshold() -> usize {
+        90
+    }
+
+    fn archive_history_days() -> u32 {
+        30
+    }
+
+    fn rrdp_delta_max_count() -> usize {
+        50
+    }
+
+    fn rrdp_delta_max_age_hours() -> u32 {
+        24
+    }
+
+    fn rsync_dir_retain_mins() -> u32 {
+        10
+    }
+
+    fn events_interval() -> u32 {
+        1
+    }
+
+    fn republish_interval() -> u32 {
+        3600
+    }
+
+    fn archive_interval() -> u32 {
+        24 * 3600
+    }
+
+    fn webhooks_interval() -> u32 {
+        10
+    }
+
+    fn announcements_interval() -> u32 {
+        1
+    }
+
+    fn mirrors_interval() -> u32 {
+        10
+    }
+
     fn post_limit_api() -> u64 {
         256 * 1024 // 256kB
     }
@@ -137,6 +200,9 @@ pub struct Config {
     #[serde(default = "ConfigDefaults::data_dir")]
     pub data_dir: PathBuf,
 
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+
     pub pid_file: Option<PathBuf>,
 
     #[serde(default = "ConfigDefaults::rsync_base")]
@@ -165,9 +231,66 @@ pub struct Config {
     #[serde(default = "ConfigDefaults::auth_token")]
     pub auth_token: Token,
 
+    #[serde(default)]
+    pub api_users: Vec<ApiUser>,
+
+    #[serde(default)]
+    pub auth_openidconnect: Option<OidcConfig>,
+
+    #[serde(default)]
+    pub signer: SignerConfig,
+
+    #[serde(default)]
+    pub key_encryption: Option<KeyEncryptionConfig>,
+
     #[serde(default = "ConfigDefaults::ca_refresh")]
     pub ca_refresh: u32,
 
+    #[serde(default = "ConfigDefaults::child_shrink_grace_hours")]
+    pub child_shrink_grace_hours: u32,
+
+    #[serde(default = "ConfigDefaults::roa_aggregate_threshold")]
+    pub roa_aggregate_threshold: usize,
+
+    #[serde(default = "ConfigDefaults::roa_deaggregate_threshold")]
+    pub roa_deaggregate_threshold: usize,
+
+    #[serde(default = "ConfigDefaults::archive_history_days")]
+    pub archive_history_days: u32,
+
+    #[serde(default = "ConfigDefaults::rrdp_delta_max_count")]
+    pub rrdp_delta_max_count: usize,
+
+    #[serde(default = "ConfigDefaults::rrdp_delta_max_age_hours")]
+    pub rrdp_delta_max_age_hours: u32,
+
+    #[serde(default = "ConfigDefaults::rsync_dir_retain_mins")]
+    pub rsync_dir_retain_mins: u32,
+
+    #[serde(default)]
+    pub rrdp_mirror_dirs: Vec<PathBuf>,
+
+    #[serde(default = "ConfigDefaults::events_interval")]
+    pub events_interval: u32,
+
+    #[serde(default = "ConfigDefaults::republish_interval")]
+    pub republish_interval: u32,
+
+    #[serde(default = "ConfigDefaults::archive_interval")]
+    pub archive_interval: u32,
+
+    #[serde(default = "ConfigDefaults::webhooks_interval")]
+    pub webhooks_interval: u32,
+
+    #[serde(default = "ConfigDefaults::announcements_interval")]
+    pub announcements_interval: u32,
+
+    #[serde(default = "ConfigDefaults::mirrors_interval")]
+    pub mirrors_interval: u32,
+
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+
     #[serde(default = "ConfigDefaults::post_limit_api")]
     pub post_limit_api: u64,
 
@@ -231,6 +354,14 @@ impl Config {
         self.use_ta
     }
 
+    pub fn roa_aggregation(&self) -> RoaAggregation {
+        RoaAggregation::new(self.roa_aggregate_threshold, self.roa_deaggregate_threshold)
+    }
+
+    pub fn rrdp_retention(&self) -> RrdpRetention {
+        RrdpRetention::new(self.rrdp_delta_max_count, self.rrdp_delta_max_age_hours)
+    }
+
     pub fn pid_file(&self) -> PathBuf {
         match &self.pid_file {
             None => {
@@ -254,6 +385,7 @@ impl Config {
         let repo_enabled = true;
         let https_mode = HttpsMode::Generate;
         let data_dir = data_dir.clone();
+        let storage_backend = StorageBackend::default();
         let rsync_base = ConfigDefaults::rsync_base();
         let service_uri = ConfigDefaults::service_uri();
         let rrdp_service_uri = Some("https://localhost:3000/test-rrdp/".to_string());
@@ -263,7 +395,26 @@ impl Config {
         log_file.push("krill.log");
         let syslog_facility = ConfigDefaults::syslog_facility();
         let auth_token = Token::from("secret");
+        let api_users = vec![];
+        let auth_openidconnect = None;
+        let signer = SignerConfig::default();
+        let key_encryption = None;
         let ca_refresh = 3600;
+        let child_shrink_grace_hours = ConfigDefaults::child_shrink_grace_hours();
+        let roa_aggregate_threshold = ConfigDefaults::roa_aggregate_threshold();
+        let roa_deaggregate_threshold = ConfigDefaults::roa_deaggregate_threshold();
+        let archive_history_days = ConfigDefaults::archive_history_days();
+        let rrdp_delta_max_count = ConfigDefaults::rrdp_delta_max_count();
+        let rrdp_delta_max_age_hours = ConfigDefaults::rrdp_delta_max_age_hours();
+        let rsync_dir_retain_mins = ConfigDefaults::rsync_dir_retain_mins();
+        let rrdp_mirror_dirs = vec![];
+        let events_interval = ConfigDefaults::events_interval();
+        let republish_interval = ConfigDefaults::republish_interval();
+        let archive_interval = ConfigDefaults::archive_interval();
+        let webhooks_interval = ConfigDefaults::webhooks_interval();
+        let announcements_interval = ConfigDefaults::announcements_interval();
+        let mirrors_interval = ConfigDefaults::mirrors_interval();
+        let webhooks = vec![];
         let post_limit_api = ConfigDefaults::post_limit_api();
         let post_limit_rfc8181 = ConfigDefaults::post_limit_rfc8181();
         let rfc8181_log_dir = {
@@ -291,6 +442,7 @@ impl Config {
             repo_enabled,
             https_mode,
             data_dir,
+            storage_backend,
             rsync_base,
             service_uri,
             rrdp_service_uri,
@@ -299,7 +451,26 @@ impl Config {
             log_file,
             syslog_facility,
             auth_token,
+            api_users,
+            auth_openidconnect,
+            signer,
+            key_encryption,
             ca_refresh,
+            child_shrink_grace_hours,
+            roa_aggregate_threshold,
+            roa_deaggregate_threshold,
+            archive_history_days,
+            rrdp_delta_max_count,
+            rrdp_delta_max_age_hours,
+            rsync_dir_retain_mins,
+            rrdp_mirror_dirs,
+            events_interval,
+            republish_interval,
+            archive_interval,
+            webhooks_interval,
+            announcements_interval,
+            mirrors_interval,
+            webhooks,
             post_limit_api,
             post_limit_rfc8181,
             rfc8181_log_dir,
@@ -327,8 +498,8 @@ impl Config {
         config
     }
 
-    pub fn get_config_filename() -> String {
-        let matches = App::new(KRILL_SERVER_APP)
+    fn get_matches() -> ArgMatches<'static> {
+        App::new(KRILL_SERVER_APP)
             .version(KRILL_VERSION)
             .arg(
                 Arg::with_name("config")
@@ -338,7 +509,73 @@ impl Config {
                     .help("Override the path to the config file (default: './defaults/krill.conf')")
                     .required(false),
             )
-            .get_matches();
+            .arg(
+                Arg::with_name("rekey")
+                    .long("rekey")
+                    .help("Re-encrypt the key store using the configured key_encryption, and exit")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("old_passphrase_file")
+                    .long("old-passphrase-file")
+                    .value_name("FILE")
+                    .help("File with the current passphrase of the key store, if encrypted")
+                    .requires("rekey")
+                    .conflicts_with("old_passphrase_env")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("old_passphrase_env")
+                    .long("old-passphrase-env")
+                    .value_name("VAR")
+                    .help("Environment variable with the current passphrase of the key store, if encrypted")
+                    .requires("rekey")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("migrate_storage")
+                    .long("migrate-storage")
+                    .help("Convert the data directory to the configured storage_backend, and exit")
+                    .conflicts_with("rekey")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("verify_snapshots")
+                    .long("verify-snapshots")
+                    .help("Verify that all snapshots match a replay of their events, and exit")
+                    .conflicts_with_all(&["rekey", "migrate_storage"])
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("restore")
+                    .long("restore")
+                    .value_name("FILE")
+                    .help("Restore the backup archive to the (empty) data directory, and exit")
+                    .conflicts_with("rekey")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("until_time")
+                    .long("until-time")
+                    .value_name("RFC3339")
+                    .help("Restore the state at this time, e.g. 2020-06-01T12:00:00Z")
+                    .requires("restore")
+                    .conflicts_with("until_command")
+                    .required(false),
+            )
+            .arg(
+                Arg::with_name("until_command")
+                    .long("until-command")
+                    .value_name("HANDLE:SEQUENCE")
+                    .help("Restore the CA up to and including this command, and all else to its time")
+                    .requires("restore")
+                    .required(false),
+            )
+            .get_matches()
+    }
+
+    pub fn get_config_filename() -> String {
+        let matches = Self::get_matches();
 
         let config_file = matches
             .value_of("config")
@@ -347,6 +584,65 @@ impl Config {
         config_file.to_string()
     }
 
+    /// Returns the arguments for re-encrypting the key store, if this was
+    /// requested instead of starting the server.
+    pub fn get_rekey_args() -> Option<RekeyArgs> {
+        let matches = Self::get_matches();
+
+        if matches.is_present("rekey") {
+            let old = match matches.value_of("old_passphrase_file") {
+                Some(file) => Some(KeyEncryptionConfig::from_file(PathBuf::from(file))),
+                None => matches
+                    .value_of("old_passphrase_env")
+                    .map(|var| KeyEncryptionConfig::from_env(var.to_string())),
+            };
+            Some(RekeyArgs { old })
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether converting the data directory to the configured
+    /// storage backend was requested instead of starting the server.
+    pub fn get_migrate_storage() -> bool {
+        Self::get_matches().is_present("migrate_storage")
+    }
+
+    /// Returns whether verifying all snapshots was requested instead of
+    /// starting the server.
+    pub fn get_verify_snapshots() -> bool {
+        Self::get_matches().is_present("verify_snapshots")
+    }
+
+    /// Returns the arguments for restoring a backup, if this was requested
+    /// instead of starting the server.
+    pub fn get_restore_args() -> Result<Option<RestoreArgs>, ConfigError> {
+        let matches = Self::get_matches();
+
+        let archive = match matches.value_of("restore") {
+            Some(archive) => PathBuf::from(archive),
+            None => return Ok(None),
+        };
+
+        let point = if let Some(time) = matches.value_of("until_time") {
+            let time = DateTime::parse_from_rfc3339(time)
+                .map_err(|e| ConfigError::Other(format!("Invalid time '{}': {}", time, e)))?;
+            RestorePoint::Time(Time::new(time.with_timezone(&Utc)))
+        } else if let Some(command) = matches.value_of("until_command") {
+            let invalid = || ConfigError::Other(format!("Invalid command '{}'", command));
+            let mut parts = command.rsplitn(2, ':');
+            let sequence = parts.next().ok_or_else(invalid)?;
+            let sequence = u64::from_str(sequence).map_err(|_| invalid())?;
+            let handle = parts.next().ok_or_else(invalid)?;
+            let handle = Handle::from_str(handle).map_err(|_| invalid())?;
+            RestorePoint::Command(handle, sequence)
+        } else {
+            RestorePoint::Latest
+        };
+
+        Ok(Some(RestoreArgs { archive, point }))
+    }
+
     /// Creates the config (at startup). Panics in case of issues.
     pub fn create() -> Result<Self, ConfigError> {
         let config_file = Self::get_config_filename();
@@ -446,6 +742,96 @@ impl Config {
             ));
         }
 
+        if self.roa_deaggregate_threshold >= self.roa_aggregate_threshold {
+            return Err(ConfigError::other(
+                "roa_deaggregate_threshold must be lower than roa_aggregate_threshold",
+            ));
+        }
+
+        if self.archive_history_days == 0 {
+            return Err(ConfigError::other(
+                "archive_history_days must be at least 1",
+            ));
+        }
+
+        if self.rrdp_delta_max_count == 0 {
+            return Err(ConfigError::other(
+                "rrdp_delta_max_count must be at least 1",
+            ));
+        }
+
+        let intervals = [
+            ("ca_refresh", self.ca_refresh),
+            ("events_interval", self.events_interval),
+            ("republish_interval", self.republish_interval),
+            ("archive_interval", self.archive_interval),
+            ("webhooks_interval", self.webhooks_interval),
+            ("announcements_interval", self.announcements_interval),
+            ("mirrors_interval", self.mirrors_interval),
+        ];
+        for (name, interval) in intervals.iter() {
+            if *interval == 0 {
+                return Err(ConfigError::Other(format!("{} must be at least 1", name)));
+            }
+        }
+
+        self.verify_api_users()?;
+
+        for webhook in &self.webhooks {
+            webhook
+                .verify()
+                .map_err(|e| ConfigError::Other(format!("Invalid webhook: {}", e)))?;
+        }
+
+        if let Some(oidc) = &self.auth_openidconnect {
+            oidc.verify().map_err(|e| {
+                ConfigError::Other(format!("Invalid auth_openidconnect config: {}", e))
+            })?;
+        }
+
+        self.signer
+            .verify()
+            .map_err(|e| ConfigError::Other(format!("Invalid signer config: {}", e)))?;
+
+        if let Some(key_encryption) = &self.key_encryption {
+            key_encryption
+                .verify()
+                .map_err(|e| ConfigError::Other(format!("Invalid key_encryption config: {}", e)))?;
+        }
+
+        Ok(())
+    }
+
+    fn verify_api_users(&self) -> Result<(), ConfigError> {
+        let mut names = vec![];
+        let mut tokens = vec![&self.auth_token];
+
+        for user in &self.api_users {
+            if names.contains(&user.name()) {
+                return Err(ConfigError::Other(format!(
+                    "Duplicate api user: {}",
+                    user.name()
+                )));
+            }
+            if tokens.contains(&user.token()) {
+                return Err(ConfigError::Other(format!(
+                    "Token for api user '{}' is already in use",
+                    user.name()
+                )));
+            }
+            if (user.role() == &Role::RoaEditor || user.role() == &Role::CaAdmin)
+                && user.cas().is_empty()
+            {
+                return Err(ConfigError::Other(format!(
+                    "Api user '{}' with role '{}' must list at least one CA in 'cas'",
+                    user.name(),
+                    user.role()
+                )));
+            }
+            names.push(user.name());
+            tokens.push(user.token());
+        }
+
         Ok(())
     }
 
@@ -576,6 +962,25 @@ impl Config {
     }
 }
 
+//------------ RekeyArgs -----------------------------------------------------
+
+/// Arguments for re-encrypting the key store, with the key_encryption set
+/// in the config file. The old config is used to unlock the key store, if
+/// it is currently encrypted.
+#[derive(Debug)]
+pub struct RekeyArgs {
+    pub old: Option<KeyEncryptionConfig>,
+}
+
+//------------ RestoreArgs ---------------------------------------------------
+
+/// Arguments for restoring a backup archive to the data directory.
+#[derive(Debug)]
+pub struct RestoreArgs {
+    pub archive: PathBuf,
+    pub point: RestorePoint,
+}
+
 #[derive(Debug, Display)]
 pub enum ConfigError {
     #[display(fmt = "{}", _0)]
@@ -686,4 +1091,171 @@ mod tests {
         let expected_socket_addr: SocketAddr = ([127, 0, 0, 1], 3000).into();
         assert_eq!(c.socket_addr(), expected_socket_addr);
     }
+
+    #[test]
+    fn should_parse_api_users() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [[api_users]]
+            name = "alice"
+            token = "alice-secret"
+            role = "roa_editor"
+            cas = [ "ca1" ]
+
+            [[api_users]]
+            name = "bob"
+            token = "bob-secret"
+            role = "read_only"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(2, c.api_users.len());
+        assert_eq!(&Role::RoaEditor, c.api_users[0].role());
+        assert_eq!(1, c.api_users[0].cas().len());
+        assert_eq!(&Role::ReadOnly, c.api_users[1].role());
+        c.verify_api_users().unwrap();
+
+        let toml = r#"
+            auth_token = "secret"
+
+            [[api_users]]
+            name = "alice"
+            token = "secret"
+            role = "admin"
+        "#;
+        let c: Config = toml::from_str(toml).unwrap();
+        assert!(c.verify_api_users().is_err());
+    }
+
+    #[test]
+    fn should_parse_webhooks() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [[webhooks]]
+            url = "https://hooks.example.com/krill"
+            secret = "hook-secret"
+            events = [ "child_added" ]
+
+            [[webhooks]]
+            url = "https://chat.example.com/krill"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(2, c.webhooks.len());
+        assert_eq!(Some(&"hook-secret".to_string()), c.webhooks[0].secret());
+        assert!(c.webhooks[0].matches("child_added"));
+        assert!(!c.webhooks[0].matches("key_roll_finished"));
+        assert!(c.webhooks[1].matches("key_roll_finished"));
+        assert!(!c.webhooks[1].matches("object_set_updated"));
+        c.webhooks[1].verify().unwrap();
+
+        let toml = r#"
+            auth_token = "secret"
+
+            [[webhooks]]
+            url = "ftp://hooks.example.com/krill"
+        "#;
+        let c: Config = toml::from_str(toml).unwrap();
+        assert!(c.webhooks[0].verify().is_err());
+    }
+
+    #[test]
+    fn should_parse_signer() {
+        let toml = r#"
+            auth_token = "secret"
+        "#;
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(SignerConfig::OpenSsl, c.signer);
+
+        let toml = r#"
+            auth_token = "secret"
+
+            [signer]
+            type = "pkcs11"
+            module = "/usr/lib/softhsm/libsofthsm2.so"
+            slot = 1
+            pin_env = "KRILL_HSM_PIN"
+        "#;
+        let c: Config = toml::from_str(toml).unwrap();
+        match c.signer {
+            SignerConfig::Pkcs11(pkcs11) => {
+                assert_eq!(1, pkcs11.slot);
+                assert_eq!(Some("KRILL_HSM_PIN".to_string()), pkcs11.pin_env);
+            }
+            _ => panic!("Expected PKCS#11 signer"),
+        }
+    }
+
+    #[test]
+    fn should_parse_storage_backend() {
+        let toml = r#"
+            auth_token = "secret"
+        "#;
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(StorageBackend::Disk, c.storage_backend);
+
+        let toml = r#"
+            auth_token = "secret"
+            storage_backend = "sqlite"
+        "#;
+        let c: Config = toml::from_str(toml).unwrap();
+        assert_eq!(StorageBackend::Sqlite, c.storage_backend);
+
+        let toml = r#"
+            auth_token = "secret"
+            storage_backend = "mysql"
+        "#;
+        assert!(toml::from_str::<Config>(toml).is_err());
+    }
+
+    #[test]
+    fn should_parse_key_encryption() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [key_encryption]
+            passphrase_file = "/etc/krill/passphrase"
+        "#;
+        let c: Config = toml::from_str(toml).unwrap();
+        let key_encryption = c.key_encryption.unwrap();
+        assert_eq!(
+            Some(PathBuf::from("/etc/krill/passphrase")),
+            key_encryption.passphrase_file
+        );
+        assert!(key_encryption.verify().is_ok());
+    }
+
+    #[test]
+    fn should_parse_openidconnect() {
+        let toml = r#"
+            auth_token = "secret"
+
+            [auth_openidconnect]
+            issuer_url = "https://login.example.com/realms/krill"
+            client_id = "krill"
+            client_secret = "client-secret"
+            role_claim = "groups"
+
+            [auth_openidconnect.role_map]
+            "krill-admins" = "admin"
+            "noc" = "roa_editor"
+        "#;
+
+        let c: Config = toml::from_str(toml).unwrap();
+        let oidc = c.auth_openidconnect.unwrap();
+        assert_eq!("groups", oidc.role_claim);
+        assert_eq!("krill_cas", oidc.cas_claim);
+        assert_eq!(Some(&Role::Admin), oidc.role_map.get("krill-admins"));
+        oidc.verify().unwrap();
+
+        let mut insecure = oidc.clone();
+        insecure.issuer_url = "http://login.example.com/".to_string();
+        assert!(insecure.verify().is_err());
+
+        let mut local = oidc;
+        local.issuer_url = "http://localhost:8080/".to_string();
+        local.verify().unwrap();
+    }
 }
@@ -0,0 +1,272 @@
+//! Streams the events applied to CAs and the embedded repository to API
+//! clients, as server-sent events. Clients subscribe with a filter, and
+//! receive events as they are stored, without polling the store.
+
+use std::sync::Mutex;
+
+use futures::channel::mpsc;
+use serde::Serialize;
+
+use crate::commons::api::Handle;
+use crate::commons::eventsourcing::{Event, EventListener};
+use crate::daemon::ca::{self, CertAuth, Signer};
+use crate::pubd::{self, Repository};
+
+/// The number of events kept for a client which does not keep up. If it
+/// falls further behind its stream is closed, and it should reconnect and
+/// resume from the last version it saw.
+pub const EVENT_STREAM_BUFFER: usize = 256;
+
+//------------ EventSource ---------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Deserialize, Display, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    #[display(fmt = "ca")]
+    Ca,
+
+    #[display(fmt = "pubd")]
+    Pubd,
+}
+
+//------------ StreamEvent ---------------------------------------------------
+
+/// An event as it is sent to clients of the event stream.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StreamEvent {
+    source: EventSource,
+    handle: Handle,
+    version: u64,
+    #[serde(rename = "type")]
+    event_type: String,
+    details: serde_json::Value,
+}
+
+impl StreamEvent {
+    /// Creates a stream event for the details of a stored event. The type
+    /// of the event is the (snake case) name of its variant.
+    pub fn new<D: Serialize>(
+        source: EventSource,
+        handle: &Handle,
+        version: u64,
+        details: &D,
+    ) -> Self {
+        let details = serde_json::to_value(details).unwrap_or(serde_json::Value::Null);
+        let event_type = match &details {
+            serde_json::Value::String(name) => name.clone(),
+            serde_json::Value::Object(map) => map.keys().next().cloned().unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        StreamEvent {
+            source,
+            handle: handle.clone(),
+            version,
+            event_type,
+            details,
+        }
+    }
+
+    pub fn ca(event: &ca::Evt) -> Self {
+        Self::new(
+            EventSource::Ca,
+            event.handle(),
+            event.version(),
+            event.details(),
+        )
+    }
+
+    pub fn pubd(event: &pubd::Evt) -> Self {
+        Self::new(
+            EventSource::Pubd,
+            event.handle(),
+            event.version(),
+            event.details(),
+        )
+    }
+
+    pub fn source(&self) -> EventSource {
+        self.source
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn event_type(&self) -> &str {
+        &self.event_type
+    }
+
+    /// Returns this event as a server-sent event message. The id is the
+    /// version, so that a client following a single CA can resume with the
+    /// Last-Event-ID header.
+    pub fn to_sse(&self) -> String {
+        let data = serde_json::to_string(self).unwrap_or_default();
+        format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            self.version, self.event_type, data
+        )
+    }
+}
+
+//------------ EventFilter ---------------------------------------------------
+
+/// Selects the events sent to a client. Events for a CA handle only
+/// include events for that CA, and not for the repository.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EventFilter {
+    ca: Option<Handle>,
+    types: Option<Vec<String>>,
+}
+
+impl EventFilter {
+    pub fn new(ca: Option<Handle>, types: Option<Vec<String>>) -> Self {
+        EventFilter { ca, types }
+    }
+
+    pub fn ca(&self) -> Option<&Handle> {
+        self.ca.as_ref()
+    }
+
+    pub fn matches(&self, event: &StreamEvent) -> bool {
+        if let Some(ca) = &self.ca {
+            if event.source != EventSource::Ca || &event.handle != ca {
+                return false;
+            }
+        }
+        if let Some(types) = &self.types {
+            if !types.iter().any(|t| t == &event.event_type) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+//------------ EventStream ---------------------------------------------------
+
+struct Subscriber {
+    filter: EventFilter,
+    sender: mpsc::Sender<StreamEvent>,
+}
+
+/// Listens to the events of CAs and the repository, and passes those
+/// matching their filter on to all subscribed clients.
+#[derive(Default)]
+pub struct EventStream {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl EventStream {
+    /// Returns a receiver for all events matching the filter, from now on.
+    pub fn subscribe(&self, filter: EventFilter) -> mpsc::Receiver<StreamEvent> {
+        let (sender, receiver) = mpsc::channel(EVENT_STREAM_BUFFER);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { filter, sender });
+        receiver
+    }
+
+    /// Returns the number of subscribed clients.
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /// Sends the event to all subscribers with a matching filter. Drops
+    /// subscribers which went away, or which fell too far behind.
+    fn publish(&self, event: StreamEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        *subscribers = subscribers
+            .drain(..)
+            .filter_map(|mut subscriber| {
+                if subscriber.sender.is_closed() {
+                    return None;
+                }
+                if !subscriber.filter.matches(&event) {
+                    return Some(subscriber);
+                }
+                match subscriber.sender.try_send(event.clone()) {
+                    Ok(()) => Some(subscriber),
+                    Err(e) => {
+                        if e.is_full() {
+                            warn!("Closing event stream for client which fell behind");
+                        }
+                        None
+                    }
+                }
+            })
+            .collect();
+    }
+}
+
+impl<S: Signer> EventListener<CertAuth<S>> for EventStream {
+    fn listen(&self, _ca: &CertAuth<S>, event: &ca::Evt) {
+        self.publish(StreamEvent::ca(event))
+    }
+}
+
+impl EventListener<Repository> for EventStream {
+    fn listen(&self, _repository: &Repository, event: &pubd::Evt) {
+        self.publish(StreamEvent::pubd(event))
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn event(ca: &str, version: u64) -> StreamEvent {
+        let handle = Handle::from_str(ca).unwrap();
+        let details = ca::EvtDet::ChildRemoved(Handle::from_str("child").unwrap());
+        StreamEvent::new(EventSource::Ca, &handle, version, &details)
+    }
+
+    #[test]
+    fn event_type_is_variant_name() {
+        let event = event("ca", 3);
+        assert_eq!("child_removed", event.event_type());
+        assert!(event
+            .to_sse()
+            .starts_with("id: 3\nevent: child_removed\ndata: {"));
+    }
+
+    #[test]
+    fn filter_on_ca_and_type() {
+        let ca = Handle::from_str("ca").unwrap();
+        let any = EventFilter::default();
+        let for_ca = EventFilter::new(Some(ca.clone()), None);
+        let other_type = EventFilter::new(Some(ca), Some(vec!["child_added".to_string()]));
+
+        assert!(any.matches(&event("other", 1)));
+        assert!(for_ca.matches(&event("ca", 1)));
+        assert!(!for_ca.matches(&event("other", 1)));
+        assert!(!other_type.matches(&event("ca", 1)));
+    }
+
+    #[test]
+    fn drop_subscribers_which_fall_behind() {
+        let stream = EventStream::default();
+        let _receiver = stream.subscribe(EventFilter::default());
+        let closed = stream.subscribe(EventFilter::default());
+        std::mem::drop(closed);
+
+        stream.publish(event("ca", 0));
+        assert_eq!(1, stream.subscribers());
+
+        // The channel has room for one more event than its buffer.
+        for version in 1..=EVENT_STREAM_BUFFER as u64 + 1 {
+            stream.publish(event("ca", version));
+        }
+        assert_eq!(0, stream.subscribers());
+    }
+}
@@ -1,21 +1,27 @@
 use serde::de::DeserializeOwned;
-use std::convert::TryInto;
-use std::io;
+use std::convert::{Infallible, TryInto};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use bytes::{Buf, BufMut, Bytes};
+use futures::StreamExt;
 use serde::Serialize;
 
 use hyper::body::HttpBody;
 use hyper::http::uri::PathAndQuery;
 use hyper::{Body, Method, StatusCode};
+use reqwest::Url;
 
 use crate::commons::api::Token;
 use crate::commons::error::Error;
 use crate::commons::remote::{rfc6492, rfc8181};
-use crate::daemon::auth::Auth;
+use crate::daemon::auth::oidc::SESSION_COOKIE;
+use crate::daemon::auth::{Actor, Auth};
 use crate::daemon::http::server::State;
 
+pub mod rrdp;
 pub mod server;
 pub mod statics;
 pub mod tls;
@@ -40,6 +46,8 @@ enum ContentType {
     Js,
     Css,
     Svg,
+    Tar,
+    EventStream,
     Woff,
     Woff2,
 }
@@ -59,6 +67,8 @@ impl AsRef<str> for ContentType {
             ContentType::Js => "application/javascript",
             ContentType::Css => "text/css",
             ContentType::Svg => "image/svg+xml",
+            ContentType::Tar => "application/x-tar",
+            ContentType::EventStream => "text/event-stream",
             ContentType::Woff => "font/woff",
             ContentType::Woff2 => "font/woff2",
         }
@@ -190,6 +200,48 @@ impl HttpResponse {
         Self::ok_response(ContentType::Svg, content.to_vec())
     }
 
+    /// Streams a tar archive from a file, which is removed after it was
+    /// sent, or when the client went away. The file is read in chunks on a
+    /// blocking thread, so that large archives do not need to fit in memory.
+    pub fn tar_file(path: PathBuf) -> io::Result<Self> {
+        const CHUNK_SIZE: usize = 64 * 1024;
+
+        let len = fs::metadata(&path)?.len();
+        let (mut sender, body) = Body::channel();
+        tokio::task::spawn_blocking(move || {
+            let res = File::open(&path).and_then(|mut file| {
+                let mut buf = vec![0; CHUNK_SIZE];
+                loop {
+                    let read = file.read(&mut buf)?;
+                    if read == 0 {
+                        return Ok(());
+                    }
+                    let chunk = Bytes::copy_from_slice(&buf[..read]);
+                    if futures::executor::block_on(sender.send_data(chunk)).is_err() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::BrokenPipe,
+                            "client went away",
+                        ));
+                    }
+                }
+            });
+            if let Err(e) = res {
+                warn!("Could not send '{}': {}", path.to_string_lossy(), e);
+                sender.abort();
+            }
+            let _ = fs::remove_file(&path);
+        });
+
+        Ok(HttpResponse(
+            hyper::Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", ContentType::Tar.as_ref())
+                .header("Content-Length", len)
+                .body(body)
+                .unwrap(),
+        ))
+    }
+
     pub fn woff(content: &[u8]) -> Self {
         Self::ok_response(ContentType::Woff, content.to_vec())
     }
@@ -198,6 +250,23 @@ impl HttpResponse {
         Self::ok_response(ContentType::Woff2, content.to_vec())
     }
 
+    /// Streams server-sent event messages to the client, for as long as it
+    /// stays connected.
+    pub fn event_stream<S>(messages: S) -> Self
+    where
+        S: futures::Stream<Item = String> + Send + Sync + 'static,
+    {
+        let body = Body::wrap_stream(messages.map(Ok::<_, Infallible>));
+        HttpResponse(
+            hyper::Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", ContentType::EventStream.as_ref())
+                .header("Cache-Control", "no-cache")
+                .body(body)
+                .unwrap(),
+        )
+    }
+
     pub fn error(error: Error) -> Self {
         error!("{}", error);
         let status = error.status();
@@ -223,6 +292,27 @@ impl HttpResponse {
     pub fn forbidden() -> Self {
         Response::new(StatusCode::FORBIDDEN).finalize()
     }
+
+    /// Redirects the client to another location.
+    pub fn redirect(location: &str) -> Self {
+        HttpResponse(
+            hyper::Response::builder()
+                .status(StatusCode::FOUND)
+                .header("location", location)
+                .body(hyper::Body::empty())
+                .unwrap(),
+        )
+    }
+
+    /// Adds a Set-Cookie header to this response.
+    pub fn with_cookie(mut self, cookie: &str) -> Self {
+        if let Ok(value) = hyper::header::HeaderValue::from_str(cookie) {
+            self.0
+                .headers_mut()
+                .append(hyper::header::SET_COOKIE, value);
+        }
+        self
+    }
 }
 
 //------------ Request -------------------------------------------------------
@@ -231,6 +321,7 @@ pub struct Request {
     request: hyper::Request<hyper::Body>,
     path: RequestPath,
     state: State,
+    actor: Option<Actor>,
 }
 
 impl Request {
@@ -240,6 +331,7 @@ impl Request {
             request,
             path,
             state,
+            actor: None,
         }
     }
 
@@ -373,22 +465,78 @@ impl Request {
         Ok(vec.into())
     }
 
-    /// Checks whether the Bearer token is set to what we expect
-    pub async fn is_authorized(&self) -> bool {
-        if let Some(header) = self.request.headers().get("Authorization") {
-            if let Ok(header) = header.to_str() {
-                if header.len() > 6 {
-                    let (bearer, token) = header.split_at(6);
-                    let bearer = bearer.trim();
-                    let token = Token::from(token.trim());
+    /// Resolves the actor for the Bearer token used in this request, or
+    /// for the session of a user who logged in, and keeps it so that it can
+    /// be recorded with any commands sent on its behalf. Returns false if
+    /// neither is present or known.
+    pub async fn authenticate(&mut self) -> bool {
+        let server = self.state.read().await;
 
-                    if "Bearer" == bearer {
-                        return self.state.read().await.is_api_allowed(&Auth::bearer(token));
+        let mut actor = match self.bearer_token() {
+            Some(token) => server.actor(&Auth::bearer(token)),
+            None => None,
+        };
+        if actor.is_none() {
+            if let Some(id) = self.cookie(SESSION_COOKIE) {
+                actor = server.actor(&Auth::session(id));
+            }
+        }
+        drop(server);
+
+        self.actor = actor;
+        self.actor.is_some()
+    }
+
+    /// Returns the authenticated actor for this request. Note that this
+    /// falls back to Krill itself for requests which were not authenticated,
+    /// so this should only be used for API requests.
+    pub fn actor(&self) -> Actor {
+        self.actor.clone().unwrap_or_else(Actor::krill)
+    }
+
+    /// Returns the value of the named cookie, if present.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        for header in self.request.headers().get_all(hyper::header::COOKIE) {
+            if let Ok(header) = header.to_str() {
+                for cookie in header.split(';') {
+                    let mut parts = cookie.trim().splitn(2, '=');
+                    if parts.next() == Some(name) {
+                        return parts.next().map(str::to_string);
                     }
                 }
             }
         }
-        false
+        None
+    }
+
+    /// Returns the value of the named header, if present.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.request.headers().get(name)?.to_str().ok()
+    }
+
+    pub fn headers(&self) -> &hyper::header::HeaderMap {
+        self.request.headers()
+    }
+
+    /// Returns the (decoded) value of the named query parameter, if present.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        let query = self.request.uri().query()?;
+        let uri = Url::parse(&format!("https://localhost/?{}", query)).ok()?;
+        uri.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+
+    fn bearer_token(&self) -> Option<Token> {
+        let header = self.request.headers().get("Authorization")?;
+        let header = header.to_str().ok()?;
+        if header.len() > 6 {
+            let (bearer, token) = header.split_at(6);
+            if "Bearer" == bearer.trim() {
+                return Some(Token::from(token.trim()));
+            }
+        }
+        None
     }
 }
 
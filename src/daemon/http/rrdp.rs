@@ -0,0 +1,497 @@
+//! Serves the RRDP files written by the repository.
+//!
+//! Relying parties poll the notification file every few minutes, so the
+//! responses support conditional requests with ETag and Last-Modified, and
+//! range requests for resuming interrupted downloads. Snapshots and deltas
+//! are gzip compressed for clients which accept this.
+//!
+//! Snapshots and deltas never change once written, so they are hashed and
+//! compressed only once. Only their ETag and modification time are kept in
+//! memory, the compressed content is written next to the file with a '.gz'
+//! extension. The repository removes it together with the file.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use chrono::{DateTime, TimeZone, Utc};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Body, StatusCode};
+use libflate::gzip::Encoder;
+
+use crate::commons::api::HexEncodedHash;
+use crate::daemon::http::HttpResponse;
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Makes the names of temporary files unique, when a file is compressed
+/// for concurrent requests.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the path of the compressed copy of an RRDP file.
+pub fn gzip_path(path: &Path) -> PathBuf {
+    let mut gzip_path = OsString::from(path);
+    gzip_path.push(".gz");
+    PathBuf::from(gzip_path)
+}
+
+//------------ RrdpFiles -----------------------------------------------------
+
+/// Keeps the details of the snapshot and delta files which were requested
+/// before. Their paths include the session and serial, so these are never
+/// stale.
+#[derive(Default)]
+pub struct RrdpFiles {
+    cache: Arc<Mutex<HashMap<PathBuf, RrdpFileInfo>>>,
+}
+
+impl RrdpFiles {
+    /// Returns the file at the given path, with the compressed content if
+    /// the request accepts this. Files are read on a blocking thread, so
+    /// that hashing and compressing large snapshots does not hold up other
+    /// requests. Details of files which were removed from disk are dropped
+    /// when a new file is added.
+    pub async fn get(&self, path: PathBuf, headers: &HeaderMap) -> io::Result<RrdpFile> {
+        let gzip = RrdpFile::accepts_gzip(headers);
+        let cache = self.cache.clone();
+        tokio::task::spawn_blocking(move || {
+            if RrdpFile::is_notification(&path) {
+                return RrdpFile::read(&path, gzip);
+            }
+
+            let info = cache.lock().unwrap().get(&path).cloned();
+            let (info, content) = match info {
+                Some(info) => (info, fs::read(&path)?),
+                None => {
+                    let (info, content) = RrdpFileInfo::read(&path)?;
+                    let mut cache = cache.lock().unwrap();
+                    cache.retain(|path, _| path.exists());
+                    cache.insert(path.clone(), info.clone());
+                    (info, content)
+                }
+            };
+            Ok(RrdpFile::new(&path, info, content, gzip))
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?
+    }
+}
+
+//------------ RrdpFileInfo --------------------------------------------------
+
+/// The details of a file which are needed to respond to requests.
+#[derive(Clone, Debug)]
+struct RrdpFileInfo {
+    etag: String,
+    last_modified: DateTime<Utc>,
+    gzip_path: Option<PathBuf>,
+}
+
+impl RrdpFileInfo {
+    /// Reads and hashes the file. Snapshots and deltas are compressed to
+    /// a file next to them, unless this was done before. The content of
+    /// the file is returned as well.
+    fn read(path: &Path) -> io::Result<(Self, Vec<u8>)> {
+        let content = fs::read(path)?;
+        let modified = fs::metadata(path)?.modified()?;
+        let last_modified = DateTime::<Utc>::from(modified);
+        // HTTP dates have a precision of seconds
+        let last_modified = Utc.timestamp(last_modified.timestamp(), 0);
+
+        let etag = format!("\"{}\"", HexEncodedHash::from_content(&content));
+
+        // if compression fails the content is served uncompressed
+        let gzip_path = if RrdpFile::is_notification(path) {
+            None
+        } else {
+            let gzip_path = gzip_path(path);
+            if gzip_path.exists() || Self::write_gzip(&content, &gzip_path).is_ok() {
+                Some(gzip_path)
+            } else {
+                None
+            }
+        };
+
+        let info = RrdpFileInfo {
+            etag,
+            last_modified,
+            gzip_path,
+        };
+        Ok((info, content))
+    }
+
+    /// Writes the compressed content to a temporary file, and then renames
+    /// it, so that a compressed file is never seen partially written.
+    fn write_gzip(content: &[u8], gzip_path: &Path) -> io::Result<()> {
+        let mut encoder = Encoder::new(Vec::new())?;
+        encoder.write_all(content)?;
+        let gzipped = encoder.finish().into_result()?;
+
+        let count = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut tmp = OsString::from(gzip_path);
+        tmp.push(format!(".{}.tmp", count));
+        let tmp = PathBuf::from(tmp);
+
+        let res = fs::write(&tmp, &gzipped).and_then(|_| fs::rename(&tmp, gzip_path));
+        if res.is_err() {
+            let _best_effort_rm = fs::remove_file(&tmp);
+        }
+        res
+    }
+}
+
+//------------ RrdpFile ------------------------------------------------------
+
+pub struct RrdpFile {
+    content: Bytes,
+    gzipped: Option<Bytes>,
+    etag: String,
+    last_modified: DateTime<Utc>,
+    max_age: usize,
+    compress: bool,
+}
+
+impl RrdpFile {
+    /// Reads the file at the given path, and its compressed content if
+    /// 'gzip' is set and the file is compressed.
+    pub fn read(path: &Path, gzip: bool) -> io::Result<Self> {
+        let (info, content) = RrdpFileInfo::read(path)?;
+        Ok(Self::new(path, info, content, gzip))
+    }
+
+    /// The notification file changes with every update, so it may only be
+    /// cached briefly. Snapshots and deltas never change, so they may be
+    /// cached for a day, and they are compressed if the client accepts this.
+    fn new(path: &Path, info: RrdpFileInfo, content: Vec<u8>, gzip: bool) -> Self {
+        // if the compressed file cannot be read the content is served
+        // uncompressed
+        let gzipped = match &info.gzip_path {
+            Some(gzip_path) if gzip => fs::read(gzip_path).ok().map(Bytes::from),
+            _ => None,
+        };
+
+        let notification = Self::is_notification(path);
+        let max_age = if notification { 60 } else { 86400 };
+
+        RrdpFile {
+            content: Bytes::from(content),
+            gzipped,
+            etag: info.etag,
+            last_modified: info.last_modified,
+            max_age,
+            compress: !notification,
+        }
+    }
+
+    fn is_notification(path: &Path) -> bool {
+        path.ends_with("notification.xml")
+    }
+
+    /// Returns the response for a request with the given headers.
+    pub fn response(&self, headers: &HeaderMap) -> HttpResponse {
+        if self.not_modified(headers) {
+            return self.respond(StatusCode::NOT_MODIFIED, Bytes::new());
+        }
+
+        if let Some(range) = self.requested_range(headers) {
+            let len = self.content.len();
+            return match range {
+                Some((first, last)) => {
+                    let content_range = format!("bytes {}-{}/{}", first, last, len);
+                    let body = self.content.slice(first..=last);
+                    self.respond(StatusCode::PARTIAL_CONTENT, body)
+                        .with_header(header::CONTENT_RANGE, &content_range)
+                }
+                None => self
+                    .respond(StatusCode::RANGE_NOT_SATISFIABLE, Bytes::new())
+                    .with_header(header::CONTENT_RANGE, &format!("bytes */{}", len)),
+            };
+        }
+
+        if let Some(body) = &self.gzipped {
+            if Self::accepts_gzip(headers) {
+                // the compressed representation needs its own strong etag
+                let etag = self.gzip_etag();
+                return self
+                    .respond(StatusCode::OK, body.clone())
+                    .with_header(header::CONTENT_ENCODING, "gzip")
+                    .with_header(header::ETAG, &etag);
+            }
+        }
+
+        self.respond(StatusCode::OK, self.content.clone())
+    }
+
+    /// Returns whether the client already has this version of the file.
+    /// If-Modified-Since is ignored if If-None-Match is present.
+    fn not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(tags) = Self::header(headers, header::IF_NONE_MATCH) {
+            tags.split(',').any(|tag| {
+                let tag = tag.trim();
+                let tag = tag.trim_start_matches("W/");
+                tag == "*" || tag == self.etag || tag == self.gzip_etag()
+            })
+        } else if let Some(since) = Self::header(headers, header::IF_MODIFIED_SINCE) {
+            match DateTime::parse_from_rfc2822(since) {
+                Ok(since) => self.last_modified <= since,
+                Err(_) => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Returns the single byte range requested, if any. Returns Some(None)
+    /// if the range cannot be satisfied. Multiple ranges are not supported,
+    /// and result in the full content, as does an If-Range which does not
+    /// match the current version.
+    fn requested_range(&self, headers: &HeaderMap) -> Option<Option<(usize, usize)>> {
+        let range = Self::header(headers, header::RANGE)?;
+        if let Some(if_range) = Self::header(headers, header::IF_RANGE) {
+            if if_range != self.etag {
+                return None;
+            }
+        }
+
+        let spec = range.trim().strip_prefix("bytes=")?;
+        if spec.contains(',') {
+            return None;
+        }
+
+        let len = self.content.len();
+        let (first, last) = {
+            let mut parts = spec.splitn(2, '-');
+            (parts.next()?.trim(), parts.next()?.trim())
+        };
+
+        let range = if first.is_empty() {
+            // the last 'n' bytes
+            let n = last.parse::<usize>().ok()?;
+            if n == 0 || len == 0 {
+                None
+            } else {
+                Some((len.saturating_sub(n), len - 1))
+            }
+        } else {
+            let first = first.parse::<usize>().ok()?;
+            let last = if last.is_empty() {
+                len.saturating_sub(1)
+            } else {
+                last.parse::<usize>().ok()?.min(len.saturating_sub(1))
+            };
+            if first >= len || first > last {
+                None
+            } else {
+                Some((first, last))
+            }
+        };
+
+        Some(range)
+    }
+
+    fn gzip_etag(&self) -> String {
+        format!("{}-gzip\"", self.etag.trim_end_matches('"'))
+    }
+
+    fn accepts_gzip(headers: &HeaderMap) -> bool {
+        Self::header(headers, header::ACCEPT_ENCODING)
+            .map(|accepted| {
+                accepted.split(',').any(|coding| {
+                    let mut parts = coding.split(';').map(str::trim);
+                    parts.next() == Some("gzip") && parts.all(|p| p.replace(' ', "") != "q=0")
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    fn header(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+        headers.get(name)?.to_str().ok()
+    }
+
+    fn respond(&self, status: StatusCode, body: Bytes) -> HttpResponse {
+        let mut builder = hyper::Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/xml")
+            .header(header::CACHE_CONTROL, format!("max-age={}", self.max_age))
+            .header(header::ETAG, self.etag.as_str())
+            .header(
+                header::LAST_MODIFIED,
+                self.last_modified.format(HTTP_DATE_FORMAT).to_string(),
+            )
+            .header(header::ACCEPT_RANGES, "bytes");
+        if self.compress {
+            builder = builder.header(header::VARY, "Accept-Encoding");
+        }
+        HttpResponse(builder.body(Body::from(body)).unwrap())
+    }
+}
+
+impl HttpResponse {
+    fn with_header(mut self, name: header::HeaderName, value: &str) -> Self {
+        if let Ok(value) = HeaderValue::from_str(value) {
+            self.0.headers_mut().insert(name, value);
+        }
+        self
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use crate::commons::util::file;
+    use crate::test;
+
+    fn rrdp_file(d: &Path, name: &str) -> RrdpFile {
+        let path = d.join(name);
+        file::save(b"<snapshot>0123456789</snapshot>", &path).unwrap();
+        RrdpFile::read(&path, true).unwrap()
+    }
+
+    fn request(file: RrdpFile, headers: &[(header::HeaderName, &str)]) -> hyper::Response<Body> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        file.response(&map).response()
+    }
+
+    fn body(res: hyper::Response<Body>) -> Vec<u8> {
+        futures::executor::block_on(hyper::body::to_bytes(res.into_body()))
+            .unwrap()
+            .to_vec()
+    }
+
+    #[test]
+    fn conditional_requests() {
+        test::test_under_tmp(|d| {
+            let file = rrdp_file(&d, "notification.xml");
+            let etag = file.etag.clone();
+            let last_modified = file.last_modified.format(HTTP_DATE_FORMAT).to_string();
+            let earlier = (file.last_modified - chrono::Duration::seconds(1))
+                .format(HTTP_DATE_FORMAT)
+                .to_string();
+
+            let file = rrdp_file(&d, "notification.xml");
+            let res = request(file, &[(header::IF_NONE_MATCH, &etag)]);
+            assert_eq!(StatusCode::NOT_MODIFIED, res.status());
+            assert_eq!(etag, res.headers()[header::ETAG]);
+            assert_eq!("max-age=60", res.headers()[header::CACHE_CONTROL]);
+
+            let file = rrdp_file(&d, "notification.xml");
+            let res = request(file, &[(header::IF_NONE_MATCH, "\"other\"")]);
+            assert_eq!(StatusCode::OK, res.status());
+
+            let file = rrdp_file(&d, "notification.xml");
+            let res = request(file, &[(header::IF_MODIFIED_SINCE, &last_modified)]);
+            assert_eq!(StatusCode::NOT_MODIFIED, res.status());
+
+            let file = rrdp_file(&d, "notification.xml");
+            let res = request(file, &[(header::IF_MODIFIED_SINCE, &earlier)]);
+            assert_eq!(StatusCode::OK, res.status());
+            assert_eq!(last_modified, res.headers()[header::LAST_MODIFIED]);
+
+            // the notification file is not compressed
+            let file = rrdp_file(&d, "notification.xml");
+            let res = request(file, &[(header::ACCEPT_ENCODING, "gzip")]);
+            assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+            assert!(!gzip_path(&d.join("notification.xml")).exists());
+        })
+    }
+
+    #[test]
+    fn gzip_and_ranges() {
+        test::test_under_tmp(|d| {
+            let content = b"<snapshot>0123456789</snapshot>".to_vec();
+
+            let file = rrdp_file(&d, "snapshot.xml");
+            let res = request(file, &[(header::ACCEPT_ENCODING, "deflate, gzip")]);
+            assert_eq!(StatusCode::OK, res.status());
+            assert_eq!("gzip", res.headers()[header::CONTENT_ENCODING]);
+            assert_eq!("max-age=86400", res.headers()[header::CACHE_CONTROL]);
+            let etag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+            assert!(etag.ends_with("-gzip\""));
+            assert!(body(res).starts_with(&[0x1f, 0x8b])); // gzip magic
+
+            let file = rrdp_file(&d, "snapshot.xml");
+            let res = request(file, &[(header::IF_NONE_MATCH, &etag)]);
+            assert_eq!(StatusCode::NOT_MODIFIED, res.status());
+
+            let file = rrdp_file(&d, "snapshot.xml");
+            let res = request(file, &[(header::ACCEPT_ENCODING, "gzip;q=0")]);
+            assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+
+            let file = rrdp_file(&d, "snapshot.xml");
+            let res = request(file, &[(header::RANGE, "bytes=10-19")]);
+            assert_eq!(StatusCode::PARTIAL_CONTENT, res.status());
+            assert_eq!("bytes 10-19/31", res.headers()[header::CONTENT_RANGE]);
+            assert_eq!(b"0123456789".to_vec(), body(res));
+
+            let file = rrdp_file(&d, "snapshot.xml");
+            let res = request(file, &[(header::RANGE, "bytes=-11")]);
+            assert_eq!(b"</snapshot>".to_vec(), body(res));
+
+            let file = rrdp_file(&d, "snapshot.xml");
+            let res = request(file, &[(header::RANGE, "bytes=31-")]);
+            assert_eq!(StatusCode::RANGE_NOT_SATISFIABLE, res.status());
+            assert_eq!("bytes */31", res.headers()[header::CONTENT_RANGE]);
+
+            // a range for an older version results in the full content
+            let file = rrdp_file(&d, "snapshot.xml");
+            let headers = [(header::RANGE, "bytes=10-"), (header::IF_RANGE, "\"old\"")];
+            let res = request(file, &headers);
+            assert_eq!(StatusCode::OK, res.status());
+            assert_eq!(content, body(res));
+        })
+    }
+
+    #[test]
+    fn cache_snapshots_and_deltas() {
+        test::test_under_tmp(|d| {
+            let mut rt = Runtime::new().unwrap();
+            let files = RrdpFiles::default();
+            let mut gzip = HeaderMap::new();
+            gzip.insert(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+
+            // snapshots are hashed and compressed only once, and only their
+            // details are kept in memory
+            let snapshot = d.join("1").join("snapshot.xml");
+            file::save(b"<snapshot/>", &snapshot).unwrap();
+            let first = rt.block_on(files.get(snapshot.clone(), &gzip)).unwrap();
+            assert!(first.gzipped.is_some());
+            assert!(gzip_path(&snapshot).exists());
+
+            file::save(b"<snapshot changed=\"never\"/>", &snapshot).unwrap();
+            let second = rt.block_on(files.get(snapshot.clone(), &gzip)).unwrap();
+            assert_eq!(first.etag, second.etag);
+            assert_eq!(first.gzipped, second.gzipped);
+
+            let plain = rt.block_on(files.get(snapshot.clone(), &HeaderMap::new()));
+            assert!(plain.unwrap().gzipped.is_none());
+
+            // the notification file is read for every request
+            let notification = d.join("notification.xml");
+            file::save(b"<notification serial=\"1\"/>", &notification).unwrap();
+            let first = rt.block_on(files.get(notification.clone(), &gzip)).unwrap();
+            assert!(first.gzipped.is_none());
+            file::save(b"<notification serial=\"2\"/>", &notification).unwrap();
+            let second = rt.block_on(files.get(notification.clone(), &gzip)).unwrap();
+            assert_ne!(first.etag, second.etag);
+
+            // removed files are dropped from the cache
+            fs::remove_file(&snapshot).unwrap();
+            let delta = d.join("2").join("delta.xml");
+            file::save(b"<delta/>", &delta).unwrap();
+            rt.block_on(files.get(delta, &gzip)).unwrap();
+            assert!(!files.cache.lock().unwrap().contains_key(&snapshot));
+        })
+    }
+}
@@ -3,8 +3,6 @@
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::env;
-use std::fs::File;
-use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -13,7 +11,9 @@ use serde::Serialize;
 
 use tokio::sync::RwLock;
 
-use futures::TryFutureExt;
+use rpki::crypto::KeyIdentifier;
+
+use futures::{StreamExt, TryFutureExt};
 
 use hyper;
 use hyper::server::conn::AddrIncoming;
@@ -22,12 +22,15 @@ use hyper::Method;
 
 use crate::commons::api::{
     BgpStats, ChildHandle, CommandHistoryCriteria, Handle, ParentCaContact, ParentCaReq,
-    ParentHandle, PublisherList, RepositoryUpdate,
+    ParentHandle, PublisherList, RepositoryUpdate, SchedulerJob,
 };
 use crate::commons::error::Error;
 use crate::commons::remote::rfc8183;
 use crate::constants::KRILL_ENV_UPGRADE_ONLY;
+use crate::daemon::auth::oidc::{LOGIN_STATE_COOKIE, SESSION_COOKIE};
+use crate::daemon::auth::{Actor, Permission};
 use crate::daemon::config::Config;
+use crate::daemon::eventstream::EventFilter;
 use crate::daemon::http::statics::statics;
 use crate::daemon::http::{tls, tls_keys, HttpResponse, Request, RequestPath, RoutingResult};
 use crate::daemon::krillserver::KrillServer;
@@ -99,6 +102,7 @@ async fn map_requests(
     let log_req = format!("{} {}", req.method(), req.path.full());
 
     let res = api(req)
+        .or_else(login)
         .or_else(health)
         .or_else(metrics)
         .or_else(stats)
@@ -275,6 +279,22 @@ pub async fn metrics(req: Request) -> RoutingResult {
                     ));
                 }
             }
+
+            let mirrors = stats.mirrors();
+            if !mirrors.is_empty() {
+                res.push_str("\n");
+                res.push_str(
+                    "# HELP krill_repo_mirror_failures consecutive failures to mirror RRDP files\n",
+                );
+                res.push_str("# TYPE krill_repo_mirror_failures gauge\n");
+                for (target, status) in mirrors {
+                    res.push_str(&format!(
+                        "krill_repo_mirror_failures{{target=\"{}\"}} {}\n",
+                        target,
+                        status.failures()
+                    ));
+                }
+            }
         }
 
         let cas_status = server.cas_stats();
@@ -307,6 +327,48 @@ pub async fn metrics(req: Request) -> RoutingResult {
             ));
         }
 
+        let repo_status = server.cas_repo_status();
+
+        res.push_str("\n");
+        res.push_str(
+            "# HELP krill_cas_repo_last_success timestamp of last successful publication for CA\n",
+        );
+        res.push_str("# TYPE krill_cas_repo_last_success gauge\n");
+        for (ca, status) in repo_status.iter() {
+            if let Some(last_success) = status.last_success() {
+                res.push_str(&format!(
+                    "krill_cas_repo_last_success{{ca=\"{}\"}} {}\n",
+                    ca, last_success
+                ));
+            }
+        }
+
+        res.push_str("\n");
+        res.push_str(
+            "# HELP krill_cas_repo_failures number of consecutive failed publication attempts for CA\n",
+        );
+        res.push_str("# TYPE krill_cas_repo_failures gauge\n");
+        for (ca, status) in repo_status.iter() {
+            res.push_str(&format!(
+                "krill_cas_repo_failures{{ca=\"{}\"}} {}\n",
+                ca,
+                status.failures()
+            ));
+        }
+
+        res.push_str("\n");
+        res.push_str(
+            "# HELP krill_cas_repo_pending number of objects pending publication for CA\n",
+        );
+        res.push_str("# TYPE krill_cas_repo_pending gauge\n");
+        for (ca, status) in repo_status.iter() {
+            res.push_str(&format!(
+                "krill_cas_repo_pending{{ca=\"{}\"}} {}\n",
+                ca,
+                status.pending()
+            ));
+        }
+
         // Aggregate ROA vs BGP stats per status
         let mut all_bgp_stats = AllBgpStats {
             announcements_valid: HashMap::new(),
@@ -437,6 +499,103 @@ pub async fn ta_cer(req: Request) -> RoutingResult {
     }
 }
 
+//------------ Login (OpenID Connect) -----------------------------------------
+
+/// Handles logging in to the UI (and API) through an OpenID Connect provider
+/// using the authorization code flow. Users are sent to '/auth/login', and
+/// are sent back to '/auth/callback' by the provider. If the login succeeds
+/// a session is started and its id is stored in a cookie.
+async fn login(req: Request) -> RoutingResult {
+    if !req.path().full().starts_with("/auth/") {
+        Err(req) // Not for us
+    } else if !req.is_get() {
+        render_unknown_method()
+    } else {
+        match req.path().full() {
+            "/auth/login" => login_start(req).await,
+            "/auth/callback" => login_callback(req).await,
+            "/auth/logout" => logout(req).await,
+            _ => render_unknown_method(),
+        }
+    }
+}
+
+fn cookie(name: &str, value: &str, path: &str, max_age: i64, same_site: &str) -> String {
+    format!(
+        "{}={}; Path={}; Max-Age={}; HttpOnly; Secure; SameSite={}",
+        name, value, path, max_age, same_site
+    )
+}
+
+async fn login_start(req: Request) -> RoutingResult {
+    let client = match req.state().read().await.oidc_client() {
+        Ok(client) => client,
+        Err(e) => return render_error(e),
+    };
+    let meta = match client.metadata().await {
+        Ok(meta) => meta,
+        Err(e) => return render_error(e),
+    };
+
+    let (state, nonce) = req.state().write().await.login_start();
+
+    match client.authorization_uri(&meta, &state, &nonce) {
+        // The state cookie must be sent along when the provider redirects
+        // the user back, so it cannot use SameSite=Strict.
+        Ok(uri) => Ok(HttpResponse::redirect(&uri).with_cookie(&cookie(
+            LOGIN_STATE_COOKIE,
+            &state,
+            "/auth/",
+            600,
+            "Lax",
+        ))),
+        Err(e) => render_error(e),
+    }
+}
+
+async fn login_callback(req: Request) -> RoutingResult {
+    let (code, state) = match (req.query_param("code"), req.query_param("state")) {
+        (Some(code), Some(state)) => (code, state),
+        _ => {
+            let reason = req
+                .query_param("error")
+                .unwrap_or_else(|| "missing code or state".to_string());
+            return render_error(Error::login_failed(reason));
+        }
+    };
+    if req.cookie(LOGIN_STATE_COOKIE).as_ref() != Some(&state) {
+        return render_error(Error::login_failed("login was not started by this browser"));
+    }
+
+    let server = req.state().clone();
+    let client = match server.read().await.oidc_client() {
+        Ok(client) => client,
+        Err(e) => return render_error(e),
+    };
+    let nonce = match server.write().await.login_nonce(&state) {
+        Ok(nonce) => nonce,
+        Err(e) => return render_error(e),
+    };
+    let actor = match client.login(&code, &nonce).await {
+        Ok(actor) => actor,
+        Err(e) => return render_error(e),
+    };
+
+    let ttl = client.session_ttl();
+    let id = server.write().await.login_session(actor, ttl);
+
+    Ok(HttpResponse::redirect("/")
+        .with_cookie(&cookie(SESSION_COOKIE, &id, "/", ttl, "Strict"))
+        .with_cookie(&cookie(LOGIN_STATE_COOKIE, "", "/auth/", 0, "Lax")))
+}
+
+async fn logout(req: Request) -> RoutingResult {
+    if let Some(id) = req.cookie(SESSION_COOKIE) {
+        req.state().write().await.logout(&id);
+    }
+    Ok(HttpResponse::redirect("/").with_cookie(&cookie(SESSION_COOKIE, "", "/", 0, "Strict")))
+}
+
 //------------ Provisioning (RFC6492) ----------------------------------------
 
 /// Process an RFC 6492 request
@@ -479,12 +638,18 @@ async fn stats(req: Request) -> RoutingResult {
 }
 
 /// Maps the API methods
-async fn api(req: Request) -> RoutingResult {
+async fn api(mut req: Request) -> RoutingResult {
     if !req.path().full().starts_with("/api/v1") {
         Err(req) // Not for us
     } else {
         // Make sure access is allowed
-        if !req.is_authorized().await {
+        if !req.authenticate().await {
+            return Ok(HttpResponse::forbidden());
+        }
+
+        // Make sure the actor has the role needed for this request
+        let permission = api_permission(&req);
+        if !req.actor().is_allowed(&permission) {
             return Ok(HttpResponse::forbidden());
         }
 
@@ -494,14 +659,55 @@ async fn api(req: Request) -> RoutingResult {
 
         match path.next() {
             Some("authorized") => api_authorized(req),
+            Some("backup") => api_backup(req).await,
             Some("bulk") => api_bulk(req, &mut path).await,
             Some("cas") => api_cas(req, &mut path).await,
+            Some("events") => api_events(req).await,
             Some("publishers") => api_publishers(req, &mut path).await,
+            Some("scheduler") => api_scheduler(req, &mut path).await,
+            Some("tokens") => api_tokens(req, &mut path).await,
+            Some("webhooks") => api_webhooks(req).await,
             _ => render_unknown_method(),
         }
     }
 }
 
+/// Returns the permission needed for an API request. Requests concerning a
+/// specific CA need a permission for that CA, so that tokens bound to some
+/// CAs cannot access others. Reading anything else is allowed for all roles
+/// except those bound to CAs, and all other changes require an admin.
+fn api_permission(req: &Request) -> Permission {
+    let mut path = req.path().clone();
+    path.next(); // gets 'v1' and drops it.
+
+    match path.next() {
+        Some("authorized") => Permission::Authenticated,
+        // A backup includes all keys, so only an admin may get it
+        Some("backup") => Permission::Admin,
+        Some("cas") => match path.path_arg() {
+            Some(ca) => {
+                if req.is_get() {
+                    Permission::CaRead(ca)
+                } else if path.next() == Some("routes") && path.next().is_none() {
+                    Permission::RoutesUpdate(ca)
+                } else {
+                    Permission::CaUpdate(ca)
+                }
+            }
+            // The list of CAs is filtered for the actor
+            None if req.is_get() => Permission::Authenticated,
+            None => Permission::Admin,
+        },
+        // Events for a single CA may be followed by those who can read it
+        Some("events") => match req.query_param("ca").map(|ca| Handle::from_str(&ca)) {
+            Some(Ok(ca)) => Permission::CaRead(ca),
+            _ => Permission::Read,
+        },
+        _ if req.is_get() => Permission::Read,
+        _ => Permission::Admin,
+    }
+}
+
 fn api_authorized(req: Request) -> RoutingResult {
     match *req.method() {
         Method::GET => render_ok(),
@@ -509,12 +715,96 @@ fn api_authorized(req: Request) -> RoutingResult {
     }
 }
 
+async fn api_backup(req: Request) -> RoutingResult {
+    match *req.method() {
+        Method::GET => match req.state().read().await.backup() {
+            Ok(archive) => match HttpResponse::tar_file(archive) {
+                Ok(response) => Ok(response),
+                Err(e) => render_error(Error::IoError(e)),
+            },
+            Err(e) => render_error(e),
+        },
+        _ => render_unknown_method(),
+    }
+}
+
+async fn api_scheduler(req: Request, path: &mut RequestPath) -> RoutingResult {
+    match *req.method() {
+        Method::GET if path.next().is_none() => {
+            render_json(req.state().read().await.scheduler_status())
+        }
+        Method::POST => match (path.path_arg::<SchedulerJob>(), path.next()) {
+            (Some(job), Some("run")) => {
+                req.state().read().await.scheduler_trigger(job);
+                render_ok()
+            }
+            _ => render_unknown_method(),
+        },
+        _ => render_unknown_method(),
+    }
+}
+
+async fn api_webhooks(req: Request) -> RoutingResult {
+    match *req.method() {
+        Method::GET => render_json_res(req.state().read().await.webhooks()),
+        _ => render_unknown_method(),
+    }
+}
+
+/// Streams CA and repository events as server-sent events. Events can be
+/// filtered by CA handle and event type, and when following a CA a client
+/// can resume from a version, or from the Last-Event-ID it saw.
+async fn api_events(req: Request) -> RoutingResult {
+    match *req.method() {
+        Method::GET => {
+            let ca = match req.query_param("ca") {
+                Some(ca) => match Handle::from_str(&ca) {
+                    Ok(ca) => Some(ca),
+                    Err(_) => return render_error(Error::ApiInvalidHandle),
+                },
+                None => None,
+            };
+
+            let types = req.query_param("type").map(|types| {
+                types
+                    .split(',')
+                    .map(|t| t.trim().to_string())
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            });
+
+            let from = match req.query_param("from") {
+                Some(from) => match u64::from_str(&from) {
+                    Ok(from) => Some(from),
+                    Err(_) => {
+                        let msg = format!("'from' must be a version, got: {}", from);
+                        return render_error(Error::ApiInvalidQuery(msg));
+                    }
+                },
+                None if ca.is_some() => req
+                    .header("Last-Event-ID")
+                    .and_then(|id| u64::from_str(id.trim()).ok())
+                    .map(|last| last + 1),
+                None => None,
+            };
+
+            let filter = EventFilter::new(ca, types);
+            match req.state().read().await.event_stream(filter, from) {
+                Ok(events) => Ok(HttpResponse::event_stream(events.map(|e| e.to_sse()))),
+                Err(e) => render_error(e),
+            }
+        }
+        _ => render_unknown_method(),
+    }
+}
+
 async fn api_bulk(req: Request, path: &mut RequestPath) -> RoutingResult {
     match path.full() {
         "/api/v1/bulk/cas/issues" => all_ca_issues(req).await,
         "/api/v1/bulk/cas/sync/parent" => refresh_all(req).await,
         "/api/v1/bulk/cas/sync/repo" => resync_all(req).await,
         "/api/v1/bulk/cas/publish" => republish_all(req).await,
+        "/api/v1/bulk/cas/archive" => archive_history(req).await,
         _ => render_unknown_method(),
     }
 }
@@ -535,6 +825,9 @@ async fn api_cas(req: Request, path: &mut RequestPath) -> RoutingResult {
             Some("parents-xml") => ca_add_parent_xml(req, path, ca).await,
             Some("repo") => api_ca_repo(req, path, ca).await,
             Some("routes") => api_ca_routes(req, path, ca).await,
+            Some("aspas") => api_ca_aspas(req, path, ca).await,
+            Some("bgpsec") => api_ca_bgpsec(req, path, ca).await,
+            Some("ghostbuster") => api_ca_ghostbuster(req, ca).await,
             _ => render_unknown_method(),
         },
         None => match *req.method() {
@@ -601,6 +894,43 @@ async fn api_ca_routes(req: Request, path: &mut RequestPath, ca: Handle) -> Rout
     }
 }
 
+async fn api_ca_aspas(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
+    match path.next() {
+        None => match *req.method() {
+            Method::GET => ca_aspas_show(req, ca).await,
+            Method::POST => ca_aspas_update(req, ca).await,
+            _ => render_unknown_method(),
+        },
+        _ => render_unknown_method(),
+    }
+}
+
+async fn api_ca_bgpsec(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
+    match path.next() {
+        None => match *req.method() {
+            Method::GET => ca_router_certs_show(req, ca).await,
+            Method::POST => ca_router_certify(req, ca).await,
+            _ => render_unknown_method(),
+        },
+        Some(key) => match *req.method() {
+            Method::DELETE => match KeyIdentifier::from_str(key) {
+                Ok(key) => ca_router_revoke(req, ca, key).await,
+                Err(_) => render_unknown_resource(),
+            },
+            _ => render_unknown_method(),
+        },
+    }
+}
+
+async fn api_ca_ghostbuster(req: Request, ca: Handle) -> RoutingResult {
+    match *req.method() {
+        Method::GET => ca_ghostbuster_show(req, ca).await,
+        Method::POST => ca_ghostbuster_update(req, ca).await,
+        Method::DELETE => ca_ghostbuster_remove(req, ca).await,
+        _ => render_unknown_method(),
+    }
+}
+
 async fn api_publishers(req: Request, path: &mut RequestPath) -> RoutingResult {
     match *req.method() {
         Method::GET => match path.path_arg() {
@@ -615,6 +945,7 @@ async fn api_publishers(req: Request, path: &mut RequestPath) -> RoutingResult {
         },
         Method::POST => match path.next() {
             None => add_pbl(req).await,
+            Some("session_reset") => session_reset(req).await,
             _ => render_unknown_method(),
         },
         Method::DELETE => match path.path_arg() {
@@ -639,6 +970,11 @@ pub async fn stale_publishers(req: Request, seconds: Option<&str>) -> RoutingRes
     }
 }
 
+/// Starts a new RRDP session, and returns what relying parties will see.
+async fn session_reset(req: Request) -> RoutingResult {
+    render_json_res(req.state().write().await.repo_session_reset())
+}
+
 /// Returns a json structure with all publishers in it.
 pub async fn list_pbl(req: Request) -> RoutingResult {
     render_json_res(
@@ -672,6 +1008,39 @@ pub async fn show_pbl(req: Request, publisher: Handle) -> RoutingResult {
     render_json_res(req.state().read().await.get_publisher(&publisher))
 }
 
+//------------ Admin: API Tokens ---------------------------------------------
+
+async fn api_tokens(req: Request, path: &mut RequestPath) -> RoutingResult {
+    match *req.method() {
+        Method::GET => match path.next() {
+            None => render_json(req.state().read().await.ca_tokens()),
+            _ => render_unknown_method(),
+        },
+        Method::POST => match path.next() {
+            None => ca_token_add(req).await,
+            _ => render_unknown_method(),
+        },
+        Method::DELETE => match path.next() {
+            Some(name) => {
+                let name = name.to_string();
+                render_empty_res(req.state().write().await.ca_token_remove(&name))
+            }
+            None => render_unknown_method(),
+        },
+        _ => render_unknown_method(),
+    }
+}
+
+/// Creates a new API token for one or more CAs, and returns it including
+/// the secret. The secret cannot be retrieved later.
+async fn ca_token_add(req: Request) -> RoutingResult {
+    let server = req.state().clone();
+    match req.json().await {
+        Ok(token_req) => render_json_res(server.write().await.ca_token_add(token_req)),
+        Err(e) => render_error(e),
+    }
+}
+
 //------------ repository_response ---------------------------------------------
 
 pub async fn repository_response_xml(req: Request, publisher: Handle) -> RoutingResult {
@@ -697,24 +1066,32 @@ async fn repository_response(
 
 async fn ca_add_child(req: Request, parent: ParentHandle) -> RoutingResult {
     let server = req.state().clone();
+    let actor = req.actor();
     match req.json().await {
-        Ok(child_req) => render_json_res(server.read().await.ca_add_child(&parent, child_req)),
+        Ok(child_req) => {
+            render_json_res(server.read().await.ca_add_child(&parent, child_req, &actor))
+        }
         Err(e) => render_error(e),
     }
 }
 
 async fn ca_child_update(req: Request, ca: Handle, child: ChildHandle) -> RoutingResult {
     let server = req.state().clone();
+    let actor = req.actor();
     match req.json().await {
-        Ok(child_req) => {
-            render_empty_res(server.read().await.ca_child_update(&ca, child, child_req))
-        }
+        Ok(child_req) => render_empty_res(
+            server
+                .read()
+                .await
+                .ca_child_update(&ca, child, child_req, &actor),
+        ),
         Err(e) => render_error(e),
     }
 }
 
 async fn ca_child_remove(req: Request, ca: Handle, child: ChildHandle) -> RoutingResult {
-    render_empty_res(req.state().read().await.ca_child_remove(&ca, child))
+    let actor = req.actor();
+    render_empty_res(req.state().read().await.ca_child_remove(&ca, child, &actor))
 }
 
 async fn ca_child_show(req: Request, ca: Handle, child: ChildHandle) -> RoutingResult {
@@ -769,7 +1146,8 @@ async fn ca_issues(req: Request, ca: Handle) -> RoutingResult {
 }
 
 async fn cas(req: Request) -> RoutingResult {
-    render_json(req.state().read().await.cas())
+    let actor = req.actor();
+    render_json(req.state().read().await.cas_for(&actor))
 }
 
 pub async fn ca_init(req: Request) -> RoutingResult {
@@ -783,7 +1161,10 @@ pub async fn ca_init(req: Request) -> RoutingResult {
 
 async fn ca_regenerate_id(req: Request, handle: Handle) -> RoutingResult {
     match *req.method() {
-        Method::POST => render_empty_res(req.state().read().await.ca_update_id(handle)),
+        Method::POST => {
+            let actor = req.actor();
+            render_empty_res(req.state().read().await.ca_update_id(handle, &actor))
+        }
         _ => render_unknown_method(),
     }
 }
@@ -959,28 +1340,34 @@ fn extract_repository_update(handle: &Handle, bytes: Bytes) -> Result<Repository
 
 pub async fn ca_repo_update(req: Request, handle: Handle) -> RoutingResult {
     let server = req.state().clone();
+    let actor = req.actor();
 
     match req
         .api_bytes()
         .await
         .map(|bytes| extract_repository_update(&handle, bytes))
     {
-        Ok(Ok(update)) => {
-            render_empty_res(server.read().await.ca_update_repo(handle, update).await)
-        }
+        Ok(Ok(update)) => render_empty_res(
+            server
+                .read()
+                .await
+                .ca_update_repo(handle, update, &actor)
+                .await,
+        ),
         Ok(Err(e)) | Err(e) => render_error(e),
     }
 }
 
 async fn ca_add_parent(req: Request, ca: Handle) -> RoutingResult {
     let server = req.state().clone();
+    let actor = req.actor();
 
     let parent_req = match req.json().await {
         Ok(req) => req,
         Err(e) => return render_error(e),
     };
 
-    match ca_parent_add(server, ca, parent_req).await {
+    match ca_parent_add(server, ca, parent_req, &actor).await {
         Ok(()) => render_ok(),
         Err(e) => render_error(e),
     }
@@ -988,6 +1375,7 @@ async fn ca_add_parent(req: Request, ca: Handle) -> RoutingResult {
 
 async fn ca_add_parent_xml(req: Request, path: &mut RequestPath, ca: Handle) -> RoutingResult {
     let server = req.state().clone();
+    let actor = req.actor();
 
     let parent = match path.path_arg() {
         Some(parent) => parent,
@@ -1019,15 +1407,24 @@ async fn ca_add_parent_xml(req: Request, path: &mut RequestPath, ca: Handle) ->
     };
 
     {
-        match ca_parent_add(server, ca, parent_req).await {
+        match ca_parent_add(server, ca, parent_req, &actor).await {
             Ok(()) => render_ok(),
             Err(e) => render_error(e),
         }
     }
 }
 
-async fn ca_parent_add(server: State, ca: Handle, parent_req: ParentCaReq) -> Result<(), Error> {
-    server.read().await.ca_parent_add(ca, parent_req).await
+async fn ca_parent_add(
+    server: State,
+    ca: Handle,
+    parent_req: ParentCaReq,
+    actor: &Actor,
+) -> Result<(), Error> {
+    server
+        .read()
+        .await
+        .ca_parent_add(ca, parent_req, actor)
+        .await
 }
 
 fn extract_parent_ca_contact(ca: &Handle, bytes: Bytes) -> Result<ParentCaContact, Error> {
@@ -1049,6 +1446,7 @@ fn extract_parent_ca_contact(ca: &Handle, bytes: Bytes) -> Result<ParentCaContac
 
 async fn ca_update_parent(req: Request, ca: Handle, parent: ParentHandle) -> RoutingResult {
     let server = req.state().clone();
+    let actor = req.actor();
 
     let bytes = match req.api_bytes().await {
         Ok(bytes) => bytes,
@@ -1060,7 +1458,7 @@ async fn ca_update_parent(req: Request, ca: Handle, parent: ParentHandle) -> Rou
             let res = server
                 .read()
                 .await
-                .ca_parent_update(ca, parent, contact)
+                .ca_parent_update(ca, parent, contact, &actor)
                 .await;
             render_empty_res(res)
         }
@@ -1069,26 +1467,37 @@ async fn ca_update_parent(req: Request, ca: Handle, parent: ParentHandle) -> Rou
 }
 
 async fn ca_remove_parent(req: Request, ca: Handle, parent: Handle) -> RoutingResult {
-    render_empty_res(req.state().read().await.ca_parent_remove(ca, parent))
+    let actor = req.actor();
+    render_empty_res(
+        req.state()
+            .read()
+            .await
+            .ca_parent_remove(ca, parent, &actor),
+    )
 }
 
 /// Force a key roll for a CA, i.e. use a max key age of 0 seconds.
 async fn ca_kr_init(req: Request, handle: Handle) -> RoutingResult {
-    render_empty_res(req.state().read().await.ca_keyroll_init(handle))
+    let actor = req.actor();
+    render_empty_res(req.state().read().await.ca_keyroll_init(handle, &actor))
 }
 
 /// Force key activation for all new keys, i.e. use a staging period of 0 seconds.
 async fn ca_kr_activate(req: Request, handle: Handle) -> RoutingResult {
-    render_empty_res(req.state().read().await.ca_keyroll_activate(handle))
+    let actor = req.actor();
+    render_empty_res(req.state().read().await.ca_keyroll_activate(handle, &actor))
 }
 
 /// Update the route authorizations for this CA
 async fn ca_routes_update(req: Request, handle: Handle) -> RoutingResult {
     let state = req.state().clone();
+    let actor = req.actor();
 
     match req.json().await {
         Err(e) => render_error(e),
-        Ok(updates) => render_empty_res(state.read().await.ca_routes_update(handle, updates)),
+        Ok(updates) => {
+            render_empty_res(state.read().await.ca_routes_update(handle, updates, &actor))
+        }
     }
 }
 
@@ -1108,6 +1517,97 @@ async fn ca_routes_analysis(req: Request, path: &mut RequestPath, handle: Handle
     }
 }
 
+/// Update the ASPA definitions for this CA
+async fn ca_aspas_update(req: Request, handle: Handle) -> RoutingResult {
+    let state = req.state().clone();
+    let actor = req.actor();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(updates) => {
+            render_empty_res(state.read().await.ca_aspas_update(handle, updates, &actor))
+        }
+    }
+}
+
+/// Show the ASPA definitions for this CA
+async fn ca_aspas_show(req: Request, handle: Handle) -> RoutingResult {
+    match req.state().read().await.ca_aspas_show(&handle) {
+        Ok(aspas) => render_json(aspas),
+        Err(_) => render_unknown_resource(),
+    }
+}
+
+/// Certify a BGPsec router key for this CA
+async fn ca_router_certify(req: Request, handle: Handle) -> RoutingResult {
+    let state = req.state().clone();
+    let actor = req.actor();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(request) => render_empty_res(
+            state
+                .read()
+                .await
+                .ca_router_certify(handle, request, &actor),
+        ),
+    }
+}
+
+/// Revoke the BGPsec router certificate for a router key
+async fn ca_router_revoke(req: Request, handle: Handle, key: KeyIdentifier) -> RoutingResult {
+    let actor = req.actor();
+    render_empty_res(
+        req.state()
+            .read()
+            .await
+            .ca_router_revoke(handle, key, &actor),
+    )
+}
+
+/// Show the BGPsec router certificates issued by this CA
+async fn ca_router_certs_show(req: Request, handle: Handle) -> RoutingResult {
+    match req.state().read().await.ca_router_certs_show(&handle) {
+        Ok(certs) => render_json(certs),
+        Err(_) => render_unknown_resource(),
+    }
+}
+
+/// Set the Ghostbusters vCard for this CA
+async fn ca_ghostbuster_update(req: Request, handle: Handle) -> RoutingResult {
+    let state = req.state().clone();
+    let actor = req.actor();
+
+    match req.json().await {
+        Err(e) => render_error(e),
+        Ok(record) => render_empty_res(
+            state
+                .read()
+                .await
+                .ca_ghostbuster_update(handle, record, &actor),
+        ),
+    }
+}
+
+/// Remove the Ghostbusters vCard for this CA
+async fn ca_ghostbuster_remove(req: Request, handle: Handle) -> RoutingResult {
+    let actor = req.actor();
+    render_empty_res(
+        req.state()
+            .read()
+            .await
+            .ca_ghostbuster_remove(handle, &actor),
+    )
+}
+
+/// Show the Ghostbusters vCard for this CA, if any
+async fn ca_ghostbuster_show(req: Request, handle: Handle) -> RoutingResult {
+    match req.state().read().await.ca_ghostbuster_show(&handle) {
+        Ok(record) => render_json(record),
+        Err(_) => render_unknown_resource(),
+    }
+}
+
 //------------ Admin: Force republish ----------------------------------------
 
 async fn republish_all(req: Request) -> RoutingResult {
@@ -1117,6 +1617,13 @@ async fn republish_all(req: Request) -> RoutingResult {
     }
 }
 
+async fn archive_history(req: Request) -> RoutingResult {
+    match *req.method() {
+        Method::POST => render_json_res(req.state().read().await.archive_history()),
+        _ => render_unknown_method(),
+    }
+}
+
 async fn resync_all(req: Request) -> RoutingResult {
     match *req.method() {
         Method::POST => render_empty_res(req.state().read().await.resync_all().await),
@@ -1138,23 +1645,15 @@ async fn rrdp(req: Request) -> RoutingResult {
     if !req.path().full().starts_with("/rrdp/") {
         Err(req) // Not for us
     } else {
-        let mut full_path: PathBuf = req.state.read().await.rrdp_base_path();
-        let (_, path) = req.path.remaining().split_at(1);
-        let cache_seconds = if path.ends_with("notification.xml") {
-            60
-        } else {
-            86400
+        let (mut full_path, files) = {
+            let server = req.state.read().await;
+            (server.rrdp_base_path(), server.rrdp_files())
         };
+        let (_, path) = req.path.remaining().split_at(1);
         full_path.push(path);
 
-        match File::open(full_path) {
-            Ok(mut file) => {
-                use std::io::Read;
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer).unwrap();
-
-                Ok(HttpResponse::xml_with_cache(buffer, cache_seconds))
-            }
+        match files.get(full_path, req.headers()).await {
+            Ok(file) => Ok(file.response(req.headers())),
             _ => Ok(HttpResponse::not_found()),
         }
     }
@@ -1,36 +1,48 @@
 //! An RPKI publication protocol server.
 use std::collections::HashMap;
+use std::fs;
+use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
 use bytes::Bytes;
-use chrono::Duration;
+use chrono::{Duration, TimeZone, Utc};
+use futures::{future, stream, Stream, StreamExt};
 
 use rpki::cert::Cert;
+use rpki::crypto::KeyIdentifier;
 use rpki::uri;
 use rpki::x509::Time;
 
 use crate::commons::api::{
-    AddChildRequest, AllCertAuthIssues, CaCommandDetails, CaRepoDetails, CertAuthInfo,
-    CertAuthInit, CertAuthIssues, CertAuthList, CertAuthStats, ChildCaInfo, ChildHandle,
-    CommandHistory, CommandHistoryCriteria, CurrentRepoState, Handle, ListReply, ParentCaContact,
-    ParentCaReq, ParentHandle, PublishDelta, PublisherDetails, PublisherHandle, RepoInfo,
-    RepositoryContact, RepositoryUpdate, RoaDefinition, RoaDefinitionUpdates, ServerInfo,
-    TaCertDetails, UpdateChildRequest,
+    AddChildRequest, AllCertAuthIssues, AspaDefinitionList, AspaDefinitionUpdates,
+    CaCommandDetails, CaRepoDetails, CaToken, CaTokenList, CaTokenRequest, CertAuthInfo,
+    CertAuthInit, CertAuthIssues, CertAuthList, CertAuthStats, CertAuthSummary, ChildCaInfo,
+    ChildHandle, CommandHistory, CommandHistoryCriteria, CurrentRepoState, GhostbusterRecord,
+    Handle, HistoryArchiveReport, ListReply, ParentCaContact, ParentCaReq, ParentHandle,
+    PublishDelta, PublisherDetails, PublisherHandle, RepoInfo, RepoStatus, RepositoryContact,
+    RepositoryUpdate, RoaDefinition, RoaDefinitionUpdates, RouterCertList, RouterCertRequest,
+    SchedulerJob, SchedulerStatus, ServerInfo, TaCertDetails, Token, UpdateChildRequest,
 };
 use crate::commons::bgp::{BgpAnalyser, BgpAnalysisReport};
 use crate::commons::error::Error;
-use crate::commons::eventsourcing::CommandKey;
+use crate::commons::eventsourcing::{CommandKey, Event};
 use crate::commons::remote::rfc8183;
+use crate::commons::util::signer::KrillSigner;
 use crate::commons::util::softsigner::OpenSslSigner;
 use crate::commons::{KrillEmptyResult, KrillResult};
 use crate::constants::*;
-use crate::daemon::auth::{Auth, Authorizer};
+use crate::daemon::auth::oidc::OidcClient;
+use crate::daemon::auth::{Actor, Auth, Authorizer};
+use crate::daemon::backup::{self, Backup};
 use crate::daemon::ca::{self, ta_handle};
 use crate::daemon::config::Config;
+use crate::daemon::eventstream::{EventFilter, EventStream, StreamEvent};
+use crate::daemon::http::rrdp::RrdpFiles;
 use crate::daemon::mq::EventQueueListener;
 use crate::daemon::scheduler::Scheduler;
-use crate::pubd::{PubServer, RepoStats};
+use crate::daemon::webhooks::{WebhookQueue, WebhookStatus};
+use crate::pubd::{PubServer, RepoStats, SessionReset};
 use crate::publish::CaPublisher;
 
 //------------ KrillServer ---------------------------------------------------
@@ -47,17 +59,28 @@ pub struct KrillServer {
     // Component responsible for API authorization checks
     authorizer: Authorizer,
 
+    // Used to generate random secrets for new API tokens
+    signer: Arc<RwLock<OpenSslSigner>>,
+
+    // Client for the OpenID Connect provider, if login is configured
+    oidc: Option<OidcClient>,
+
     // Publication server, with configured publishers
     pubserver: Option<Arc<PubServer>>,
 
     // Handles the internal TA and/or CAs
-    caserver: Arc<ca::CaServer<OpenSslSigner>>,
+    caserver: Arc<ca::CaServer<KrillSigner>>,
 
     // Handles the internal TA and/or CAs
     bgp_analyser: Arc<BgpAnalyser>,
 
+    // Passes CA and repository events on to API clients
+    event_stream: Arc<EventStream>,
+
+    // Sends notifications about important CA events to webhook targets
+    webhooks: Arc<WebhookQueue>,
+
     // Responsible for background tasks, e.g. re-publishing
-    #[allow(dead_code)] // just need to keep this in scope
     scheduler: Scheduler,
 
     // Time this server was started
@@ -65,6 +88,15 @@ pub struct KrillServer {
 
     // Global size constraints on things which can be posted
     post_limits: PostLimits,
+
+    // The RRDP snapshots and deltas served so far
+    rrdp_files: Arc<RrdpFiles>,
+
+    // Republish commands older than this are moved to the history archive
+    archive_history_days: u32,
+
+    // Over-claiming child certificates are shrunk after this grace period
+    child_shrink_grace_hours: u32,
 }
 
 pub struct PostLimits {
@@ -103,7 +135,7 @@ impl KrillServer {
         let service_uri = config.service_uri();
         let rrdp_base_uri = &config.rrdp_service_uri();
         let token = &config.auth_token;
-        let ca_refresh_rate = config.ca_refresh;
+        let key_encryption = config.key_encryption.as_ref();
 
         info!("Starting {} v{}", KRILL_SERVER_APP, KRILL_VERSION);
         info!("{} uses service uri: {}", KRILL_SERVER_APP, service_uri);
@@ -111,10 +143,22 @@ impl KrillServer {
         let mut repo_dir = work_dir.clone();
         repo_dir.push("repo");
 
-        let signer = OpenSslSigner::build(work_dir)?;
+        // Remove backups which were not sent because Krill stopped
+        let _ = fs::remove_dir_all(work_dir.join(TMP_DIR));
+
+        // The soft signer is used for the ID key of the embedded repository
+        // and for random secrets. CA keys use the configured signer.
+        let signer = OpenSslSigner::build_with_encryption(work_dir, key_encryption)?;
         let signer = Arc::new(RwLock::new(signer));
 
-        let authorizer = Authorizer::new(token);
+        let authorizer = Authorizer::build(token, &config.api_users, work_dir)?;
+        let oidc = config.auth_openidconnect.as_ref().map(|oidc| {
+            let redirect_uri = format!("{}auth/callback", service_uri);
+            OidcClient::new(oidc.clone(), redirect_uri)
+        });
+
+        let event_stream = Arc::new(EventStream::default());
+        let webhooks = Arc::new(WebhookQueue::build(work_dir, config.webhooks.clone())?);
 
         let pubserver = {
             if config.repo_enabled {
@@ -123,6 +167,11 @@ impl KrillServer {
                     rrdp_base_uri.clone(),
                     work_dir,
                     config.rfc8181_log_dir.as_ref(),
+                    config.storage_backend,
+                    config.rrdp_retention(),
+                    config.rsync_dir_retain_mins,
+                    config.rrdp_mirror_dirs.clone(),
+                    event_stream.clone(),
                     signer.clone(),
                 )?)
             } else {
@@ -131,21 +180,37 @@ impl KrillServer {
                     rrdp_base_uri.clone(),
                     work_dir,
                     config.rfc8181_log_dir.as_ref(),
+                    config.storage_backend,
+                    config.rrdp_retention(),
+                    config.rsync_dir_retain_mins,
+                    config.rrdp_mirror_dirs.clone(),
+                    event_stream.clone(),
                     signer.clone(),
                 )?
             }
         };
         let pubserver: Option<Arc<PubServer>> = pubserver.map(Arc::new);
 
-        let event_queue = Arc::new(EventQueueListener::in_mem());
+        let ca_signer = KrillSigner::build(&config.signer, work_dir, key_encryption)?;
+        let ca_signer = Arc::new(RwLock::new(ca_signer));
+
+        let event_queue = Arc::new(EventQueueListener::build(work_dir)?);
         let caserver = Arc::new(ca::CaServer::build(
             work_dir,
             config.rfc8181_log_dir.as_ref(),
             config.rfc6492_log_dir.as_ref(),
             event_queue.clone(),
-            signer,
+            event_stream.clone(),
+            webhooks.clone(),
+            config.roa_aggregation(),
+            config.storage_backend,
+            ca_signer,
         )?);
 
+        // Refuse to start if CA keys are not found, e.g. because the signer
+        // was changed after CAs were created.
+        caserver.verify_signer_keys()?;
+
         if config.use_ta() {
             let ta_handle = ta_handle();
             if !caserver.has_ca(&ta_handle) {
@@ -177,6 +242,31 @@ impl KrillServer {
             }
         }
 
+        // After a backup was restored, relying parties may already have seen
+        // higher manifest and CRL numbers than the restored CAs use.
+        if let Some(numbers) = backup::restored_publication_numbers(work_dir)? {
+            let mut raised = true;
+            for (handle, number) in numbers {
+                if !caserver.has_ca(&handle) {
+                    continue;
+                }
+                info!(
+                    "Raising manifest and CRL numbers above {} for '{}'",
+                    number, handle
+                );
+                if let Err(e) = caserver.raise_publication_numbers(&handle, number) {
+                    error!(
+                        "Could not raise manifest and CRL numbers for '{}': {}",
+                        handle, e
+                    );
+                    raised = false;
+                }
+            }
+            if raised {
+                backup::remove_restored_publication_numbers(work_dir)?;
+            }
+        }
+
         let bgp_analyser = Arc::new(BgpAnalyser::new(
             config.bgp_risdumps_enabled,
             &config.bgp_risdumps_v4_uri,
@@ -188,7 +278,8 @@ impl KrillServer {
             caserver.clone(),
             pubserver.clone(),
             bgp_analyser.clone(),
-            ca_refresh_rate,
+            webhooks.clone(),
+            config,
         );
 
         let post_limits = PostLimits::new(
@@ -201,12 +292,19 @@ impl KrillServer {
             service_uri,
             work_dir: work_dir.clone(),
             authorizer,
+            signer,
+            oidc,
             pubserver,
             caserver,
             bgp_analyser,
+            event_stream,
+            webhooks,
             scheduler,
             started: Time::now(),
             post_limits,
+            rrdp_files: Arc::new(RrdpFiles::default()),
+            archive_history_days: config.archive_history_days,
+            child_shrink_grace_hours: config.child_shrink_grace_hours,
         })
     }
 
@@ -221,8 +319,82 @@ impl KrillServer {
 
 /// # Authentication and Access
 impl KrillServer {
-    pub fn is_api_allowed(&self, auth: &Auth) -> bool {
-        self.authorizer.is_api_allowed(auth)
+    /// Returns the actor for the authentication used, if it is allowed
+    /// to access the API at all.
+    pub fn actor(&self, auth: &Auth) -> Option<Actor> {
+        self.authorizer.actor(auth)
+    }
+
+    /// Returns the names and CAs of the runtime CA tokens.
+    pub fn ca_tokens(&self) -> CaTokenList {
+        self.authorizer.ca_tokens()
+    }
+
+    /// Creates a new API token which can only access the CAs in the request.
+    /// The returned value contains the secret token.
+    pub fn ca_token_add(&mut self, req: CaTokenRequest) -> KrillResult<CaToken> {
+        let (name, cas) = req.unpack();
+        for ca in &cas {
+            if !self.caserver.has_ca(ca) {
+                return Err(Error::CaUnknown(ca.clone()));
+            }
+        }
+
+        let token = {
+            let signer = self.signer.read().unwrap();
+            Token::random(signer.deref())
+        };
+
+        self.authorizer
+            .add_ca_token(CaTokenRequest::new(&name, cas), token)
+    }
+
+    /// Revokes a runtime CA token.
+    pub fn ca_token_remove(&mut self, name: &str) -> KrillEmptyResult {
+        self.authorizer.remove_ca_token(name)
+    }
+
+    fn random_secret(&self) -> String {
+        let signer = self.signer.read().unwrap();
+        Token::random(signer.deref()).to_string()
+    }
+
+    /// Returns the client for the OpenID Connect provider.
+    pub fn oidc_client(&self) -> KrillResult<OidcClient> {
+        self.oidc.clone().ok_or_else(|| Error::LoginNotConfigured)
+    }
+
+    /// Starts a login, and returns the new state and nonce for it.
+    pub fn login_start(&mut self) -> (String, String) {
+        let state = self.random_secret();
+        let nonce = self.random_secret();
+        self.authorizer
+            .sessions_mut()
+            .add_pending(state.clone(), nonce.clone());
+        (state, nonce)
+    }
+
+    /// Returns the nonce for a login in progress.
+    pub fn login_nonce(&mut self, state: &str) -> KrillResult<String> {
+        self.authorizer
+            .sessions_mut()
+            .take_pending(state)
+            .ok_or_else(|| Error::login_failed("unknown or expired login state"))
+    }
+
+    /// Starts a session for a user who logged in, and returns its id.
+    pub fn login_session(&mut self, actor: Actor, ttl: i64) -> String {
+        let id = self.random_secret();
+        info!("User '{}' logged in", actor);
+        self.authorizer
+            .sessions_mut()
+            .add_session(id.clone(), actor, ttl);
+        id
+    }
+
+    /// Ends a session.
+    pub fn logout(&mut self, id: &str) {
+        self.authorizer.sessions_mut().remove_session(id)
     }
 
     pub fn limit_api(&self) -> u64 {
@@ -251,6 +423,11 @@ impl KrillServer {
         self.get_embedded()?.repo_stats()
     }
 
+    /// Starts a new RRDP session for the embedded repository.
+    pub fn repo_session_reset(&self) -> KrillResult<SessionReset> {
+        self.get_embedded()?.reset_session()
+    }
+
     /// Returns all currently configured publishers. (excludes deactivated)
     pub fn publishers(&self) -> KrillResult<Vec<Handle>> {
         self.get_embedded()?.publishers()
@@ -283,6 +460,10 @@ impl KrillServer {
         path.push("repo/rrdp");
         path
     }
+
+    pub fn rrdp_files(&self) -> Arc<RrdpFiles> {
+        self.rrdp_files.clone()
+    }
 }
 
 /// # Manage RFC8181 clients
@@ -326,8 +507,11 @@ impl KrillServer {
         &self,
         parent: &ParentHandle,
         req: AddChildRequest,
+        actor: &Actor,
     ) -> KrillResult<ParentCaContact> {
-        let contact = self.caserver.ca_add_child(parent, req, &self.service_uri)?;
+        let contact = self
+            .caserver
+            .ca_add_child(parent, req, &self.service_uri, actor)?;
         Ok(contact)
     }
 
@@ -361,14 +545,20 @@ impl KrillServer {
         parent: &ParentHandle,
         child: ChildHandle,
         req: UpdateChildRequest,
+        actor: &Actor,
     ) -> KrillEmptyResult {
-        self.caserver.ca_child_update(parent, child, req)?;
+        self.caserver.ca_child_update(parent, child, req, actor)?;
         Ok(())
     }
 
     /// Update IdCert or resources of a child.
-    pub fn ca_child_remove(&self, handle: &Handle, child: ChildHandle) -> KrillEmptyResult {
-        self.caserver.ca_child_remove(handle, child)?;
+    pub fn ca_child_remove(
+        &self,
+        handle: &Handle,
+        child: ChildHandle,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        self.caserver.ca_child_remove(handle, child, actor)?;
         Ok(())
     }
 
@@ -392,13 +582,18 @@ impl KrillServer {
     }
 
     /// Adds a parent to a CA, will check first if the parent can be reached.
-    pub async fn ca_parent_add(&self, handle: Handle, parent: ParentCaReq) -> KrillEmptyResult {
+    pub async fn ca_parent_add(
+        &self,
+        handle: Handle,
+        parent: ParentCaReq,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
         self.ca_parent_reachable(&handle, parent.handle(), parent.contact())
             .await
             .map_err(|_| {
                 Error::CaParentAddNotResponsive(handle.clone(), parent.handle().clone())
             })?;
-        Ok(self.caserver.ca_parent_add(handle, parent)?)
+        Ok(self.caserver.ca_parent_add(handle, parent, actor)?)
     }
 
     /// Updates a parent contact for a CA
@@ -407,9 +602,12 @@ impl KrillServer {
         handle: Handle,
         parent: ParentHandle,
         contact: ParentCaContact,
+        actor: &Actor,
     ) -> KrillEmptyResult {
         self.ca_parent_reachable(&handle, &parent, &contact).await?;
-        Ok(self.caserver.ca_parent_update(handle, parent, contact)?)
+        Ok(self
+            .caserver
+            .ca_parent_update(handle, parent, contact, actor)?)
     }
 
     async fn ca_parent_reachable(
@@ -424,8 +622,13 @@ impl KrillServer {
         Ok(())
     }
 
-    pub fn ca_parent_remove(&self, handle: Handle, parent: ParentHandle) -> KrillEmptyResult {
-        Ok(self.caserver.ca_parent_remove(handle, parent)?)
+    pub fn ca_parent_remove(
+        &self,
+        handle: Handle,
+        parent: ParentHandle,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self.caserver.ca_parent_remove(handle, parent, actor)?)
     }
 }
 
@@ -455,6 +658,12 @@ impl KrillServer {
 
         res
     }
+
+    /// Returns the publication status for each CA which tried to publish.
+    pub fn cas_repo_status(&self) -> HashMap<Handle, RepoStatus> {
+        self.caserver.get_repo_statuses()
+    }
+
     pub async fn all_ca_issues(&self) -> KrillResult<AllCertAuthIssues> {
         let mut all_issues = AllCertAuthIssues::default();
         for ca in self.cas().cas() {
@@ -474,24 +683,104 @@ impl KrillServer {
             issues.add_repo_issue(msg);
         }
 
+        // Warn if the objects which were last published are about to go
+        // stale, because publication has not succeeded since.
+        if let Some(next_update) = self
+            .caserver
+            .get_repo_status(ca_handle)
+            .and_then(|status| status.next_update())
+        {
+            let warn_after = Time::now() + Duration::hours(PUBLISH_WARN_HOURS);
+            if next_update < warn_after.timestamp() {
+                let stale = Time::new(Utc.timestamp(next_update, 0)).to_rfc3339();
+                let e = Error::CaRepoPublicationStale(ca_handle.clone(), stale);
+                issues.add_publication_issue(e.to_error_response());
+            }
+        }
+
+        // Use the recorded status of parents, rather than contacting them
+        // here, so that failing parents are not retried on every call.
         let ca = self.caserver.get_ca(ca_handle)?;
+        let statuses = self.caserver.get_parent_statuses(ca_handle);
 
         for parent_handle in ca.parents() {
-            let contact = ca.parent(parent_handle).unwrap(); // parent is always known
-            if !contact.is_ta() {
-                if let Err(e) = self
-                    .ca_parent_reachable(ca_handle, parent_handle, contact)
-                    .await
-                {
-                    issues.add_parent_issue(parent_handle.clone(), e.to_error_response());
-                }
+            if let Some(error) = statuses.get(parent_handle).and_then(|s| s.last_error()) {
+                issues.add_parent_issue(parent_handle.clone(), error.clone());
             }
         }
 
+        // Warn about children which still hold certificates for resources
+        // that were taken away, until these certificates are shrunk.
+        let grace = Duration::hours(i64::from(self.child_shrink_grace_hours));
+        for (child, reduced) in ca.children_overclaiming() {
+            let shrink = (reduced + grace).to_rfc3339();
+            let e = Error::CaChildOverclaiming(ca_handle.clone(), child.clone(), shrink);
+            issues.add_child_issue(child, e.to_error_response());
+        }
+
         Ok(issues)
     }
 }
 
+/// # Event stream
+///
+impl KrillServer {
+    /// Returns the CA and repository events matching the filter, as they
+    /// are applied. If a version to resume from is given, the stream starts
+    /// with the stored events from that version for the CA in the filter.
+    pub fn event_stream(
+        &self,
+        filter: EventFilter,
+        from: Option<u64>,
+    ) -> KrillResult<impl Stream<Item = StreamEvent> + Send + Sync> {
+        // Subscribe before reading the stored events, so that no events are
+        // missed in between. Events seen twice are skipped below.
+        let live = self.event_stream.subscribe(filter.clone());
+
+        let (stored, next) = match (filter.ca(), from) {
+            (Some(ca), Some(from)) => {
+                let events = self.caserver.get_ca_events(ca, from)?;
+                let next = events.last().map(|e| e.version() + 1).unwrap_or(from);
+                let stored: Vec<StreamEvent> = events
+                    .iter()
+                    .map(StreamEvent::ca)
+                    .filter(|e| filter.matches(e))
+                    .collect();
+                (stored, next)
+            }
+            (None, Some(_)) => {
+                return Err(Error::ApiInvalidQuery("'from' requires 'ca'".to_string()));
+            }
+            _ => (vec![], 0),
+        };
+
+        let live = live.filter(move |e| future::ready(e.version() >= next));
+        Ok(stream::iter(stored).chain(live))
+    }
+}
+
+/// # Scheduler
+///
+impl KrillServer {
+    pub fn scheduler_status(&self) -> SchedulerStatus {
+        self.scheduler.status()
+    }
+
+    pub fn scheduler_trigger(&self, job: SchedulerJob) {
+        self.scheduler.trigger(job)
+    }
+}
+
+/// # Webhooks
+///
+impl KrillServer {
+    /// Returns the webhook deliveries which are waiting to be retried, and
+    /// those which failed.
+    pub fn webhooks(&self) -> KrillResult<WebhookStatus> {
+        self.webhooks.status()
+    }
+}
+
 /// # Bulk background operations CAS
 ///
 impl KrillServer {
@@ -501,6 +790,12 @@ impl KrillServer {
         Ok(())
     }
 
+    /// Moves republish commands older than the configured number of days
+    /// out of the history of all CAs, into the history archive.
+    pub fn archive_history(&self) -> KrillResult<HistoryArchiveReport> {
+        self.caserver.archive_history(self.archive_history_days)
+    }
+
     /// Re-sync all CAs with their repositories
     pub async fn resync_all(&self) -> KrillEmptyResult {
         let publisher = CaPublisher::new(self.caserver.clone(), self.pubserver.clone());
@@ -514,10 +809,13 @@ impl KrillServer {
         Ok(())
     }
 
-    /// Refresh all CAs: ask for updates and shrink as needed.
+    /// Refresh all CAs: ask for updates and shrink as needed. CAs which
+    /// could not get updates from a parent are logged, but do not keep the
+    /// other CAs from being refreshed.
     pub async fn refresh_all(&self) -> KrillEmptyResult {
         let server = self.caserver.clone();
-        server.refresh_all().await;
+        let _ = server.refresh_all(true).await;
+        server.shrink_children_all(self.child_shrink_grace_hours)?;
         Ok(())
     }
 }
@@ -529,9 +827,23 @@ impl KrillServer {
         self.caserver.ca_list()
     }
 
+    /// Returns the CAs which the given actor is allowed to see.
+    pub fn cas_for(&self, actor: &Actor) -> CertAuthList {
+        let cas: Vec<CertAuthSummary> = self
+            .cas()
+            .cas()
+            .iter()
+            .filter(|ca| actor.can_see(ca.handle()))
+            .cloned()
+            .collect();
+        CertAuthList::new(cas)
+    }
+
     /// Returns the public CA info for a CA, or NONE if the CA cannot be found.
     pub fn ca_info(&self, handle: &Handle) -> KrillResult<CertAuthInfo> {
-        self.caserver.get_ca(handle).map(|ca| ca.as_ca_info())
+        let mut info = self.caserver.get_ca(handle)?.as_ca_info();
+        info.set_parent_statuses(&self.caserver.get_parent_statuses(handle));
+        Ok(info)
     }
 
     /// Returns the parent contact for a CA and parent, or NONE if either the CA or the parent cannot be found.
@@ -583,7 +895,8 @@ impl KrillServer {
     pub fn ca_repo_details(&self, handle: &Handle) -> KrillResult<CaRepoDetails> {
         let ca = self.caserver.get_ca(handle)?;
         let contact = ca.get_repository_contact()?;
-        Ok(CaRepoDetails::new(contact.clone()))
+        let status = self.caserver.get_repo_status(handle);
+        Ok(CaRepoDetails::new(contact.clone(), status))
     }
 
     /// Returns the state of the current configured repo for a ca
@@ -598,6 +911,7 @@ impl KrillServer {
         &self,
         handle: Handle,
         update: RepositoryUpdate,
+        actor: &Actor,
     ) -> KrillEmptyResult {
         let contact = match update {
             RepositoryUpdate::Embedded => {
@@ -625,7 +939,7 @@ impl KrillServer {
             }
         };
 
-        Ok(self.caserver.update_repo(handle, contact)?)
+        Ok(self.caserver.update_repo(handle, contact, actor)?)
     }
 
     async fn repo_state(
@@ -648,20 +962,20 @@ impl KrillServer {
         }
     }
 
-    pub fn ca_update_id(&self, handle: Handle) -> KrillEmptyResult {
-        Ok(self.caserver.ca_update_id(handle)?)
+    pub fn ca_update_id(&self, handle: Handle, actor: &Actor) -> KrillEmptyResult {
+        Ok(self.caserver.ca_update_id(handle, actor)?)
     }
 
-    pub fn ca_keyroll_init(&self, handle: Handle) -> KrillEmptyResult {
+    pub fn ca_keyroll_init(&self, handle: Handle, actor: &Actor) -> KrillEmptyResult {
         Ok(self
             .caserver
-            .ca_keyroll_init(handle, Duration::seconds(0))?)
+            .ca_keyroll_init(handle, Duration::seconds(0), actor)?)
     }
 
-    pub fn ca_keyroll_activate(&self, handle: Handle) -> KrillEmptyResult {
+    pub fn ca_keyroll_activate(&self, handle: Handle, actor: &Actor) -> KrillEmptyResult {
         Ok(self
             .caserver
-            .ca_keyroll_activate(handle, Duration::seconds(0))?)
+            .ca_keyroll_activate(handle, Duration::seconds(0), actor)?)
     }
 
     pub fn rfc6492(&self, handle: Handle, msg_bytes: Bytes) -> KrillResult<Bytes> {
@@ -676,8 +990,11 @@ impl KrillServer {
         &self,
         handle: Handle,
         updates: RoaDefinitionUpdates,
+        actor: &Actor,
     ) -> KrillEmptyResult {
-        Ok(self.caserver.ca_routes_update(handle, updates.into())?)
+        Ok(self
+            .caserver
+            .ca_routes_update(handle, updates.into(), actor)?)
     }
 
     pub fn ca_routes_show(&self, handle: &Handle) -> KrillResult<Vec<RoaDefinition>> {
@@ -695,6 +1012,73 @@ impl KrillServer {
     }
 }
 
+/// # Handle ASPA requests
+///
+impl KrillServer {
+    pub fn ca_aspas_update(
+        &self,
+        handle: Handle,
+        updates: AspaDefinitionUpdates,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self.caserver.ca_aspas_update(handle, updates, actor)?)
+    }
+
+    pub fn ca_aspas_show(&self, handle: &Handle) -> KrillResult<AspaDefinitionList> {
+        let ca = self.caserver.get_ca(handle)?;
+        Ok(ca.aspa_definitions())
+    }
+}
+
+/// # BGPsec router certificates
+///
+impl KrillServer {
+    pub fn ca_router_certify(
+        &self,
+        handle: Handle,
+        request: RouterCertRequest,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self.caserver.ca_router_certify(handle, request, actor)?)
+    }
+
+    pub fn ca_router_revoke(
+        &self,
+        handle: Handle,
+        key: KeyIdentifier,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self.caserver.ca_router_revoke(handle, key, actor)?)
+    }
+
+    pub fn ca_router_certs_show(&self, handle: &Handle) -> KrillResult<RouterCertList> {
+        let ca = self.caserver.get_ca(handle)?;
+        Ok(ca.router_certs())
+    }
+}
+
+/// # Ghostbusters
+///
+impl KrillServer {
+    pub fn ca_ghostbuster_update(
+        &self,
+        handle: Handle,
+        record: GhostbusterRecord,
+        actor: &Actor,
+    ) -> KrillEmptyResult {
+        Ok(self.caserver.ca_ghostbuster_update(handle, record, actor)?)
+    }
+
+    pub fn ca_ghostbuster_remove(&self, handle: Handle, actor: &Actor) -> KrillEmptyResult {
+        Ok(self.caserver.ca_ghostbuster_remove(handle, actor)?)
+    }
+
+    pub fn ca_ghostbuster_show(&self, handle: &Handle) -> KrillResult<Option<GhostbusterRecord>> {
+        let ca = self.caserver.get_ca(handle)?;
+        Ok(ca.ghostbuster().cloned())
+    }
+}
+
 /// # Handle publication requests
 ///
 impl KrillServer {
@@ -714,4 +1098,27 @@ impl KrillServer {
     }
 }
 
+/// # Backup
+///
+impl KrillServer {
+    /// Creates a backup of the data directory, in a temporary file which
+    /// the caller must remove. Changes to CAs and the repository are blocked
+    /// until the backup is done, so that the backup is consistent. The CA
+    /// store is locked first, because CA changes can lead to publication in
+    /// the repository.
+    pub fn backup(&self) -> KrillResult<PathBuf> {
+        let _ca_lock = self.caserver.lock_for_backup();
+        let _pubd_lock = self.pubserver.as_ref().map(|p| p.lock_for_backup());
+        let numbers = self.caserver.publication_numbers()?;
+
+        let name = format!("backup-{}.tar", Time::now().timestamp_millis());
+        let target = self.work_dir.join(TMP_DIR).join(name);
+        if let Err(e) = Backup::create(&self.work_dir, numbers, &target) {
+            let _ = fs::remove_file(&target);
+            return Err(e);
+        }
+        Ok(target)
+    }
+}
+
 // Tested through integration tests
@@ -1,7 +1,10 @@
 pub mod auth;
+pub mod backup;
 pub mod ca;
 pub mod config;
+pub mod eventstream;
 pub mod http;
 pub mod krillserver;
 pub mod mq;
 pub mod scheduler;
+pub mod webhooks;
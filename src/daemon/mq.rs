@@ -2,13 +2,23 @@
 //! making them available for triggered processing, such as publishing
 //! signed material, or asking a newly added parent for resource
 //! entitlements.
+//!
+//! The queue is kept on disk, so that entries which were not yet processed
+//! when Krill stopped are processed after it starts again. Entries are only
+//! removed from the queue after they were processed, so that an entry which
+//! was being processed when Krill stopped is processed again as well.
 
 use std::collections::{HashMap, VecDeque};
 use std::fmt;
+use std::fs;
+use std::path::PathBuf;
 use std::sync::RwLock;
 
 use crate::commons::api::{Handle, ParentHandle, ResourceClassName, RevocationRequest};
 use crate::commons::eventsourcing::{self, Event};
+use crate::commons::util::file;
+use crate::commons::KrillResult;
+use crate::constants::EVENT_QUEUE_DIR;
 use crate::daemon::ca::{CertAuth, Evt, EvtDet, Signer};
 use rpki::x509::Time;
 
@@ -16,7 +26,7 @@ use rpki::x509::Time;
 
 /// This type contains all the events of interest for a KrillServer, with
 /// the details needed for triggered processing.
-#[derive(Clone, Debug, Display, Eq, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Display, Eq, PartialEq, Serialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum QueueEvent {
     #[display(fmt = "delta for '{}' version '{}'", _0, _1)]
@@ -54,22 +64,67 @@ pub enum QueueEvent {
     ReschedulePublish(Handle, Time),
 }
 
+impl QueueEvent {
+    /// Returns whether processing this event would have the same effect as
+    /// processing the other event, so that only one needs to be queued.
+    /// Versions and times are ignored, because the processing always uses
+    /// the current state of the CA.
+    pub fn is_equivalent(&self, other: &QueueEvent) -> bool {
+        match (self, other) {
+            (QueueEvent::Delta(h1, _), QueueEvent::Delta(h2, _))
+            | (QueueEvent::RepositoryConfigured(h1, _), QueueEvent::RepositoryConfigured(h2, _))
+            | (QueueEvent::RequestsPending(h1, _), QueueEvent::RequestsPending(h2, _))
+            | (QueueEvent::CleanOldRepo(h1, _), QueueEvent::CleanOldRepo(h2, _))
+            | (QueueEvent::ReschedulePublish(h1, _), QueueEvent::ReschedulePublish(h2, _)) => {
+                h1 == h2
+            }
+            (QueueEvent::ParentAdded(h1, _, p1), QueueEvent::ParentAdded(h2, _, p2)) => {
+                h1 == h2 && p1 == p2
+            }
+            (
+                QueueEvent::ResourceClassRemoved(h1, _, p1, r1),
+                QueueEvent::ResourceClassRemoved(h2, _, p2, r2),
+            ) => h1 == h2 && p1 == p2 && r1 == r2,
+            (
+                QueueEvent::UnexpectedKey(h1, _, rcn1, r1),
+                QueueEvent::UnexpectedKey(h2, _, rcn2, r2),
+            ) => h1 == h2 && rcn1 == rcn2 && r1 == r2,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct EventQueueListener {
     q: RwLock<Box<dyn EventQueueStore>>,
 }
 
 impl EventQueueListener {
-    pub fn in_mem() -> Self {
-        EventQueueListener {
-            q: RwLock::new(Box::new(MemoryEventQueue::new())),
-        }
+    /// Builds a listener with a queue kept under the work_dir. Entries left
+    /// from a previous run are kept, so that they are processed first.
+    pub fn build(work_dir: &PathBuf) -> KrillResult<Self> {
+        Ok(EventQueueListener {
+            q: RwLock::new(Box::new(DiskEventQueue::build(work_dir)?)),
+        })
     }
 }
 
 impl EventQueueListener {
-    pub fn pop(&self) -> Option<QueueEvent> {
-        self.q.write().unwrap().pop()
+    /// Takes the next event for processing. The event stays in the queue
+    /// until it is removed after processing, or released to be taken again.
+    pub fn take_next(&self) -> Option<QueueEvent> {
+        self.q.write().unwrap().take_next()
+    }
+
+    /// Removes a processed event, taken earlier, from the queue.
+    pub fn remove(&self, evt: &QueueEvent) {
+        self.q.write().unwrap().remove(evt)
+    }
+
+    /// Releases all events which were taken, but not removed, so that they
+    /// can be taken again.
+    pub fn release(&self) {
+        self.q.write().unwrap().release()
     }
 
     pub fn push_back(&self, evt: QueueEvent) {
@@ -148,44 +203,217 @@ impl<S: Signer> eventsourcing::EventListener<CertAuth<S>> for EventQueueListener
 //------------ EventQueue ----------------------------------------------------
 
 /// This trait provides the public contract for an EventQueue used by the
-/// KrillServer. First implementation can be a simple in memory thing, but
-/// we will need someting more robust, and possibly multi-master later.
+/// KrillServer. The current implementation is kept on disk, but we may
+/// need something more robust, and possibly multi-master later.
 ///
 /// The EventQueue should implement Eventlistener
 trait EventQueueStore: fmt::Debug {
-    fn pop(&self) -> Option<QueueEvent>;
+    fn take_next(&self) -> Option<QueueEvent>;
+    fn remove(&self, evt: &QueueEvent);
+    fn release(&self);
     fn push_back(&self, evt: QueueEvent);
 }
 
-//------------ MemoryEventQueue ----------------------------------------------
+//------------ DiskEventQueue ------------------------------------------------
+
+/// Event queue which is saved to disk whenever it changes. Equivalent
+/// entries are only queued once.
+#[derive(Debug)]
+struct DiskEventQueue {
+    path: PathBuf,
+    q: RwLock<QueuedEvents>,
+}
 
-/// In memory event queue implementation.
+/// The queued events. The first 'taken' events were handed out for
+/// processing, but were not yet removed.
 #[derive(Debug)]
-struct MemoryEventQueue {
-    q: RwLock<VecDeque<QueueEvent>>,
+struct QueuedEvents {
+    events: VecDeque<QueueEvent>,
+    taken: usize,
 }
 
-impl MemoryEventQueue {
-    pub fn new() -> Self {
-        MemoryEventQueue {
-            q: RwLock::new(VecDeque::new()),
+impl DiskEventQueue {
+    const FILE: &'static str = "queue.json";
+
+    fn build(work_dir: &PathBuf) -> KrillResult<Self> {
+        let mut path = work_dir.clone();
+        path.push(EVENT_QUEUE_DIR);
+        fs::create_dir_all(&path)?;
+        path.push(Self::FILE);
+
+        let q: VecDeque<QueueEvent> = if path.exists() {
+            file::load_json(&path)?
+        } else {
+            VecDeque::new()
+        };
+
+        if !q.is_empty() {
+            info!("Resuming {} queued events from previous run", q.len());
+        }
+
+        Ok(DiskEventQueue {
+            path,
+            q: RwLock::new(QueuedEvents {
+                events: q,
+                taken: 0,
+            }),
+        })
+    }
+
+    fn save(&self, q: &VecDeque<QueueEvent>) {
+        let mut tmp = self.path.clone();
+        tmp.set_extension("tmp");
+
+        let res = file::save_json(q, &tmp).and_then(|_| fs::rename(&tmp, &self.path));
+        if let Err(e) = res {
+            error!(
+                "Could not save event queue to '{}': {}",
+                self.path.to_string_lossy(),
+                e
+            );
         }
     }
 }
 
-impl EventQueueStore for MemoryEventQueue {
-    fn pop(&self) -> Option<QueueEvent> {
-        let res = self.q.write().unwrap().pop_front();
+impl EventQueueStore for DiskEventQueue {
+    fn take_next(&self) -> Option<QueueEvent> {
+        let mut q = self.q.write().unwrap();
+        let res = q.events.get(q.taken).cloned();
 
         if let Some(evt) = res.as_ref() {
-            trace!("Popping evt from schedule queue: {}", evt)
+            trace!("Taking evt from schedule queue: {}", evt);
+            q.taken += 1;
         }
 
         res
     }
 
+    fn remove(&self, evt: &QueueEvent) {
+        let mut q = self.q.write().unwrap();
+        let taken = q.taken;
+        if let Some(pos) = q.events.iter().take(taken).position(|e| e == evt) {
+            trace!("Removing processed evt from schedule queue: {}", evt);
+            q.events.remove(pos);
+            q.taken -= 1;
+            self.save(&q.events);
+        }
+    }
+
+    fn release(&self) {
+        let mut q = self.q.write().unwrap();
+        let taken = q.taken;
+        let released: Vec<QueueEvent> = q.events.drain(..taken).collect();
+        q.taken = 0;
+
+        // Released events are dropped if an equivalent event was queued
+        // while they were taken.
+        let mut changed = false;
+        for evt in released.into_iter().rev() {
+            if q.events.iter().any(|queued| queued.is_equivalent(&evt)) {
+                changed = true;
+            } else {
+                q.events.push_front(evt);
+            }
+        }
+        if changed {
+            self.save(&q.events);
+        }
+    }
+
+    /// Pushes an event, unless an equivalent event is queued and was not
+    /// yet taken. Taken events may already have been partly processed,
+    /// using an earlier state.
     fn push_back(&self, evt: QueueEvent) {
-        trace!("Pushing event to schedule queue: {}", evt);
-        self.q.write().unwrap().push_back(evt);
+        let mut q = self.q.write().unwrap();
+        let taken = q.taken;
+        if q.events
+            .iter()
+            .skip(taken)
+            .any(|queued| queued.is_equivalent(&evt))
+        {
+            trace!("Event already in schedule queue: {}", evt);
+        } else {
+            trace!("Pushing event to schedule queue: {}", evt);
+            q.events.push_back(evt);
+            self.save(&q.events);
+        }
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use std::str::FromStr;
+
+    use super::*;
+
+    use crate::test;
+
+    #[test]
+    fn keep_queue_on_disk_without_duplicates() {
+        test::test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let other = Handle::from_str("other").unwrap();
+
+            let queue = EventQueueListener::build(&d).unwrap();
+            queue.push_back(QueueEvent::Delta(ca.clone(), 1));
+            queue.push_back(QueueEvent::RequestsPending(ca.clone(), 1));
+            queue.push_back(QueueEvent::Delta(ca.clone(), 2));
+            queue.push_back(QueueEvent::Delta(other.clone(), 1));
+
+            // Drained after a restart
+            let queue = EventQueueListener::build(&d).unwrap();
+            assert_eq!(Some(QueueEvent::Delta(ca.clone(), 1)), pop(&queue));
+            assert_eq!(Some(QueueEvent::RequestsPending(ca, 1)), pop(&queue));
+            assert_eq!(Some(QueueEvent::Delta(other, 1)), pop(&queue));
+            assert_eq!(None, pop(&queue));
+
+            let queue = EventQueueListener::build(&d).unwrap();
+            assert_eq!(None, pop(&queue));
+        })
+    }
+
+    fn pop(queue: &EventQueueListener) -> Option<QueueEvent> {
+        let evt = queue.take_next()?;
+        queue.remove(&evt);
+        Some(evt)
+    }
+
+    #[test]
+    fn keep_events_until_processed() {
+        test::test_under_tmp(|d| {
+            let ca = Handle::from_str("ca").unwrap();
+            let delta = QueueEvent::Delta(ca.clone(), 1);
+            let pending = QueueEvent::RequestsPending(ca.clone(), 1);
+
+            let queue = EventQueueListener::build(&d).unwrap();
+            queue.push_back(delta.clone());
+            queue.push_back(pending.clone());
+
+            // Krill stops after taking the event, before it is processed
+            assert_eq!(Some(delta.clone()), queue.take_next());
+            std::mem::drop(queue);
+
+            let queue = EventQueueListener::build(&d).unwrap();
+            assert_eq!(Some(delta.clone()), queue.take_next());
+
+            // An equivalent event queued during processing is kept
+            queue.push_back(QueueEvent::Delta(ca.clone(), 2));
+            queue.remove(&delta);
+
+            // A released event can be taken again, and stays on disk
+            assert_eq!(Some(pending.clone()), queue.take_next());
+            assert_eq!(Some(QueueEvent::Delta(ca.clone(), 2)), queue.take_next());
+            assert_eq!(None, queue.take_next());
+            queue.release();
+            std::mem::drop(queue);
+
+            let queue = EventQueueListener::build(&d).unwrap();
+            assert_eq!(Some(pending), pop(&queue));
+            assert_eq!(Some(QueueEvent::Delta(ca, 2)), pop(&queue));
+            assert_eq!(None, pop(&queue));
+        })
     }
 }
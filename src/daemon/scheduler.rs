@@ -1,19 +1,21 @@
 //! Deal with asynchronous scheduled processes, either triggered by an
 //! event that occurred, or planned (e.g. re-publishing).
 
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use clokwerk::{self, ScheduleHandle, TimeUnits};
 use tokio::runtime::Runtime;
 
 use rpki::x509::Time;
 
-use crate::commons::api::Handle;
+use crate::commons::api::{Handle, JobResult, JobStatus, SchedulerJob, SchedulerStatus};
 use crate::commons::bgp::BgpAnalyser;
-use crate::commons::util::softsigner::OpenSslSigner;
-use crate::daemon::ca::CaServer;
+use crate::daemon::ca::{CaServer, Signer};
+use crate::daemon::config::Config;
 use crate::daemon::mq::{EventQueueListener, QueueEvent};
+use crate::daemon::webhooks::WebhookQueue;
 use crate::pubd::PubServer;
 use crate::publish::CaPublisher;
 
@@ -33,43 +35,187 @@ pub struct Scheduler {
     #[allow(dead_code)] // just need to keep this in scope
     ca_refresh_sh: ScheduleHandle,
 
+    /// Responsible for moving old republish commands out of the CA history
+    /// into the history archive.
+    #[allow(dead_code)] // just need to keep this in scope
+    archive_sh: ScheduleHandle,
+
+    /// Responsible for sending (and retrying) webhook deliveries.
+    #[allow(dead_code)] // just need to keep this in scope
+    webhook_sh: ScheduleHandle,
+
     /// Responsible for refreshing announcement information
     #[allow(dead_code)] // just need to keep this in scope
     announcements_refresh_sh: ScheduleHandle,
+
+    /// Responsible for retrying to mirror the RRDP files to failing targets.
+    #[allow(dead_code)] // just need to keep this in scope
+    mirrors_sh: ScheduleHandle,
+
+    /// Keeps track of the runs of all the jobs above
+    jobs: Vec<Arc<Job>>,
 }
 
 impl Scheduler {
-    pub fn build(
+    pub fn build<S: Signer>(
         event_queue: Arc<EventQueueListener>,
-        caserver: Arc<CaServer<OpenSslSigner>>,
+        caserver: Arc<CaServer<S>>,
         pubserver: Option<Arc<PubServer>>,
         bgp_analyser: Arc<BgpAnalyser>,
-        ca_refresh_rate: u32,
+        webhooks: Arc<WebhookQueue>,
+        config: &Config,
     ) -> Self {
-        let event_sh = make_event_sh(event_queue, caserver.clone(), pubserver);
-        let republish_sh = make_republish_sh(caserver.clone());
-        let ca_refresh_sh = make_ca_refresh_sh(caserver, ca_refresh_rate);
-        let announcements_refresh_sh = make_announcements_refresh_sh(bgp_analyser);
+        let events = Job::new(SchedulerJob::Events, config.events_interval);
+        let republish = Job::new(SchedulerJob::Republish, config.republish_interval);
+        let ca_refresh = Job::new(SchedulerJob::CaRefresh, config.ca_refresh);
+        let archive = Job::new(SchedulerJob::Archive, config.archive_interval);
+        let webhook = Job::new(SchedulerJob::Webhooks, config.webhooks_interval);
+        let announcements = Job::new(SchedulerJob::Announcements, config.announcements_interval);
+        let mirrors = Job::new(SchedulerJob::Mirrors, config.mirrors_interval);
+
+        let jobs = vec![
+            events.clone(),
+            republish.clone(),
+            ca_refresh.clone(),
+            archive.clone(),
+            webhook.clone(),
+            announcements.clone(),
+            mirrors.clone(),
+        ];
+
+        let days = config.archive_history_days;
+
+        let mirrors_sh = make_mirrors_sh(mirrors, pubserver.clone());
+        let event_sh = make_event_sh(events, event_queue, caserver.clone(), pubserver);
+        let republish_sh = make_republish_sh(republish, caserver.clone());
+        let archive_sh = make_archive_sh(archive, caserver.clone(), days);
+        let grace_hours = config.child_shrink_grace_hours;
+        let ca_refresh_sh = make_ca_refresh_sh(ca_refresh, caserver, grace_hours);
+        let webhook_sh = make_webhook_sh(webhook, webhooks);
+        let announcements_refresh_sh = make_announcements_refresh_sh(announcements, bgp_analyser);
 
         Scheduler {
             event_sh,
             republish_sh,
             ca_refresh_sh,
+            archive_sh,
+            webhook_sh,
             announcements_refresh_sh,
+            mirrors_sh,
+            jobs,
+        }
+    }
+
+    pub fn status(&self) -> SchedulerStatus {
+        SchedulerStatus::new(self.jobs.iter().map(|job| job.status()).collect())
+    }
+
+    /// Runs the job as soon as possible, regardless of its interval. If it
+    /// is running already, it will run again when it is done.
+    pub fn trigger(&self, job: SchedulerJob) {
+        if let Some(job) = self.jobs.iter().find(|j| j.job == job) {
+            info!("Triggering scheduler job '{}'", job.job);
+            job.triggered.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+//------------ Job -----------------------------------------------------------
+
+/// Keeps track of the runs of a scheduled job, so that they can be
+/// reported, and lets the job be triggered on demand. Jobs are checked
+/// every second, and run when their interval has passed since the start
+/// of their last run, or when they were triggered.
+struct Job {
+    job: SchedulerJob,
+    interval: u32,
+    triggered: AtomicBool,
+    state: Mutex<JobState>,
+}
+
+struct JobState {
+    running: bool,
+    last_run: Option<i64>,
+    last_duration_ms: Option<u64>,
+    last_result: Option<JobResult>,
+    next_run: i64,
+}
+
+impl Job {
+    fn new(job: SchedulerJob, interval: u32) -> Arc<Self> {
+        let state = JobState {
+            running: false,
+            last_run: None,
+            last_duration_ms: None,
+            last_result: None,
+            next_run: Time::now().timestamp() + i64::from(interval),
+        };
+        Arc::new(Job {
+            job,
+            interval,
+            triggered: AtomicBool::new(false),
+            state: Mutex::new(state),
+        })
+    }
+
+    fn run_if_due<F>(&self, op: F)
+    where
+        F: FnOnce() -> Result<(), String>,
+    {
+        let now = Time::now().timestamp();
+        let triggered = self.triggered.swap(false, Ordering::SeqCst);
+        {
+            let mut state = self.state.lock().unwrap();
+            if !triggered && now < state.next_run {
+                return;
+            }
+            state.running = true;
         }
+
+        let start = Instant::now();
+        let res = op();
+
+        let mut state = self.state.lock().unwrap();
+        state.running = false;
+        state.last_run = Some(now);
+        state.last_duration_ms = Some(start.elapsed().as_millis() as u64);
+        state.last_result = Some(match res {
+            Ok(()) => JobResult::Success,
+            Err(error) => JobResult::Failure { error },
+        });
+        state.next_run = now + i64::from(self.interval);
+    }
+
+    fn status(&self) -> JobStatus {
+        let state = self.state.lock().unwrap();
+        JobStatus::new(
+            self.job,
+            self.interval,
+            state.running,
+            state.last_run,
+            state.last_duration_ms,
+            state.last_result.clone(),
+            state.next_run,
+        )
     }
 }
 
-fn make_event_sh(
+fn make_event_sh<S: Signer>(
+    job: Arc<Job>,
     event_queue: Arc<EventQueueListener>,
-    caserver: Arc<CaServer<OpenSslSigner>>,
+    caserver: Arc<CaServer<S>>,
     pubserver: Option<Arc<PubServer>>,
 ) -> ScheduleHandle {
     let mut scheduler = clokwerk::Scheduler::new();
-    scheduler.every(1.seconds()).run(move || {
-        while let Some(evt) = event_queue.pop() {
+    scheduler.every(1.seconds()).run(move || job.run_if_due(|| {
+        // Events are only removed from the queue after they were processed.
+        // Publications which are rescheduled for later stay in the queue,
+        // and are released to be taken again when the queue is drained.
+        // Errors are collected, so that they show in the job status.
+        let mut errors = vec![];
+        while let Some(evt) = event_queue.take_next() {
             let mut rt = Runtime::new().unwrap();
-            match evt {
+            let res = match evt.clone() {
                 QueueEvent::Delta(handle, _version) => {
                     rt.block_on(
                         try_publish(&event_queue, caserver.clone(), pubserver.clone(), handle)
@@ -81,7 +227,7 @@ fn make_event_sh(
                             try_publish(&event_queue, caserver.clone(), pubserver.clone(), handle)
                         )
                     } else {
-                        event_queue.push_back(QueueEvent::ReschedulePublish(handle, last_try));
+                        continue;
                     }
                 }
                 QueueEvent::ResourceClassRemoved(handle, _, parent, revocations) => {
@@ -91,14 +237,19 @@ fn make_event_sh(
                             handle,
                             parent
                         );
-                        if caserver
-                            .send_revoke_requests(&handle, &parent, revocations).await
-                            .is_err()
-                        {
+                        let res = caserver
+                            .send_revoke_requests(&handle, &parent, revocations).await;
+                        if res.is_err() {
                             warn!("Could not revoke key for removed resource class. This is not \
                             an issue, because typically the parent will revoke our keys pro-actively, \
                             just before removing the resource class entitlements.");
                         }
+                        res.map(|_| ()).map_err(|e| {
+                            format!(
+                                "Could not revoke key for removed resource class of '{}': {}",
+                                handle, e
+                            )
+                        })
                     })
                 }
                 QueueEvent::UnexpectedKey(handle, _, rcn, revocation) => {
@@ -108,10 +259,16 @@ fn make_event_sh(
                             revocation.key(),
                             rcn
                         );
-                        if let Err(e) = caserver
-                            .send_revoke_unexpected_key(&handle, rcn, revocation).await {
-                            error!("Could not revoke unexpected surplus key at parent: {}", e);
-                        }
+                        caserver
+                            .send_revoke_unexpected_key(&handle, rcn, revocation).await
+                            .map(|_| ())
+                            .map_err(|e| {
+                                error!("Could not revoke unexpected surplus key at parent: {}", e);
+                                format!(
+                                    "Could not revoke unexpected surplus key for '{}': {}",
+                                    handle, e
+                                )
+                            })
                     })
                 }
                 QueueEvent::ParentAdded(handle, _, parent) => {
@@ -121,35 +278,44 @@ fn make_event_sh(
                             handle,
                             parent
                         );
-                        if let Err(e) = caserver.get_updates_from_parent(&handle, &parent).await {
+                        caserver.get_updates_from_parent(&handle, &parent).await.map_err(|e| {
                             error!(
                                 "Error getting updates for '{}', from parent '{}',  error: '{}'",
                                 &handle, &parent, e
+                            );
+                            format!(
+                                "Error getting updates for '{}' from parent '{}': {}",
+                                handle, parent, e
                             )
-                        }
+                        })
                     })
                 }
                 QueueEvent::RepositoryConfigured(ca, _) => {
                     rt.block_on(async {
                         info!("Repository configured for '{}'", ca);
-                        if let Err(e) = caserver.get_delayed_updates(&ca).await {
+                        caserver.get_delayed_updates(&ca).await.map_err(|e| {
                             error!(
                                 "Error getting updates after configuring repository for '{}',  error: '{}'",
                                 &ca, e
+                            );
+                            format!(
+                                "Error getting updates after configuring repository for '{}': {}",
+                                ca, e
                             )
-                        }
+                        })
                     })
                 }
 
                 QueueEvent::RequestsPending(handle, _) => {
                     rt.block_on(async {
                         info!("Get updates for pending requests for '{}'.", handle);
-                        if let Err(e) = caserver.send_all_requests(&handle).await {
+                        caserver.send_all_requests(&handle).await.map_err(|e| {
                             error!(
                                 "Failed to send pending requests for '{}', error '{}'",
                                 &handle, e
                             );
-                        }
+                            format!("Failed to send pending requests for '{}': {}", handle, e)
+                        })
                     })
                 }
                 QueueEvent::CleanOldRepo(handle, _) => {
@@ -161,26 +327,39 @@ fn make_event_sh(
                                 &handle, e
                             );
                         }
-                        if let Err(e) = caserver.remove_old_repo(&handle) {
+                        caserver.remove_old_repo(&handle).map_err(|e| {
                             error!(
                                 "Failed to remove old repo from ca '{}', error '{}'",
                                 &handle, e
                             );
-                        }
+                            format!("Failed to remove old repo from ca '{}': {}", handle, e)
+                        })
                     })
                 }
+            };
+            if let Err(e) = res {
+                errors.push(e);
             }
+            event_queue.remove(&evt);
         }
-    });
+
+        event_queue.release();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }));
     scheduler.watch_thread(Duration::from_millis(100))
 }
 
-async fn try_publish(
+async fn try_publish<S: Signer>(
     event_queue: &Arc<EventQueueListener>,
-    caserver: Arc<CaServer<OpenSslSigner>>,
+    caserver: Arc<CaServer<S>>,
     pubserver: Option<Arc<PubServer>>,
     ca: Handle,
-) {
+) -> Result<(), String> {
     info!("Try to publish for '{}'", ca);
     let publisher = CaPublisher::new(caserver, pubserver);
 
@@ -189,42 +368,156 @@ async fn try_publish(
             "Failed to publish for '{}' will reschedule, error: {}",
             ca, e
         );
-        event_queue.push_back(QueueEvent::ReschedulePublish(ca, Time::now()))
+        let error = format!("Failed to publish for '{}': {}", ca, e);
+        event_queue.push_back(QueueEvent::ReschedulePublish(ca, Time::now()));
+        return Err(error);
     }
+    Ok(())
 }
 
-fn make_republish_sh(caserver: Arc<CaServer<OpenSslSigner>>) -> ScheduleHandle {
+fn make_republish_sh<S: Signer>(job: Arc<Job>, caserver: Arc<CaServer<S>>) -> ScheduleHandle {
     let mut scheduler = clokwerk::Scheduler::new();
-    scheduler.every(1.hours()).run(move || {
-        info!("Triggering background republication for all CAs");
-        if let Err(e) = caserver.republish_all() {
-            error!("Background republishing failed: {}", e);
-        }
+    scheduler.every(1.seconds()).run(move || {
+        job.run_if_due(|| {
+            info!("Triggering background republication for all CAs");
+            caserver.republish_all().map_err(|e| {
+                error!("Background republishing failed: {}", e);
+                e.to_string()
+            })
+        })
     });
     scheduler.watch_thread(Duration::from_millis(100))
 }
 
-fn make_ca_refresh_sh(caserver: Arc<CaServer<OpenSslSigner>>, refresh_rate: u32) -> ScheduleHandle {
+fn make_ca_refresh_sh<S: Signer>(
+    job: Arc<Job>,
+    caserver: Arc<CaServer<S>>,
+    grace_hours: u32,
+) -> ScheduleHandle {
     let mut scheduler = clokwerk::Scheduler::new();
-    scheduler.every(refresh_rate.seconds()).run(move || {
-        let mut rt = Runtime::new().unwrap();
-        rt.block_on(async {
-            info!("Triggering background refresh for all CAs");
-            caserver.refresh_all().await
+    scheduler.every(1.seconds()).run(move || {
+        job.run_if_due(|| {
+            let mut rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                info!("Triggering background refresh for all CAs");
+                let refreshed = caserver.refresh_all(false).await;
+                let shrunk = caserver.shrink_children_all(grace_hours);
+                refreshed.and(shrunk).map_err(|e| e.to_string())
+            })
         })
     });
     scheduler.watch_thread(Duration::from_millis(100))
 }
 
-fn make_announcements_refresh_sh(bgp_analyser: Arc<BgpAnalyser>) -> ScheduleHandle {
+fn make_archive_sh<S: Signer>(
+    job: Arc<Job>,
+    caserver: Arc<CaServer<S>>,
+    days: u32,
+) -> ScheduleHandle {
     let mut scheduler = clokwerk::Scheduler::new();
     scheduler.every(1.seconds()).run(move || {
-        let mut rt = Runtime::new().unwrap();
-        rt.block_on(async {
-            if let Err(e) = bgp_analyser.update().await {
-                error!("Failed to update BGP announcements: {}", e)
+        job.run_if_due(|| match caserver.archive_history(days) {
+            Ok(report) => {
+                info!("{}", report);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Could not archive CA history: {}", e);
+                Err(e.to_string())
             }
         })
     });
     scheduler.watch_thread(Duration::from_millis(100))
 }
+
+fn make_webhook_sh(job: Arc<Job>, webhooks: Arc<WebhookQueue>) -> ScheduleHandle {
+    let mut scheduler = clokwerk::Scheduler::new();
+    scheduler.every(1.seconds()).run(move || {
+        job.run_if_due(|| {
+            let mut rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                webhooks.deliver().await.map_err(|e| {
+                    error!("Could not deliver webhooks: {}", e);
+                    e.to_string()
+                })
+            })
+        })
+    });
+    scheduler.watch_thread(Duration::from_millis(100))
+}
+
+fn make_announcements_refresh_sh(job: Arc<Job>, bgp_analyser: Arc<BgpAnalyser>) -> ScheduleHandle {
+    let mut scheduler = clokwerk::Scheduler::new();
+    scheduler.every(1.seconds()).run(move || {
+        job.run_if_due(|| {
+            let mut rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                bgp_analyser.update().await.map(|_| ()).map_err(|e| {
+                    error!("Failed to update BGP announcements: {}", e);
+                    e.to_string()
+                })
+            })
+        })
+    });
+    scheduler.watch_thread(Duration::from_millis(100))
+}
+
+fn make_mirrors_sh(job: Arc<Job>, pubserver: Option<Arc<PubServer>>) -> ScheduleHandle {
+    let mut scheduler = clokwerk::Scheduler::new();
+    scheduler.every(1.seconds()).run(move || {
+        job.run_if_due(|| match pubserver.as_ref() {
+            Some(pubserver) => pubserver.retry_mirrors().map_err(|e| {
+                error!("Could not retry mirroring RRDP files: {}", e);
+                e.to_string()
+            }),
+            None => Ok(()),
+        })
+    });
+    scheduler.watch_thread(Duration::from_millis(100))
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn run_jobs_when_due_or_triggered() {
+        let job = Job::new(SchedulerJob::Republish, 3600);
+        let mut runs = 0;
+
+        job.run_if_due(|| {
+            runs += 1;
+            Ok(())
+        });
+        assert_eq!(0, runs);
+        assert!(job.status().last_run().is_none());
+
+        job.triggered.store(true, Ordering::SeqCst);
+        job.run_if_due(|| {
+            runs += 1;
+            Err("parent unreachable".to_string())
+        });
+        assert_eq!(1, runs);
+
+        let status = job.status();
+        assert!(!status.running());
+        assert!(status.last_run().is_some());
+        assert_eq!(status.last_run().unwrap() + 3600, status.next_run());
+        assert_eq!(
+            Some(&JobResult::Failure {
+                error: "parent unreachable".to_string()
+            }),
+            status.last_result()
+        );
+
+        // The trigger is reset after the run
+        job.run_if_due(|| {
+            runs += 1;
+            Ok(())
+        });
+        assert_eq!(1, runs);
+    }
+}
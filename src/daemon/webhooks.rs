@@ -0,0 +1,526 @@
+//! Notifies external systems, such as chat or ticketing systems, about
+//! important CA events through webhooks. Deliveries are kept on disk until
+//! they succeed, so that notifications survive a restart, and are retried
+//! with an increasing delay. Deliveries which keep failing, or whose target
+//! is no longer configured, are set aside, so that they can be inspected
+//! through the API.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer as HmacSigner;
+use reqwest::header::CONTENT_TYPE;
+use rpki::x509::Time;
+
+use crate::commons::eventsourcing::EventListener;
+use crate::commons::util::{file, httpclient};
+use crate::commons::KrillResult;
+use crate::constants::WEBHOOKS_DIR;
+use crate::daemon::ca::{self, CertAuth, Signer};
+use crate::daemon::eventstream::StreamEvent;
+
+/// The event types sent to targets which do not list any.
+pub const WEBHOOK_DEFAULT_EVENTS: &[&str] = &[
+    "child_added",
+    "child_removed",
+    "parent_added",
+    "parent_removed",
+    "resource_class_removed",
+    "key_roll_finished",
+    "unexpected_key_found",
+];
+
+/// The delay before the first retry of a failed delivery. It is doubled
+/// for every further attempt, up to WEBHOOK_RETRY_MAX_SECS.
+pub const WEBHOOK_RETRY_BASE_SECS: i64 = 30;
+pub const WEBHOOK_RETRY_MAX_SECS: i64 = 3600;
+
+/// The number of attempts after which a delivery is set aside as failed.
+pub const WEBHOOK_MAX_ATTEMPTS: u32 = 12;
+
+const PENDING_DIR: &str = "pending";
+const FAILED_DIR: &str = "failed";
+
+//------------ WebhookConfig -------------------------------------------------
+
+/// A webhook target, as configured in krill.conf:
+///
+/// ```toml
+/// [[webhooks]]
+/// url = "https://hooks.example.com/krill"
+/// secret = "shared-secret"
+/// events = [ "child_added", "key_roll_finished" ]
+/// ```
+///
+/// If a secret is given, the body of each delivery is signed with it using
+/// HMAC-SHA256, in the "X-Krill-Signature" header.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct WebhookConfig {
+    url: String,
+    #[serde(default)]
+    secret: Option<String>,
+    #[serde(default)]
+    events: Vec<String>,
+}
+
+impl WebhookConfig {
+    pub fn new(url: &str, secret: Option<String>, events: Vec<String>) -> Self {
+        WebhookConfig {
+            url: url.to_string(),
+            secret,
+            events,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn secret(&self) -> Option<&String> {
+        self.secret.as_ref()
+    }
+
+    pub fn events(&self) -> &[String] {
+        &self.events
+    }
+
+    /// Returns whether events of the given type should be sent to this
+    /// target.
+    pub fn matches(&self, event_type: &str) -> bool {
+        if self.events.is_empty() {
+            WEBHOOK_DEFAULT_EVENTS.contains(&event_type)
+        } else {
+            self.events.iter().any(|t| t == event_type)
+        }
+    }
+
+    pub fn verify(&self) -> Result<(), String> {
+        let url = reqwest::Url::parse(&self.url).map_err(|e| format!("{}: {}", self.url, e))?;
+        if url.scheme() != "https" && url.scheme() != "http" {
+            return Err(format!("url must use http or https: {}", self.url));
+        }
+        Ok(())
+    }
+}
+
+//------------ WebhookDelivery -----------------------------------------------
+
+/// An event which is (still) to be sent to a webhook target.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WebhookDelivery {
+    id: String,
+    url: String,
+    event: StreamEvent,
+    attempts: u32,
+    next_attempt: i64,
+    last_error: Option<String>,
+}
+
+impl WebhookDelivery {
+    fn new(url: &str, event: StreamEvent) -> Self {
+        WebhookDelivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            url: url.to_string(),
+            event,
+            attempts: 0,
+            next_attempt: Time::now().timestamp(),
+            last_error: None,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn event(&self) -> &StreamEvent {
+        &self.event
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    pub fn next_attempt(&self) -> i64 {
+        self.next_attempt
+    }
+
+    pub fn last_error(&self) -> Option<&String> {
+        self.last_error.as_ref()
+    }
+
+    /// Records a failed attempt, and schedules the next one.
+    fn failed(&mut self, error: String) {
+        self.attempts += 1;
+        self.next_attempt = Time::now().timestamp() + retry_delay(self.attempts);
+        self.last_error = Some(error);
+    }
+}
+
+/// Returns the delay in seconds before the next attempt, after the given
+/// number of failed attempts.
+fn retry_delay(attempts: u32) -> i64 {
+    let exp = attempts.saturating_sub(1).min(16);
+    (WEBHOOK_RETRY_BASE_SECS << exp).min(WEBHOOK_RETRY_MAX_SECS)
+}
+
+/// Returns the hex encoded HMAC-SHA256 of the body, using the secret.
+fn signature(secret: &str, body: &[u8]) -> Result<String, String> {
+    let key = PKey::hmac(secret.as_bytes()).map_err(|e| e.to_string())?;
+    let mut signer = HmacSigner::new(MessageDigest::sha256(), &key).map_err(|e| e.to_string())?;
+    signer.update(body).map_err(|e| e.to_string())?;
+    let hmac = signer.sign_to_vec().map_err(|e| e.to_string())?;
+    Ok(hex::encode(hmac))
+}
+
+//------------ WebhookStatus -------------------------------------------------
+
+/// The deliveries which are waiting to be retried, and those which failed.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WebhookStatus {
+    pending: Vec<WebhookDelivery>,
+    failed: Vec<WebhookDelivery>,
+}
+
+impl WebhookStatus {
+    pub fn pending(&self) -> &Vec<WebhookDelivery> {
+        &self.pending
+    }
+
+    pub fn failed(&self) -> &Vec<WebhookDelivery> {
+        &self.failed
+    }
+}
+
+//------------ WebhookQueue --------------------------------------------------
+
+/// Listens to CA events, and keeps a delivery on disk for each webhook
+/// target interested in them, until it is sent.
+pub struct WebhookQueue {
+    targets: Vec<WebhookConfig>,
+    pending_dir: PathBuf,
+    failed_dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl WebhookQueue {
+    pub fn build(work_dir: &PathBuf, targets: Vec<WebhookConfig>) -> KrillResult<Self> {
+        let mut base = work_dir.clone();
+        base.push(WEBHOOKS_DIR);
+
+        let mut pending_dir = base.clone();
+        pending_dir.push(PENDING_DIR);
+        fs::create_dir_all(&pending_dir)?;
+
+        let mut failed_dir = base;
+        failed_dir.push(FAILED_DIR);
+        fs::create_dir_all(&failed_dir)?;
+
+        Ok(WebhookQueue {
+            targets,
+            pending_dir,
+            failed_dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Adds a delivery of the event for each target interested in it.
+    pub fn add(&self, event: &StreamEvent) {
+        let _lock = self.lock.lock().unwrap();
+        for target in self
+            .targets
+            .iter()
+            .filter(|t| t.matches(event.event_type()))
+        {
+            let delivery = WebhookDelivery::new(target.url(), event.clone());
+            if let Err(e) = self.save(&self.pending_dir, &delivery) {
+                error!(
+                    "Could not save webhook delivery for event '{}' of '{}': {}",
+                    event.version(),
+                    event.handle(),
+                    e
+                );
+            }
+        }
+    }
+
+    pub fn status(&self) -> KrillResult<WebhookStatus> {
+        let _lock = self.lock.lock().unwrap();
+        Ok(WebhookStatus {
+            pending: self.load_all(&self.pending_dir)?,
+            failed: self.load_all(&self.failed_dir)?,
+        })
+    }
+
+    /// Sends all pending deliveries which are due. Deliveries which fail
+    /// are retried later, or set aside when they failed too often.
+    /// Deliveries for targets which were removed from the configuration
+    /// are set aside without sending them.
+    pub async fn deliver(&self) -> KrillResult<()> {
+        let due: Vec<WebhookDelivery> = {
+            let _lock = self.lock.lock().unwrap();
+            let now = Time::now().timestamp();
+            let mut due = vec![];
+            for mut delivery in self.load_all(&self.pending_dir)? {
+                if !self.targets.iter().any(|t| t.url() == delivery.url) {
+                    warn!(
+                        "Setting aside webhook '{}', target '{}' is no longer configured",
+                        delivery.id, delivery.url
+                    );
+                    delivery.last_error = Some("target is no longer configured".to_string());
+                    self.save(&self.failed_dir, &delivery)?;
+                    file::delete(&self.path(&self.pending_dir, &delivery))?;
+                } else if delivery.next_attempt <= now {
+                    due.push(delivery);
+                }
+            }
+            due
+        };
+
+        for mut delivery in due {
+            let res = self.send(&delivery).await;
+
+            let _lock = self.lock.lock().unwrap();
+            match res {
+                Ok(()) => {
+                    debug!("Delivered webhook '{}' to '{}'", delivery.id, delivery.url);
+                }
+                Err(e) => {
+                    delivery.failed(e);
+                    if delivery.attempts >= WEBHOOK_MAX_ATTEMPTS {
+                        error!(
+                            "Giving up on webhook '{}' to '{}' after {} attempts, error: {}",
+                            delivery.id,
+                            delivery.url,
+                            delivery.attempts,
+                            delivery.last_error.as_deref().unwrap_or_default()
+                        );
+                        self.save(&self.failed_dir, &delivery)?;
+                    } else {
+                        warn!(
+                            "Could not deliver webhook '{}' to '{}', will retry, error: {}",
+                            delivery.id,
+                            delivery.url,
+                            delivery.last_error.as_deref().unwrap_or_default()
+                        );
+                        self.save(&self.pending_dir, &delivery)?;
+                        continue;
+                    }
+                }
+            }
+            file::delete(&self.path(&self.pending_dir, &delivery))?;
+        }
+
+        Ok(())
+    }
+
+    async fn send(&self, delivery: &WebhookDelivery) -> Result<(), String> {
+        let body = serde_json::to_string(&delivery.event).map_err(|e| e.to_string())?;
+
+        let mut req = httpclient::client(&delivery.url)
+            .await
+            .map_err(|e| e.to_string())?
+            .post(&delivery.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header("X-Krill-Delivery", delivery.id.as_str());
+
+        // Look up the secret from the current config, so that it is never
+        // stored with the delivery.
+        let secret = self
+            .targets
+            .iter()
+            .find(|t| t.url() == delivery.url)
+            .and_then(|t| t.secret());
+        if let Some(secret) = secret {
+            let signature = signature(secret, body.as_bytes())?;
+            req = req.header("X-Krill-Signature", format!("sha256={}", signature));
+        }
+
+        let res = req.body(body).send().await.map_err(|e| e.to_string())?;
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("received status: {}", res.status()))
+        }
+    }
+
+    fn path(&self, dir: &Path, delivery: &WebhookDelivery) -> PathBuf {
+        dir.join(format!("{}.json", delivery.id))
+    }
+
+    /// Saves the delivery to a temporary file first, and then renames it,
+    /// so that a delivery is never left partially written.
+    fn save(&self, dir: &Path, delivery: &WebhookDelivery) -> KrillResult<()> {
+        let path = self.path(dir, delivery);
+        let tmp = path.with_extension("tmp");
+        file::save_json(delivery, &tmp).and_then(|_| fs::rename(&tmp, &path))?;
+        Ok(())
+    }
+
+    /// Loads all deliveries in the dir. Files which cannot be parsed are
+    /// renamed to '<id>.corrupt' in the failed dir, so that they can be
+    /// inspected, and do not stop other deliveries.
+    fn load_all(&self, dir: &Path) -> KrillResult<Vec<WebhookDelivery>> {
+        let mut deliveries = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+                match file::load_json::<WebhookDelivery>(&path) {
+                    Ok(delivery) => deliveries.push(delivery),
+                    Err(e) => {
+                        let mut corrupt = self.failed_dir.clone();
+                        corrupt.push(path.file_name().unwrap());
+                        corrupt.set_extension("corrupt");
+                        error!(
+                            "Could not read webhook delivery '{}', moving it to '{}': {}",
+                            path.display(),
+                            corrupt.display(),
+                            e
+                        );
+                        fs::rename(&path, &corrupt)?;
+                    }
+                }
+            }
+        }
+        deliveries.sort_by_key(|d| (d.next_attempt, d.event.version()));
+        Ok(deliveries)
+    }
+}
+
+impl<S: Signer> EventListener<CertAuth<S>> for WebhookQueue {
+    fn listen(&self, _ca: &CertAuth<S>, event: &ca::Evt) {
+        if !self.targets.is_empty() {
+            self.add(&StreamEvent::ca(event))
+        }
+    }
+}
+
+//------------ Tests ---------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+
+    use std::str::FromStr;
+
+    use super::*;
+
+    use crate::commons::api::Handle;
+    use crate::daemon::eventstream::EventSource;
+    use crate::test;
+
+    fn event(details: ca::EvtDet) -> StreamEvent {
+        let handle = Handle::from_str("ca").unwrap();
+        StreamEvent::new(EventSource::Ca, &handle, 1, &details)
+    }
+
+    fn child_removed() -> StreamEvent {
+        event(ca::EvtDet::ChildRemoved(Handle::from_str("child").unwrap()))
+    }
+
+    #[test]
+    fn sign_with_hmac_sha256() {
+        let signature = signature("key", b"The quick brown fox jumps over the lazy dog").unwrap();
+        assert_eq!(
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+            signature
+        );
+    }
+
+    #[test]
+    fn retry_with_backoff() {
+        assert_eq!(30, retry_delay(1));
+        assert_eq!(60, retry_delay(2));
+        assert_eq!(1920, retry_delay(7));
+        assert_eq!(WEBHOOK_RETRY_MAX_SECS, retry_delay(8));
+        assert_eq!(WEBHOOK_RETRY_MAX_SECS, retry_delay(WEBHOOK_MAX_ATTEMPTS));
+    }
+
+    #[test]
+    fn keep_deliveries_for_interested_targets() {
+        test::test_under_tmp(|d| {
+            let targets = vec![
+                WebhookConfig::new("https://localhost/all", None, vec![]),
+                WebhookConfig::new(
+                    "https://localhost/keys",
+                    None,
+                    vec!["key_roll_finished".into()],
+                ),
+            ];
+
+            let queue = WebhookQueue::build(&d, targets.clone()).unwrap();
+            queue.add(&child_removed());
+
+            // Deliveries survive a restart
+            let queue = WebhookQueue::build(&d, targets).unwrap();
+            let status = queue.status().unwrap();
+            assert_eq!(1, status.pending().len());
+            assert_eq!("https://localhost/all", status.pending()[0].url());
+            assert_eq!(&child_removed(), status.pending()[0].event());
+            assert!(status.failed().is_empty());
+        })
+    }
+
+    #[tokio::test]
+    async fn set_aside_failing_deliveries() {
+        let d = test::tmp_dir();
+        let url = "http://localhost:1/unreachable";
+        let queue = WebhookQueue::build(&d, vec![WebhookConfig::new(url, None, vec![])]).unwrap();
+
+        let mut delivery = WebhookDelivery::new(url, child_removed());
+        delivery.attempts = WEBHOOK_MAX_ATTEMPTS - 2;
+        queue.save(&queue.pending_dir, &delivery).unwrap();
+
+        queue.deliver().await.unwrap();
+        let status = queue.status().unwrap();
+        assert_eq!(WEBHOOK_MAX_ATTEMPTS - 1, status.pending()[0].attempts());
+        assert!(status.pending()[0].last_error().is_some());
+
+        // Not due yet, so nothing happens
+        queue.deliver().await.unwrap();
+        assert_eq!(1, queue.status().unwrap().pending().len());
+
+        let mut delivery = status.pending()[0].clone();
+        delivery.next_attempt = Time::now().timestamp();
+        queue.save(&queue.pending_dir, &delivery).unwrap();
+
+        queue.deliver().await.unwrap();
+        let status = queue.status().unwrap();
+        assert!(status.pending().is_empty());
+        assert_eq!(WEBHOOK_MAX_ATTEMPTS, status.failed()[0].attempts());
+
+        let _ = fs::remove_dir_all(d);
+    }
+
+    #[tokio::test]
+    async fn set_aside_corrupt_and_unconfigured_deliveries() {
+        let d = test::tmp_dir();
+        let url = "https://localhost/all";
+        let queue = WebhookQueue::build(&d, vec![WebhookConfig::new(url, None, vec![])]).unwrap();
+
+        let corrupt = queue.pending_dir.join("corrupt.json");
+        file::save(b"{ \"id\": ", &corrupt).unwrap();
+
+        // the target was removed from the config since this was queued
+        let mut delivery = WebhookDelivery::new("https://localhost/removed", child_removed());
+        delivery.next_attempt = Time::now().timestamp() + 3600;
+        queue.save(&queue.pending_dir, &delivery).unwrap();
+
+        queue.deliver().await.unwrap();
+        let status = queue.status().unwrap();
+        assert!(status.pending().is_empty());
+        assert_eq!(1, status.failed().len());
+        assert_eq!(delivery.id(), status.failed()[0].id());
+        assert_eq!(0, status.failed()[0].attempts());
+
+        assert!(!corrupt.exists());
+        assert!(queue.failed_dir.join("corrupt.corrupt").exists());
+
+        let _ = fs::remove_dir_all(d);
+    }
+}
@@ -6,7 +6,7 @@ use crate::commons::api::{
 use crate::commons::eventsourcing::CommandDetails;
 use crate::commons::eventsourcing::SentCommand;
 use crate::commons::remote::rfc8183;
-use crate::pubd::Evt;
+use crate::pubd::{Evt, RrdpRetention};
 
 //------------ Cmd ---------------------------------------------------------
 pub type Cmd = SentCommand<CmdDet>;
@@ -17,8 +17,9 @@ pub type Cmd = SentCommand<CmdDet>;
 #[serde(rename_all = "snake_case")]
 pub enum CmdDet {
     AddPublisher(rfc8183::PublisherRequest),
-    RemovePublisher(PublisherHandle),
-    Publish(PublisherHandle, PublishDelta),
+    RemovePublisher(PublisherHandle, RrdpRetention),
+    Publish(PublisherHandle, PublishDelta, RrdpRetention),
+    ResetSession,
 }
 
 impl CommandDetails for CmdDet {
@@ -35,16 +36,25 @@ impl CmdDet {
         SentCommand::new(handle, None, CmdDet::AddPublisher(request))
     }
 
-    pub fn remove_publisher(handle: &RepositoryHandle, publisher: PublisherHandle) -> Cmd {
-        SentCommand::new(handle, None, CmdDet::RemovePublisher(publisher))
+    pub fn remove_publisher(
+        handle: &RepositoryHandle,
+        publisher: PublisherHandle,
+        retention: RrdpRetention,
+    ) -> Cmd {
+        SentCommand::new(handle, None, CmdDet::RemovePublisher(publisher, retention))
     }
 
     pub fn publish(
         handle: &RepositoryHandle,
         publisher: PublisherHandle,
         delta: PublishDelta,
+        retention: RrdpRetention,
     ) -> Cmd {
-        SentCommand::new(handle, None, CmdDet::Publish(publisher, delta))
+        SentCommand::new(handle, None, CmdDet::Publish(publisher, delta, retention))
+    }
+
+    pub fn reset_session(handle: &RepositoryHandle) -> Cmd {
+        SentCommand::new(handle, None, CmdDet::ResetSession)
     }
 }
 
@@ -61,13 +71,14 @@ impl From<CmdDet> for StorableRepositoryCommand {
                 let (_, pbl, id) = req.unpack();
                 StorableRepositoryCommand::AddPublisher(pbl, id.ski_hex())
             }
-            CmdDet::RemovePublisher(pbl) => StorableRepositoryCommand::RemovePublisher(pbl),
-            CmdDet::Publish(pbl, delta) => StorableRepositoryCommand::Publish(
+            CmdDet::RemovePublisher(pbl, _) => StorableRepositoryCommand::RemovePublisher(pbl),
+            CmdDet::Publish(pbl, delta, _) => StorableRepositoryCommand::Publish(
                 pbl,
                 delta.publishes().len(),
                 delta.updates().len(),
                 delta.withdraws().len(),
             ),
+            CmdDet::ResetSession => StorableRepositoryCommand::ResetSession,
         }
     }
 }
@@ -136,6 +136,9 @@ pub enum EvtDet {
     // RRDP publication events
     #[display(fmt = "Publisher with handle '{}' published", _0)]
     Published(PublisherHandle, RrdpUpdate),
+
+    #[display(fmt = "RRDP session reset")]
+    SessionReset(Notification),
 }
 
 impl EvtDet {
@@ -173,4 +176,12 @@ impl EvtDet {
     ) -> Evt {
         StoredEvent::new(repository, version, EvtDet::Published(publisher, update))
     }
+
+    pub(super) fn session_reset(
+        repository: &RepositoryHandle,
+        version: u64,
+        notification: Notification,
+    ) -> Evt {
+        StoredEvent::new(repository, version, EvtDet::SessionReset(notification))
+    }
 }
@@ -8,5 +8,8 @@ pub use self::commands::{Cmd, CmdDet};
 pub use self::events::{Evt, EvtDet, Ini, IniDet, RrdpUpdate};
 pub use self::publishers::Publisher;
 pub use self::pubserver::PubServer;
+pub use self::repository::MirrorStatus;
 pub use self::repository::RepoStats;
 pub use self::repository::Repository;
+pub use self::repository::RrdpRetention;
+pub use self::repository::SessionReset;
@@ -1,25 +1,46 @@
+use std::collections::HashMap;
 use std::fs;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
 
 use bytes::Bytes;
 use rpki::uri;
+use rpki::x509::Time;
 
 use crate::commons::api::{
     Handle, ListReply, PublishDelta, PublisherDetails, PublisherHandle, RepoInfo, RepositoryHandle,
 };
 use crate::commons::error::Error;
-use crate::commons::eventsourcing::{AggregateStore, AggregateStoreError, DiskAggregateStore};
+use crate::commons::eventsourcing::{
+    AggregateStore, AggregateStoreError, DiskAggregateStore, StorageBackend,
+};
 use crate::commons::remote::builder::SignedMessageBuilder;
 use crate::commons::remote::cmslogger::CmsLogger;
 use crate::commons::remote::rfc8181;
 use crate::commons::remote::rfc8183;
 use crate::commons::remote::sigmsg::SignedMessage;
+use crate::commons::util::file;
 use crate::commons::util::softsigner::OpenSslSigner;
 use crate::commons::KrillResult;
 use crate::constants::*;
-use crate::pubd::{self, CmdDet, RepoStats, Repository};
+use crate::daemon::eventstream::EventStream;
+use crate::pubd::{self, CmdDet, MirrorStatus, RepoStats, Repository, RrdpRetention, SessionReset};
+
+/// The delay before mirroring to a failing target is tried again after its
+/// first failure. It is doubled for every further consecutive failure, up
+/// to MIRROR_BACKOFF_MAX_SECS.
+pub const MIRROR_BACKOFF_BASE_SECS: i64 = 30;
+pub const MIRROR_BACKOFF_MAX_SECS: i64 = 3600;
+
+const MIRRORS_FILE: &str = "mirrors.json";
+
+/// Returns the delay in seconds before the next attempt after the given
+/// number of consecutive failures.
+fn mirror_backoff_delay(failures: u32) -> i64 {
+    let exp = failures.saturating_sub(1).min(16);
+    (MIRROR_BACKOFF_BASE_SECS << exp).min(MIRROR_BACKOFF_MAX_SECS)
+}
 
 //------------ PubServer -----------------------------------------------------
 
@@ -38,6 +59,12 @@ pub struct PubServer {
     store: Arc<DiskAggregateStore<Repository>>,
     signer: Arc<RwLock<OpenSslSigner>>,
     rfc8181_log_dir: Option<PathBuf>,
+    rrdp_retention: RrdpRetention,
+    rsync_retain_mins: u32,
+    rrdp_mirrors: Vec<PathBuf>,
+    write_lock: Mutex<()>,
+    mirror_statuses: RwLock<HashMap<String, MirrorStatus>>,
+    mirror_statuses_path: PathBuf,
 }
 
 /// # Constructing
@@ -48,13 +75,28 @@ impl PubServer {
         rrdp_base_uri: uri::Https,         // for the RRDP files
         work_dir: &PathBuf,                // for the aggregate stores
         rfc8181_log_dir: Option<&PathBuf>, // for optional CMS exchange logging
+        storage_backend: StorageBackend,   // for the aggregate store
+        rrdp_retention: RrdpRetention,     // for the deltas listed in the notification
+        rsync_retain_mins: u32,            // for removing superseded rsync dirs
+        rrdp_mirrors: Vec<PathBuf>,        // for copying the RRDP files elsewhere
+        event_stream: Arc<EventStream>,    // for streaming events to API clients
         signer: Arc<RwLock<OpenSslSigner>>,
     ) -> Result<Option<Self>, Error> {
         let mut pub_server_dir = work_dir.clone();
         pub_server_dir.push(PUBSERVER_DIR);
         if pub_server_dir.exists() {
-            let server =
-                PubServer::build(rsync_base, rrdp_base_uri, work_dir, rfc8181_log_dir, signer)?;
+            let server = PubServer::build(
+                rsync_base,
+                rrdp_base_uri,
+                work_dir,
+                rfc8181_log_dir,
+                storage_backend,
+                rrdp_retention,
+                rsync_retain_mins,
+                rrdp_mirrors,
+                event_stream,
+                signer,
+            )?;
             if server.publishers()?.is_empty() {
                 let _result = fs::remove_dir_all(pub_server_dir);
                 Ok(None)
@@ -71,14 +113,22 @@ impl PubServer {
         rrdp_base_uri: uri::Https,         // for the RRDP files
         work_dir: &PathBuf,                // for the aggregate stores
         rfc8181_log_dir: Option<&PathBuf>, // for optional CMS exchange logging
+        storage_backend: StorageBackend,   // for the aggregate store
+        rrdp_retention: RrdpRetention,     // for the deltas listed in the notification
+        rsync_retain_mins: u32,            // for removing superseded rsync dirs
+        rrdp_mirrors: Vec<PathBuf>,        // for copying the RRDP files elsewhere
+        event_stream: Arc<EventStream>,    // for streaming events to API clients
         signer: Arc<RwLock<OpenSslSigner>>,
     ) -> Result<Self, Error> {
         let default = Self::repository_handle();
 
-        let store = Arc::new(DiskAggregateStore::<Repository>::new(
+        let mut store = DiskAggregateStore::<Repository>::with_backend(
             work_dir,
             PUBSERVER_DIR,
-        )?);
+            storage_backend,
+        )?;
+        store.add_listener(event_stream);
+        let store = Arc::new(store);
 
         if !store.has(&default) {
             info!("Creating default repository");
@@ -94,10 +144,29 @@ impl PubServer {
             store.add(ini)?;
         }
 
+        // The statuses of mirrors which are no longer configured are dropped
+        let mirror_statuses_path = work_dir.join(STATUS_DIR).join(MIRRORS_FILE);
+        let mut mirror_statuses: HashMap<String, MirrorStatus> = if mirror_statuses_path.exists() {
+            file::load_json(&mirror_statuses_path)?
+        } else {
+            HashMap::new()
+        };
+        mirror_statuses.retain(|target, _| {
+            rrdp_mirrors
+                .iter()
+                .any(|mirror| mirror.to_string_lossy() == target.as_str())
+        });
+
         Ok(PubServer {
             store,
             signer,
             rfc8181_log_dir: rfc8181_log_dir.cloned(),
+            rrdp_retention,
+            rsync_retain_mins,
+            rrdp_mirrors,
+            write_lock: Mutex::new(()),
+            mirror_statuses: RwLock::new(mirror_statuses),
+            mirror_statuses_path,
         })
     }
 }
@@ -181,14 +250,33 @@ impl PubServer {
     /// Let a known publisher publish in a repository.
     pub fn publish(&self, publisher: PublisherHandle, delta: PublishDelta) -> KrillResult<()> {
         let repository_handle = Self::repository_handle();
-        let cmd = CmdDet::publish(&repository_handle, publisher, delta);
+        let cmd = CmdDet::publish(&repository_handle, publisher, delta, self.rrdp_retention);
         self.store.command(cmd)?;
         self.write_repository()
     }
 
+    /// Starts a new RRDP session with serial 1, and writes the new snapshot
+    /// of all current objects and the notification file. This can be used
+    /// to recover when RRDP files were lost or corrupted.
+    pub fn reset_session(&self) -> KrillResult<SessionReset> {
+        let old = self.repository()?.stats().clone();
+        let repository_handle = Self::repository_handle();
+        let cmd = CmdDet::reset_session(&repository_handle);
+        let repository = self.store.command(cmd)?;
+        self.write_repository()?;
+        Ok(repository.session_reset_report(&old))
+    }
+
+    /// Blocks all changes to the repository until the returned guard is
+    /// dropped.
+    pub fn lock_for_backup(&self) -> RwLockReadGuard<'_, ()> {
+        self.store.lock_for_backup()
+    }
+
     pub fn repo_stats(&self) -> KrillResult<RepoStats> {
         let repo = self.repository()?;
-        Ok(repo.stats().clone())
+        let mirrors = self.mirror_statuses.read().unwrap().clone();
+        Ok(repo.stats().clone().with_mirrors(mirrors))
     }
 
     pub fn publishers(&self) -> KrillResult<Vec<PublisherHandle>> {
@@ -247,7 +335,7 @@ impl PubServer {
     /// entities that would get confusing.
     pub fn remove_publisher(&self, publisher: PublisherHandle) -> KrillResult<()> {
         let repository_handle = Self::repository_handle();
-        let cmd = CmdDet::remove_publisher(&repository_handle, publisher);
+        let cmd = CmdDet::remove_publisher(&repository_handle, publisher, self.rrdp_retention);
         self.store.command(cmd)?;
         self.write_repository()
     }
@@ -256,10 +344,82 @@ impl PubServer {
 /// # Publishing RRDP and rsync
 ///
 impl PubServer {
-    /// Update the RRDP files and rsync content on disk.
+    /// Update the RRDP files and rsync content on disk, and mirror the
+    /// RRDP files to any configured targets which are not failing. Writes
+    /// are serialized, so that concurrent updates cannot write or mirror an
+    /// older state of the repository over a newer one.
     pub fn write_repository(&self) -> KrillResult<()> {
+        let _lock = self.write_lock.lock().unwrap();
         let repository = self.repository()?;
-        repository.write()
+        repository.write(self.rsync_retain_mins)?;
+        for target in &self.rrdp_mirrors {
+            if !self.mirror_status(target).is_failing() {
+                self.mirror(&repository, target);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tries to mirror the RRDP files again to failing targets, when they
+    /// are no longer backed off. This is done by the scheduler, so that
+    /// failing targets do not hold up publication. Because all missing
+    /// files are copied, a target catches up as soon as it succeeds.
+    pub fn retry_mirrors(&self) -> KrillResult<()> {
+        let now = Time::now().timestamp();
+        let due: Vec<&PathBuf> = self
+            .rrdp_mirrors
+            .iter()
+            .filter(|target| {
+                let status = self.mirror_status(target);
+                status.is_failing() && status.is_due(now)
+            })
+            .collect();
+
+        if !due.is_empty() {
+            let _lock = self.write_lock.lock().unwrap();
+            let repository = self.repository()?;
+            for target in due {
+                info!("Retrying to mirror RRDP files to '{}'", target.display());
+                self.mirror(&repository, target);
+            }
+        }
+        Ok(())
+    }
+
+    /// Mirrors the RRDP files to a target. Failures are logged and kept in
+    /// the mirror status, but do not fail the update of the repository.
+    /// The caller must hold the write lock.
+    fn mirror(&self, repository: &Repository, target: &PathBuf) {
+        let result = repository.mirror(target);
+
+        let mut statuses = self.mirror_statuses.write().unwrap();
+        let status = statuses
+            .entry(target.to_string_lossy().to_string())
+            .or_default();
+        let now = Time::now().timestamp();
+        match result {
+            Ok(()) => status.set_success(now),
+            Err(e) => {
+                let delay = mirror_backoff_delay(status.failures() + 1);
+                error!(
+                    "Could not mirror RRDP files to '{}', will retry in {} seconds: {}",
+                    target.display(),
+                    delay,
+                    e
+                );
+                status.set_failure(now, e.to_error_response(), now + delay);
+            }
+        }
+
+        if let Err(e) = file::save_json(&*statuses, &self.mirror_statuses_path) {
+            error!("Could not save mirror statuses: {}", e);
+        }
+    }
+
+    fn mirror_status(&self, target: &PathBuf) -> MirrorStatus {
+        let statuses = self.mirror_statuses.read().unwrap();
+        let target = target.to_string_lossy();
+        statuses.get(target.as_ref()).cloned().unwrap_or_default()
     }
 }
 
@@ -279,7 +439,7 @@ mod tests {
     use crate::commons::api::{ListElement, PublishDeltaBuilder};
     use crate::commons::remote::builder::IdCertBuilder;
     use crate::commons::remote::id::IdCert;
-    use crate::commons::util::file::CurrentFile;
+    use crate::commons::util::file::{self, CurrentFile};
     use crate::pubd::Publisher;
     use crate::test;
 
@@ -310,6 +470,10 @@ mod tests {
     }
 
     fn make_server(work_dir: &PathBuf) -> PubServer {
+        make_server_with_mirrors(work_dir, vec![])
+    }
+
+    fn make_server_with_mirrors(work_dir: &PathBuf, mirrors: Vec<PathBuf>) -> PubServer {
         let signer = OpenSslSigner::build(work_dir).unwrap();
         let signer = Arc::new(RwLock::new(signer));
 
@@ -318,6 +482,11 @@ mod tests {
             server_base_http_uri(),
             work_dir,
             None,
+            StorageBackend::Disk,
+            RrdpRetention::new(50, 24),
+            10,
+            mirrors,
+            Arc::new(EventStream::default()),
             signer,
         )
         .unwrap()
@@ -343,6 +512,135 @@ mod tests {
         })
     }
 
+    #[test]
+    fn should_mirror_rrdp_files() {
+        test::test_under_tmp(|d| {
+            let mirror = d.join("mirror");
+            let not_a_dir = d.join("not-a-dir");
+            file::save(b"", &not_a_dir).unwrap();
+
+            let server = make_server_with_mirrors(&d, vec![mirror.clone(), not_a_dir.clone()]);
+
+            let alice = publisher_alice(&d);
+            let publisher_req = make_publisher_req("alice", alice.id_cert());
+            server.create_publisher(publisher_req).unwrap();
+            server.write_repository().unwrap();
+
+            assert!(mirror.join("notification.xml").exists());
+
+            let stats = server.repo_stats().unwrap();
+            let mirrors = stats.mirrors();
+            assert!(!mirrors[mirror.to_string_lossy().as_ref()].is_failing());
+            let failing = &mirrors[not_a_dir.to_string_lossy().as_ref()];
+            assert_eq!(1, failing.failures());
+            assert!(failing.last_error().is_some());
+            assert_eq!(None, failing.last_success());
+        })
+    }
+
+    #[test]
+    fn should_retry_failing_mirrors() {
+        test::test_under_tmp(|d| {
+            let target = d.join("target");
+            file::save(b"", &target).unwrap();
+            let key = target.to_string_lossy().to_string();
+
+            let server = make_server_with_mirrors(&d, vec![target.clone()]);
+            let alice = publisher_alice(&d);
+            let publisher_req = make_publisher_req("alice", alice.id_cert());
+            server.create_publisher(publisher_req).unwrap();
+            server.write_repository().unwrap();
+
+            let status = server.repo_stats().unwrap().mirrors()[&key].clone();
+            assert_eq!(1, status.failures());
+            assert!(status.next_attempt().is_some());
+
+            // failing targets are skipped on updates, and not retried
+            // before they are due
+            server.write_repository().unwrap();
+            server.retry_mirrors().unwrap();
+            assert_eq!(1, server.repo_stats().unwrap().mirrors()[&key].failures());
+
+            fs::remove_file(&target).unwrap();
+            server.retry_mirrors().unwrap();
+            assert!(!target.join("notification.xml").exists());
+
+            let past = Time::now().timestamp() - 1;
+            let error = status.last_error().unwrap().clone();
+            let mut statuses = server.mirror_statuses.write().unwrap();
+            statuses
+                .get_mut(&key)
+                .unwrap()
+                .set_failure(past, error, past);
+            drop(statuses);
+
+            server.retry_mirrors().unwrap();
+            assert!(target.join("notification.xml").exists());
+            assert!(!server.repo_stats().unwrap().mirrors()[&key].is_failing());
+
+            // the status is kept across restarts
+            let server = make_server_with_mirrors(&d, vec![target.clone()]);
+            let stats = server.repo_stats().unwrap();
+            assert!(stats.mirrors()[&key].last_success().is_some());
+        })
+    }
+
+    #[test]
+    fn mirror_retry_with_backoff() {
+        assert_eq!(30, mirror_backoff_delay(1));
+        assert_eq!(60, mirror_backoff_delay(2));
+        assert_eq!(1920, mirror_backoff_delay(7));
+        assert_eq!(MIRROR_BACKOFF_MAX_SECS, mirror_backoff_delay(8));
+    }
+
+    #[test]
+    fn should_reset_session() {
+        test::test_under_tmp(|d| {
+            let server = make_server(&d);
+            let alice = publisher_alice(&d);
+
+            let alice_handle = unsafe { Handle::from_str_unsafe("alice") };
+            let publisher_req = make_publisher_req(alice_handle.as_str(), alice.id_cert());
+            server.create_publisher(publisher_req).unwrap();
+
+            let file = CurrentFile::new(
+                test::rsync("rsync://localhost/repo/alice/file.txt"),
+                &Bytes::from("example content"),
+            );
+            let mut builder = PublishDeltaBuilder::new();
+            builder.add_publish(file.as_publish());
+            server
+                .publish(alice_handle.clone(), builder.finish())
+                .unwrap();
+
+            let old = server.repo_stats().unwrap();
+            let reset = server.reset_session().unwrap();
+
+            assert_eq!(old.session(), reset.old_session());
+            assert_ne!(reset.old_session(), reset.new_session());
+            assert_eq!(1, reset.new_serial());
+            assert_eq!(1, reset.objects());
+
+            let stats = server.repo_stats().unwrap();
+            assert_eq!(reset.new_session(), stats.session());
+            assert_eq!(1, stats.serial());
+
+            let snapshot_rel = reset
+                .snapshot_uri()
+                .as_str()
+                .trim_start_matches(server_base_http_uri().as_str());
+            let snapshot = d
+                .join(REPOSITORY_DIR)
+                .join(REPOSITORY_RRDP_DIR)
+                .join(snapshot_rel);
+            assert!(snapshot.exists());
+
+            // the publisher's objects are unaffected
+            let list_reply = server.list(&alice_handle).unwrap();
+            assert_eq!(1, list_reply.elements().len());
+        })
+    }
+
     #[test]
     fn should_not_add_publisher_twice() {
         test::test_under_tmp(|d| {
@@ -1,19 +1,23 @@
 use std::collections::HashMap;
-use std::fs;
-use std::path::PathBuf;
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
 use std::str::{from_utf8_unchecked, FromStr};
-use std::time::SystemTime;
+use std::{fmt, fs};
 
+use chrono::Duration;
 use rpki::crypto::KeyIdentifier;
 use rpki::uri;
 use rpki::x509::Time;
+use uuid::Uuid;
 
 use crate::commons::api::rrdp::{
     CurrentObjects, Delta, DeltaElements, DeltaRef, FileRef, Notification, RrdpSession, Snapshot,
     SnapshotRef,
 };
 use crate::commons::api::{
-    Handle, HexEncodedHash, PublishDelta, PublisherHandle, RepoInfo, StorableRepositoryCommand,
+    ErrorResponse, Handle, HexEncodedHash, PublishDelta, PublisherHandle, RepoInfo,
+    StorableRepositoryCommand,
 };
 use crate::commons::error::Error;
 use crate::commons::eventsourcing::Aggregate;
@@ -22,8 +26,9 @@ use crate::commons::remote::rfc8183;
 use crate::commons::util::file;
 use crate::commons::KrillResult;
 use crate::constants::{
-    REPOSITORY_RRDP_DIR, REPOSITORY_RRDP_SNAPSHOT_RETAIN_MINS, REPOSITORY_RSYNC_DIR,
+    REPOSITORY_RRDP_DIR, REPOSITORY_RRDP_FILES_RETAIN_MINS, REPOSITORY_RSYNC_DIR, RSYNC_CURRENT,
 };
+use crate::daemon::http::rrdp::gzip_path;
 use crate::pubd::publishers::Publisher;
 use crate::pubd::{Cmd, CmdDet, Evt, EvtDet, Ini, RrdpUpdate};
 
@@ -56,17 +61,75 @@ impl RsyncdStore {
 /// # Publishing
 ///
 impl RsyncdStore {
+    /// Write all the files to disk for rsync to a new version dir, then
+    /// atomically switch the "current" symlink over to it, so that rsync
+    /// clients never see a mix of old and new files. Versions which were
+    /// superseded more than the given number of minutes ago are removed,
+    /// so that clients which are still fetching an older version can
+    /// finish doing so.
+    #[cfg(unix)]
+    pub fn write(&self, snapshot: &Snapshot, retain_mins: u32) -> KrillResult<()> {
+        let now = Time::now();
+        let version = format!("{}-{}", now.timestamp_millis(), snapshot.serial());
+
+        let new_dir = self.rsync_dir.join(&version);
+        self.write_files(snapshot, &new_dir)?;
+
+        let current = self.rsync_dir.join(RSYNC_CURRENT);
+
+        // Earlier versions kept the files in a "current" dir, rather than
+        // behind a symlink. Move it aside, so it can be replaced.
+        if current.is_dir() && !Self::is_symlink(&current) {
+            fs::rename(
+                &current,
+                self.rsync_dir.join(format!("0-{}", RSYNC_CURRENT)),
+            )?;
+        }
+
+        // Renaming a symlink over the existing one is atomic. The temporary
+        // link is named after the version, so that it is never shared.
+        let tmp_link = self
+            .rsync_dir
+            .join(format!("{}.{}.tmp", RSYNC_CURRENT, version));
+        symlink(&version, &tmp_link)?;
+        fs::rename(&tmp_link, &current)?;
+
+        self.remove_old_versions(&version, now, retain_mins)
+    }
+
     /// Write all the files to disk for rsync to a tmp-dir, then switch
     /// things over in an effort to minimise the chance of people getting
-    /// inconsistent syncs..
-    pub fn write(&self, snapshot: &Snapshot) -> KrillResult<()> {
-        let mut new_dir = self.rsync_dir.clone();
-        new_dir.push(&format!("tmp-{}", snapshot.serial()));
-        fs::create_dir_all(&new_dir)?;
+    /// inconsistent syncs. Symlinks cannot be used on this platform, so
+    /// earlier versions are not kept.
+    #[cfg(not(unix))]
+    pub fn write(&self, snapshot: &Snapshot, _retain_mins: u32) -> KrillResult<()> {
+        let new_dir = self.rsync_dir.join(format!("tmp-{}", snapshot.serial()));
+        self.write_files(snapshot, &new_dir)?;
+
+        let current_dir = self.rsync_dir.join(RSYNC_CURRENT);
+        let old_dir = self.rsync_dir.join("old");
+
+        if current_dir.exists() {
+            fs::rename(&current_dir, &old_dir)?;
+        }
+
+        fs::rename(&new_dir, &current_dir)?;
+
+        if old_dir.exists() {
+            fs::remove_dir_all(&old_dir)?;
+        }
 
-        let elements = snapshot.elements();
+        Ok(())
+    }
+
+    /// Writes all files in the snapshot to a new, empty, dir.
+    fn write_files(&self, snapshot: &Snapshot, dir: &PathBuf) -> KrillResult<()> {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::create_dir_all(dir)?;
 
-        for publish in elements {
+        for publish in snapshot.elements() {
             let rel = publish
                 .uri()
                 .relative_to(&self.base_uri)
@@ -74,32 +137,100 @@ impl RsyncdStore {
 
             let rel = unsafe { from_utf8_unchecked(rel) };
 
-            let mut path = new_dir.clone();
+            let mut path = dir.clone();
             path.push(rel);
 
             file::save(&publish.base64().to_bytes(), &path)?;
         }
 
-        let mut current_dir = self.rsync_dir.clone();
-        current_dir.push("current");
-
-        let mut old_dir = self.rsync_dir.clone();
-        old_dir.push("old");
+        Ok(())
+    }
 
-        if current_dir.exists() {
-            fs::rename(&current_dir, &old_dir)?;
+    /// Removes versions, other than the current, which were superseded by
+    /// a later version at least 'retain_mins' ago. The time at which a
+    /// version was superseded is the creation time of the next version,
+    /// which is the first part of its name.
+    #[cfg(unix)]
+    fn remove_old_versions(&self, current: &str, now: Time, retain_mins: u32) -> KrillResult<()> {
+        let mut versions = vec![];
+        for entry in fs::read_dir(&self.rsync_dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == current {
+                continue;
+            }
+            if !entry.file_type()?.is_dir() {
+                if name.ends_with(".tmp") && entry.file_type()?.is_symlink() {
+                    // a temporary link left by an interrupted write
+                    let _best_effort_rm = fs::remove_file(entry.path());
+                }
+                continue;
+            }
+            let created = name.split('-').next().and_then(|t| i64::from_str(t).ok());
+            match created {
+                Some(created) => versions.push((created, entry.path())),
+                None => {
+                    // not a version, e.g. a left-over tmp dir
+                    let _best_effort_rm = fs::remove_dir_all(entry.path());
+                }
+            }
         }
+        versions.sort();
 
-        fs::rename(&new_dir, &current_dir)?;
+        let retain_since = now - Duration::minutes(i64::from(retain_mins));
+        let retain_since = retain_since.timestamp_millis();
 
-        if old_dir.exists() {
-            fs::remove_dir_all(&old_dir)?;
+        let mut superseded = versions.iter().skip(1).map(|(created, _)| *created);
+        for (_, path) in &versions {
+            let superseded = superseded.next().unwrap_or_else(|| now.timestamp_millis());
+            if superseded <= retain_since {
+                let _best_effort_rm = fs::remove_dir_all(path);
+            }
         }
 
         Ok(())
     }
+
+    #[cfg(unix)]
+    fn is_symlink(path: &PathBuf) -> bool {
+        fs::symlink_metadata(path)
+            .map(|meta| meta.file_type().is_symlink())
+            .unwrap_or(false)
+    }
 }
 
+//------------ RrdpRetention -------------------------------------------------
+
+/// Determines which deltas are listed in the RRDP notification file.
+///
+/// The latest delta is always listed. Older deltas are dropped once their
+/// combined size would exceed the size of the snapshot - at which point
+/// relying parties are better off fetching the snapshot - or once there
+/// would be more than the maximum number of deltas, or when they are older
+/// than the maximum age.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RrdpRetention {
+    max_deltas: usize,
+    max_delta_age_hours: u32,
+}
+
+impl RrdpRetention {
+    pub fn new(max_deltas: usize, max_delta_age_hours: u32) -> Self {
+        RrdpRetention {
+            max_deltas,
+            max_delta_age_hours,
+        }
+    }
+
+    /// Returns whether a delta created at the given time is too old to be
+    /// listed.
+    fn is_expired(&self, time: &Time) -> bool {
+        *time < Time::now() - Duration::hours(i64::from(self.max_delta_age_hours))
+    }
+}
+
+//------------ RrdpServer ----------------------------------------------------
+
 /// The RRDP server used by a Repository instance
 #[derive(Clone, Debug, Deserialize, Serialize)]
 struct RrdpServer {
@@ -115,6 +246,21 @@ struct RrdpServer {
     notification: Notification,
     snapshot: Snapshot,
     deltas: Vec<Delta>,
+
+    /// Files and dirs, relative to the base dir, which are no longer
+    /// referenced in the notification file, and the time since when. They
+    /// are kept on disk for REPOSITORY_RRDP_FILES_RETAIN_MINS, so that
+    /// relying parties which just fetched the previous notification file
+    /// can still retrieve them.
+    #[serde(default)]
+    stale_files: HashMap<String, Time>,
+
+    /// The time since when files and dirs which are not referenced, and
+    /// not in stale_files either, are considered stale. This is set on the
+    /// first update, so that files left by a version which did not track
+    /// stale files are still kept for the retention time after an upgrade.
+    #[serde(default)]
+    untracked_stale_since: Option<Time>,
 }
 
 impl RrdpServer {
@@ -142,6 +288,8 @@ impl RrdpServer {
             notification,
             snapshot,
             deltas,
+            stale_files: HashMap::new(),
+            untracked_stale_since: None,
         }
     }
 
@@ -153,7 +301,11 @@ impl RrdpServer {
     /// the delta has already been checked against the jail and current
     /// objects of the publisher. Also note that this only becomes effective
     /// after the corresponding events have been applied.
-    fn publish(&self, elements: DeltaElements) -> Result<RrdpUpdate, Error> {
+    fn publish(
+        &self,
+        elements: DeltaElements,
+        retention: &RrdpRetention,
+    ) -> Result<RrdpUpdate, Error> {
         let next = self.serial + 1;
 
         let delta = Delta::new(self.session, next, elements);
@@ -167,25 +319,22 @@ impl RrdpServer {
         let snapshot_hash = HexEncodedHash::from_content(snapshot_xml.as_slice());
         let snapshot_ref = SnapshotRef::new(snapshot_uri, snapshot_path, snapshot_hash);
 
-        // keep at least 5 deltas if available, but beyond that no
-        // more then the combined size of which would exceed the
-        // size of the snapshot.
+        // keep the new delta, and older deltas for as long as the
+        // retention allows.
         let snapshot_size = next_snapshot.size();
         let mut deltas_size = delta.elements().size();
 
         let mut deltas = vec![&delta];
 
         for delta in &self.deltas {
-            if deltas.len() < 5 {
-                deltas.push(delta)
-            } else {
-                deltas_size += delta.elements().size();
-                if deltas_size < snapshot_size {
-                    deltas.push(delta)
-                } else {
-                    break;
-                }
+            deltas_size += delta.elements().size();
+            if deltas_size > snapshot_size
+                || deltas.len() >= retention.max_deltas
+                || retention.is_expired(delta.time())
+            {
+                break;
             }
+            deltas.push(delta);
         }
 
         let refs: Vec<DeltaRef> = deltas
@@ -207,10 +356,45 @@ impl RrdpServer {
         Ok(RrdpUpdate::new(delta, notification))
     }
 
+    /// Starts a new RRDP session with serial 1, with a snapshot for the
+    /// current objects and no deltas. Relying parties will notice the new
+    /// session and do a full resync from the snapshot.
+    fn reset_session(&self) -> Notification {
+        let session = RrdpSession::new();
+        let serial = 1;
+
+        let snapshot = self.snapshot.with_session(session, serial);
+        let snapshot_uri = Self::new_snapshot_uri(&self.rrdp_base_uri, &session, serial);
+        let snapshot_path = Self::new_snapshot_path(&self.rrdp_base_dir, &session, serial);
+        let snapshot_hash = HexEncodedHash::from_content(snapshot.xml().as_slice());
+        let snapshot_ref = SnapshotRef::new(snapshot_uri, snapshot_path, snapshot_hash);
+
+        Notification::new(session, serial, snapshot_ref, vec![])
+    }
+
+    /// Apply a session reset (as recorded in an event)
+    pub fn apply_session_reset(&mut self, notification: Notification) {
+        let time = notification.time();
+        self.stale_files.insert(self.session.to_string(), time);
+        self.expire_stale_files(time);
+
+        self.session = notification.session();
+        self.serial = notification.serial();
+        self.snapshot = self.snapshot.with_session(self.session, self.serial);
+        self.deltas = vec![];
+        self.notification = notification;
+    }
+
     /// Update the current RRDP state (as recorded in an event)
     pub fn apply_update(&mut self, update: RrdpUpdate) {
         let (delta, notification) = update.unpack();
 
+        // the current snapshot is superseded by this update
+        let time = notification.time();
+        let snapshot_rel = Self::snapshot_rel(&self.session, self.serial);
+        self.stale_files.insert(snapshot_rel, time);
+        self.expire_stale_files(time);
+
         self.serial = notification.serial();
 
         self.notification = notification;
@@ -221,6 +405,10 @@ impl RrdpServer {
 
         let last_delta = self.notification.last_delta().unwrap(); // always at least 1 delta for updates
         self.deltas.insert(0, delta);
+        for dropped in self.deltas.iter().filter(|d| d.serial() < last_delta) {
+            let delta_rel = Self::delta_rel(&self.session, dropped.serial());
+            self.stale_files.insert(delta_rel, time);
+        }
         self.deltas.retain(|d| d.serial() >= last_delta);
     }
 
@@ -246,76 +434,120 @@ impl RrdpServer {
             }
         }
 
-        // if nothing changed then we're done
-        if !something_changed {
-            return Ok(());
+        // something changed, update notification file
+        if something_changed {
+            let notification_path = self.notification_path();
+            self.notification.write_xml(&notification_path)?;
         }
 
-        // something changes, update notification file
-        let notification_path = self.notification_path();
-        self.notification.write_xml(&notification_path)?;
+        self.remove_stale_files(&self.rrdp_base_dir)
+    }
+
+    /// Copies the current snapshot and deltas to the target dir, unless
+    /// they are there already, and then the notification file, so that the
+    /// notification file in the target never refers to missing files. Files
+    /// which are no longer referenced are removed from the target in the
+    /// same way as they are removed locally.
+    fn mirror(&self, target: &Path) -> Result<(), Error> {
+        let mut rels = vec![Self::snapshot_rel(&self.session, self.serial)];
+        if let Some(last) = self.notification.last_delta() {
+            for serial in last..=self.serial {
+                rels.push(Self::delta_rel(&self.session, serial));
+            }
+        }
+
+        for rel in rels {
+            let target_path = target.join(&rel);
+            if !target_path.exists() {
+                Self::mirror_file(&self.rrdp_base_dir.join(&rel), &target_path)?;
+            }
+        }
+
+        let notification_path = target.join("notification.xml");
+        Self::mirror_file(&self.notification_path(), &notification_path)?;
+
+        self.remove_stale_files(target)
+    }
+
+    /// Copies a file to a temporary file next to the target, and then
+    /// renames it, so that the target is never seen partially written.
+    fn mirror_file(source: &Path, target: &Path) -> Result<(), Error> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = target.with_extension("tmp");
+        if let Err(e) = fs::copy(source, &tmp).and_then(|_| fs::rename(&tmp, target)) {
+            let _best_effort_rm = fs::remove_file(&tmp);
+            return Err(Error::IoError(e));
+        }
+        Ok(())
+    }
+
+    /// Removes files and dirs under the base dir which are not referenced
+    /// in the notification file, unless they became unreferenced less than
+    /// REPOSITORY_RRDP_FILES_RETAIN_MINS ago.
+    fn remove_stale_files(&self, base_dir: &Path) -> Result<(), Error> {
+        let retain_since = Time::now() - Duration::minutes(REPOSITORY_RRDP_FILES_RETAIN_MINS);
+        let is_stale = |rel: &str| match self.stale_files.get(rel) {
+            Some(since) => *since <= retain_since,
+            None => match self.untracked_stale_since {
+                Some(since) => since <= retain_since,
+                None => false,
+            },
+        };
 
         // clean up under the base dir:
-        // - old session dirs
-        for entry in fs::read_dir(&self.rrdp_base_dir)? {
+        // - old session dirs, leaving anything else alone
+        let session = self.session.to_string();
+        for entry in fs::read_dir(base_dir)? {
             let entry = entry?;
-            if self.session.to_string() == entry.file_name().to_string_lossy() {
-                continue;
-            } else {
-                let path = entry.path();
-                if path.is_dir() {
-                    let _best_effort_rm = fs::remove_dir_all(path);
-                }
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = entry.path();
+            let is_session = Uuid::parse_str(&name).is_ok();
+            if name != session && is_session && path.is_dir() && is_stale(&name) {
+                let _best_effort_rm = fs::remove_dir_all(path);
             }
         }
 
         // clean up under the current session
-        let mut session_dir = self.rrdp_base_dir.clone();
-        session_dir.push(self.session.to_string());
+        let session_dir = base_dir.join(&session);
 
         for entry in fs::read_dir(&session_dir)? {
             let entry = entry?;
             let path = entry.path();
 
-            // remove any dir or file that is:
-            // - not a number
-            // - a number that is higher than the current serial
-            // - a number that is lower than the last delta (if set)
             if let Ok(serial) = u64::from_str(entry.file_name().to_string_lossy().as_ref()) {
-                // Clean up old serial dirs
-                if let Some(last) = self.notification.last_delta() {
-                    if serial < last {
-                        if path.is_dir() {
-                            let _best_effort_rm = fs::remove_dir_all(path);
-                        } else {
-                            let _best_effort_rm = fs::remove_file(path);
-                        }
-
-                        continue;
+                // remove the delta and snapshot for the serial if they are
+                // no longer referenced, and then the dir if it is empty
+                let delta_referenced = match self.notification.last_delta() {
+                    Some(last) => serial >= last && serial <= self.serial,
+                    None => false,
+                };
+                let snapshot_referenced = serial == self.serial;
+
+                let files = [
+                    (Self::delta_rel(&self.session, serial), delta_referenced),
+                    (
+                        Self::snapshot_rel(&self.session, serial),
+                        snapshot_referenced,
+                    ),
+                ];
+
+                for (rel, referenced) in files.iter() {
+                    let file_path = base_dir.join(rel);
+                    if !referenced && file_path.exists() && is_stale(rel) {
+                        // and the compressed copy served over HTTP, if any
+                        let _best_effort_rm = fs::remove_file(gzip_path(&file_path));
+                        let _best_effort_rm = fs::remove_file(file_path);
                     }
                 }
 
-                // Clean up snapshots in all dirs except the current
-                // *IF* the snapshot is older than REPOSITORY_RRDP_SNAPSHOT_RETAIN_MINS
-                if serial != self.serial {
-                    let snapshot_path =
-                        Self::new_snapshot_path(&self.rrdp_base_dir, &self.session, serial);
-                    if snapshot_path.exists() {
-                        if let Ok(meta) = fs::metadata(&snapshot_path) {
-                            if let Ok(created) = meta.created() {
-                                let now = SystemTime::now();
-                                if let Ok(duration) = now.duration_since(created) {
-                                    let minutes_old = duration.as_secs() / 60;
-                                    if minutes_old >= REPOSITORY_RRDP_SNAPSHOT_RETAIN_MINS {
-                                        let _best_effort_rm = fs::remove_file(snapshot_path);
-                                    }
-                                }
-                            }
-                        }
-                    }
+                if path.is_dir() {
+                    // fails if the dir is not empty
+                    let _best_effort_rm = fs::remove_dir(path);
                 }
             } else {
-                // clean up dirs or files under the base dir which are not sessions
+                // clean up dirs or files under the session which are not serials
                 if path.is_dir() {
                     let _best_effort_rm = fs::remove_dir_all(path);
                 } else {
@@ -326,6 +558,14 @@ impl RrdpServer {
 
         Ok(())
     }
+
+    /// Forgets about stale files which are past the retention time, so that
+    /// they will be removed from disk.
+    fn expire_stale_files(&mut self, now: Time) {
+        self.untracked_stale_since.get_or_insert(now);
+        let retain_since = now - Duration::minutes(REPOSITORY_RRDP_FILES_RETAIN_MINS);
+        self.stale_files.retain(|_, since| *since > retain_since);
+    }
 }
 
 /// rrdp paths and uris
@@ -487,6 +727,10 @@ impl Aggregate for Repository {
                 self.stats
                     .publish(&publisher_handle, publisher_stats, notification)
             }
+            EvtDet::SessionReset(notification) => {
+                self.rrdp.apply_session_reset(notification);
+                self.stats.session_reset(&self.rrdp.notification);
+            }
         }
     }
 
@@ -498,8 +742,13 @@ impl Aggregate for Repository {
 
         match command.into_details() {
             CmdDet::AddPublisher(publisher_request) => self.add_publisher(publisher_request),
-            CmdDet::RemovePublisher(publisher) => self.remove_publisher(publisher),
-            CmdDet::Publish(publisher_handle, delta) => self.publish(publisher_handle, delta),
+            CmdDet::RemovePublisher(publisher, retention) => {
+                self.remove_publisher(publisher, &retention)
+            }
+            CmdDet::Publish(publisher_handle, delta, retention) => {
+                self.publish(publisher_handle, delta, &retention)
+            }
+            CmdDet::ResetSession => Ok(self.reset_session()),
         }
     }
 }
@@ -530,7 +779,11 @@ impl Repository {
     }
 
     /// Removes a publisher and all its content
-    fn remove_publisher(&self, publisher_handle: PublisherHandle) -> Result<Vec<Evt>, Error> {
+    fn remove_publisher(
+        &self,
+        publisher_handle: PublisherHandle,
+        retention: &RrdpRetention,
+    ) -> Result<Vec<Evt>, Error> {
         let publisher = self.get_publisher(&publisher_handle)?;
 
         let withdraws = publisher
@@ -540,7 +793,7 @@ impl Repository {
             .map(|p| p.as_withdraw())
             .collect();
         let elements = DeltaElements::new(vec![], vec![], withdraws);
-        let update = self.rrdp.publish(elements)?;
+        let update = self.rrdp.publish(elements, retention)?;
 
         Ok(vec![EvtDet::publisher_removed(
             &self.handle,
@@ -587,6 +840,20 @@ impl Repository {
         &self.stats
     }
 
+    /// Describes the current RRDP session as what relying parties will see
+    /// after a reset from the session in the given earlier stats.
+    pub fn session_reset_report(&self, old: &RepoStats) -> SessionReset {
+        SessionReset {
+            old_session: old.session(),
+            old_serial: old.serial(),
+            new_session: self.rrdp.session,
+            new_serial: self.rrdp.serial,
+            notification_uri: self.rrdp.notification_uri(),
+            snapshot_uri: self.rrdp.snapshot_uri(self.rrdp.serial),
+            objects: self.rrdp.snapshot.elements().len(),
+        }
+    }
+
     pub fn publishers(&self) -> Vec<PublisherHandle> {
         self.publishers.keys().cloned().collect()
     }
@@ -606,11 +873,12 @@ impl Repository {
         &self,
         publisher_handle: PublisherHandle,
         delta: PublishDelta,
+        retention: &RrdpRetention,
     ) -> Result<Vec<Evt>, Error> {
         let publisher = self.get_publisher(&publisher_handle)?;
         let delta_elements = DeltaElements::from(delta);
         publisher.verify_delta(&delta_elements)?;
-        let rrdp_update = self.rrdp.publish(delta_elements)?;
+        let rrdp_update = self.rrdp.publish(delta_elements, retention)?;
 
         Ok(vec![EvtDet::published(
             &self.handle,
@@ -620,17 +888,35 @@ impl Repository {
         )])
     }
 
-    /// Update the RRPD and Rsync files on disk.
-    pub fn write(&self) -> Result<(), Error> {
+    /// Starts a new RRDP session, e.g. after the repository was restored
+    /// from a backup and relying parties may have seen later serials.
+    fn reset_session(&self) -> Vec<Evt> {
+        let notification = self.rrdp.reset_session();
+        vec![EvtDet::session_reset(
+            &self.handle,
+            self.version,
+            notification,
+        )]
+    }
+
+    /// Update the RRPD and Rsync files on disk. Old rsync versions are kept
+    /// for the given number of minutes after they were superseded.
+    pub fn write(&self, rsync_retain_mins: u32) -> Result<(), Error> {
         // update RRDP
         self.rrdp.write()?;
 
         // re-sync RRDP snapshot to rsync files
         let snapshot = self.rrdp.snapshot();
-        self.rsync.write(snapshot)?;
+        self.rsync.write(snapshot, rsync_retain_mins)?;
 
         Ok(())
     }
+
+    /// Mirrors the RRDP files to the target dir, e.g. a web root which is
+    /// served by a CDN. This should be called after they were written.
+    pub fn mirror(&self, target: &Path) -> Result<(), Error> {
+        self.rrdp.mirror(target)
+    }
 }
 
 /// # Miscellaneous
@@ -657,6 +943,11 @@ pub struct RepoStats {
     session: RrdpSession,
     serial: u64,
     last_update: Option<Time>,
+
+    /// The status of mirroring the RRDP files to each target dir. This is
+    /// not part of the repository state, but added when stats are shown.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    mirrors: HashMap<String, MirrorStatus>,
 }
 
 impl Default for RepoStats {
@@ -666,6 +957,7 @@ impl Default for RepoStats {
             session: RrdpSession::default(),
             serial: 0,
             last_update: None,
+            mirrors: HashMap::new(),
         }
     }
 }
@@ -677,9 +969,19 @@ impl RepoStats {
             session,
             serial: 0,
             last_update: None,
+            mirrors: HashMap::new(),
         }
     }
 
+    pub fn with_mirrors(mut self, mirrors: HashMap<String, MirrorStatus>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    pub fn mirrors(&self) -> &HashMap<String, MirrorStatus> {
+        &self.mirrors
+    }
+
     pub fn publish(
         &mut self,
         publisher: &PublisherHandle,
@@ -702,6 +1004,12 @@ impl RepoStats {
         self.last_update = Some(notification.time())
     }
 
+    pub fn session_reset(&mut self, notification: &Notification) {
+        self.session = notification.session();
+        self.serial = notification.serial();
+        self.last_update = Some(notification.time());
+    }
+
     pub fn get_publishers(&self) -> &HashMap<PublisherHandle, PublisherStats> {
         &self.publishers
     }
@@ -784,16 +1092,356 @@ impl Default for PublisherStats {
     }
 }
 
+//------------ SessionReset --------------------------------------------------
+
+/// Describes a reset of the RRDP session, and what relying parties will see
+/// as a result.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SessionReset {
+    old_session: RrdpSession,
+    old_serial: u64,
+    new_session: RrdpSession,
+    new_serial: u64,
+    notification_uri: uri::Https,
+    snapshot_uri: uri::Https,
+    objects: usize,
+}
+
+impl SessionReset {
+    pub fn old_session(&self) -> RrdpSession {
+        self.old_session
+    }
+
+    pub fn new_session(&self) -> RrdpSession {
+        self.new_session
+    }
+
+    pub fn new_serial(&self) -> u64 {
+        self.new_serial
+    }
+
+    pub fn snapshot_uri(&self) -> &uri::Https {
+        &self.snapshot_uri
+    }
+
+    /// The number of objects in the new snapshot.
+    pub fn objects(&self) -> usize {
+        self.objects
+    }
+}
+
+impl fmt::Display for SessionReset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "RRDP session reset from '{}' (serial {}) to '{}' (serial {})",
+            self.old_session, self.old_serial, self.new_session, self.new_serial
+        )?;
+        writeln!(
+            f,
+            "Relying parties will do a full resync using the new snapshot"
+        )?;
+        writeln!(f, "Notification file: {}", self.notification_uri)?;
+        writeln!(
+            f,
+            "Snapshot:          {} ({} objects)",
+            self.snapshot_uri, self.objects
+        )
+    }
+}
+
+//------------ MirrorStatus --------------------------------------------------
+
+/// The status of mirroring the RRDP files to a target dir. Times are in
+/// seconds since the epoch. While a target keeps failing, the next attempt
+/// is backed off.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct MirrorStatus {
+    last_attempt: Option<i64>,
+    last_success: Option<i64>,
+    last_error: Option<ErrorResponse>,
+    failures: u32,
+    #[serde(default)]
+    next_attempt: Option<i64>,
+}
+
+impl MirrorStatus {
+    pub fn last_attempt(&self) -> Option<i64> {
+        self.last_attempt
+    }
+
+    pub fn last_success(&self) -> Option<i64> {
+        self.last_success
+    }
+
+    pub fn last_error(&self) -> Option<&ErrorResponse> {
+        self.last_error.as_ref()
+    }
+
+    /// The number of consecutive failed attempts.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// The earliest time at which mirroring to a failing target is tried
+    /// again.
+    pub fn next_attempt(&self) -> Option<i64> {
+        self.next_attempt
+    }
+
+    pub fn is_failing(&self) -> bool {
+        self.failures > 0
+    }
+
+    /// Returns whether a failing target should be tried again at the given
+    /// time.
+    pub fn is_due(&self, now: i64) -> bool {
+        self.next_attempt.map(|next| next <= now).unwrap_or(true)
+    }
+
+    pub fn set_success(&mut self, now: i64) {
+        self.last_attempt = Some(now);
+        self.last_success = Some(now);
+        self.last_error = None;
+        self.failures = 0;
+        self.next_attempt = None;
+    }
+
+    pub fn set_failure(&mut self, now: i64, error: ErrorResponse, next_attempt: i64) {
+        self.last_attempt = Some(now);
+        self.last_error = Some(error);
+        self.failures += 1;
+        self.next_attempt = Some(next_attempt);
+    }
+}
+
 //------------ Tests ---------------------------------------------------------
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
+    use crate::commons::api::rrdp::PublishElement;
+    use crate::commons::api::Base64;
+    use crate::test;
+
+    fn rrdp_server(d: &PathBuf) -> RrdpServer {
+        RrdpServer::new(
+            test::https("https://localhost/rrdp/"),
+            d,
+            RrdpSession::new(),
+        )
+    }
+
+    fn publish(server: &mut RrdpServer, publishes: Vec<PublishElement>, withdraws: usize) {
+        let withdraws = server
+            .snapshot()
+            .elements()
+            .iter()
+            .take(withdraws)
+            .map(|p| p.as_withdraw())
+            .collect();
+        let elements = DeltaElements::new(publishes, vec![], withdraws);
+        let update = server
+            .publish(elements, &RrdpRetention::new(3, 24))
+            .unwrap();
+        server.apply_update(update);
+        server.write().unwrap();
+    }
+
+    fn object(name: &str, content: &[u8]) -> PublishElement {
+        let uri = test::rsync(&format!("rsync://localhost/repo/{}", name));
+        PublishElement::new(Base64::from_content(content), uri)
+    }
+
     #[test]
     fn deserialize_0_4_2_snapshot() {
         let json = include_str!("../../test-resources/repository/snapshot-v042.json");
         let mut repo: Repository = serde_json::from_str(json).unwrap();
         repo.regenerate_stats();
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn rsync_write_versions() {
+        test::test_under_tmp(|d| {
+            let store = RsyncdStore::new(test::rsync("rsync://localhost/repo/"), &d);
+            let current = d.join(REPOSITORY_RSYNC_DIR).join(RSYNC_CURRENT);
+
+            // a "current" dir written by an earlier version is moved aside
+            file::save(b"old", &current.join("0.cer")).unwrap();
+
+            let mut server = rrdp_server(&d);
+            publish(&mut server, vec![object("1.cer", b"1")], 0);
+            store.write(server.snapshot(), 10).unwrap();
+
+            assert!(RsyncdStore::is_symlink(&current));
+            assert!(current.join("1.cer").exists());
+            assert!(!current.join("0.cer").exists());
+
+            // superseded versions are kept for the retention time
+            publish(&mut server, vec![object("2.cer", b"2")], 0);
+            store.write(server.snapshot(), 10).unwrap();
+            assert!(current.join("2.cer").exists());
+            let versions = fs::read_dir(d.join(REPOSITORY_RSYNC_DIR)).unwrap();
+            assert_eq!(4, versions.count()); // 0-current, two versions and the link
+
+            // a temporary link left by an interrupted write is removed
+            let tmp_link = d.join(REPOSITORY_RSYNC_DIR).join("current.1-1.tmp");
+            symlink("1-1", &tmp_link).unwrap();
+
+            store.write(server.snapshot(), 0).unwrap();
+            assert!(!RsyncdStore::is_symlink(&tmp_link));
+            let versions = fs::read_dir(d.join(REPOSITORY_RSYNC_DIR)).unwrap();
+            assert_eq!(2, versions.count());
+            assert!(current.join("1.cer").exists());
+            assert!(current.join("2.cer").exists());
+        })
+    }
+
+    #[test]
+    fn rrdp_mirror() {
+        test::test_under_tmp(|d| {
+            let mut server = rrdp_server(&d);
+            let target = d.join("mirror");
+            file::save(b"<html/>", &target.join("index.html")).unwrap();
+            fs::create_dir_all(target.join("other")).unwrap();
+
+            for i in 0..5 {
+                let name = format!("{}.cer", i);
+                publish(&mut server, vec![object(&name, name.as_bytes())], 0);
+                server.mirror(&target).unwrap();
+            }
+
+            let local = fs::read(server.notification_path()).unwrap();
+            assert_eq!(local, fs::read(target.join("notification.xml")).unwrap());
+            let snapshot_rel = RrdpServer::snapshot_rel(&server.session, 5);
+            assert!(target.join(snapshot_rel).exists());
+            for serial in 3..=5 {
+                let delta_rel = RrdpServer::delta_rel(&server.session, serial);
+                assert!(target.join(delta_rel).exists());
+            }
+
+            // unreferenced files are removed after the retention time, but
+            // content which is not ours is left alone
+            let old_delta = target.join(RrdpServer::delta_rel(&server.session, 1));
+            assert!(old_delta.exists());
+            let retain = Duration::minutes(REPOSITORY_RRDP_FILES_RETAIN_MINS);
+            age_stale_files(&mut server, retain);
+            server.mirror(&target).unwrap();
+            assert!(!old_delta.exists());
+            assert!(target.join("index.html").exists());
+            assert!(target.join("other").exists());
+        })
+    }
+
+    #[test]
+    fn rrdp_retention() {
+        test::test_under_tmp(|d| {
+            let mut server = rrdp_server(&d);
+
+            // no more than the maximum number of deltas
+            for i in 0..5 {
+                let name = format!("{}.cer", i);
+                publish(&mut server, vec![object(&name, name.as_bytes())], 0);
+            }
+            assert_eq!(5, server.notification.serial());
+            assert_eq!(Some(3), server.notification.last_delta());
+            assert_eq!(3, server.deltas.len());
+
+            // no more deltas than fit in the size of the snapshot
+            let big = vec![0; 1024];
+            publish(&mut server, vec![], 5);
+            publish(&mut server, vec![object("big.cer", &big)], 0);
+            publish(&mut server, vec![], 1);
+            publish(&mut server, vec![object("big.cer", &big)], 0);
+            assert_eq!(Some(8), server.notification.last_delta());
+            assert_eq!(2, server.deltas.len());
+        })
+    }
+
+    #[test]
+    fn rrdp_remove_stale_files() {
+        test::test_under_tmp(|d| {
+            let mut server = rrdp_server(&d);
+            let old_session = RrdpSession::new().to_string();
+            let old_session = d.join(REPOSITORY_RRDP_DIR).join(old_session);
+            fs::create_dir_all(&old_session).unwrap();
+
+            for i in 0..5 {
+                let name = format!("{}.cer", i);
+                publish(&mut server, vec![object(&name, name.as_bytes())], 0);
+            }
+
+            // unknown session dirs are kept for a while as well
+            assert!(old_session.exists());
+
+            // unreferenced files are kept for a while
+            let rel_path = |rel: String| server.rrdp_base_dir.join(rel);
+            let old_delta = rel_path(RrdpServer::delta_rel(&server.session, 1));
+            let old_snapshot = rel_path(RrdpServer::snapshot_rel(&server.session, 4));
+            let snapshot = rel_path(RrdpServer::snapshot_rel(&server.session, 5));
+            assert!(old_delta.exists());
+            assert!(old_snapshot.exists());
+
+            // the compressed copies served over HTTP go with them
+            file::save(b"gzipped", &gzip_path(&old_delta)).unwrap();
+
+            // but removed after the retention time
+            let retain = Duration::minutes(REPOSITORY_RRDP_FILES_RETAIN_MINS);
+            age_stale_files(&mut server, retain);
+            server.write().unwrap();
+            assert!(!old_session.exists());
+            assert!(!old_delta.exists());
+            assert!(!gzip_path(&old_delta).exists());
+            assert!(!old_delta.parent().unwrap().exists());
+            assert!(!old_snapshot.exists());
+            assert!(snapshot.exists());
+        })
+    }
+
+    #[test]
+    fn rrdp_keep_untracked_files_after_upgrade() {
+        test::test_under_tmp(|d| {
+            let mut server = rrdp_server(&d);
+            for i in 0..5 {
+                let name = format!("{}.cer", i);
+                publish(&mut server, vec![object(&name, name.as_bytes())], 0);
+            }
+
+            // state saved by a version which did not track stale files
+            let json = serde_json::to_value(&server).unwrap();
+            let mut json = json.as_object().unwrap().clone();
+            json.remove("stale_files");
+            json.remove("untracked_stale_since");
+            let mut server: RrdpServer = serde_json::from_value(json.into()).unwrap();
+
+            let rel_path = |rel: String| server.rrdp_base_dir.join(rel);
+            let old_delta = rel_path(RrdpServer::delta_rel(&server.session, 1));
+            let old_snapshot = rel_path(RrdpServer::snapshot_rel(&server.session, 3));
+            assert!(old_delta.exists());
+            assert!(old_snapshot.exists());
+
+            // the first update after the upgrade keeps untracked files
+            publish(&mut server, vec![object("5.cer", b"5")], 0);
+            assert!(old_delta.exists());
+            assert!(old_snapshot.exists());
+
+            // until they are past the retention time
+            let retain = Duration::minutes(REPOSITORY_RRDP_FILES_RETAIN_MINS);
+            age_stale_files(&mut server, retain);
+            server.write().unwrap();
+            assert!(!old_delta.exists());
+            assert!(!old_snapshot.exists());
+        })
+    }
+
+    fn age_stale_files(server: &mut RrdpServer, age: Duration) {
+        for since in server.stale_files.values_mut() {
+            *since = *since - age;
+        }
+        if let Some(since) = server.untracked_stale_since.as_mut() {
+            *since = *since - age;
+        }
+    }
 }
@@ -3,25 +3,24 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::commons::api::Handle;
-use crate::commons::api::{Publish, PublishDelta, RepositoryContact, Update, Withdraw};
+use crate::commons::api::{ListReply, Publish, PublishDelta, RepositoryContact, Update, Withdraw};
 use crate::commons::error::Error;
-use crate::commons::util::softsigner::OpenSslSigner;
-use crate::daemon::ca::CaServer;
+use crate::daemon::ca::{CaServer, Signer};
 use crate::pubd::PubServer;
 
 //------------ CaPublisher ---------------------------------------------------
 
 /// A helper which orchestrates publishing by CAs at either local, or
 /// remote, repositories.
-pub struct CaPublisher {
-    caserver: Arc<CaServer<OpenSslSigner>>,
+pub struct CaPublisher<S: Signer> {
+    caserver: Arc<CaServer<S>>,
     pubserver: Option<Arc<PubServer>>,
 }
 
 /// # Construct
 ///
-impl CaPublisher {
-    pub fn new(caserver: Arc<CaServer<OpenSslSigner>>, pubserver: Option<Arc<PubServer>>) -> Self {
+impl<S: Signer> CaPublisher<S> {
+    pub fn new(caserver: Arc<CaServer<S>>, pubserver: Option<Arc<PubServer>>) -> Self {
         CaPublisher {
             caserver,
             pubserver,
@@ -29,13 +28,15 @@ impl CaPublisher {
     }
 }
 
-impl CaPublisher {
+impl<S: Signer> CaPublisher<S> {
     fn get_embedded(&self) -> Result<&Arc<PubServer>, Error> {
         self.pubserver
             .as_ref()
             .ok_or_else(|| Error::PublisherNoEmbeddedRepo)
     }
 
+    /// Publishes the current objects of the CA at its repository. The
+    /// outcome is recorded in the publication status of the CA.
     pub async fn publish(&self, ca_handle: &Handle) -> Result<(), Error> {
         let ca = self.caserver.get_ca(ca_handle)?;
 
@@ -46,11 +47,9 @@ impl CaPublisher {
             Err(_) => return Ok(()),
         };
 
-        let list_reply = match &repo_contact {
-            RepositoryContact::Embedded(_) => self.get_embedded()?.list(ca_handle)?,
-            RepositoryContact::Rfc8181(repo) => {
-                self.caserver.send_rfc8181_list(ca_handle, repo).await?
-            }
+        let list_reply = match self.list(ca_handle, repo_contact).await {
+            Ok(list_reply) => list_reply,
+            Err(e) => return Err(self.publish_failed(ca_handle, e, None)),
         };
 
         let delta = {
@@ -83,20 +82,26 @@ impl CaPublisher {
             PublishDelta::new(publishes, updates, withdraws)
         };
 
-        match &repo_contact {
-            RepositoryContact::Embedded(_) => {
-                self.get_embedded()?.publish(ca_handle.clone(), delta)?
-            }
-            RepositoryContact::Rfc8181(repo) => {
-                self.caserver
-                    .send_rfc8181_delta(ca_handle, repo, delta)
-                    .await?
-            }
-        };
+        let pending = delta.len();
+        if let Err(e) = self.send(ca_handle, repo_contact, delta).await {
+            return Err(self.publish_failed(ca_handle, e, Some(pending)));
+        }
 
+        self.caserver.set_repo_success(ca_handle, ca.next_update());
         Ok(())
     }
 
+    fn publish_failed(&self, ca_handle: &Handle, e: Error, pending: Option<usize>) -> Error {
+        let status = self.caserver.set_repo_failure(ca_handle, &e, pending);
+        warn!(
+            "Publication for '{}' failed {} consecutive time(s), {} objects pending",
+            ca_handle,
+            status.failures(),
+            status.pending()
+        );
+        e
+    }
+
     pub async fn clean_up(&self, ca_handle: &Handle) -> Result<(), Error> {
         let ca = self.caserver.get_ca(ca_handle)?;
 
@@ -110,26 +115,35 @@ impl CaPublisher {
             repo
         );
 
-        let list_reply = match repo {
-            RepositoryContact::Embedded(_) => self.get_embedded()?.list(ca_handle)?,
+        let list_reply = self.list(ca_handle, repo).await?;
+        let delta = list_reply.into_withdraw_delta();
+        self.send(ca_handle, repo, delta).await
+    }
+
+    async fn list(&self, ca_handle: &Handle, repo: &RepositoryContact) -> Result<ListReply, Error> {
+        match repo {
+            RepositoryContact::Embedded(_) => self.get_embedded()?.list(ca_handle),
             RepositoryContact::Rfc8181(repo) => {
-                self.caserver.send_rfc8181_list(ca_handle, repo).await?
+                self.caserver.send_rfc8181_list(ca_handle, repo).await
             }
-        };
-
-        let delta = list_reply.into_withdraw_delta();
+        }
+    }
 
+    async fn send(
+        &self,
+        ca_handle: &Handle,
+        repo: &RepositoryContact,
+        delta: PublishDelta,
+    ) -> Result<(), Error> {
         match repo {
             RepositoryContact::Embedded(_) => {
-                self.get_embedded()?.publish(ca_handle.clone(), delta)?
+                self.get_embedded()?.publish(ca_handle.clone(), delta)
             }
-            RepositoryContact::Rfc8181(res) => {
+            RepositoryContact::Rfc8181(repo) => {
                 self.caserver
-                    .send_rfc8181_delta(ca_handle, res, delta)
-                    .await?
+                    .send_rfc8181_delta(ca_handle, repo, delta)
+                    .await
             }
         }
-
-        Ok(())
     }
 }
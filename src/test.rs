@@ -13,6 +13,7 @@ use rand::{thread_rng, Rng};
 use hyper::StatusCode;
 use tokio::time::{delay_for, timeout};
 
+use rpki::crypto::KeyIdentifier;
 use rpki::uri;
 use rpki::uri::Rsync;
 
@@ -20,10 +21,11 @@ use crate::cli::options::{BulkCaCommand, CaCommand, Command, Options, Publishers
 use crate::cli::report::{ApiResponse, ReportFormat};
 use crate::cli::{Error, KrillClient};
 use crate::commons::api::{
-    AddChildRequest, CertAuthInfo, CertAuthInit, CertifiedKeyInfo, ChildAuthRequest, ChildHandle,
-    Handle, ParentCaContact, ParentCaReq, ParentHandle, Publish, PublisherDetails, PublisherHandle,
-    RepositoryUpdate, ResourceClassKeysInfo, ResourceClassName, ResourceSet, RoaDefinition,
-    RoaDefinitionUpdates, UpdateChildRequest,
+    AddChildRequest, AspaDefinitionList, AspaDefinitionUpdates, CertAuthInfo, CertAuthInit,
+    CertifiedKeyInfo, ChildAuthRequest, ChildHandle, GhostbusterRecord, Handle, ParentCaContact,
+    ParentCaReq, ParentHandle, Publish, PublisherDetails, PublisherHandle, RepositoryUpdate,
+    ResourceClassKeysInfo, ResourceClassName, ResourceSet, RoaDefinition, RoaDefinitionUpdates,
+    RouterCertList, RouterCertRequest, UpdateChildRequest,
 };
 use crate::commons::bgp::Announcement;
 use crate::commons::remote::rfc8183;
@@ -302,6 +304,105 @@ pub async fn ca_route_authorizations_update_expect_error(
     .await;
 }
 
+pub async fn ca_aspas_update(handle: &Handle, updates: AspaDefinitionUpdates) {
+    krill_admin(Command::CertAuth(CaCommand::AspasUpdate(
+        handle.clone(),
+        updates,
+    )))
+    .await;
+}
+
+pub async fn ca_aspas_update_expect_error(handle: &Handle, updates: AspaDefinitionUpdates) {
+    krill_admin_expect_error(Command::CertAuth(CaCommand::AspasUpdate(
+        handle.clone(),
+        updates,
+    )))
+    .await;
+}
+
+pub async fn ca_aspas_list(handle: &Handle) -> AspaDefinitionList {
+    match krill_admin(Command::CertAuth(CaCommand::AspasList(handle.clone()))).await {
+        ApiResponse::AspaDefinitions(list) => list,
+        _ => panic!("Expected ASPA definitions"),
+    }
+}
+
+pub async fn ca_router_certify(handle: &Handle, request: RouterCertRequest) {
+    krill_admin(Command::CertAuth(CaCommand::RouterCertify(
+        handle.clone(),
+        request,
+    )))
+    .await;
+}
+
+pub async fn ca_router_certify_expect_error(handle: &Handle, request: RouterCertRequest) {
+    krill_admin_expect_error(Command::CertAuth(CaCommand::RouterCertify(
+        handle.clone(),
+        request,
+    )))
+    .await;
+}
+
+pub async fn ca_router_revoke(handle: &Handle, key: KeyIdentifier) {
+    krill_admin(Command::CertAuth(CaCommand::RouterRevoke(
+        handle.clone(),
+        key,
+    )))
+    .await;
+}
+
+pub async fn ca_router_certs_list(handle: &Handle) -> RouterCertList {
+    match krill_admin(Command::CertAuth(CaCommand::RouterCertsList(
+        handle.clone(),
+    )))
+    .await
+    {
+        ApiResponse::RouterCerts(list) => list,
+        _ => panic!("Expected router certificates"),
+    }
+}
+
+pub async fn ca_ghostbuster_update(handle: &Handle, record: GhostbusterRecord) {
+    krill_admin(Command::CertAuth(CaCommand::GhostbusterUpdate(
+        handle.clone(),
+        record,
+    )))
+    .await;
+}
+
+pub async fn ca_ghostbuster_update_expect_error(handle: &Handle, record: GhostbusterRecord) {
+    krill_admin_expect_error(Command::CertAuth(CaCommand::GhostbusterUpdate(
+        handle.clone(),
+        record,
+    )))
+    .await;
+}
+
+pub async fn ca_ghostbuster_remove(handle: &Handle) {
+    krill_admin(Command::CertAuth(CaCommand::GhostbusterRemove(
+        handle.clone(),
+    )))
+    .await;
+}
+
+pub async fn ca_ghostbuster_remove_expect_error(handle: &Handle) {
+    krill_admin_expect_error(Command::CertAuth(CaCommand::GhostbusterRemove(
+        handle.clone(),
+    )))
+    .await;
+}
+
+pub async fn ca_ghostbuster_show(handle: &Handle) -> Option<GhostbusterRecord> {
+    match krill_admin(Command::CertAuth(CaCommand::GhostbusterShow(
+        handle.clone(),
+    )))
+    .await
+    {
+        ApiResponse::Ghostbuster(record) => record,
+        _ => panic!("Expected Ghostbusters vCard"),
+    }
+}
+
 pub async fn ca_details(handle: &Handle) -> CertAuthInfo {
     match krill_admin(Command::CertAuth(CaCommand::Show(handle.clone()))).await {
         ApiResponse::CertAuthInfo(inf) => inf,
@@ -6,9 +6,16 @@ use std::path::PathBuf;
 use std::{fmt, fs, io};
 
 use crate::commons::api::Handle;
-use crate::commons::eventsourcing::{DiskKeyStore, KeyStore, KeyStoreError, KeyStoreVersion};
+use crate::commons::eventsourcing::{
+    AnyKeyStore, DiskKeyStore, KeyStore, KeyStoreError, KeyStoreVersion, SnapshotCheck,
+    StorageBackend,
+};
 use crate::commons::util::file;
+use crate::commons::util::signer::KrillSigner;
+use crate::constants::{CASERVER_DIR, PUBSERVER_DIR};
+use crate::daemon::ca::CertAuth;
 use crate::daemon::krillserver::KrillServer;
+use crate::pubd::Repository;
 use crate::upgrades::roa_cleanup_0_7_0::RoaCleanupError;
 
 pub mod pre_0_6_0;
@@ -88,18 +95,70 @@ pub fn pre_start_upgrade(work_dir: &PathBuf) -> Result<(), UpgradeError> {
 /// Should be called right after the KrillServer is initiated
 pub fn post_start_upgrade(work_dir: &PathBuf, server: &KrillServer) -> Result<(), UpgradeError> {
     let version_0_7 = KeyStoreVersion::V0_7;
-    let ca_store = DiskKeyStore::new(work_dir, "cas");
-    let pubd_store = DiskKeyStore::new(work_dir, "pubd");
-    if ca_store.get_version()? != version_0_7 {
+    let version_0_8 = KeyStoreVersion::V0_8;
+    let ca_store = AnyKeyStore::existing(work_dir, "cas")?;
+    let pubd_store = AnyKeyStore::existing(work_dir, "pubd")?;
+
+    let version = ca_store.get_version()?;
+    if version != version_0_7 && version != version_0_8 {
         info!("Will clean up redundant ROAs for all CAs and update version of storage dirs");
         roa_cleanup_0_7_0::roa_cleanup(server)?;
         ca_store.set_version(&version_0_7)?;
         pubd_store.set_version(&version_0_7)?;
     }
 
+    if ca_store.get_version()? != version_0_8 {
+        // Republishing will aggregate the ROAs for CAs which have more
+        // authorizations than the configured threshold. The new aggregated
+        // ROAs are published in the same delta that withdraws the old ones,
+        // so there is no gap in coverage.
+        info!("Will republish all CAs to aggregate ROAs and update version of storage dirs");
+        server.republish_all().map_err(UpgradeError::custom)?;
+        ca_store.set_version(&version_0_8)?;
+        pubd_store.set_version(&version_0_8)?;
+    }
+
     Ok(())
 }
 
+/// Converts the stores for the CAs and the publication server to the given
+/// storage backend. Returns the number of aggregates converted for each.
+pub fn migrate_storage(
+    work_dir: &PathBuf,
+    backend: StorageBackend,
+) -> Result<Vec<(&'static str, usize)>, UpgradeError> {
+    let mut res = vec![];
+    for name_space in &["cas", "pubd"] {
+        let migrated = AnyKeyStore::migrate(work_dir, name_space, backend)?;
+        if migrated > 0 {
+            info!(
+                "Converted {} aggregates in '{}' to {} storage",
+                migrated, name_space, backend
+            );
+        }
+        res.push((*name_space, migrated));
+    }
+    Ok(res)
+}
+
+/// Checks all snapshots of the CAs and the publication server against a
+/// replay of their events.
+pub fn verify_snapshots(work_dir: &PathBuf) -> Result<Vec<SnapshotCheck>, UpgradeError> {
+    let mut res = vec![];
+
+    let ca_store = AnyKeyStore::existing(work_dir, CASERVER_DIR)?;
+    for handle in ca_store.aggregates() {
+        res.append(&mut ca_store.verify_snapshots::<CertAuth<KrillSigner>>(&handle)?);
+    }
+
+    let pubd_store = AnyKeyStore::existing(work_dir, PUBSERVER_DIR)?;
+    for handle in pubd_store.aggregates() {
+        res.append(&mut pubd_store.verify_snapshots::<Repository>(&handle)?);
+    }
+
+    Ok(res)
+}
+
 fn upgrade_pre_0_6_0_cas_commands(work_dir: &PathBuf) -> Result<(), UpgradeError> {
     let pre_0_6_0_ca_commands = pre_0_6_0::UpgradeCas;
 
@@ -103,6 +103,7 @@ impl UpgradeStore for UpgradeCas {
                     last_event: ca.version(),
                     last_command,
                     last_update,
+                    ..StoredValueInfo::default()
                 };
                 store.save_info(&ca_handle, &info)?;
                 info!("Saved updated snapshot for CA: {}", ca_handle);
@@ -195,6 +196,7 @@ impl UpgradeStore for UpgradePubd {
                     last_event: repository.version(),
                     last_command,
                     last_update,
+                    ..StoredValueInfo::default()
                 };
                 store.save_info(&pubd_handle, &info)?;
             }
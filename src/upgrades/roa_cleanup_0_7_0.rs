@@ -2,6 +2,7 @@ use std::collections::HashSet;
 
 use crate::commons::api::{RoaDefinition, RoaDefinitionUpdates};
 use crate::commons::bgp::make_roa_tree;
+use crate::daemon::auth::Actor;
 use crate::daemon::krillserver::KrillServer;
 
 pub fn roa_cleanup(server: &KrillServer) -> Result<(), RoaCleanupError> {
@@ -21,7 +22,7 @@ pub fn roa_cleanup(server: &KrillServer) -> Result<(), RoaCleanupError> {
 
         if let Some(updates) = clean(roas) {
             info!("Will clean up ROAs as follows:\n{}", updates);
-            server.ca_routes_update(ca.handle().clone(), updates)?;
+            server.ca_routes_update(ca.handle().clone(), updates, &Actor::krill())?;
         } else {
             info!("No clean up needed");
         }
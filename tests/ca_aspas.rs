@@ -0,0 +1,87 @@
+extern crate krill;
+
+use std::fs;
+use std::str::FromStr;
+
+use krill::commons::api::{
+    AsNumber, AspaDefinition, AspaDefinitionUpdates, Handle, ObjectName, ParentCaReq, ResourceSet,
+};
+use krill::daemon::ca::ta_handle;
+use krill::test::*;
+
+#[tokio::test]
+/// Test that CAs can issue and publish ASPA objects for customer ASes they
+/// hold, and that these are updated and withdrawn when definitions or the
+/// resources of the CA change.
+async fn ca_aspas() {
+    let dir = start_krill().await;
+
+    let ta_handle = ta_handle();
+    let child = unsafe { Handle::from_str_unsafe("child") };
+    let child_resources = ResourceSet::from_strs("AS64496", "10.0.0.0/16", "").unwrap();
+
+    init_child_with_embedded_repo(&child).await;
+
+    // Set up under parent  ----------------------------------------------------------------
+    {
+        let parent = {
+            let parent_contact = add_child_to_ta_embedded(&child, child_resources.clone()).await;
+            ParentCaReq::new(ta_handle.clone(), parent_contact)
+        };
+        add_parent_to_ca(&child, parent).await;
+        assert!(ca_gets_resources(&child, &child_resources).await);
+    }
+
+    let customer = AsNumber::new(64496);
+
+    let crl_file = ".crl";
+    let mft_file = ".mft";
+    let aspa_file = ObjectName::aspa(customer).to_string();
+    let aspa_file = aspa_file.as_str();
+
+    // Add an ASPA definition
+    let aspa = AspaDefinition::from_str("64496 => 64497, 64498").unwrap();
+    let updates = AspaDefinitionUpdates::new(vec![aspa.clone()], vec![]);
+    ca_aspas_update(&child, updates).await;
+    will_publish_objects(&child, &[crl_file, mft_file, aspa_file]).await;
+    assert_eq!(&vec![aspa], ca_aspas_list(&child).await.definitions());
+
+    // Replace the providers for the customer AS
+    let aspa = AspaDefinition::from_str("64496 => 64499").unwrap();
+    let updates = AspaDefinitionUpdates::new(vec![aspa.clone()], vec![]);
+    ca_aspas_update(&child, updates).await;
+    assert_eq!(&vec![aspa], ca_aspas_list(&child).await.definitions());
+
+    // Refuse a customer AS not held by the CA
+    let not_held = AspaDefinition::from_str("64500 => 64497").unwrap();
+    let updates = AspaDefinitionUpdates::new(vec![not_held], vec![]);
+    ca_aspas_update_expect_error(&child, updates).await;
+
+    // Refuse the customer AS as its own provider
+    let own_provider = AspaDefinition::from_str("64496 => 64496, 64497").unwrap();
+    let updates = AspaDefinitionUpdates::new(vec![own_provider], vec![]);
+    ca_aspas_update_expect_error(&child, updates).await;
+
+    // Refuse removing an unknown definition
+    let updates = AspaDefinitionUpdates::new(vec![], vec![AsNumber::new(64500)]);
+    ca_aspas_update_expect_error(&child, updates).await;
+
+    // The ASPA object should remain there during a roll.
+    ca_roll_init(&child).await;
+    rc_state_becomes_new_key(&child).await;
+    ca_roll_activate(&child).await;
+    rc_state_becomes_active(&child).await;
+    will_publish_objects(&child, &[crl_file, mft_file, aspa_file]).await;
+
+    // Shrink resources and see that the ASPA object is withdrawn
+    let child_resources = ResourceSet::from_strs("", "10.0.0.0/16", "").unwrap();
+    update_child(&ta_handle, &child, &child_resources).await;
+    will_publish_objects(&child, &[crl_file, mft_file]).await;
+
+    // Remove the definition
+    let updates = AspaDefinitionUpdates::new(vec![], vec![customer]);
+    ca_aspas_update(&child, updates).await;
+    assert!(ca_aspas_list(&child).await.definitions().is_empty());
+
+    let _ = fs::remove_dir_all(dir);
+}
@@ -0,0 +1,110 @@
+extern crate krill;
+
+use std::fs;
+
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::x509::X509ReqBuilder;
+
+use krill::commons::api::{
+    AsNumber, Base64, Handle, ObjectName, ParentCaReq, ResourceSet, RouterCertRequest,
+};
+use krill::daemon::ca::{ta_handle, RouterKey};
+use krill::test::*;
+
+/// Makes a DER encoded PKCS#10 request for a new P-256 router key.
+fn router_csr() -> Vec<u8> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let pkey = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+    let mut builder = X509ReqBuilder::new().unwrap();
+    builder.set_pubkey(&pkey).unwrap();
+    builder.sign(&pkey, MessageDigest::sha256()).unwrap();
+    builder.build().to_der().unwrap()
+}
+
+#[tokio::test]
+/// Test that CAs can issue, publish and revoke BGPsec router certificates
+/// for ASNs they hold, and that these are re-issued when the ASNs change
+/// and withdrawn when the resources of the CA change.
+async fn ca_bgpsec() {
+    let dir = start_krill().await;
+
+    let ta_handle = ta_handle();
+    let child = unsafe { Handle::from_str_unsafe("child") };
+    let child_resources = ResourceSet::from_strs("AS64496-AS64497", "10.0.0.0/16", "").unwrap();
+
+    init_child_with_embedded_repo(&child).await;
+
+    // Set up under parent  ----------------------------------------------------------------
+    {
+        let parent = {
+            let parent_contact = add_child_to_ta_embedded(&child, child_resources.clone()).await;
+            ParentCaReq::new(ta_handle.clone(), parent_contact)
+        };
+        add_parent_to_ca(&child, parent).await;
+        assert!(ca_gets_resources(&child, &child_resources).await);
+    }
+
+    let csr = router_csr();
+    let key = RouterKey::from_csr(&csr).unwrap().key_identifier().clone();
+    let csr = Base64::from_content(&csr);
+
+    let crl_file = ".crl";
+    let mft_file = ".mft";
+    let router_file = ObjectName::router_cert(&key).to_string();
+    let router_file = router_file.as_str();
+
+    // Certify the router key
+    let request = RouterCertRequest::new(vec![AsNumber::new(64496)], csr.clone());
+    ca_router_certify(&child, request).await;
+    will_publish_objects(&child, &[crl_file, mft_file, router_file]).await;
+
+    let certs = ca_router_certs_list(&child).await;
+    assert_eq!(1, certs.certs().len());
+    assert_eq!(&key, certs.certs()[0].key());
+
+    // Update the ASNs for the router key
+    let asns = vec![AsNumber::new(64496), AsNumber::new(64497)];
+    let request = RouterCertRequest::new(asns.clone(), csr.clone());
+    ca_router_certify(&child, request).await;
+    assert_eq!(&asns, ca_router_certs_list(&child).await.certs()[0].asns());
+
+    // Refuse ASNs not held by the CA
+    let request = RouterCertRequest::new(vec![AsNumber::new(64500)], csr.clone());
+    ca_router_certify_expect_error(&child, request).await;
+
+    // Refuse a request without ASNs
+    let request = RouterCertRequest::new(vec![], csr.clone());
+    ca_router_certify_expect_error(&child, request).await;
+
+    // Refuse an invalid CSR
+    let invalid = Base64::from_content(b"not a csr");
+    let request = RouterCertRequest::new(vec![AsNumber::new(64496)], invalid);
+    ca_router_certify_expect_error(&child, request).await;
+
+    // The router certificate should remain there during a roll.
+    ca_roll_init(&child).await;
+    rc_state_becomes_new_key(&child).await;
+    ca_roll_activate(&child).await;
+    rc_state_becomes_active(&child).await;
+    will_publish_objects(&child, &[crl_file, mft_file, router_file]).await;
+
+    // Shrink resources and see that the router certificate is withdrawn
+    let child_resources = ResourceSet::from_strs("AS64496", "10.0.0.0/16", "").unwrap();
+    update_child(&ta_handle, &child, &child_resources).await;
+    will_publish_objects(&child, &[crl_file, mft_file]).await;
+
+    // Certify it again for the remaining ASN, and then revoke it
+    let request = RouterCertRequest::new(vec![AsNumber::new(64496)], csr);
+    ca_router_certify(&child, request).await;
+    will_publish_objects(&child, &[crl_file, mft_file, router_file]).await;
+
+    ca_router_revoke(&child, key).await;
+    will_publish_objects(&child, &[crl_file, mft_file]).await;
+    assert!(ca_router_certs_list(&child).await.certs().is_empty());
+
+    let _ = fs::remove_dir_all(dir);
+}
@@ -0,0 +1,82 @@
+extern crate krill;
+
+use std::fs;
+
+use krill::commons::api::{GhostbusterRecord, Handle, ObjectName, ParentCaReq, ResourceSet};
+use krill::daemon::ca::ta_handle;
+use krill::test::*;
+
+#[tokio::test]
+/// Test that CAs publish a Ghostbusters Record for their vCard, that it is
+/// kept through a key roll, and that it is withdrawn when the vCard is
+/// removed.
+async fn ca_ghostbuster() {
+    let dir = start_krill().await;
+
+    let ta_handle = ta_handle();
+    let child = unsafe { Handle::from_str_unsafe("child") };
+    let child_resources = ResourceSet::from_strs("AS64496", "10.0.0.0/16", "").unwrap();
+
+    init_child_with_embedded_repo(&child).await;
+
+    // Set up under parent  ----------------------------------------------------------------
+    {
+        let parent = {
+            let parent_contact = add_child_to_ta_embedded(&child, child_resources.clone()).await;
+            ParentCaReq::new(ta_handle.clone(), parent_contact)
+        };
+        add_parent_to_ca(&child, parent).await;
+        assert!(ca_gets_resources(&child, &child_resources).await);
+    }
+
+    let crl_file = ".crl";
+    let mft_file = ".mft";
+    let gbr_file = ObjectName::ghostbuster().to_string();
+    let gbr_file = gbr_file.as_str();
+
+    assert_eq!(None, ca_ghostbuster_show(&child).await);
+
+    // Refuse a vCard which does not follow the RFC 6493 profile
+    let invalid = GhostbusterRecord::new("BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Ops\r\n".to_string());
+    ca_ghostbuster_update_expect_error(&child, invalid).await;
+
+    // Refuse to remove a vCard which was never set
+    ca_ghostbuster_remove_expect_error(&child).await;
+
+    // Set the vCard
+    let record = GhostbusterRecord::from_fields(
+        "Operations",
+        Some("Example"),
+        Some("noc@example.com"),
+        None,
+        None,
+    );
+    ca_ghostbuster_update(&child, record.clone()).await;
+    will_publish_objects(&child, &[crl_file, mft_file, gbr_file]).await;
+    assert_eq!(Some(record), ca_ghostbuster_show(&child).await);
+
+    // Update the vCard
+    let record = GhostbusterRecord::from_fields(
+        "Operations",
+        Some("Example"),
+        Some("noc@example.com"),
+        Some("+31 20 123 4567"),
+        None,
+    );
+    ca_ghostbuster_update(&child, record.clone()).await;
+    assert_eq!(Some(record), ca_ghostbuster_show(&child).await);
+
+    // The Ghostbusters Record should remain there during a roll.
+    ca_roll_init(&child).await;
+    rc_state_becomes_new_key(&child).await;
+    ca_roll_activate(&child).await;
+    rc_state_becomes_active(&child).await;
+    will_publish_objects(&child, &[crl_file, mft_file, gbr_file]).await;
+
+    // Remove the vCard and see that the object is withdrawn
+    ca_ghostbuster_remove(&child).await;
+    will_publish_objects(&child, &[crl_file, mft_file]).await;
+    assert_eq!(None, ca_ghostbuster_show(&child).await);
+
+    let _ = fs::remove_dir_all(dir);
+}
@@ -0,0 +1,87 @@
+extern crate krill;
+
+use std::fs;
+
+use krill::cli::options::{CaCommand, Command, Options, TokensCommand};
+use krill::cli::report::{ApiResponse, ReportFormat};
+use krill::cli::{Error, KrillClient};
+use krill::commons::api::{CaTokenRequest, CertAuthInit, Handle};
+use krill::test::*;
+
+async fn krill_with_token(token: &str, command: Command) -> Result<ApiResponse, Error> {
+    let krillc_opts = Options::new(
+        https("https://localhost:3000/"),
+        token,
+        ReportFormat::Json,
+        command,
+    );
+    KrillClient::process(krillc_opts).await
+}
+
+#[tokio::test]
+/// Test that tokens bound to a CA can be added and revoked at runtime, and
+/// that such tokens can only see and manage their own CA.
+async fn ca_tokens() {
+    let dir = start_krill().await;
+
+    let ca1 = unsafe { Handle::from_str_unsafe("ca1") };
+    let ca2 = unsafe { Handle::from_str_unsafe("ca2") };
+
+    krill_admin(Command::CertAuth(CaCommand::Init(CertAuthInit::new(
+        ca1.clone(),
+    ))))
+    .await;
+    krill_admin(Command::CertAuth(CaCommand::Init(CertAuthInit::new(
+        ca2.clone(),
+    ))))
+    .await;
+
+    // Tokens can only be bound to existing CAs
+    let req = CaTokenRequest::new("unit-x", vec![unsafe { Handle::from_str_unsafe("ca3") }]);
+    krill_admin_expect_error(Command::Tokens(TokensCommand::Add(req))).await;
+
+    let req = CaTokenRequest::new("unit-1", vec![ca1.clone()]);
+    let token = match krill_admin(Command::Tokens(TokensCommand::Add(req))).await {
+        ApiResponse::CaToken(ca_token) => ca_token.token().to_string(),
+        _ => panic!("Expected CA token"),
+    };
+
+    match krill_admin(Command::Tokens(TokensCommand::List)).await {
+        ApiResponse::CaTokenList(list) => assert_eq!(1, list.tokens().len()),
+        _ => panic!("Expected CA token list"),
+    }
+
+    // The token only sees ca1
+    match krill_with_token(&token, Command::CertAuth(CaCommand::List)).await {
+        Ok(ApiResponse::CertAuths(list)) => {
+            assert_eq!(1, list.cas().len());
+            assert_eq!(&ca1, list.cas()[0].handle());
+        }
+        _ => panic!("Expected CA list"),
+    }
+
+    let show_ca1 = Command::CertAuth(CaCommand::Show(ca1.clone()));
+    let show_ca2 = Command::CertAuth(CaCommand::Show(ca2.clone()));
+    assert!(krill_with_token(&token, show_ca1.clone()).await.is_ok());
+    assert!(krill_with_token(&token, show_ca2).await.is_err());
+
+    // The token can manage ca1, but not ca2
+    let update_id_ca1 = Command::CertAuth(CaCommand::UpdateId(ca1.clone()));
+    let update_id_ca2 = Command::CertAuth(CaCommand::UpdateId(ca2.clone()));
+    assert!(krill_with_token(&token, update_id_ca1).await.is_ok());
+    assert!(krill_with_token(&token, update_id_ca2).await.is_err());
+
+    // The token cannot be used to mint other tokens
+    let req = CaTokenRequest::new("unit-2", vec![ca2.clone()]);
+    assert!(
+        krill_with_token(&token, Command::Tokens(TokensCommand::Add(req)))
+            .await
+            .is_err()
+    );
+
+    // Once revoked the token is no longer accepted
+    krill_admin(Command::Tokens(TokensCommand::Remove("unit-1".to_string()))).await;
+    assert!(krill_with_token(&token, show_ca1).await.is_err());
+
+    let _ = fs::remove_dir_all(dir);
+}
@@ -4,10 +4,13 @@ use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
 use krill::commons::api::{CaCommandDetails, CommandHistoryCriteria, Handle};
+use krill::commons::eventsourcing::StorageBackend;
 use krill::commons::util::file;
 use krill::commons::util::softsigner::OpenSslSigner;
-use krill::daemon::ca::CaServer;
+use krill::daemon::ca::{CaServer, RoaAggregation};
+use krill::daemon::eventstream::EventStream;
 use krill::daemon::mq::EventQueueListener;
+use krill::daemon::webhooks::WebhookQueue;
 use krill::test::*;
 use std::env;
 
@@ -57,9 +60,23 @@ fn make_server(work_dir: &PathBuf, scenario: &str) -> CaServer<OpenSslSigner> {
         let signer = OpenSslSigner::build(&server_dir).unwrap();
         let signer = Arc::new(RwLock::new(signer));
 
-        let event_queue = Arc::new(EventQueueListener::in_mem());
-
-        CaServer::<OpenSslSigner>::build(&server_dir, None, None, event_queue, signer).unwrap()
+        let event_queue = Arc::new(EventQueueListener::build(&server_dir).unwrap());
+        let event_stream = Arc::new(EventStream::default());
+        let webhooks = Arc::new(WebhookQueue::build(&server_dir, vec![]).unwrap());
+        let roa_aggregation = RoaAggregation::new(100, 90);
+
+        CaServer::<OpenSslSigner>::build(
+            &server_dir,
+            None,
+            None,
+            event_queue,
+            event_stream,
+            webhooks,
+            roa_aggregation,
+            StorageBackend::Disk,
+            signer,
+        )
+        .unwrap()
     };
 
     server